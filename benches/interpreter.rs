@@ -0,0 +1,234 @@
+// Times the programs in benches/programs, compile and run separately.
+//
+//     cargo bench --bench interpreter
+//         all programs, 5 runs each
+//     cargo bench --bench interpreter -- fib closures --runs 10
+//         only the named programs, with more runs for steadier numbers
+//     cargo bench --bench interpreter -- --save-baseline before
+//         remember the medians as `before`
+//     cargo bench --bench interpreter -- --baseline before
+//         compare the medians against `before`
+//
+// Each program stores what it computed in the global `result`, which is
+// printed so a change that breaks a program doesn't go unnoticed as a
+// speedup.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+
+use rlox::interpreter::Interpreter;
+use rlox::parser::Parser;
+use rlox::resolver::Resolver;
+use rlox::scanner::Scanner;
+use rlox::stmt::Stmt;
+
+const PROGRAMS: &[(&str, &str)] = &[
+    ("fib", include_str!("programs/fib.lox")),
+    ("string_concat", include_str!("programs/string_concat.lox")),
+    ("closures", include_str!("programs/closures.lox")),
+    ("methods", include_str!("programs/methods.lox")),
+];
+
+struct Options {
+    filter: Vec<String>,
+    runs: usize,
+    save_baseline: Option<String>,
+    baseline: Option<String>,
+}
+
+struct Measurement {
+    compile: Duration,
+    run: Duration,
+    calls: usize,
+    result: String,
+}
+
+fn main() {
+    let options = parse_options();
+    let baseline = options.baseline.as_deref().map(load_baseline);
+
+    let mut medians = Vec::new();
+    for &(name, source) in PROGRAMS {
+        if !options.filter.is_empty()
+            && !options.filter.iter().any(|f| f == name)
+        {
+            continue;
+        }
+
+        // One run to warm up caches and the allocator, not counted.
+        measure(name, source);
+        let mut measurements: Vec<Measurement> =
+            (0..options.runs).map(|_| measure(name, source)).collect();
+        measurements.sort_by_key(|m| m.run);
+
+        let median = &measurements[measurements.len() / 2];
+        let compared = match baseline.as_ref().and_then(|b| b.get(name)) {
+            Some(&before) => {
+                let change = median.run.as_nanos() as f64 / before as f64;
+                format!("  {:+.1}%", (change - 1.0) * 100.0)
+            }
+            None => String::new(),
+        };
+        println!(
+            "{name:<14} compile {:>8.2} ms  run min {:>9.2} ms  median {:>9.2} ms{compared}",
+            millis(median.compile),
+            millis(measurements[0].run),
+            millis(median.run),
+        );
+        println!(
+            "{:<14} calls {}, result {}",
+            "",
+            median.calls,
+            shorten(&median.result)
+        );
+        medians.push((name, median.run));
+    }
+
+    if let Some(name) = options.save_baseline {
+        save_baseline(&name, &medians);
+    }
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        filter: Vec::new(),
+        runs: 5,
+        save_baseline: None,
+        baseline: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--runs" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(runs) if runs > 0 => options.runs = runs,
+                _ => usage(),
+            },
+            "--save-baseline" => options.save_baseline = args.next(),
+            "--baseline" => options.baseline = args.next(),
+            // Cargo passes `--bench` to every benchmark binary.
+            "--bench" => {}
+            _ if arg.starts_with('-') => usage(),
+            _ => options.filter.push(arg),
+        }
+    }
+
+    options
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: cargo bench --bench interpreter -- [program...] [--runs n]\n\
+         \x20      [--save-baseline name] [--baseline name]"
+    );
+    process::exit(64);
+}
+
+fn measure(name: &str, source: &str) -> Measurement {
+    let start = Instant::now();
+    let statements = compile(name, source);
+    let compile = start.elapsed();
+
+    let mut interpreter = Interpreter::new();
+    let start = Instant::now();
+    if interpreter.interpret(&statements).is_err() {
+        eprintln!("{name} failed at runtime");
+        process::exit(70);
+    }
+    let run = start.elapsed();
+
+    let result = match interpreter.get_global("result") {
+        Some(value) => value.to_string(),
+        None => "none".to_string(),
+    };
+
+    Measurement {
+        compile,
+        run,
+        calls: interpreter.stats().calls,
+        result,
+    }
+}
+
+fn compile(name: &str, source: &str) -> Vec<Stmt> {
+    let mut scanner = Scanner::with_name(source, name);
+    let tokens = scanner.scan_tokens().clone();
+    let statements = match Parser::new(&tokens).parse() {
+        Ok(statements) if !scanner.had_error() => statements,
+        _ => {
+            eprintln!("{name} doesn't compile");
+            process::exit(65);
+        }
+    };
+
+    if Resolver::new().resolve(&statements).is_err() {
+        eprintln!("{name} doesn't compile");
+        process::exit(65);
+    }
+
+    statements
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn shorten(result: &str) -> String {
+    if result.chars().count() <= 40 {
+        return result.to_string();
+    }
+
+    let start: String = result.chars().take(37).collect();
+    format!("{start}...")
+}
+
+// Baselines are kept under target/, one `name nanoseconds` line per program.
+fn baseline_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("rlox-bench")
+        .join(format!("{name}.txt"))
+}
+
+fn save_baseline(name: &str, medians: &[(&str, Duration)]) {
+    let path = baseline_path(name);
+    let contents: String = medians
+        .iter()
+        .map(|(program, run)| format!("{program} {}\n", run.as_nanos()))
+        .collect();
+
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, contents));
+    match written {
+        Ok(()) => println!("saved baseline {name} to {}", path.display()),
+        Err(e) => {
+            eprintln!("Unable to write {}: {e}", path.display());
+            process::exit(74);
+        }
+    }
+}
+
+fn load_baseline(name: &str) -> HashMap<String, u128> {
+    let path = baseline_path(name);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Unable to read {}: {e}", path.display());
+            process::exit(66);
+        }
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (program, nanos) = line.split_once(' ')?;
+            Some((program.to_string(), nanos.parse().ok()?))
+        })
+        .collect()
+}
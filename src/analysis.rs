@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::token::{Token, REPL};
+use crate::token_type::TokenType;
+
+// What a declared name is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Parameter,
+    Local,
+    Global,
+    Function,
+    Class,
+}
+
+#[derive(Debug)]
+pub struct Declaration {
+    pub name: Token,
+    pub kind: SymbolKind,
+    // How many local scopes it is declared inside of, 0 for a global.
+    pub depth: usize,
+}
+
+// A place a name is written, either declaring it or referring to it.
+#[derive(Debug)]
+pub struct Occurrence {
+    pub name: Token,
+    // The index of its declaration, or None for a name never declared in
+    // the source, like a native function.
+    pub declaration: Option<usize>,
+}
+
+// Where every name in a script is declared and which declaration each use
+// of a name refers to, as the resolver worked it out. It's what an editor
+// needs to jump to a definition, describe a name or rename it everywhere.
+#[derive(Debug, Default)]
+pub struct SemanticModel {
+    declarations: Vec<Declaration>,
+    occurrences: Vec<Occurrence>,
+    // The first declaration of each global, which references to globals are
+    // linked to once the whole script has been seen.
+    globals: HashMap<Symbol, usize>,
+}
+
+impl SemanticModel {
+    pub fn declarations(&self) -> &[Declaration] {
+        &self.declarations
+    }
+
+    // Every occurrence, in source order.
+    pub fn occurrences(&self) -> &[Occurrence] {
+        &self.occurrences
+    }
+
+    pub fn declaration(&self, occurrence: &Occurrence) -> Option<&Declaration> {
+        occurrence
+            .declaration
+            .map(|index| &self.declarations[index])
+    }
+
+    // The first occurrence of `name` on `line`.
+    pub fn find(&self, line: u32, name: &str) -> Option<&Occurrence> {
+        self.occurrences
+            .iter()
+            .find(|o| o.name.line == line && *o.name.lexeme == *name)
+    }
+
+    // The occurrence written over `column` of `line`, as an editor's cursor
+    // would be.
+    pub fn at(&self, line: u32, column: u32) -> Option<&Occurrence> {
+        self.occurrences.iter().find(|o| {
+            let start = o.name.column;
+            let end = start + o.name.lexeme.chars().count() as u32;
+            o.name.line == line && (start..end).contains(&column)
+        })
+    }
+
+    // Every occurrence that is the declaration at `index` or refers to it,
+    // which is what renaming it has to change.
+    pub fn references(
+        &self,
+        index: usize,
+    ) -> impl Iterator<Item = &Occurrence> + '_ {
+        self.occurrences
+            .iter()
+            .filter(move |o| o.declaration == Some(index))
+    }
+
+    // Records a declaration, which is also an occurrence of the name, and
+    // returns its index, or None for a name the parser made up.
+    pub(crate) fn declare(
+        &mut self,
+        name: &Token,
+        kind: SymbolKind,
+        depth: usize,
+    ) -> Option<usize> {
+        if hidden(name) {
+            return None;
+        }
+        let index = self.declarations.len();
+        self.declarations.push(Declaration {
+            name: name.clone(),
+            kind,
+            depth,
+        });
+        if depth == 0 {
+            self.globals.entry(name.symbol).or_insert(index);
+        }
+        self.occurrences.push(Occurrence {
+            name: name.clone(),
+            declaration: Some(index),
+        });
+        Some(index)
+    }
+
+    // Records a reference to a local's declaration, or to a global when
+    // `declaration` is None.
+    pub(crate) fn refer(&mut self, name: &Token, declaration: Option<usize>) {
+        if !hidden(name) {
+            self.occurrences.push(Occurrence {
+                name: name.clone(),
+                declaration,
+            });
+        }
+    }
+
+    // Links the references to globals, which can come before the global is
+    // declared, as when a function calls one declared after it.
+    pub(crate) fn finish(mut self) -> SemanticModel {
+        for occurrence in &mut self.occurrences {
+            if occurrence.declaration.is_none() {
+                occurrence.declaration =
+                    self.globals.get(&occurrence.name.symbol).copied();
+            }
+        }
+        self.occurrences
+            .sort_by_key(|o| (o.name.line, o.name.column));
+        self
+    }
+}
+
+// Names the parser makes up for the code it desugars into, which can't be
+// written in a script: hidden variables have a space in them, and an enum
+// declaration calls the enum() native.
+fn hidden(name: &Token) -> bool {
+    name.token_type != TokenType::Identifier
+        || name.lexeme.contains(' ')
+        || &*name.lexeme == "enum"
+}
+
+// Works out the names in `source` without running it. Returns None if it
+// doesn't parse, once the errors are reported. Errors the resolver finds,
+// like a return outside a function, still leave every name worked out.
+pub fn analyze(source: &str) -> Option<SemanticModel> {
+    analyze_named(REPL, source)
+}
+
+// Like analyze, with errors reported as being in the file `name`.
+pub fn analyze_named(name: &str, source: &str) -> Option<SemanticModel> {
+    analyze_program(name, source).map(|(_, model)| model)
+}
+
+// The parsed program along with its model, for passes that walk the tree
+// and need to know what the names in it refer to.
+pub fn analyze_program(
+    name: &str,
+    source: &str,
+) -> Option<(Vec<Stmt>, SemanticModel)> {
+    let mut scanner = Scanner::with_name(source, name);
+    let tokens = scanner.scan_tokens().clone();
+    let statements = Parser::new(&tokens)
+        .parse()
+        .ok()
+        .filter(|_| !scanner.had_error())?;
+
+    let mut resolver = Resolver::with_semantic_model();
+    let _ = resolver.resolve(&statements);
+    let model = resolver.take_semantic_model()?;
+    Some((statements, model))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(source: &str) -> SemanticModel {
+        analyze(source).unwrap_or_else(|| panic!("{source:?} should parse"))
+    }
+
+    // Each occurrence as `name line -> kind line depth`, or `-> ?` for an
+    // undeclared name.
+    fn describe(model: &SemanticModel) -> Vec<String> {
+        model
+            .occurrences()
+            .iter()
+            .map(|o| {
+                let target = match model.declaration(o) {
+                    Some(d) => {
+                        format!("{:?} {} {}", d.kind, d.name.line, d.depth)
+                    }
+                    None => "?".to_string(),
+                };
+                format!("{} {} -> {target}", o.name.lexeme, o.name.line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn links_names_to_their_declarations() {
+        let model = model(
+            "var total = 0;
+            fun add(n) {
+              var sum = total + n;
+              return helper(sum);
+            }
+            class Box {}
+            fun helper(x) { return Box; }
+            print clock;",
+        );
+        assert_eq!(
+            describe(&model),
+            [
+                "total 1 -> Global 1 0",
+                "add 2 -> Function 2 0",
+                "n 2 -> Parameter 2 1",
+                "sum 3 -> Local 3 1",
+                "total 3 -> Global 1 0",
+                "n 3 -> Parameter 2 1",
+                "helper 4 -> Function 7 0",
+                "sum 4 -> Local 3 1",
+                "Box 6 -> Class 6 0",
+                "helper 7 -> Function 7 0",
+                "x 7 -> Parameter 7 1",
+                "Box 7 -> Class 6 0",
+                "clock 8 -> ?",
+            ]
+        );
+    }
+
+    #[test]
+    fn tells_shadowed_names_apart() {
+        let model = model(
+            "var a = 1;
+            { var a = 2; print a; }
+            print a;",
+        );
+        let inner = model.find(2, "a").and_then(|o| o.declaration);
+        let outer = model.find(3, "a").and_then(|o| o.declaration);
+        assert_eq!(inner, Some(1));
+        assert_eq!(outer, Some(0));
+        let lines: Vec<u32> =
+            model.references(1).map(|o| o.name.line).collect();
+        assert_eq!(lines, [2, 2]);
+    }
+
+    #[test]
+    fn leaves_out_names_the_parser_makes_up() {
+        let model = model(
+            "enum Color { Red }
+            match (Color.Red) { case Color.Red: print 1; }",
+        );
+        assert_eq!(
+            describe(&model),
+            [
+                "Color 1 -> Global 1 0",
+                "Color 2 -> Global 1 0",
+                "Color 2 -> Global 1 0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+// Declares a syntax tree enum with a struct for each kind of node, and the
+// visitor trait that walks it. For every node this generates the struct,
+// its variant, a constructor taking the fields in order, and an accept()
+// calling the visitor method named after `=>`:
+//
+//     define_ast! {
+//         pub enum Expr, visited by ExprVisitor as expr;
+//
+//         Unary(UnaryExpr { operator: Token, right: Rc<Expr> })
+//             => visit_unary_expr,
+//     }
+//
+// Fields after a `;` aren't taken by the constructor but start out as the
+// value given for them, for state the parser doesn't fill in.
+macro_rules! define_ast {
+    (
+        pub enum $enum:ident, visited by $visitor:ident as $param:ident;
+
+        $(
+            $(#[$meta:meta])*
+            $variant:ident($node:ident {
+                $( $(#[$field_meta:meta])* $field:ident: $field_ty:ty ),* $(,)?
+                $(;
+                    $(
+                        $(#[$extra_meta:meta])*
+                        $extra:ident: $extra_ty:ty = $default:expr
+                    ),* $(,)?
+                )?
+            }) => $visit:ident,
+        )*
+    ) => {
+        #[derive(Debug)]
+        pub enum $enum {
+            $( $variant($node), )*
+        }
+
+        impl $enum {
+            pub fn accept<T>(&self, visitor: &mut dyn $visitor<T>) -> T {
+                match self {
+                    $( $enum::$variant(node) => node.accept(visitor), )*
+                }
+            }
+        }
+
+        $(
+            $(#[$meta])*
+            #[derive(Debug)]
+            pub struct $node {
+                $( $(#[$field_meta])* pub $field: $field_ty, )*
+                $($( $(#[$extra_meta])* pub $extra: $extra_ty, )*)?
+            }
+
+            impl $node {
+                pub fn new($( $field: $field_ty ),*) -> $node {
+                    $node {
+                        $( $field, )*
+                        $($( $extra: $default, )*)?
+                    }
+                }
+
+                pub fn accept<T>(&self, visitor: &mut dyn $visitor<T>) -> T {
+                    visitor.$visit(self)
+                }
+            }
+        )*
+
+        pub trait $visitor<T> {
+            $( fn $visit(&mut self, $param: &$node) -> T; )*
+        }
+    };
+}
+
+pub(crate) use define_ast;
@@ -1,12 +1,25 @@
 use std::rc::Rc;
 
 use crate::expr::{
-    AssignExpr, BinaryExpr, Expr, ExprVisitor, GroupingExpr, LiteralExpr,
-    UnaryExpr, VariableExpr,
+    AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr,
+    LiteralExpr, LogicalExpr, MatchExpr, SetExpr, ThisExpr, UnaryExpr,
+    VariableExpr,
 };
+use crate::stmt::{
+    BlockStmt, BreakStmt, ClassStmt, ContinueStmt, ExpressionStmt, ForStmt,
+    FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt, StmtVisitor, VarStmt,
+    WhileStmt,
+};
+use crate::token::Token;
 
 pub struct AstPrinter;
 
+impl Default for AstPrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AstPrinter {
     pub fn new() -> AstPrinter {
         AstPrinter
@@ -16,7 +29,33 @@ impl AstPrinter {
         expr.accept(self)
     }
 
-    fn parenthesize(&mut self, name: &str, exprs: &Vec<&Rc<Expr>>) -> String {
+    pub fn print_stmt(&mut self, stmt: &Stmt) -> String {
+        stmt.accept(self)
+    }
+
+    fn parts(&mut self, name: &str, parts: &[String]) -> String {
+        let mut result_string = format!("({name}");
+        for part in parts {
+            result_string = format!("{result_string} {part}");
+        }
+        format!("{result_string})")
+    }
+
+    fn optional(&mut self, expr: &Option<Rc<Expr>>) -> String {
+        match expr {
+            Some(e) => e.accept(self),
+            None => "()".to_string(),
+        }
+    }
+
+    fn labeled(label: &Option<Token>, printed: String) -> String {
+        match label {
+            Some(l) => format!("{}: {printed}", l.lexeme),
+            None => printed,
+        }
+    }
+
+    fn parenthesize(&mut self, name: &str, exprs: &[&Rc<Expr>]) -> String {
         let mut result_string = format!("({name}");
         for expr in exprs {
             result_string = format!("{result_string} {}", expr.accept(self));
@@ -29,17 +68,14 @@ impl AstPrinter {
 
 impl ExprVisitor<String> for AstPrinter {
     fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> String {
-        self.parenthesize(
-            &expr.operator.lexeme.to_owned(),
-            &vec![&expr.left, &expr.right],
-        )
+        self.parenthesize(&expr.operator.lexeme, &[&expr.left, &expr.right])
     }
 
     fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> String {
-        self.parenthesize("group", &vec![&expr.expression])
+        self.parenthesize("group", &[&expr.expression])
     }
 
-    fn visit_literal_expr(&self, expr: &LiteralExpr) -> String {
+    fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> String {
         match &expr.value {
             None => "nil".to_string(),
             Some(literal) => literal.to_string(),
@@ -47,22 +83,153 @@ impl ExprVisitor<String> for AstPrinter {
     }
 
     fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> String {
-        self.parenthesize(&expr.operator.lexeme.to_owned(), &vec![&expr.right])
+        self.parenthesize(&expr.operator.lexeme, &[&expr.right])
     }
 
-    fn visit_variable_expr(&self, expr: &VariableExpr) -> String {
-        todo!()
+    fn visit_variable_expr(&mut self, expr: &VariableExpr) -> String {
+        expr.name.lexeme.to_string()
     }
 
     fn visit_assignment_expr(&mut self, expr: &AssignExpr) -> String {
-        todo!()
+        let name = format!("= {}", expr.name.lexeme);
+        self.parenthesize(&name, &[&expr.value])
+    }
+
+    fn visit_logical_exp(&mut self, expr: &LogicalExpr) -> String {
+        self.parenthesize(&expr.operator.lexeme, &[&expr.left, &expr.right])
+    }
+
+    fn visit_match_expr(&mut self, expr: &MatchExpr) -> String {
+        let mut result = format!("(match {}", expr.subject.accept(self));
+        for arm in &expr.arms {
+            let pattern = match (&arm.pattern, &arm.binding) {
+                (Some(pattern), _) => pattern.accept(self),
+                (None, Some(binding)) => binding.lexeme.to_string(),
+                (None, None) => "_".to_string(),
+            };
+            let guard = match &arm.guard {
+                Some(guard) => format!(" (if {})", guard.accept(self)),
+                None => String::new(),
+            };
+            let value = arm.value.accept(self);
+            result = format!("{result} (case {pattern}{guard} {value})");
+        }
+        format!("{result})")
     }
 
-    fn visit_logical_exp(&mut self, expr: &crate::expr::LogicalExpr) -> String {
-        todo!()
+    fn visit_call_expr(&mut self, expr: &CallExpr) -> String {
+        let mut exprs = vec![&expr.callee];
+        exprs.extend(expr.arguments.iter());
+        self.parenthesize("call", &exprs)
+    }
+
+    fn visit_get_expr(&mut self, expr: &GetExpr) -> String {
+        let object = expr.object.accept(self);
+        let operator = if expr.optional { "?." } else { "." };
+        format!("({operator} {object} {})", expr.name.lexeme)
+    }
+
+    fn visit_set_expr(&mut self, expr: &SetExpr) -> String {
+        let object = expr.object.accept(self);
+        let value = expr.value.accept(self);
+        format!("(= (. {object} {}) {value})", expr.name.lexeme)
+    }
+
+    fn visit_this_expr(&mut self, _expr: &ThisExpr) -> String {
+        "this".to_string()
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_expression_stmt(&mut self, stmt: &ExpressionStmt) -> String {
+        self.parenthesize(";", &[&stmt.expression])
+    }
+
+    fn visit_print_stmt(&mut self, stmt: &PrintStmt) -> String {
+        self.parenthesize("print", &[&stmt.expression])
+    }
+
+    fn visit_var_stmt(&mut self, stmt: &VarStmt) -> String {
+        let name = match &stmt.type_annotation {
+            Some(t) => format!("var {}: {}", stmt.name.lexeme, t.lexeme),
+            None => format!("var {}", stmt.name.lexeme),
+        };
+        match &stmt.initializer {
+            Some(initializer) => self.parenthesize(&name, &[initializer]),
+            None => format!("({name})"),
+        }
+    }
+
+    fn visit_block_stmt(&mut self, stmt: &BlockStmt) -> String {
+        let parts: Vec<String> =
+            stmt.statements.iter().map(|s| s.accept(self)).collect();
+        self.parts("block", &parts)
+    }
+
+    fn visit_if_stmt(&mut self, stmt: &IfStmt) -> String {
+        let mut parts =
+            vec![stmt.condition.accept(self), stmt.then_branch.accept(self)];
+        if let Some(else_branch) = &stmt.else_branch {
+            parts.push(else_branch.accept(self));
+        }
+        self.parts("if", &parts)
+    }
+
+    fn visit_while_stmt(&mut self, stmt: &WhileStmt) -> String {
+        let parts = [stmt.condition.accept(self), stmt.body.accept(self)];
+        let printed = self.parts("while", &parts);
+        Self::labeled(&stmt.label, printed)
+    }
+
+    fn visit_for_stmt(&mut self, stmt: &ForStmt) -> String {
+        let parts = [
+            match &stmt.initializer {
+                Some(i) => i.accept(self),
+                None => "()".to_string(),
+            },
+            self.optional(&stmt.condition),
+            self.optional(&stmt.increment),
+            stmt.body.accept(self),
+        ];
+        let printed = self.parts("for", &parts);
+        Self::labeled(&stmt.label, printed)
+    }
+
+    fn visit_function_stmt(&mut self, stmt: &FunctionStmt) -> String {
+        let params: Vec<&str> =
+            stmt.params.iter().map(|p| p.lexeme.as_ref()).collect();
+        let mut parts = vec![format!("({})", params.join(" "))];
+        parts.extend(stmt.body.iter().map(|s| s.accept(self)));
+        self.parts(&format!("fun {}", stmt.name.lexeme), &parts)
+    }
+
+    fn visit_return_stmt(&mut self, stmt: &ReturnStmt) -> String {
+        match &stmt.value {
+            Some(value) => self.parenthesize("return", &[value]),
+            None => "(return)".to_string(),
+        }
+    }
+
+    fn visit_break_stmt(&mut self, stmt: &BreakStmt) -> String {
+        match &stmt.label {
+            Some(label) => format!("(break {})", label.lexeme),
+            None => "(break)".to_string(),
+        }
+    }
+
+    fn visit_continue_stmt(&mut self, stmt: &ContinueStmt) -> String {
+        match &stmt.label {
+            Some(label) => format!("(continue {})", label.lexeme),
+            None => "(continue)".to_string(),
+        }
     }
 
-    fn visit_call_expr(&mut self, expr: &crate::expr::CallExpr) -> String {
-        todo!()
+    fn visit_class_stmt(&mut self, stmt: &ClassStmt) -> String {
+        let parts: Vec<String> = stmt
+            .methods
+            .iter()
+            .map(|m| self.visit_function_stmt(m))
+            .collect();
+        self.parts(&format!("class {}", stmt.name.lexeme), &parts)
     }
 }
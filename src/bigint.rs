@@ -0,0 +1,358 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::ops::{Add, Mul, Neg, Sub};
+
+// Digits of a magnitude, least significant first, in base 2^32.
+type Limbs = Vec<u32>;
+
+// The largest power of ten that fits in a limb, which decimal text is read
+// and written in chunks of.
+const DECIMAL_CHUNK: u32 = 1_000_000_000;
+const DECIMAL_CHUNK_DIGITS: usize = 9;
+
+// An integer of any size, for arithmetic that would overflow an i64 and lose
+// digits as a float. Kept normalized: no leading zero limbs, and zero is
+// never negative, so equal values have equal fields.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Limbs,
+}
+
+impl BigInt {
+    // Reads decimal digits with an optional sign, as in "-123".
+    pub fn parse(text: &str) -> Option<BigInt> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut magnitude = Limbs::new();
+        // A short first chunk, so the rest are all whole ones.
+        let first = match digits.len() % DECIMAL_CHUNK_DIGITS {
+            0 => DECIMAL_CHUNK_DIGITS,
+            n => n,
+        };
+        let mut start = 0;
+        let mut end = first;
+        while start < digits.len() {
+            let chunk: u32 = digits[start..end].parse().ok()?;
+            let scale = 10u32.pow((end - start) as u32);
+            multiply_add(&mut magnitude, scale, chunk);
+            start = end;
+            end += DECIMAL_CHUNK_DIGITS;
+        }
+        Some(BigInt::new(negative, magnitude))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    // The value as an i64, if it is in range.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let magnitude = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0u64, |n, &limb| (n << 32) | limb as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    // The quotient rounded toward zero, or None when dividing by zero.
+    pub fn checked_div(&self, divisor: &BigInt) -> Option<BigInt> {
+        let (quotient, _) = divide(&self.magnitude, &divisor.magnitude)?;
+        Some(BigInt::new(self.negative != divisor.negative, quotient))
+    }
+
+    // What is left after checked_div, which has the sign of the dividend.
+    pub fn checked_rem(&self, divisor: &BigInt) -> Option<BigInt> {
+        let (_, remainder) = divide(&self.magnitude, &divisor.magnitude)?;
+        Some(BigInt::new(self.negative, remainder))
+    }
+
+    fn new(negative: bool, mut magnitude: Limbs) -> BigInt {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        BigInt {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let magnitude = n.unsigned_abs();
+        BigInt::new(n < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare(&self.magnitude, &other.magnitude),
+            (true, true) => compare(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(
+                self.negative,
+                add(&self.magnitude, &other.magnitude),
+            );
+        }
+        // Opposite signs: the larger magnitude wins, less the smaller one.
+        match compare(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::new(
+                other.negative,
+                subtract(&other.magnitude, &self.magnitude),
+            ),
+            _ => BigInt::new(
+                self.negative,
+                subtract(&self.magnitude, &other.magnitude),
+            ),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut product =
+            vec![0u32; self.magnitude.len() + other.magnitude.len()];
+        for (i, &a) in self.magnitude.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.magnitude.iter().enumerate() {
+                let sum = product[i + j] as u64 + a as u64 * b as u64 + carry;
+                product[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            product[i + other.magnitude.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, product)
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.magnitude.clone())
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let mut chunks = Vec::new();
+        let mut rest = self.magnitude.clone();
+        while !rest.is_empty() {
+            chunks.push(divide_small(&mut rest, DECIMAL_CHUNK));
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        if let Some(first) = chunks.next() {
+            write!(f, "{first}")?;
+        }
+        for chunk in chunks {
+            write!(f, "{chunk:0width$}", width = DECIMAL_CHUNK_DIGITS)?;
+        }
+        Ok(())
+    }
+}
+
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add(a: &[u32], b: &[u32]) -> Limbs {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Limbs::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &limb) in long.iter().enumerate() {
+        let total = limb as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+// `a - b`, where a is at least b.
+fn subtract(a: &[u32], b: &[u32]) -> Limbs {
+    let mut difference = Limbs::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut total = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (total < 0) as i64;
+        total += borrow << 32;
+        difference.push(total as u32);
+    }
+    difference
+}
+
+// `n = n * factor + addend`, in place.
+fn multiply_add(n: &mut Limbs, factor: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for limb in n.iter_mut() {
+        let total = *limb as u64 * factor as u64 + carry;
+        *limb = total as u32;
+        carry = total >> 32;
+    }
+    if carry > 0 {
+        n.push(carry as u32);
+    }
+}
+
+// Divides `n` by `divisor` in place, normalizing it, and gives the remainder.
+fn divide_small(n: &mut Limbs, divisor: u32) -> u32 {
+    let mut remainder = 0u64;
+    for limb in n.iter_mut().rev() {
+        let current = (remainder << 32) | *limb as u64;
+        *limb = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    while n.last() == Some(&0) {
+        n.pop();
+    }
+    remainder as u32
+}
+
+// The quotient and remainder of two magnitudes, by long division a bit at a
+// time once the divisor is more than a limb long.
+fn divide(dividend: &[u32], divisor: &[u32]) -> Option<(Limbs, Limbs)> {
+    match divisor {
+        [] => None,
+        &[divisor] => {
+            let mut quotient = dividend.to_vec();
+            let remainder = divide_small(&mut quotient, divisor);
+            Some((quotient, vec![remainder]))
+        }
+        _ => {
+            let mut quotient = vec![0u32; dividend.len()];
+            let mut remainder = Limbs::new();
+            for bit in (0..dividend.len() * 32).rev() {
+                shift_left_one(
+                    &mut remainder,
+                    dividend[bit / 32] >> (bit % 32) & 1,
+                );
+                if compare(&remainder, divisor) != Ordering::Less {
+                    remainder = subtract(&remainder, divisor);
+                    while remainder.last() == Some(&0) {
+                        remainder.pop();
+                    }
+                    quotient[bit / 32] |= 1 << (bit % 32);
+                }
+            }
+            Some((quotient, remainder))
+        }
+    }
+}
+
+// `n = n * 2 + bit`, in place.
+fn shift_left_one(n: &mut Limbs, bit: u32) {
+    let mut carry = bit;
+    for limb in n.iter_mut() {
+        let next = *limb >> 31;
+        *limb = (*limb << 1) | carry;
+        carry = next;
+    }
+    if carry > 0 {
+        n.push(carry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text).unwrap_or_else(|| panic!("{text} should parse"))
+    }
+
+    #[test]
+    fn reads_and_writes_decimal() {
+        for text in [
+            "0",
+            "7",
+            "-42",
+            "4294967296",
+            "123456789012345678901234567890",
+        ] {
+            assert_eq!(big(text).to_string(), text);
+        }
+        assert_eq!(big("-0").to_string(), "0");
+        assert_eq!(big("+0001000000000").to_string(), "1000000000");
+        assert_eq!(BigInt::from(i64::MIN).to_string(), "-9223372036854775808");
+        assert_eq!(big("-9223372036854775808").to_i64(), Some(i64::MIN));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert!(BigInt::parse("").is_none() && BigInt::parse("1e3").is_none());
+    }
+
+    #[test]
+    fn does_arithmetic_past_i64() {
+        let a = big("18446744073709551616"); // 2^64
+        let b = big("-340282366920938463463374607431768211455"); // 1 - 2^128
+        assert_eq!(
+            (&a * &a).to_string(),
+            "340282366920938463463374607431768211456"
+        );
+        assert_eq!((&(&a * &a) + &b).to_string(), "1");
+        assert_eq!(
+            (&b - &a).to_string(),
+            "-340282366920938463481821351505477763071"
+        );
+        assert_eq!(
+            b.checked_div(&a).map(|q| q.to_string()),
+            Some("-18446744073709551615".to_string())
+        );
+        assert_eq!(
+            b.checked_rem(&a).map(|r| r.to_string()),
+            Some("-18446744073709551615".to_string())
+        );
+        assert_eq!(big("-7").checked_div(&big("2")), Some(big("-3")));
+        assert_eq!(big("-7").checked_rem(&big("2")), Some(big("-1")));
+        assert!(a.checked_div(&BigInt::default()).is_none());
+        assert!(b < a && big("-2") < big("-1") && a > big("4294967296"));
+    }
+}
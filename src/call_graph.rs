@@ -0,0 +1,232 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use crate::analysis::{self, SemanticModel, SymbolKind};
+use crate::expr::Expr;
+use crate::stmt::Stmt;
+use crate::walk::{walk_expr, walk_function, walk_stmt, walk_stmts, Walk};
+
+// The node for the top-level code of the script, which is where the calls
+// that start everything else come from.
+const SCRIPT: usize = 0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GraphFormat {
+    // Graphviz's dot language, for `dot -Tsvg` and the like.
+    #[default]
+    Dot,
+    // A `caller -> callee` line for each call.
+    Text,
+}
+
+impl GraphFormat {
+    pub fn named(name: &str) -> Option<GraphFormat> {
+        match name {
+            "dot" => Some(GraphFormat::Dot),
+            "text" => Some(GraphFormat::Text),
+            _ => None,
+        }
+    }
+}
+
+// Which functions call which. A node is the script itself, a function
+// declared anywhere, or a method, and an edge is a call the source makes by
+// naming a function directly. Calls through a variable holding a function,
+// and calls to methods, depend on what happens at runtime, so they aren't
+// drawn.
+#[derive(Debug)]
+pub struct CallGraph {
+    names: Vec<String>,
+    calls: BTreeSet<(usize, usize)>,
+}
+
+impl CallGraph {
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    // Each call as (caller, callee), indexes into names().
+    pub fn calls(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.calls.iter().copied()
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Text => self.to_text(),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph calls {\n");
+        for (node, name) in self.names.iter().enumerate() {
+            let _ = writeln!(dot, "  n{node} [label=\"{name}\"];");
+        }
+        for (caller, callee) in self.calls() {
+            let _ = writeln!(dot, "  n{caller} -> n{callee};");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_text(&self) -> String {
+        self.calls()
+            .map(|(caller, callee)| {
+                format!("{} -> {}\n", self.names[caller], self.names[callee])
+            })
+            .collect()
+    }
+}
+
+// The call graph of `source`, or None if it doesn't parse, once the errors
+// are reported.
+pub fn call_graph(name: &str, source: &str) -> Option<CallGraph> {
+    let (statements, model) = analysis::analyze_program(name, source)?;
+
+    let mut calls = Calls {
+        model: &model,
+        graph: CallGraph {
+            names: vec!["<script>".to_string()],
+            calls: BTreeSet::new(),
+        },
+        functions: HashMap::new(),
+        current: SCRIPT,
+    };
+    // Every function gets its node up front, so a call to one declared
+    // further down has somewhere to go.
+    for (index, declaration) in model.declarations().iter().enumerate() {
+        if declaration.kind == SymbolKind::Function {
+            let node = calls.add_node(declaration.name.lexeme.to_string());
+            calls.functions.insert(index, node);
+        }
+    }
+    walk_stmts(&mut calls, &statements);
+    Some(calls.graph)
+}
+
+struct Calls<'a> {
+    model: &'a SemanticModel,
+    graph: CallGraph,
+    // Function declarations, by index in the model, to their nodes.
+    functions: HashMap<usize, usize>,
+    // The node the code being walked belongs to.
+    current: usize,
+}
+
+impl Calls<'_> {
+    fn add_node(&mut self, name: String) -> usize {
+        self.graph.names.push(name);
+        self.graph.names.len() - 1
+    }
+
+    fn inside<F: FnOnce(&mut Self)>(&mut self, node: usize, walk: F) {
+        let enclosing = std::mem::replace(&mut self.current, node);
+        walk(self);
+        self.current = enclosing;
+    }
+}
+
+impl Walk for Calls<'_> {
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Function(fs) => {
+                let name = &fs.name;
+                let node = self
+                    .model
+                    .at(name.line, name.column)
+                    .and_then(|o| o.declaration)
+                    .and_then(|index| self.functions.get(&index).copied());
+                match node {
+                    Some(node) => self.inside(node, |c| walk_function(c, fs)),
+                    None => walk_function(self, fs),
+                }
+            }
+            Stmt::Class(cs) => {
+                for method in &cs.methods {
+                    let name =
+                        format!("{}.{}", cs.name.lexeme, method.name.lexeme);
+                    let node = self.add_node(name);
+                    self.inside(node, |c| walk_function(c, method));
+                }
+            }
+            stmt => walk_stmt(self, stmt),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        if let Expr::Call(ce) = expr {
+            if let Expr::Variable(ve) = &*ce.callee {
+                let callee = self
+                    .model
+                    .at(ve.name.line, ve.name.column)
+                    .and_then(|o| o.declaration)
+                    .and_then(|index| self.functions.get(&index));
+                if let Some(&callee) = callee {
+                    self.graph.calls.insert((self.current, callee));
+                }
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(source: &str) -> String {
+        call_graph("test.lox", source)
+            .map(|graph| graph.to_text())
+            .unwrap_or_else(|| panic!("{source:?} should parse"))
+    }
+
+    #[test]
+    fn draws_the_calls_made_by_name() {
+        let source = "
+            fun fib(n) {
+              if (n < 2) return n;
+              return fib(n - 1) + fib(n - 2);
+            }
+            fun main() {
+              fun show(x) { print x; }
+              show(fib(10));
+              var f = show;
+              f(1);
+            }
+            class Runner { run() { main(); clock(); } }
+            main();
+            Runner().run();";
+        assert_eq!(
+            text(source),
+            "<script> -> main
+fib -> fib
+main -> fib
+main -> show
+Runner.run -> main
+"
+        );
+    }
+
+    #[test]
+    fn tells_shadowing_locals_from_functions() {
+        let source = "
+            fun helper() {}
+            fun user(helper) { helper(); }";
+        assert_eq!(text(source), "");
+
+        let dot = call_graph("test.lox", "fun a() { b(); } fun b() {}")
+            .map(|graph| graph.to_dot());
+        assert_eq!(
+            dot.as_deref(),
+            Some(
+                "digraph calls {
+  n0 [label=\"<script>\"];
+  n1 [label=\"a\"];
+  n2 [label=\"b\"];
+  n1 -> n2;
+}
+"
+            )
+        );
+    }
+}
@@ -1,14 +1,47 @@
 use std::fmt::{Debug, Display};
 
 use crate::error_reporter::LoxError;
+use crate::expr::CallExpr;
 use crate::interpreter::Interpreter;
 use crate::literal::Literal;
 
 pub trait LoxCallable: Display + Debug {
     fn arity(&self) -> usize;
+    // Natives with optional arguments take anywhere from arity() up to
+    // max_arity() of them.
+    fn max_arity(&self) -> usize {
+        self.arity()
+    }
     fn call(
         &self,
         interpreter: &mut Interpreter,
         arguments: Vec<Literal>,
     ) -> Result<Literal, LoxError>;
+    // Calls written in Lox come through here with the call expression, for
+    // natives that report on how they were called, like assert(). Calls
+    // made by natives have no expression and go straight to call().
+    fn call_at(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+        _call: &CallExpr,
+    ) -> Result<Literal, LoxError> {
+        self.call(interpreter, arguments)
+    }
+}
+
+// The "Expected 2 arguments" part of the error for calling `function` with
+// `count` arguments, if that is the wrong number.
+pub fn arity_mismatch(
+    function: &dyn LoxCallable,
+    count: usize,
+) -> Option<String> {
+    let (min, max) = (function.arity(), function.max_arity());
+    if (min..=max).contains(&count) {
+        None
+    } else if min == max {
+        Some(format!("Expected {min} arguments but got {count}"))
+    } else {
+        Some(format!("Expected {min} to {max} arguments but got {count}"))
+    }
 }
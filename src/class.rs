@@ -0,0 +1,99 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::{Rc, Weak};
+
+use crate::callable::LoxCallable;
+use crate::error_reporter::LoxError;
+use crate::function::LoxFunction;
+use crate::instance::LoxInstance;
+use crate::interpreter::Interpreter;
+use crate::literal::Literal;
+
+#[derive(Debug)]
+pub struct LoxClass {
+    name: String,
+    methods: HashMap<Rc<str>, Rc<LoxFunction>>,
+    frozen: Cell<bool>,
+    // Instances point back at their class, and `call` only gets `&self`.
+    this: Weak<LoxClass>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: String,
+        methods: HashMap<Rc<str>, Rc<LoxFunction>>,
+    ) -> Rc<LoxClass> {
+        Rc::new_cyclic(|this| LoxClass {
+            name,
+            methods,
+            frozen: Cell::new(false),
+            this: this.clone(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        self.methods.get(name).cloned()
+    }
+
+    // Instances of a frozen class can't replace its methods with fields.
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.get()
+    }
+
+    // Sorted, so listing them doesn't depend on hashing order.
+    pub fn method_names(&self) -> Vec<Rc<str>> {
+        let mut names: Vec<Rc<str>> = self.methods.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+impl LoxCallable for LoxClass {
+    // Calling a class takes whatever its initializer takes.
+    fn arity(&self) -> usize {
+        match self.find_method("init") {
+            Some(initializer) => initializer.arity(),
+            None => 0,
+        }
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let class = match self.this.upgrade() {
+            Some(class) => class,
+            None => {
+                return Err(LoxError::system_error(format!(
+                    "Class '{}' is no longer alive.",
+                    self.name
+                )))
+            }
+        };
+        let instance = Rc::new(LoxInstance::new(class));
+
+        if let Some(initializer) = self.find_method("init") {
+            initializer
+                .bind(instance.clone())
+                .call(interpreter, arguments)?;
+        }
+
+        Ok(Literal::Instance(instance))
+    }
+}
+
+impl Display for LoxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
@@ -0,0 +1,21 @@
+use std::rc::Rc;
+
+use crate::error_reporter::LoxError;
+use crate::literal::Literal;
+
+// Why a statement stopped before finishing normally. Return, break and
+// continue unwind only to the function or loop that handles them; an error
+// unwinds all the way out. Keeping them apart from LoxError means a `?` on
+// a diagnostic can't swallow a return, and a return can't be reported.
+pub enum ControlFlow {
+    Return(Literal),
+    Break(Option<Rc<str>>),
+    Continue(Option<Rc<str>>),
+    Error(LoxError),
+}
+
+impl From<LoxError> for ControlFlow {
+    fn from(error: LoxError) -> Self {
+        ControlFlow::Error(error)
+    }
+}
@@ -0,0 +1,277 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::expr::Expr;
+use crate::interpreter::Interpreter;
+use crate::literal::Literal;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+use crate::token::Token;
+
+// Where the debugger stops the program: before each statement that starts
+// on a line, every time it runs. With a condition, only when the condition
+// is true in the scope stopped in.
+pub struct Breakpoint {
+    // The file the line is in, or None for a line in any of them.
+    file: Option<String>,
+    line: u32,
+    condition: Option<Expr>,
+}
+
+impl Breakpoint {
+    pub fn at(line: u32) -> Breakpoint {
+        Breakpoint {
+            file: None,
+            line,
+            condition: None,
+        }
+    }
+
+    pub fn in_file(file: &str, line: u32) -> Breakpoint {
+        Breakpoint {
+            file: Some(file.to_string()),
+            ..Breakpoint::at(line)
+        }
+    }
+
+    // Only stops when `condition`, a Lox expression, is true. A condition
+    // that fails to evaluate stops too, with its error reported.
+    pub fn when(self, condition: &str) -> Result<Breakpoint, String> {
+        Ok(Breakpoint {
+            condition: Some(parse_expression(condition).ok_or_else(|| {
+                format!("Invalid breakpoint condition '{condition}'.")
+            })?),
+            ..self
+        })
+    }
+
+    // Reads a breakpoint as given to --break: `[file:]line`, optionally
+    // followed by ` if condition`.
+    pub fn parse(spec: &str) -> Result<Breakpoint, String> {
+        let (location, condition) = match spec.split_once(" if ") {
+            Some((location, condition)) => (location, Some(condition)),
+            None => (spec, None),
+        };
+        let invalid = || format!("Invalid breakpoint '{spec}'.");
+        let breakpoint = match location.trim().rsplit_once(':') {
+            Some((file, line)) => {
+                Breakpoint::in_file(file, line.parse().map_err(|_| invalid())?)
+            }
+            None => {
+                Breakpoint::at(location.trim().parse().map_err(|_| invalid())?)
+            }
+        };
+        match condition {
+            Some(condition) => breakpoint.when(condition),
+            None => Ok(breakpoint),
+        }
+    }
+
+    fn matches(&self, token: &Token) -> bool {
+        token.line == self.line
+            && self.file.as_ref().is_none_or(|file| {
+                Path::new(&*token.source).ends_with(Path::new(file))
+            })
+    }
+
+    pub fn condition(&self) -> Option<&Expr> {
+        self.condition.as_ref()
+    }
+}
+
+pub enum PauseReason {
+    Breakpoint,
+    // A watched variable was defined, or assigned a value other than the
+    // one it had. `old` is None when it was defined.
+    Watchpoint { old: Option<Literal>, new: Literal },
+}
+
+// Where the program stopped and why. `environment` is the scope it stopped
+// in, for Interpreter::evaluate_in.
+pub struct Pause {
+    pub reason: PauseReason,
+    pub token: Token,
+    pub environment: Rc<RefCell<Environment>>,
+}
+
+impl Display for Pause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Paused at {}", self.token.location())?;
+        match &self.reason {
+            PauseReason::Breakpoint => write!(f, "."),
+            PauseReason::Watchpoint { old: None, new } => {
+                write!(f, ": {} defined as {new}.", self.token.lexeme)
+            }
+            PauseReason::Watchpoint {
+                old: Some(old),
+                new,
+            } => write!(
+                f,
+                ": {} changed from {old} to {new}.",
+                self.token.lexeme
+            ),
+        }
+    }
+}
+
+// What happens while the program is paused. The program carries on once
+// `paused` returns.
+pub trait PauseHandler {
+    fn paused(&mut self, interpreter: &mut Interpreter, pause: &Pause);
+}
+
+// Breakpoints and watchpoints for the interpreter to check as it runs, and
+// the handler it calls when one of them is hit.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    // The watched variables, with the value each last had.
+    watched: HashMap<Rc<str>, Option<Literal>>,
+    handler: Box<dyn PauseHandler>,
+}
+
+impl Debugger {
+    pub fn new(handler: Box<dyn PauseHandler>) -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watched: HashMap::new(),
+            handler,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    // Pauses whenever a variable called `name` changes, in any scope.
+    pub fn watch(&mut self, name: &str) {
+        self.watched.insert(name.into(), None);
+    }
+
+    pub fn watches(&self, name: &str) -> bool {
+        self.watched.contains_key(name)
+    }
+
+    // The breakpoint for the line `token` is on, if there is one.
+    pub fn breakpoint_at(&self, token: &Token) -> Option<&Breakpoint> {
+        self.breakpoints.iter().find(|b| b.matches(token))
+    }
+
+    // The value a watched variable had before, when `value` is a change.
+    pub fn changed(
+        &mut self,
+        name: &str,
+        value: &Literal,
+    ) -> Option<Option<Literal>> {
+        let last = self.watched.get_mut(name)?;
+        if last.as_ref() == Some(value) {
+            return None;
+        }
+        Some(last.replace(value.clone()))
+    }
+
+    pub fn pause(&mut self, interpreter: &mut Interpreter, pause: &Pause) {
+        self.handler.paused(interpreter, pause);
+    }
+}
+
+// The token a statement is stopped at: the first one in it the tree keeps.
+// Literals don't keep theirs, so a statement like `print 1;` has none and
+// never stops the program.
+pub fn statement_token(stmt: &Stmt) -> Option<&Token> {
+    match stmt {
+        Stmt::Block(_) => None,
+        Stmt::Break(bs) => Some(&bs.keyword),
+        Stmt::Class(cs) => Some(&cs.name),
+        Stmt::Continue(cs) => Some(&cs.keyword),
+        Stmt::Expression(es) => expression_token(&es.expression),
+        Stmt::For(fs) => Some(&fs.keyword),
+        Stmt::Function(fs) => Some(&fs.name),
+        Stmt::If(is) => Some(&is.keyword),
+        Stmt::Print(ps) => expression_token(&ps.expression),
+        Stmt::Return(rs) => Some(&rs.keyword),
+        Stmt::Var(vs) => Some(&vs.name),
+        Stmt::While(ws) => Some(&ws.keyword),
+    }
+}
+
+fn expression_token(expr: &Expr) -> Option<&Token> {
+    match expr {
+        Expr::Assign(ae) => Some(&ae.name),
+        Expr::Binary(be) => expression_token(&be.left).or(Some(&be.operator)),
+        Expr::Call(ce) => expression_token(&ce.callee).or(Some(&ce.paren)),
+        Expr::Get(ge) => expression_token(&ge.object).or(Some(&ge.name)),
+        Expr::Grouping(ge) => expression_token(&ge.expression),
+        Expr::Literal(_) => None,
+        Expr::Logical(le) => expression_token(&le.left).or(Some(&le.operator)),
+        Expr::Match(me) => Some(&me.keyword),
+        Expr::Set(se) => expression_token(&se.object).or(Some(&se.name)),
+        Expr::This(te) => Some(&te.keyword),
+        Expr::Unary(ue) => Some(&ue.operator),
+        Expr::Variable(ve) => Some(&ve.name),
+    }
+}
+
+fn parse_expression(source: &str) -> Option<Expr> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().clone();
+    let expr = Parser::new(&tokens).parse_expression().ok()?;
+    (!scanner.had_error()).then_some(expr)
+}
+
+// Pauses at the terminal: says where the program stopped, then evaluates
+// each expression typed in the scope it stopped in, until an empty line
+// lets it carry on.
+pub struct ConsoleDebugger;
+
+impl PauseHandler for ConsoleDebugger {
+    fn paused(&mut self, interpreter: &mut Interpreter, pause: &Pause) {
+        println!("{pause}");
+        loop {
+            print!("debug> ");
+            let _ = io::stdout().flush();
+            let mut input = String::new();
+            if io::stdin().read_line(&mut input).is_err() {
+                return;
+            }
+            if input.trim().is_empty() {
+                return;
+            }
+            let Some(expr) = parse_expression(&input) else {
+                continue;
+            };
+            let value = interpreter
+                .evaluate_in(pause.environment.clone(), &expr)
+                .and_then(|value| interpreter.stringify(&value));
+            if let Ok(value) = value {
+                println!("=> {value}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_breakpoints() {
+        let breakpoint = Breakpoint::parse("lib/list.lox:12 if n > 3");
+        let breakpoint = breakpoint.unwrap_or_else(|e| panic!("{e}"));
+        assert_eq!(breakpoint.file.as_deref(), Some("lib/list.lox"));
+        assert_eq!(breakpoint.line, 12);
+        assert!(breakpoint.condition.is_some());
+
+        let breakpoint =
+            Breakpoint::parse("7").unwrap_or_else(|e| panic!("{e}"));
+        assert_eq!((breakpoint.file, breakpoint.line), (None, 7));
+
+        assert!(Breakpoint::parse("main.lox:x").is_err());
+        assert!(Breakpoint::parse("3 if (").is_err());
+    }
+}
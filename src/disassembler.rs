@@ -0,0 +1,426 @@
+use crate::error_reporter::LoxError;
+use crate::loxc::{op, Constant, Reader};
+use crate::parser::MAX_NESTING;
+use crate::token::Token;
+
+// Prints a .loxc file one record per line in the style of clox's debug
+// output: byte offset, source line ("|" when unchanged), opcode and operands.
+// Nested records are indented under the statement or expression owning them.
+pub struct Disassembler<'a> {
+    reader: Reader<'a>,
+    output: String,
+    depth: usize,
+    last_line: Option<u32>,
+}
+
+impl Disassembler<'_> {
+    pub fn new(bytes: &[u8]) -> Result<Disassembler<'_>, LoxError> {
+        Ok(Disassembler {
+            reader: Reader::new(bytes)?,
+            output: String::new(),
+            depth: 0,
+            last_line: None,
+        })
+    }
+
+    pub fn disassemble(mut self) -> Result<String, LoxError> {
+        self.output.push_str("== constants ==\n");
+        for (i, constant) in self.reader.constants().iter().enumerate() {
+            let value = match constant {
+                Constant::Number(n) => format!("{n}"),
+                Constant::Integer(n) => format!("{n}"),
+                Constant::String(s) => format!("'{s}'"),
+            };
+            self.output.push_str(&format!("{i:04} {value}\n"));
+        }
+
+        let name = self.reader.source_name().to_owned();
+        self.output.push_str(&format!("== {name} ==\n"));
+        let count = self.reader.u32()?;
+        for _ in 0..count {
+            self.stmt()?;
+        }
+
+        if !self.reader.is_at_end() {
+            return Err(self
+                .reader
+                .error("trailing bytes after the last statement"));
+        }
+
+        Ok(self.output)
+    }
+
+    fn emit(&mut self, offset: usize, line: Option<u32>, text: &str) {
+        let line = match line {
+            Some(l) if self.last_line != Some(l) => {
+                self.last_line = Some(l);
+                format!("{l:4}")
+            }
+            _ => "   |".to_string(),
+        };
+        let indent = "  ".repeat(self.depth);
+        self.output
+            .push_str(&format!("{offset:04} {line} {indent}{text}\n"));
+    }
+
+    fn nested<F>(&mut self, f: F) -> Result<(), LoxError>
+    where
+        F: FnOnce(&mut Self) -> Result<(), LoxError>,
+    {
+        if self.depth == MAX_NESTING {
+            return Err(self.reader.error("nesting is too deep"));
+        }
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn option_token(&mut self) -> Result<Option<Token>, LoxError> {
+        match self.reader.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.reader.token()?)),
+        }
+    }
+
+    fn option_expr(&mut self) -> Result<(), LoxError> {
+        match self.reader.u8()? {
+            0 => Ok(()),
+            _ => self.nested(|d| d.expr()),
+        }
+    }
+
+    fn stmts(&mut self) -> Result<(), LoxError> {
+        let count = self.reader.u32()?;
+        self.nested(|d| {
+            for _ in 0..count {
+                d.stmt()?;
+            }
+            Ok(())
+        })
+    }
+
+    // A function declaration or method after its opcode, if it has one.
+    fn function(&mut self, offset: usize, kind: &str) -> Result<(), LoxError> {
+        let name = self.reader.token()?;
+        let count = self.reader.u32()?;
+        let mut params = Vec::new();
+        for _ in 0..count {
+            let param = self.reader.token()?;
+            params.push(match self.option_token()? {
+                Some(t) => format!("{}: {}", param.lexeme, t.lexeme),
+                None => param.lexeme.to_string(),
+            });
+        }
+        let mut text =
+            format!("{kind:<16} '{}' ({})", name.lexeme, params.join(", "));
+        if let Some(return_type) = self.option_token()? {
+            text = format!("{text} -> {}", return_type.lexeme);
+        }
+        self.emit(offset, Some(name.line), &text);
+        self.stmts()
+    }
+
+    fn stmt(&mut self) -> Result<(), LoxError> {
+        let offset = self.reader.offset();
+
+        match self.reader.u8()? {
+            op::BLOCK => {
+                self.emit(offset, None, "BLOCK");
+                self.stmts()?;
+            }
+            code @ (op::BREAK | op::CONTINUE) => {
+                let keyword = self.reader.token()?;
+                let name = if code == op::BREAK {
+                    "BREAK"
+                } else {
+                    "CONTINUE"
+                };
+                let text = match self.option_token()? {
+                    Some(label) => format!("{name:<16} '{}'", label.lexeme),
+                    None => name.to_string(),
+                };
+                self.emit(offset, Some(keyword.line), &text);
+            }
+            op::EXPRESSION => {
+                self.emit(offset, None, "EXPRESSION");
+                self.nested(|d| d.expr())?;
+            }
+            op::FUNCTION => self.function(offset, "FUNCTION")?,
+            op::CLASS => {
+                let name = self.reader.token()?;
+                let text = format!("{:<16} '{}'", "CLASS", name.lexeme);
+                self.emit(offset, Some(name.line), &text);
+                let count = self.reader.u32()?;
+                self.nested(|d| {
+                    for _ in 0..count {
+                        let offset = d.reader.offset();
+                        d.function(offset, "METHOD")?;
+                    }
+                    Ok(())
+                })?;
+            }
+            op::IF => {
+                let keyword = self.reader.token()?;
+                self.emit(offset, Some(keyword.line), "IF");
+                self.nested(|d| {
+                    d.expr()?;
+                    d.stmt()?;
+                    match d.reader.u8()? {
+                        0 => Ok(()),
+                        _ => d.stmt(),
+                    }
+                })?;
+            }
+            op::PRINT => {
+                self.emit(offset, None, "PRINT");
+                self.nested(|d| d.expr())?;
+            }
+            op::RETURN => {
+                let keyword = self.reader.token()?;
+                self.emit(offset, Some(keyword.line), "RETURN");
+                self.option_expr()?;
+            }
+            op::VAR => {
+                let name = self.reader.token()?;
+                let mut text = format!("{:<16} '{}'", "VAR", name.lexeme);
+                if let Some(annotation) = self.option_token()? {
+                    text = format!("{text}: {}", annotation.lexeme);
+                }
+                self.emit(offset, Some(name.line), &text);
+                self.option_expr()?;
+            }
+            op::WHILE => {
+                let keyword = self.reader.token()?;
+                let text = match self.option_token()? {
+                    Some(label) => {
+                        format!("{:<16} '{}'", "WHILE", label.lexeme)
+                    }
+                    None => "WHILE".to_string(),
+                };
+                self.emit(offset, Some(keyword.line), &text);
+                self.nested(|d| {
+                    d.expr()?;
+                    d.stmt()
+                })?;
+            }
+            op::FOR => {
+                let keyword = self.reader.token()?;
+                let text = match self.option_token()? {
+                    Some(label) => format!("{:<16} '{}'", "FOR", label.lexeme),
+                    None => "FOR".to_string(),
+                };
+                self.emit(offset, Some(keyword.line), &text);
+                // Initializer, condition, increment and body in that order;
+                // clauses left out of the loop are absent from the listing.
+                self.nested(|d| {
+                    if d.reader.u8()? != 0 {
+                        d.stmt()?;
+                    }
+                    for _ in 0..2 {
+                        if d.reader.u8()? != 0 {
+                            d.expr()?;
+                        }
+                    }
+                    d.stmt()
+                })?;
+            }
+            code => {
+                return Err(self
+                    .reader
+                    .error(&format!("unknown statement opcode {code:#04x}")))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn expr(&mut self) -> Result<(), LoxError> {
+        let offset = self.reader.offset();
+
+        match self.reader.u8()? {
+            op::ASSIGN => {
+                let name = self.reader.token()?;
+                let text = format!("{:<16} '{}'", "ASSIGN", name.lexeme);
+                self.emit(offset, Some(name.line), &text);
+                self.nested(|d| d.expr())?;
+            }
+            code @ (op::BINARY | op::LOGICAL) => {
+                let operator = self.reader.token()?;
+                let name = if code == op::BINARY {
+                    "BINARY"
+                } else {
+                    "LOGICAL"
+                };
+                let text = format!("{name:<16} '{}'", operator.lexeme);
+                self.emit(offset, Some(operator.line), &text);
+                self.nested(|d| {
+                    d.expr()?;
+                    d.expr()
+                })?;
+            }
+            op::CALL => {
+                let paren = self.reader.token()?;
+                self.emit(offset, Some(paren.line), "CALL");
+                self.nested(|d| {
+                    d.expr()?;
+                    let count = d.reader.u32()?;
+                    for _ in 0..count {
+                        d.expr()?;
+                    }
+                    Ok(())
+                })?;
+            }
+            op::GROUPING => {
+                self.emit(offset, None, "GROUPING");
+                self.nested(|d| d.expr())?;
+            }
+            op::LITERAL => {
+                let text = self.literal()?;
+                self.emit(offset, None, &format!("{:<16} {text}", "LITERAL"));
+            }
+            op::UNARY => {
+                let operator = self.reader.token()?;
+                let text = format!("{:<16} '{}'", "UNARY", operator.lexeme);
+                self.emit(offset, Some(operator.line), &text);
+                self.nested(|d| d.expr())?;
+            }
+            op::VARIABLE => {
+                let name = self.reader.token()?;
+                let text = format!("{:<16} '{}'", "VARIABLE", name.lexeme);
+                self.emit(offset, Some(name.line), &text);
+            }
+            code @ (op::GET | op::OPTIONAL_GET | op::SET) => {
+                let name = self.reader.token()?;
+                let (op_name, operands) = match code {
+                    op::GET => ("GET", 1),
+                    op::OPTIONAL_GET => ("OPTIONAL_GET", 1),
+                    _ => ("SET", 2),
+                };
+                let text = format!("{op_name:<16} '{}'", name.lexeme);
+                self.emit(offset, Some(name.line), &text);
+                self.nested(|d| {
+                    for _ in 0..operands {
+                        d.expr()?;
+                    }
+                    Ok(())
+                })?;
+            }
+            op::THIS => {
+                let keyword = self.reader.token()?;
+                self.emit(offset, Some(keyword.line), "THIS");
+            }
+            op::MATCH => {
+                let keyword = self.reader.token()?;
+                self.emit(offset, Some(keyword.line), "MATCH");
+                self.nested(|d| {
+                    d.expr()?;
+                    let count = d.reader.u32()?;
+                    for _ in 0..count {
+                        d.case()?;
+                    }
+                    Ok(())
+                })?;
+            }
+            code => {
+                return Err(self
+                    .reader
+                    .error(&format!("unknown expression opcode {code:#04x}")))
+            }
+        }
+
+        Ok(())
+    }
+
+    // One case of a match expression: its pattern, guard and value, with
+    // whichever of the first two it lacks absent from the listing.
+    fn case(&mut self) -> Result<(), LoxError> {
+        let offset = self.reader.offset();
+        let text = match self.option_token()? {
+            Some(binding) => format!("{:<16} '{}'", "CASE", binding.lexeme),
+            None => "CASE".to_string(),
+        };
+        self.emit(offset, None, &text);
+        self.nested(|d| {
+            for _ in 0..2 {
+                if d.reader.u8()? != 0 {
+                    d.expr()?;
+                }
+            }
+            d.expr()
+        })
+    }
+
+    // Shows constant pool references as `index 'value'` like OP_CONSTANT.
+    fn literal(&mut self) -> Result<String, LoxError> {
+        let text = match self.reader.u8()? {
+            0 | 1 => "nil".to_string(),
+            2 => "false".to_string(),
+            3 => "true".to_string(),
+            4 => {
+                let index = self.reader.u32()?;
+                match self.reader.constant(index)? {
+                    Constant::Number(n) => format!("{index:4} '{n}'"),
+                    Constant::Integer(n) => format!("{index:4} '{n}'"),
+                    Constant::String(s) => format!("{index:4} '\"{s}\"'"),
+                }
+            }
+            // Bigints are kept as their digits in a string constant.
+            5 => {
+                let index = self.reader.u32()?;
+                match self.reader.constant(index)? {
+                    Constant::String(digits) => {
+                        format!("{index:4} '{digits}n'")
+                    }
+                    _ => {
+                        return Err(self
+                            .reader
+                            .error("expected a string constant"))
+                    }
+                }
+            }
+            tag => {
+                return Err(self
+                    .reader
+                    .error(&format!("unknown literal tag {tag}")))
+            }
+        };
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loxc;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn lists_every_kind_of_literal() {
+        let source = "var a; print nil; print false; print true; print 1.5; \
+                      print 2; print \"text\"; print 12n; print -34n;";
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let statements = Parser::new(&tokens).parse().unwrap_or_default();
+        let compiled = loxc::compile("literals.lox", &statements);
+        assert_eq!(loxc::load(&compiled).map(|s| s.len()).ok(), Some(9));
+
+        let listing = Disassembler::new(&compiled)
+            .and_then(Disassembler::disassemble)
+            .unwrap_or_else(|_| panic!("the literals should disassemble"));
+        for shown in [
+            "nil",
+            "false",
+            "true",
+            "'1.5'",
+            "'2'",
+            "'\"text\"'",
+            "'12n'",
+            "'34n'",
+        ] {
+            assert!(listing.contains(shown), "{shown} in\n{listing}");
+        }
+    }
+}
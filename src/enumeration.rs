@@ -0,0 +1,66 @@
+use std::any::Any;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+use crate::foreign::Foreign;
+use crate::literal::Literal;
+use crate::namespace::LoxNamespace;
+
+// One of the constants an `enum` declaration defines. Each is only equal to
+// itself, like any foreign value, so they can be compared with `==`, used as
+// map keys and matched on, and print as `Color.Red`.
+#[derive(Debug)]
+pub struct EnumVariant {
+    enumeration: Rc<str>,
+    name: Rc<str>,
+}
+
+impl Foreign for EnumVariant {
+    fn type_name(&self) -> &'static str {
+        "enum value"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Display for EnumVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.enumeration, self.name)
+    }
+}
+
+// An enum is a namespace with a new variant for each name, in order. Scripts
+// can't set namespace members, so its variants stay what they were.
+pub fn new_enum(name: &str, variants: &[Rc<str>]) -> LoxNamespace {
+    let enumeration = LoxNamespace::new(name);
+    for variant in variants {
+        let value = EnumVariant {
+            enumeration: name.into(),
+            name: variant.clone(),
+        };
+        enumeration.define(variant, Literal::Foreign(Rc::new(value)));
+    }
+    enumeration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variants_are_only_equal_to_themselves() {
+        let names: Vec<Rc<str>> = vec!["Red".into(), "Green".into()];
+        let colors = new_enum("Color", &names);
+        let red = colors.member("Red");
+        assert_eq!(
+            red.as_ref().map(Literal::to_string).as_deref(),
+            Some("Color.Red")
+        );
+        assert!(red == colors.member("Red"));
+        assert!(red != colors.member("Green"));
+        assert!(red != new_enum("Color", &names).member("Red"));
+        assert_eq!(colors.member_names(), names);
+    }
+}
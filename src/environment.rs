@@ -1,70 +1,468 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use crate::error_reporter::LoxError;
 use crate::literal::Literal;
+use crate::stats;
+use crate::suggest;
+use crate::symbol::{Symbol, SymbolMap};
 use crate::token::Token;
 
+// Tells environments apart in a GlobalCache. A cleared environment takes a
+// new id, so slots cached before no longer match.
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+fn next_id() -> u32 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Where a variable expression's global lives, so evaluating it again skips
+// hashing the name. The resolver marks the references that can only mean a
+// global, and the first lookup of one fills in its slot.
+#[derive(Debug, Default)]
+pub struct GlobalCache {
+    global: Cell<bool>,
+    slot: Cell<Option<(u32, u32)>>,
+}
+
+impl GlobalCache {
+    pub fn is_global(&self) -> bool {
+        self.global.get()
+    }
+
+    pub fn set_global(&self, global: bool) {
+        self.global.set(global);
+    }
+}
+
 #[derive(Debug)]
 pub struct Environment {
+    id: u32,
     enclosing: Option<Rc<RefCell<Environment>>>,
-    values: HashMap<String, Literal>,
+    // Where each name's value is in `slots`. A name keeps its slot when it
+    // is defined again, and slots are only dropped all at once by clear().
+    values: SymbolMap<usize>,
+    slots: Vec<Literal>,
+    // Slots below this one were filled before freeze() and can't be assigned
+    // or defined again.
+    frozen: usize,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
     pub fn new() -> Environment {
+        stats::environment_created();
         Environment {
+            id: next_id(),
             enclosing: None,
-            values: HashMap::new(),
+            values: SymbolMap::default(),
+            slots: Vec::new(),
+            frozen: 0,
         }
     }
 
     pub fn new_with_enclosing(
         enclosing: Rc<RefCell<Environment>>,
     ) -> Environment {
+        stats::environment_created();
         Environment {
+            id: next_id(),
             enclosing: Some(enclosing),
-            values: HashMap::new(),
+            values: SymbolMap::default(),
+            slots: Vec::new(),
+            frozen: 0,
         }
     }
 
-    pub fn get(&self, name: Token) -> Result<Literal, LoxError> {
-        match self.values.get(&name.lexeme) {
-            Some(v) => Ok(v.clone()),
-            None => {
-                if let Some(e) = &self.enclosing {
-                    return e.borrow().get(name);
-                }
+    pub fn get(&self, name: &Token) -> Result<Literal, LoxError> {
+        self.find(name.symbol).ok_or_else(|| self.undefined(name))
+    }
 
-                let message = format!("Undefined variable '{}'.", name.lexeme);
-                let error = LoxError::runtime_error(name, message);
-                Err(error)
-            }
+    // Looks `name` up here and in the enclosing scopes, without reporting
+    // an error when it is missing.
+    pub fn find(&self, name: Symbol) -> Option<Literal> {
+        match self.values.get(&name) {
+            Some(&slot) => Some(self.slots[slot].clone()),
+            None => self.enclosing.as_ref()?.borrow().find(name),
         }
     }
 
     pub fn assign(
         &mut self,
-        name: Token,
+        name: &Token,
         value: Literal,
     ) -> Result<(), LoxError> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme, value);
-            return Ok(());
+        match self.assign_defined(name, value) {
+            Some(result) => result,
+            None => Err(self.undefined(name)),
+        }
+    }
+
+    // Assigns to `name` in the innermost scope defining it, or returns None
+    // if none does.
+    fn assign_defined(
+        &mut self,
+        name: &Token,
+        value: Literal,
+    ) -> Option<Result<(), LoxError>> {
+        if let Some(&slot) = self.values.get(&name.symbol) {
+            if slot < self.frozen {
+                let message =
+                    format!("Can't assign to builtin '{}'.", name.lexeme);
+                return Some(Err(LoxError::runtime_error(
+                    name.clone(),
+                    message,
+                )));
+            }
+            self.slots[slot] = value;
+            return Some(Ok(()));
+        }
+
+        self.enclosing
+            .as_ref()?
+            .borrow_mut()
+            .assign_defined(name, value)
+    }
+
+    // Every name defined here and in the enclosing scopes, innermost first.
+    pub fn visible_names(&self) -> Vec<Symbol> {
+        let mut names: Vec<Symbol> = self
+            .definitions()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        if let Some(e) = &self.enclosing {
+            names.extend(e.borrow().visible_names());
+        }
+        names
+    }
+
+    // The error for a name no scope defines, with the closest visible name
+    // suggested in case it is a typo.
+    fn undefined(&self, name: &Token) -> LoxError {
+        let names: Vec<Rc<str>> =
+            self.visible_names().into_iter().map(Symbol::name).collect();
+        let mut message = format!("Undefined variable '{}'.", name.lexeme);
+        if let Some(close) =
+            suggest::closest(&name.lexeme, names.iter().map(|n| &**n))
+        {
+            message.push_str(&format!(" Did you mean '{close}'?"));
+        }
+        LoxError::runtime_error(name.clone(), message)
+    }
+
+    // Like `get`, for a reference to a global in this environment, looked
+    // up through and remembered in `cache`.
+    pub fn get_cached(
+        &self,
+        name: &Token,
+        cache: &GlobalCache,
+    ) -> Result<Literal, LoxError> {
+        if let Some((id, slot)) = cache.slot.get() {
+            if id == self.id {
+                return Ok(self.slots[slot as usize].clone());
+            }
+        }
+
+        match self.values.get(&name.symbol) {
+            Some(&slot) => {
+                cache.slot.set(Some((self.id, slot as u32)));
+                Ok(self.slots[slot].clone())
+            }
+            None => self.get(name),
         }
+    }
 
-        if let Some(e) = &mut self.enclosing {
-            e.borrow_mut().assign(name.clone(), value)?;
-            return Ok(());
+    // Callers check is_frozen first, as defining a frozen name again would
+    // change a builtin under every cached reference to it.
+    pub fn define(&mut self, name: Symbol, value: Literal) {
+        match self.values.get(&name) {
+            Some(&slot) => {
+                debug_assert!(slot >= self.frozen, "redefined a frozen name");
+                self.slots[slot] = value;
+            }
+            None => {
+                self.values.insert(name, self.slots.len());
+                self.slots.push(value);
+            }
         }
+    }
+
+    // Looks `name` up in this environment only, without reporting an error
+    // when it is missing.
+    pub fn get_defined(&self, name: Symbol) -> Option<Literal> {
+        let slot = *self.values.get(&name)?;
+        Some(self.slots[slot].clone())
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.slots.clear();
+        self.frozen = 0;
+        self.id = next_id();
+    }
 
-        let message = format!("Undefined variable '{}'.", name.lexeme);
-        let error = LoxError::runtime_error(name, message);
-        Err(error)
+    // Makes every name defined so far read-only. Only assign_defined and
+    // define write to a slot, and both refuse one below `frozen`. A slot
+    // never moves either, as defining a name again reuses its slot and only
+    // clear() drops them, under a new id, so the index is all a GlobalCache
+    // needs to keep reading a frozen global.
+    pub fn freeze(&mut self) {
+        self.frozen = self.slots.len();
     }
 
-    pub fn define(&mut self, name: String, value: Literal) {
-        self.values.insert(name, value);
+    pub fn is_frozen(&self, name: Symbol) -> bool {
+        self.values
+            .get(&name)
+            .is_some_and(|&slot| slot < self.frozen)
+    }
+
+    // Empties the environment and points it at a new enclosing scope, keeping
+    // the table's allocation around for the next scope that uses it.
+    pub fn reuse(&mut self, enclosing: Option<Rc<RefCell<Environment>>>) {
+        self.values.clear();
+        self.slots.clear();
+        self.frozen = 0;
+        self.enclosing = enclosing;
+    }
+
+    // Every name defined here with its value, in the order the names were
+    // first defined.
+    pub fn definitions(&self) -> Vec<(Symbol, Literal)> {
+        let mut slots: Vec<(Symbol, usize)> = self
+            .values
+            .iter()
+            .map(|(&name, &slot)| (name, slot))
+            .collect();
+        slots.sort_by_key(|&(_, slot)| slot);
+        slots
+            .into_iter()
+            .map(|(name, slot)| (name, self.slots[slot].clone()))
+            .collect()
+    }
+
+    pub fn defined_count(&self) -> usize {
+        self.values.len()
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        stats::environment_dropped();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::REPL;
+    use crate::token_type::TokenType;
+
+    fn name(lexeme: &str) -> Token {
+        Token::new(TokenType::Identifier, lexeme.into(), None, 1, REPL.into())
+    }
+
+    fn scope(enclosing: &Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+        let scope = Environment::new_with_enclosing(enclosing.clone());
+        Rc::new(RefCell::new(scope))
+    }
+
+    fn get(environment: &Rc<RefCell<Environment>>, lexeme: &str) -> Literal {
+        match environment.borrow().get(&name(lexeme)) {
+            Ok(value) => value,
+            Err(_) => panic!("'{lexeme}' should be defined"),
+        }
+    }
+
+    #[test]
+    fn looks_names_up_through_enclosing_scopes() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("a"), Literal::Integer(1));
+        let outer = scope(&globals);
+        outer
+            .borrow_mut()
+            .define(Symbol::intern("b"), Literal::Integer(2));
+        let inner = scope(&outer);
+
+        assert_eq!(get(&inner, "a"), Literal::Integer(1));
+        assert_eq!(get(&inner, "b"), Literal::Integer(2));
+        assert!(inner.borrow().get(&name("missing")).is_err());
+        assert!(inner.borrow().get_defined(Symbol::intern("a")).is_none());
+        assert!(outer.borrow().get(&name("b")).is_ok());
+        assert!(globals.borrow().get(&name("b")).is_err());
+    }
+
+    #[test]
+    fn shadowing_leaves_the_outer_variable_alone() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("x"), Literal::Integer(1));
+        let inner = scope(&globals);
+        inner
+            .borrow_mut()
+            .define(Symbol::intern("x"), Literal::Integer(2));
+
+        assert!(inner
+            .borrow_mut()
+            .assign(&name("x"), Literal::Integer(3))
+            .is_ok());
+        assert_eq!(get(&inner, "x"), Literal::Integer(3));
+        assert_eq!(get(&globals, "x"), Literal::Integer(1));
+
+        // Defining a name again replaces it in place.
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("x"), Literal::Nil);
+        assert_eq!(get(&globals, "x"), Literal::Nil);
+        assert_eq!(globals.borrow().defined_count(), 1);
+    }
+
+    #[test]
+    fn assigns_to_the_scope_that_defines_the_name() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("g"), Literal::Integer(0));
+        let inner = scope(&scope(&globals));
+
+        assert!(inner
+            .borrow_mut()
+            .assign(&name("g"), Literal::Integer(7))
+            .is_ok());
+        assert_eq!(get(&globals, "g"), Literal::Integer(7));
+        assert_eq!(inner.borrow().defined_count(), 0);
+
+        assert!(inner
+            .borrow_mut()
+            .assign(&name("undefined"), Literal::Nil)
+            .is_err());
+        assert!(globals.borrow().get(&name("undefined")).is_err());
+    }
+
+    #[test]
+    fn reused_scopes_start_empty_under_their_new_enclosing_scope() {
+        let first = scope(&Rc::new(RefCell::new(Environment::new())));
+        first
+            .borrow_mut()
+            .define(Symbol::intern("a"), Literal::Integer(1));
+        let second = Rc::new(RefCell::new(Environment::new()));
+        second
+            .borrow_mut()
+            .define(Symbol::intern("b"), Literal::Integer(2));
+        let pooled = scope(&first);
+        pooled
+            .borrow_mut()
+            .define(Symbol::intern("x"), Literal::Integer(3));
+
+        pooled.borrow_mut().reuse(Some(second));
+        assert_eq!(pooled.borrow().defined_count(), 0);
+        assert!(pooled.borrow().get(&name("x")).is_err());
+        assert!(pooled.borrow().get(&name("a")).is_err());
+        assert_eq!(get(&pooled, "b"), Literal::Integer(2));
+    }
+
+    #[test]
+    fn cached_slots_go_stale_when_cleared() {
+        let mut globals = Environment::new();
+        globals.define(Symbol::intern("c"), Literal::Integer(1));
+        let cache = GlobalCache::default();
+
+        assert!(globals.get_cached(&name("c"), &cache).is_ok());
+        globals.define(Symbol::intern("c"), Literal::Integer(2));
+        let value = globals.get_cached(&name("c"), &cache);
+        assert_eq!(value.ok(), Some(Literal::Integer(2)));
+
+        globals.clear();
+        globals.define(Symbol::intern("other"), Literal::Nil);
+        globals.define(Symbol::intern("c"), Literal::Integer(3));
+        let value = globals.get_cached(&name("c"), &cache);
+        assert_eq!(value.ok(), Some(Literal::Integer(3)));
+    }
+
+    fn error_message(result: Result<Literal, LoxError>) -> String {
+        match result {
+            Err(LoxError::RuntimeError { message, .. }) => message,
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn suggests_a_close_name_from_any_visible_scope() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("counter"), Literal::Integer(0));
+        let inner = scope(&globals);
+        inner
+            .borrow_mut()
+            .define(Symbol::intern("total"), Literal::Integer(0));
+
+        assert_eq!(
+            error_message(inner.borrow().get(&name("countr"))),
+            "Undefined variable 'countr'. Did you mean 'counter'?"
+        );
+        assert_eq!(
+            error_message(inner.borrow().get(&name("totl"))),
+            "Undefined variable 'totl'. Did you mean 'total'?"
+        );
+        assert_eq!(
+            error_message(inner.borrow().get(&name("elsewhere"))),
+            "Undefined variable 'elsewhere'."
+        );
+        // The global scope can't see the inner one's names.
+        assert_eq!(
+            error_message(globals.borrow().get(&name("totl"))),
+            "Undefined variable 'totl'."
+        );
+
+        let assigned = inner.borrow_mut().assign(&name("countr"), Literal::Nil);
+        assert!(matches!(
+            assigned,
+            Err(LoxError::RuntimeError { message, .. })
+                if message.ends_with("Did you mean 'counter'?")
+        ));
+    }
+
+    #[test]
+    fn frozen_names_refuse_assignment() {
+        let mut globals = Environment::new();
+        globals.define(Symbol::intern("builtin"), Literal::Integer(1));
+        globals.freeze();
+        globals.define(Symbol::intern("script"), Literal::Integer(2));
+
+        assert!(globals.is_frozen(Symbol::intern("builtin")));
+        assert!(!globals.is_frozen(Symbol::intern("script")));
+        assert!(globals.assign(&name("builtin"), Literal::Nil).is_err());
+        assert!(globals.assign(&name("script"), Literal::Nil).is_ok());
+        assert_eq!(
+            globals.get_defined(Symbol::intern("builtin")),
+            Some(Literal::Integer(1))
+        );
+    }
+
+    #[test]
+    fn cached_frozen_globals_keep_their_value() {
+        let mut globals = Environment::new();
+        globals.define(Symbol::intern("builtin"), Literal::Integer(1));
+        globals.freeze();
+        let cache = GlobalCache::default();
+        let cached = globals.get_cached(&name("builtin"), &cache);
+        assert_eq!(cached.ok(), Some(Literal::Integer(1)));
+
+        for n in 0..32 {
+            globals.define(Symbol::intern(&format!("g{n}")), Literal::Nil);
+        }
+        assert!(globals.assign(&name("builtin"), Literal::Nil).is_err());
+        let cached = globals.get_cached(&name("builtin"), &cache);
+        assert_eq!(cached.ok(), Some(Literal::Integer(1)));
     }
 }
@@ -1,16 +1,66 @@
-use crate::{literal::Literal, token::Token, token_type::TokenType};
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::token::{self, Token};
+use crate::token_type::TokenType;
+
+thread_local! {
+    // Where the errors and warnings made on this thread are written. They are
+    // reported as they are made, far from any Lox, so this is per thread.
+    static ERROR_OUTPUT: RefCell<Rc<RefCell<dyn Write>>> =
+        RefCell::new(Rc::new(RefCell::new(io::stderr())));
+}
+
+// Sends the errors and warnings made on this thread from now on to `output`
+// instead of stderr.
+pub fn set_error_output(output: Rc<RefCell<dyn Write>>) {
+    ERROR_OUTPUT.with(|current| *current.borrow_mut() = output);
+}
+
+// Writes `line` to this thread's error output. A failure to write it has
+// nowhere left to be reported, so it is dropped.
+pub fn report_line(line: impl Display) {
+    let output = ERROR_OUTPUT.with(|current| current.borrow().clone());
+    let _ = writeln!(output.borrow_mut(), "{line}");
+}
 
 pub enum LoxError {
-    ScanError { line: u32, message: String },
-    ParseError { token: Token, message: String },
-    RuntimeError { token: Token, message: String },
-    SystemError { message: String },
-    ReturnValue { value: Literal },
+    ScanError {
+        source: Rc<str>,
+        line: u32,
+        message: String,
+    },
+    ParseError {
+        token: Token,
+        message: String,
+    },
+    TypeError {
+        token: Token,
+        message: String,
+    },
+    RuntimeError {
+        token: Token,
+        message: String,
+    },
+    SystemError {
+        message: String,
+    },
+    // Raised by exit(): unwinds every call so the program ends with `code`
+    // once its atExit() callbacks have run.
+    Exit {
+        code: i32,
+    },
 }
 
 impl LoxError {
-    pub fn scan_error(line: u32, message: String) -> LoxError {
-        let error = LoxError::ScanError { line, message };
+    pub fn scan_error(source: Rc<str>, line: u32, message: String) -> LoxError {
+        let error = LoxError::ScanError {
+            source,
+            line,
+            message,
+        };
         error.report();
         error
     }
@@ -21,6 +71,12 @@ impl LoxError {
         error
     }
 
+    pub fn type_error(token: Token, message: String) -> LoxError {
+        let error = LoxError::TypeError { token, message };
+        error.report();
+        error
+    }
+
     pub fn runtime_error(token: Token, message: String) -> LoxError {
         let error = LoxError::RuntimeError { token, message };
         error.report();
@@ -33,43 +89,87 @@ impl LoxError {
         error
     }
 
-    pub fn return_value(value: Literal) -> LoxError {
-        LoxError::ReturnValue { value }
+    pub fn exit(code: i32) -> LoxError {
+        LoxError::Exit { code }
+    }
+
+    // The name of the source the error is in and the line, if it has one.
+    pub fn location(&self) -> Option<(&str, u32)> {
+        match self {
+            LoxError::ScanError { source, line, .. } => Some((source, *line)),
+            LoxError::ParseError { token, .. }
+            | LoxError::TypeError { token, .. }
+            | LoxError::RuntimeError { token, .. } => {
+                Some((&token.source, token.line))
+            }
+            LoxError::SystemError { .. } | LoxError::Exit { .. } => None,
+        }
     }
 
     fn report(&self) {
         match self {
-            LoxError::ScanError { line, message } => {
-                eprintln!("[line {}] Error {}: {}", line, "", message);
+            LoxError::ScanError {
+                source,
+                line,
+                message,
+            } => {
+                let location = token::location(source, *line);
+                report_line(format!("[{}] Error: {}", location, message));
             }
-            LoxError::ParseError { token, message } => {
+            LoxError::ParseError { token, message }
+            | LoxError::TypeError { token, message } => {
                 if token.token_type == TokenType::EOF {
-                    eprintln!(
-                        "[line {}] Error {}: {}",
-                        token.line, "at end", message
-                    );
+                    report_line(format!(
+                        "[{}] Error at end: {}",
+                        token.location(),
+                        message
+                    ));
                 } else {
                     let place = format!("at '{}'", token.lexeme);
-                    eprintln!(
-                        "[line {}] Error {}: {}",
-                        token.line, place, message
-                    );
+                    report_line(format!(
+                        "[{}] Error {}: {}",
+                        token.location(),
+                        place,
+                        message
+                    ));
                 }
             }
             LoxError::RuntimeError { token, message } => {
                 if token.token_type == TokenType::EOF {
-                    eprintln!(
-                        "[line {}] Error {}: {}",
-                        token.line, "at end", message
-                    );
+                    report_line(format!(
+                        "[{}] Error at end: {}",
+                        token.location(),
+                        message
+                    ));
                 } else {
-                    eprintln!("{} \n[line {}]", message, token.line);
+                    report_line(format!(
+                        "{} \n[{}]",
+                        message,
+                        token.location()
+                    ));
                 }
             }
             LoxError::SystemError { message } => {
-                eprintln!("System Error: {message}");
+                report_line(format!("System Error: {message}"));
             }
-            _ => {}
+            LoxError::Exit { .. } => {}
         }
     }
 }
+
+pub fn report_warning(token: &Token, message: String) {
+    if token.token_type == TokenType::EOF {
+        report_line(format!(
+            "[{}] Warning at end: {}",
+            token.location(),
+            message
+        ));
+    } else {
+        report_line(format!(
+            "[{}] Warning at '{}': {}",
+            token.location(),
+            token.lexeme,
+            message
+        ));
+    }
+}
@@ -1,190 +1,72 @@
 use std::rc::Rc;
 
+use crate::ast::define_ast;
+use crate::environment::GlobalCache;
 use crate::literal::Literal;
 use crate::token::Token;
 
-#[derive(Debug)]
-pub enum Expr {
-    Assign(AssignExpr),
-    Binary(BinaryExpr),
-    Call(CallExpr),
-    Grouping(GroupingExpr),
-    Literal(LiteralExpr),
-    Logical(LogicalExpr),
-    Unary(UnaryExpr),
-    Variable(VariableExpr),
-}
-
-impl Expr {
-    pub fn accept<T>(&self, expr_visitor: &mut dyn ExprVisitor<T>) -> T {
-        match self {
-            Expr::Assign(ae) => ae.accept(expr_visitor),
-            Expr::Call(ce) => ce.accept(expr_visitor),
-            Expr::Binary(be) => be.accept(expr_visitor),
-            Expr::Grouping(ge) => ge.accept(expr_visitor),
-            Expr::Literal(le) => le.accept(expr_visitor),
-            Expr::Logical(le) => le.accept(expr_visitor),
-            Expr::Unary(ue) => ue.accept(expr_visitor),
-            Expr::Variable(ve) => ve.accept(expr_visitor),
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct AssignExpr {
-    pub name: Token,
-    pub value: Rc<Expr>,
-}
-
-impl AssignExpr {
-    pub fn new(name: Token, value: Rc<Expr>) -> AssignExpr {
-        AssignExpr { name, value }
-    }
-
-    pub fn accept<T>(&self, visitor: &mut dyn ExprVisitor<T>) -> T {
-        visitor.visit_assignment_expr(self)
-    }
-}
-
-#[derive(Debug)]
-pub struct BinaryExpr {
-    pub left: Rc<Expr>,
-    pub operator: Token,
-    pub right: Rc<Expr>,
-}
-
-impl BinaryExpr {
-    pub fn new(left: Rc<Expr>, operator: Token, right: Rc<Expr>) -> BinaryExpr {
-        BinaryExpr {
-            left,
-            operator,
-            right,
-        }
-    }
-
-    pub fn accept<T>(&self, visitor: &mut dyn ExprVisitor<T>) -> T {
-        visitor.visit_binary_expr(self)
-    }
-}
-
-#[derive(Debug)]
-pub struct CallExpr {
-    pub callee: Rc<Expr>,
-    pub paren: Token,
-    pub arguments: Vec<Rc<Expr>>,
-}
+define_ast! {
+    pub enum Expr, visited by ExprVisitor as expr;
 
-impl CallExpr {
-    pub fn new(
+    Assign(AssignExpr { name: Token, value: Rc<Expr> })
+        => visit_assignment_expr,
+    Binary(BinaryExpr { left: Rc<Expr>, operator: Token, right: Rc<Expr> })
+        => visit_binary_expr,
+    Call(CallExpr {
         callee: Rc<Expr>,
         paren: Token,
         arguments: Vec<Rc<Expr>>,
-    ) -> CallExpr {
-        CallExpr {
-            callee,
-            paren,
-            arguments,
-        }
-    }
-
-    pub fn accept<T>(&self, visitor: &mut dyn ExprVisitor<T>) -> T {
-        visitor.visit_call_expr(self)
-    }
-}
-
-#[derive(Debug)]
-pub struct GroupingExpr {
-    pub expression: Rc<Expr>,
+        // The callee follows a `?.`, as in `object?.method()`, so the call
+        // is nil when the callee is.
+        optional: bool,
+    }) => visit_call_expr,
+    Get(GetExpr {
+        object: Rc<Expr>,
+        name: Token,
+        // Written `object?.name`, which is nil when the object is nil.
+        optional: bool,
+    }) => visit_get_expr,
+    Grouping(GroupingExpr { expression: Rc<Expr> }) => visit_grouping_expr,
+    Literal(LiteralExpr { value: Option<Literal> }) => visit_literal_expr,
+    Logical(LogicalExpr { left: Rc<Expr>, operator: Token, right: Rc<Expr> })
+        => visit_logical_exp,
+    Match(MatchExpr {
+        keyword: Token,
+        subject: Rc<Expr>,
+        arms: Vec<MatchArm>,
+    }) => visit_match_expr,
+    Set(SetExpr { object: Rc<Expr>, name: Token, value: Rc<Expr> })
+        => visit_set_expr,
+    This(ThisExpr { keyword: Token }) => visit_this_expr,
+    Unary(UnaryExpr { operator: Token, right: Rc<Expr> }) => visit_unary_expr,
+    Variable(VariableExpr {
+        name: Token;
+        // Shared with the resolver, which may take back its mark until it
+        // has seen the rest of the enclosing scopes.
+        global: Rc<GlobalCache> = Rc::default(),
+    }) => visit_variable_expr,
 }
 
-impl GroupingExpr {
-    pub fn new(expression: Rc<Expr>) -> GroupingExpr {
-        GroupingExpr { expression }
-    }
-
-    pub fn accept<T>(&self, visitor: &mut dyn ExprVisitor<T>) -> T {
-        visitor.visit_grouping_expr(self)
-    }
-}
-
-#[derive(Debug)]
-pub struct LiteralExpr {
-    pub value: Option<Literal>,
-}
-
-impl LiteralExpr {
-    pub fn new(value: Option<Literal>) -> LiteralExpr {
-        LiteralExpr { value }
-    }
-
-    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> T {
-        visitor.visit_literal_expr(self)
-    }
-}
-
-#[derive(Debug)]
-pub struct LogicalExpr {
-    pub left: Rc<Expr>,
-    pub operator: Token,
-    pub right: Rc<Expr>,
-}
-
-impl LogicalExpr {
-    pub fn new(
-        left: Rc<Expr>,
-        operator: Token,
-        right: Rc<Expr>,
-    ) -> LogicalExpr {
-        LogicalExpr {
-            left,
-            operator,
-            right,
+impl Expr {
+    // Whether this is a property access or call that follows a `?.` in the
+    // same chain, like `a?.b.c` or `a?.b()`, which is nil as soon as a link
+    // of the chain is nil. Parentheses end a chain.
+    pub fn in_optional_chain(&self) -> bool {
+        match self {
+            Expr::Call(ce) => ce.optional,
+            Expr::Get(ge) => ge.optional || ge.object.in_optional_chain(),
+            _ => false,
         }
     }
-
-    pub fn accept<T>(&self, visitor: &mut dyn ExprVisitor<T>) -> T {
-        visitor.visit_logical_exp(self)
-    }
-}
-
-#[derive(Debug)]
-pub struct UnaryExpr {
-    pub operator: Token,
-    pub right: Rc<Expr>,
-}
-
-impl UnaryExpr {
-    pub fn new(operator: Token, right: Rc<Expr>) -> UnaryExpr {
-        UnaryExpr { operator, right }
-    }
-
-    pub fn accept<T>(&self, visitor: &mut dyn ExprVisitor<T>) -> T {
-        visitor.visit_unary_expr(self)
-    }
 }
 
+// One `case pattern if guard: value` of a match expression. As in the match
+// statement, a pattern is either a value the subject has to equal or a name
+// binding the subject, and neither is given for `_`.
 #[derive(Debug)]
-pub struct VariableExpr {
-    pub name: Token,
-}
-
-impl VariableExpr {
-    pub fn new(name: Token) -> VariableExpr {
-        VariableExpr { name }
-    }
-
-    pub fn accept<T>(&self, visitor: &dyn ExprVisitor<T>) -> T {
-        visitor.visit_variable_expr(self)
-    }
-}
-
-pub trait ExprVisitor<T> {
-    fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> T;
-    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> T;
-    fn visit_literal_expr(&self, expr: &LiteralExpr) -> T;
-    fn visit_logical_exp(&mut self, expr: &LogicalExpr) -> T;
-    fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> T;
-    fn visit_variable_expr(&self, expr: &VariableExpr) -> T;
-    fn visit_assignment_expr(&mut self, expr: &AssignExpr) -> T;
-    fn visit_call_expr(&mut self, expr: &CallExpr) -> T;
+pub struct MatchArm {
+    pub pattern: Option<Rc<Expr>>,
+    pub binding: Option<Token>,
+    pub guard: Option<Rc<Expr>>,
+    pub value: Rc<Expr>,
 }
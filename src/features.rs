@@ -0,0 +1,158 @@
+use std::fmt::{self, Display};
+use std::ops::BitOr;
+
+// The parts of the language that go beyond the book's Lox, each of which
+// can be turned off to get a script rejected where book Lox would reject it.
+// Everything is on by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LanguageFeatures(u32);
+
+impl LanguageFeatures {
+    // 'break' and 'continue', with or without a loop label.
+    pub const LOOP_JUMPS: LanguageFeatures = LanguageFeatures(1 << 0);
+    // The '??' operator.
+    pub const COALESCE: LanguageFeatures = LanguageFeatures(1 << 1);
+    // The 'is' operator.
+    pub const IS: LanguageFeatures = LanguageFeatures(1 << 2);
+    // '+' with a string on one side and a number or instance on the other.
+    pub const STRING_COERCION: LanguageFeatures = LanguageFeatures(1 << 3);
+    // """strings""" that may hold double quotes.
+    pub const TRIPLE_QUOTES: LanguageFeatures = LanguageFeatures(1 << 4);
+    // The 'match' statement.
+    pub const MATCH: LanguageFeatures = LanguageFeatures(1 << 5);
+    // 'enum' declarations.
+    pub const ENUMS: LanguageFeatures = LanguageFeatures(1 << 6);
+    // The '%' operator.
+    pub const REMAINDER: LanguageFeatures = LanguageFeatures(1 << 7);
+    // Integer literals ending in 'n', like 123n, for bigints.
+    pub const BIGINTS: LanguageFeatures = LanguageFeatures(1 << 8);
+    // Property access with '?.', which gives nil on nil.
+    pub const OPTIONAL_CHAINING: LanguageFeatures = LanguageFeatures(1 << 9);
+
+    pub const NONE: LanguageFeatures = LanguageFeatures(0);
+    pub const ALL: LanguageFeatures = LanguageFeatures((1 << 10) - 1);
+
+    // The name each feature goes by in --features.
+    pub const NAMES: &'static [(&'static str, LanguageFeatures)] = &[
+        ("loop-jumps", LanguageFeatures::LOOP_JUMPS),
+        ("coalesce", LanguageFeatures::COALESCE),
+        ("is", LanguageFeatures::IS),
+        ("string-coercion", LanguageFeatures::STRING_COERCION),
+        ("triple-quotes", LanguageFeatures::TRIPLE_QUOTES),
+        ("match", LanguageFeatures::MATCH),
+        ("enum", LanguageFeatures::ENUMS),
+        ("remainder", LanguageFeatures::REMAINDER),
+        ("bigint", LanguageFeatures::BIGINTS),
+        ("optional-chaining", LanguageFeatures::OPTIONAL_CHAINING),
+    ];
+
+    pub fn contains(self, features: LanguageFeatures) -> bool {
+        self.0 & features.0 == features.0
+    }
+
+    pub fn with(self, features: LanguageFeatures) -> LanguageFeatures {
+        LanguageFeatures(self.0 | features.0)
+    }
+
+    pub fn without(self, features: LanguageFeatures) -> LanguageFeatures {
+        LanguageFeatures(self.0 & !features.0)
+    }
+
+    // Reads a comma-separated list of feature names, as given to
+    // --features. `none` stands for book Lox and `all` for every extension.
+    pub fn parse(list: &str) -> Result<LanguageFeatures, String> {
+        let mut features = LanguageFeatures::NONE;
+        for name in list.split(',').map(str::trim) {
+            features = features.with(match name {
+                "none" | "" => LanguageFeatures::NONE,
+                "all" => LanguageFeatures::ALL,
+                name => LanguageFeatures::named(name)
+                    .ok_or_else(|| format!("Unknown feature '{name}'."))?,
+            });
+        }
+        Ok(features)
+    }
+
+    pub fn named(name: &str) -> Option<LanguageFeatures> {
+        LanguageFeatures::NAMES
+            .iter()
+            .find(|&&(n, _)| n == name)
+            .map(|&(_, feature)| feature)
+    }
+
+    // The name of a single feature, for messages about it.
+    pub fn name(self) -> &'static str {
+        LanguageFeatures::NAMES
+            .iter()
+            .find(|&&(_, feature)| feature == self)
+            .map_or("unknown", |&(name, _)| name)
+    }
+}
+
+impl Default for LanguageFeatures {
+    fn default() -> Self {
+        LanguageFeatures::ALL
+    }
+}
+
+impl BitOr for LanguageFeatures {
+    type Output = LanguageFeatures;
+
+    fn bitor(self, rhs: LanguageFeatures) -> LanguageFeatures {
+        self.with(rhs)
+    }
+}
+
+// The enabled features' names, comma-separated, or `none`.
+impl Display for LanguageFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = LanguageFeatures::NAMES
+            .iter()
+            .filter(|&&(_, feature)| self.contains(feature))
+            .map(|&(name, _)| name)
+            .collect();
+        match names.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", names.join(",")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_feature_lists() {
+        let features = LanguageFeatures::parse("coalesce, is").ok();
+        assert_eq!(
+            features,
+            Some(LanguageFeatures::COALESCE | LanguageFeatures::IS)
+        );
+        assert_eq!(
+            LanguageFeatures::parse("none").ok(),
+            Some(LanguageFeatures::NONE)
+        );
+        assert_eq!(
+            LanguageFeatures::parse("all").ok(),
+            Some(LanguageFeatures::ALL)
+        );
+        assert!(LanguageFeatures::parse("coalesce,ternary").is_err());
+    }
+
+    #[test]
+    fn displays_as_a_feature_list() {
+        assert_eq!(
+            LanguageFeatures::ALL.to_string(),
+            "loop-jumps,coalesce,is,string-coercion,triple-quotes,match,enum,\
+             remainder,bigint,optional-chaining"
+        );
+        assert_eq!(LanguageFeatures::NONE.to_string(), "none");
+        let features = LanguageFeatures::ALL.without(LanguageFeatures::IS);
+        assert!(!features.contains(LanguageFeatures::IS));
+        assert_eq!(
+            LanguageFeatures::parse(&features.to_string()).ok(),
+            Some(features)
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+// Everything the interpreter reads or writes by path goes through one of
+// these: the scripts it runs, the io natives and, later, imports. The CLI
+// uses the real file system; tests and embedders that shouldn't touch the
+// disk give Lox a MemoryFileSystem instead.
+pub trait FileSystem: Debug {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    // Replaces the file, creating it if needed.
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+    fn exists(&self, path: &Path) -> bool;
+    // The absolute form of an existing path with `.` and `..` resolved, so
+    // two paths naming the same file compare equal.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    // Streams for the open() native, so a file is read and written a piece
+    // at a time rather than held in memory whole.
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>>;
+    // Empties the file first, creating it if needed.
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn Write>>;
+    // Writes after what is already there, creating the file if needed.
+    fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write>>;
+}
+
+#[derive(Debug, Default)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+        Ok(Box::new(File::create(path)?))
+    }
+
+    fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+        Ok(Box::new(
+            OpenOptions::new().append(true).create(true).open(path)?,
+        ))
+    }
+}
+
+// Files held in memory, keyed by their canonical path. Relative paths are
+// taken to be relative to the root, and there are no directories beyond
+// what the file paths imply.
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    // Shared with the files opened for writing, which add to them directly.
+    files: Rc<RefCell<HashMap<PathBuf, Vec<u8>>>>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> MemoryFileSystem {
+        MemoryFileSystem::default()
+    }
+
+    // Adds a file, replacing any already at `path`.
+    pub fn with_file<P, C>(self, path: P, contents: C) -> MemoryFileSystem
+    where
+        P: AsRef<Path>,
+        C: Into<Vec<u8>>,
+    {
+        self.files
+            .borrow_mut()
+            .insert(normalize(path.as_ref()), contents.into());
+        self
+    }
+
+    // The contents of a file, if there is one at `path`.
+    pub fn contents(&self, path: &Path) -> Option<Vec<u8>> {
+        self.files.borrow().get(&normalize(path)).cloned()
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.contents(path).ok_or_else(|| not_found(path))
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.files
+            .borrow_mut()
+            .insert(normalize(path), contents.to_vec());
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.borrow().contains_key(&normalize(path))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = normalize(path);
+        if self.files.borrow().contains_key(&path) {
+            Ok(path)
+        } else {
+            Err(not_found(&path))
+        }
+    }
+
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn Read>> {
+        Ok(Box::new(Cursor::new(self.read(path)?)))
+    }
+
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+        self.write(path, &[])?;
+        self.open_append(path)
+    }
+
+    fn open_append(&self, path: &Path) -> io::Result<Box<dyn Write>> {
+        let path = normalize(path);
+        self.files.borrow_mut().entry(path.clone()).or_default();
+        Ok(Box::new(MemoryFile {
+            files: self.files.clone(),
+            path,
+        }))
+    }
+}
+
+// A file in a MemoryFileSystem open for writing. Each write lands in the
+// file straight away.
+struct MemoryFile {
+    files: Rc<RefCell<HashMap<PathBuf, Vec<u8>>>>,
+    path: PathBuf,
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.files
+            .borrow_mut()
+            .entry(self.path.clone())
+            .or_default()
+            .extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// `path` made absolute from the root, with `.` and `..` resolved by
+// dropping or popping components. `..` at the root stays at the root.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normal.push(part),
+            Component::ParentDir => {
+                normal.pop();
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    normal
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no file at {}", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_files_are_found_by_any_path_to_them() {
+        let files = MemoryFileSystem::new().with_file("lib/a.lox", "print 1;");
+        assert!(files.exists(Path::new("/lib/a.lox")));
+        assert!(files.exists(Path::new("./lib/../lib/a.lox")));
+        assert!(!files.exists(Path::new("a.lox")));
+        assert_eq!(
+            files.read(Path::new("lib/./a.lox")).ok(),
+            Some(b"print 1;".to_vec())
+        );
+        assert_eq!(
+            files.canonicalize(Path::new("other/../lib/a.lox")).ok(),
+            Some(PathBuf::from("/lib/a.lox"))
+        );
+    }
+
+    #[test]
+    fn memory_files_can_be_replaced() {
+        let files = MemoryFileSystem::new();
+        let path = Path::new("out.txt");
+        assert_eq!(
+            files.read(path).map_err(|e| e.kind()),
+            Err(io::ErrorKind::NotFound)
+        );
+        assert!(files.canonicalize(path).is_err());
+
+        assert!(files.write(path, b"first").is_ok());
+        assert!(files.write(path, b"second").is_ok());
+        assert_eq!(files.contents(path), Some(b"second".to_vec()));
+    }
+
+    #[test]
+    fn memory_files_are_streamed() {
+        let files = MemoryFileSystem::new().with_file("log.txt", "a");
+        let path = Path::new("log.txt");
+        let write = |mut file: Box<dyn Write>, text: &str| {
+            assert!(file.write_all(text.as_bytes()).is_ok());
+        };
+
+        write(
+            files.open_append(path).unwrap_or_else(|e| panic!("{e}")),
+            "b",
+        );
+        assert_eq!(files.contents(path), Some(b"ab".to_vec()));
+        write(
+            files.open_write(path).unwrap_or_else(|e| panic!("{e}")),
+            "c",
+        );
+        assert_eq!(files.contents(path), Some(b"c".to_vec()));
+
+        let mut text = String::new();
+        let mut file = files.open_read(path).unwrap_or_else(|e| panic!("{e}"));
+        assert!(file.read_to_string(&mut text).is_ok());
+        assert_eq!(text, "c");
+        assert!(files.open_read(Path::new("missing.txt")).is_err());
+    }
+}
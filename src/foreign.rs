@@ -0,0 +1,18 @@
+use std::any::Any;
+use std::fmt::{Debug, Display};
+use std::io;
+
+// Host data that natives hand to scripts, such as an open file. Scripts can
+// store and pass it around, but only the natives that made it know what is
+// inside.
+pub trait Foreign: Debug + Display {
+    // What the value is called in error messages, e.g. "file".
+    fn type_name(&self) -> &'static str;
+    fn as_any(&self) -> &dyn Any;
+
+    // Releases what the value holds, for the close() native. Natives refuse
+    // to use a closed value; closing it again does nothing.
+    fn close(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
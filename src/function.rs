@@ -5,9 +5,12 @@ use std::rc::Rc;
 use crate::callable::LoxCallable;
 use crate::environment::Environment;
 use crate::error_reporter::LoxError;
+use crate::instance::LoxInstance;
 use crate::interpreter::Interpreter;
 use crate::literal::Literal;
+use crate::recorder::RecordKind;
 use crate::stmt::{FunctionStmt, Stmt};
+use crate::symbol::{INIT, THIS};
 use crate::token::Token;
 
 #[derive(Debug)]
@@ -16,6 +19,7 @@ pub struct LoxFunction {
     params: Vec<Token>,
     body: Rc<Vec<Stmt>>,
     closure: Rc<RefCell<Environment>>,
+    is_initializer: bool,
 }
 
 impl LoxFunction {
@@ -28,8 +32,43 @@ impl LoxFunction {
             params: declaration.params.to_owned(),
             body: Rc::clone(&declaration.body),
             closure,
+            is_initializer: false,
         }
     }
+
+    // A method's `init` returns the instance it was called on, whatever its
+    // body returns.
+    pub fn new_method(
+        declaration: &FunctionStmt,
+        closure: Rc<RefCell<Environment>>,
+    ) -> LoxFunction {
+        LoxFunction {
+            is_initializer: declaration.name.symbol == INIT,
+            ..LoxFunction::new(declaration, closure)
+        }
+    }
+
+    // Returns a copy of this method with `this` bound to `instance`.
+    pub fn bind(&self, instance: Rc<LoxInstance>) -> LoxFunction {
+        let mut environment =
+            Environment::new_with_enclosing(self.closure.clone());
+        environment.define(THIS, Literal::Instance(instance));
+
+        LoxFunction {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: Rc::clone(&self.body),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    fn this(&self) -> Literal {
+        self.closure
+            .borrow()
+            .get_defined(THIS)
+            .unwrap_or(Literal::Nil)
+    }
 }
 
 impl LoxCallable for LoxFunction {
@@ -42,28 +81,37 @@ impl LoxCallable for LoxFunction {
         interpreter: &mut Interpreter,
         arguments: Vec<Literal>,
     ) -> Result<Literal, LoxError> {
-        let mut environment =
-            Environment::new_with_enclosing(self.closure.clone());
+        let environment = interpreter.new_scope(self.closure.clone());
 
+        // Entered before the parameters are defined, so a traced one shows
+        // up as defined inside the call.
+        let tracing = interpreter.tracer().is_tracing();
+        if tracing {
+            interpreter.tracer().enter(&self.name);
+        }
         for (param, arg) in self.params.iter().zip(arguments.iter()) {
-            environment.define(param.lexeme.to_owned(), arg.clone());
+            interpreter.record(RecordKind::Define, param, arg);
+            environment.borrow_mut().define(param.symbol, arg.clone());
+        }
+
+        let returned =
+            interpreter.execute_call(&self.name, &self.body, environment);
+        if tracing {
+            interpreter.tracer().leave();
         }
+        let returned = returned?;
 
-        match interpreter.execute_block(&self.body, environment) {
-            Ok(_) => {}
-            Err(e) => match e {
-                LoxError::ReturnValue { value } => return Ok(value),
-                _ => return Err(e),
-            },
+        if self.is_initializer {
+            return Ok(self.this());
         }
 
-        Ok(Literal::Nil)
+        Ok(returned.unwrap_or(Literal::Nil))
     }
 }
 
 impl Display for LoxFunction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let fn_name = self.name.lexeme.to_owned();
+        let fn_name = &self.name.lexeme;
         write!(f, "<fn {fn_name}>")
     }
 }
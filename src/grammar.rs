@@ -0,0 +1,366 @@
+use crate::token_type::TokenType;
+
+// How tightly each level of the expression grammar binds, loosest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    Assignment,
+    Or,
+    Coalesce,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    // Precedence of `token_type` used as a binary or logical operator.
+    // Assignment isn't one of them, it builds its own kind of expression.
+    pub fn of_infix(token_type: TokenType) -> Option<Precedence> {
+        match infix(token_type) {
+            Some(operator) if operator.precedence != Precedence::Assignment => {
+                Some(operator.precedence)
+            }
+            _ => None,
+        }
+    }
+
+    // The next tighter level, used for the right operand of a
+    // left-associative operator.
+    pub fn next(self) -> Precedence {
+        match self {
+            Precedence::Assignment => Precedence::Or,
+            Precedence::Or => Precedence::Coalesce,
+            Precedence::Coalesce => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Call,
+            Precedence::Call | Precedence::Primary => Precedence::Primary,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Precedence::Assignment => "assignment",
+            Precedence::Or => "or",
+            Precedence::Coalesce => "coalesce",
+            Precedence::And => "and",
+            Precedence::Equality => "equality",
+            Precedence::Comparison => "comparison",
+            Precedence::Term => "term",
+            Precedence::Factor => "factor",
+            Precedence::Unary => "unary",
+            Precedence::Call => "call",
+            Precedence::Primary => "primary",
+        }
+    }
+}
+
+// How a chain of operators with the same precedence groups: `a - b - c` is
+// `(a - b) - c`, `a = b = c` is `a = (b = c)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+impl Associativity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Associativity::Left => "left",
+            Associativity::Right => "right",
+        }
+    }
+}
+
+pub struct Operator {
+    pub token_type: TokenType,
+    pub symbol: &'static str,
+    pub precedence: Precedence,
+    pub associativity: Associativity,
+}
+
+const fn operator(
+    token_type: TokenType,
+    symbol: &'static str,
+    precedence: Precedence,
+    associativity: Associativity,
+) -> Operator {
+    Operator {
+        token_type,
+        symbol,
+        precedence,
+        associativity,
+    }
+}
+
+// Every operator written between its operands, loosest first. The parser
+// reads which tokens continue an expression at each level from here.
+pub const INFIX_OPERATORS: &[Operator] = &[
+    operator(
+        TokenType::Equal,
+        "=",
+        Precedence::Assignment,
+        Associativity::Right,
+    ),
+    operator(TokenType::Or, "or", Precedence::Or, Associativity::Left),
+    operator(
+        TokenType::QuestionQuestion,
+        "??",
+        Precedence::Coalesce,
+        Associativity::Left,
+    ),
+    operator(TokenType::And, "and", Precedence::And, Associativity::Left),
+    operator(
+        TokenType::BangEqual,
+        "!=",
+        Precedence::Equality,
+        Associativity::Left,
+    ),
+    operator(
+        TokenType::EqualEqual,
+        "==",
+        Precedence::Equality,
+        Associativity::Left,
+    ),
+    operator(
+        TokenType::Greater,
+        ">",
+        Precedence::Comparison,
+        Associativity::Left,
+    ),
+    operator(
+        TokenType::GreaterEqual,
+        ">=",
+        Precedence::Comparison,
+        Associativity::Left,
+    ),
+    operator(
+        TokenType::Less,
+        "<",
+        Precedence::Comparison,
+        Associativity::Left,
+    ),
+    operator(
+        TokenType::LessEqual,
+        "<=",
+        Precedence::Comparison,
+        Associativity::Left,
+    ),
+    operator(
+        TokenType::Is,
+        "is",
+        Precedence::Comparison,
+        Associativity::Left,
+    ),
+    operator(TokenType::Minus, "-", Precedence::Term, Associativity::Left),
+    operator(TokenType::Plus, "+", Precedence::Term, Associativity::Left),
+    operator(
+        TokenType::Slash,
+        "/",
+        Precedence::Factor,
+        Associativity::Left,
+    ),
+    operator(
+        TokenType::Star,
+        "*",
+        Precedence::Factor,
+        Associativity::Left,
+    ),
+    operator(
+        TokenType::Percent,
+        "%",
+        Precedence::Factor,
+        Associativity::Left,
+    ),
+];
+
+// Operators written before their single operand. They nest, so `!!a` is
+// `!(!a)`.
+pub const PREFIX_OPERATORS: &[Operator] = &[
+    operator(
+        TokenType::Bang,
+        "!",
+        Precedence::Unary,
+        Associativity::Right,
+    ),
+    operator(
+        TokenType::Minus,
+        "-",
+        Precedence::Unary,
+        Associativity::Right,
+    ),
+];
+
+// Calls and property accesses chain after their operand: `a.b(c)?.d`.
+pub const POSTFIX_OPERATORS: &[Operator] = &[
+    operator(
+        TokenType::LeftParen,
+        "()",
+        Precedence::Call,
+        Associativity::Left,
+    ),
+    operator(TokenType::Dot, ".", Precedence::Call, Associativity::Left),
+    operator(
+        TokenType::QuestionDot,
+        "?.",
+        Precedence::Call,
+        Associativity::Left,
+    ),
+];
+
+impl Operator {
+    // The loosest level the operand on the right may be parsed at: the same
+    // level again for right-associative operators, the next tighter one for
+    // left-associative ones so that `a - b - c` can't group as `a - (b - c)`.
+    pub fn operand_precedence(&self) -> Precedence {
+        match self.associativity {
+            Associativity::Left => self.precedence.next(),
+            Associativity::Right => self.precedence,
+        }
+    }
+}
+
+pub fn infix(token_type: TokenType) -> Option<&'static Operator> {
+    INFIX_OPERATORS
+        .iter()
+        .find(|operator| operator.token_type == token_type)
+}
+
+pub fn prefix(token_type: TokenType) -> Option<&'static Operator> {
+    PREFIX_OPERATORS
+        .iter()
+        .find(|operator| operator.token_type == token_type)
+}
+
+pub fn postfix(token_type: TokenType) -> Option<&'static Operator> {
+    POSTFIX_OPERATORS
+        .iter()
+        .find(|operator| operator.token_type == token_type)
+}
+
+// The operator table as Markdown, one row per precedence level from loosest
+// to tightest, as it appears in the README.
+pub fn render_markdown() -> String {
+    let mut table = String::from(
+        "| Precedence | Operators | Associativity |\n\
+         |------------|-----------|---------------|\n",
+    );
+
+    let operators = INFIX_OPERATORS
+        .iter()
+        .chain(PREFIX_OPERATORS)
+        .chain(POSTFIX_OPERATORS);
+    let mut rows: Vec<(Precedence, Associativity, Vec<&str>)> = Vec::new();
+    for operator in operators {
+        match rows.last_mut() {
+            Some((precedence, _, symbols))
+                if *precedence == operator.precedence =>
+            {
+                symbols.push(operator.symbol)
+            }
+            _ => rows.push((
+                operator.precedence,
+                operator.associativity,
+                vec![operator.symbol],
+            )),
+        }
+    }
+
+    for (precedence, associativity, symbols) in rows {
+        let symbols: Vec<String> =
+            symbols.iter().map(|s| format!("`{s}`")).collect();
+        table.push_str(&format!(
+            "| {} | {} | {} |\n",
+            precedence.name(),
+            symbols.join(" "),
+            associativity.name()
+        ));
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast_printer::AstPrinter;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn print(source: &str) -> String {
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let expr = Parser::new(&tokens)
+            .parse_expression()
+            .unwrap_or_else(|_| panic!("{source} should parse"));
+        AstPrinter::new().print(&expr)
+    }
+
+    fn binary_operators() -> impl Iterator<Item = &'static Operator> {
+        INFIX_OPERATORS
+            .iter()
+            .filter(|operator| operator.precedence != Precedence::Assignment)
+    }
+
+    #[test]
+    fn table_is_ordered_loosest_first() {
+        for pair in INFIX_OPERATORS.windows(2) {
+            assert!(pair[0].precedence <= pair[1].precedence);
+        }
+        for operator in PREFIX_OPERATORS.iter().chain(POSTFIX_OPERATORS) {
+            assert!(operator.precedence > Precedence::Factor);
+        }
+    }
+
+    #[test]
+    fn table_matches_the_scanner() {
+        for operator in INFIX_OPERATORS.iter().chain(PREFIX_OPERATORS) {
+            let tokens = Scanner::new(operator.symbol).scan_tokens().clone();
+            assert_eq!(tokens[0].token_type, operator.token_type);
+        }
+    }
+
+    #[test]
+    fn binary_operators_group_by_the_table() {
+        for first in binary_operators() {
+            for second in binary_operators() {
+                let (a, b) = (first.symbol, second.symbol);
+                let expected = if second.precedence > first.precedence {
+                    format!("({a} a ({b} b c))")
+                } else {
+                    format!("({b} ({a} a b) c)")
+                };
+                assert_eq!(print(&format!("a {a} b {b} c")), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn prefix_operators_bind_tighter_than_binary_ones() {
+        for operator in binary_operators() {
+            let symbol = operator.symbol;
+            assert_eq!(
+                print(&format!("-a {symbol} !b")),
+                format!("({symbol} (- a) (! b))")
+            );
+        }
+    }
+
+    #[test]
+    fn assignment_groups_to_the_right() {
+        assert_eq!(print("a = b = c"), "(= a (= b c))");
+        assert_eq!(print("a = b or c"), "(= a (or b c))");
+    }
+
+    #[test]
+    fn readme_lists_the_table() {
+        let readme = include_str!("../README.md");
+        assert!(readme.contains(&render_markdown()));
+    }
+}
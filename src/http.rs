@@ -0,0 +1,241 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// Just enough HTTP for fetch(): plain http:// URLs, one request per
+// connection. Requests are sent as HTTP/1.0 so that servers answer with the
+// whole body instead of chunks, and the body ends when the connection does.
+
+// How long connecting, and then each read or write, may wait before fetch()
+// gives up on the server.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    // Names are lowercased, since HTTP header names ignore case.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct Url<'a> {
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+}
+
+fn parse_url(url: &str) -> Result<Url<'_>, String> {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None => return Err(format!("only http:// URLs are supported: {url}")),
+    };
+
+    let (authority, path) = match rest.find('/') {
+        Some(at) => (&rest[..at], &rest[at..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => match port.parse() {
+            Ok(port) => (host, port),
+            Err(_) => return Err(format!("invalid port in {url}")),
+        },
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in {url}"));
+    }
+
+    Ok(Url { host, port, path })
+}
+
+fn request_bytes(request: &Request, url: &Url) -> Result<Vec<u8>, String> {
+    // A line break would end the header early and let the rest be read as
+    // headers, or a request, of its own.
+    for (name, value) in &request.headers {
+        if [name, value].iter().any(|s| s.contains(['\r', '\n'])) {
+            return Err(format!("header {name:?} contains a line break"));
+        }
+    }
+
+    let mut head = format!("{} {} HTTP/1.0\r\n", request.method, url.path);
+    if url.port == 80 {
+        head.push_str(&format!("Host: {}\r\n", url.host));
+    } else {
+        head.push_str(&format!("Host: {}:{}\r\n", url.host, url.port));
+    }
+    head.push_str("User-Agent: rlox\r\n");
+    for (name, value) in &request.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if !request.body.is_empty() {
+        head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+    }
+    head.push_str("\r\n");
+
+    let mut bytes = head.into_bytes();
+    bytes.extend_from_slice(request.body.as_bytes());
+    Ok(bytes)
+}
+
+fn parse_response(bytes: &[u8]) -> Result<Response, String> {
+    let end = match bytes.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(end) => end,
+        None => return Err("incomplete response headers".to_string()),
+    };
+    let head = String::from_utf8_lossy(&bytes[..end]);
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse().ok());
+    let status = match status {
+        Some(status) => status,
+        None => return Err("invalid status line".to_string()),
+    };
+
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| {
+            (name.trim().to_ascii_lowercase(), value.trim().to_string())
+        })
+        .collect();
+    let body = String::from_utf8_lossy(&bytes[end + 4..]).into_owned();
+
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+pub fn fetch(request: &Request) -> Result<Response, String> {
+    let url = parse_url(&request.url)?;
+    let bytes = request_bytes(request, &url)?;
+    let mut stream = connect(&url)
+        .map_err(|e| format!("can't connect to {}: {e}", request.url))?;
+
+    stream
+        .write_all(&bytes)
+        .map_err(|e| format!("can't send the request: {e}"))?;
+    let mut bytes = Vec::new();
+    stream
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("can't read the response: {e}"))?;
+
+    parse_response(&bytes)
+}
+
+// Connects to the first of the host's addresses that answers within TIMEOUT,
+// with reads and writes limited to it too.
+fn connect(url: &Url) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in (url.host, url.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "the host has no addresses")
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_urls() {
+        assert_eq!(
+            parse_url("http://example.com"),
+            Ok(Url {
+                host: "example.com",
+                port: 80,
+                path: "/"
+            })
+        );
+        assert_eq!(
+            parse_url("http://127.0.0.1:8080/a/b?c=d"),
+            Ok(Url {
+                host: "127.0.0.1",
+                port: 8080,
+                path: "/a/b?c=d"
+            })
+        );
+        assert!(parse_url("https://example.com").is_err());
+        assert!(parse_url("http://:80/").is_err());
+        assert!(parse_url("http://host:http/").is_err());
+    }
+
+    #[test]
+    fn writes_requests() {
+        let request = Request {
+            method: "POST".to_string(),
+            url: "http://localhost:8080/submit".to_string(),
+            headers: vec![("Accept".to_string(), "text/plain".to_string())],
+            body: "x=1".to_string(),
+        };
+        let url = parse_url(&request.url).unwrap_or_else(|e| panic!("{e}"));
+        let bytes =
+            request_bytes(&request, &url).unwrap_or_else(|e| panic!("{e}"));
+        assert_eq!(
+            String::from_utf8_lossy(&bytes),
+            "POST /submit HTTP/1.0\r\nHost: localhost:8080\r\n\
+             User-Agent: rlox\r\nAccept: text/plain\r\n\
+             Content-Length: 3\r\n\r\nx=1"
+        );
+    }
+
+    #[test]
+    fn refuses_line_breaks_in_headers() {
+        let url =
+            parse_url("http://localhost/").unwrap_or_else(|e| panic!("{e}"));
+        for header in [
+            ("Accept", "text/plain\r\nX-Injected: 1"),
+            ("X-Injected: 1\nAccept", "text/plain"),
+            ("Accept", "text/plain\r"),
+        ] {
+            let request = Request {
+                method: "GET".to_string(),
+                url: "http://localhost/".to_string(),
+                headers: vec![(header.0.to_string(), header.1.to_string())],
+                body: String::new(),
+            };
+            assert!(request_bytes(&request, &url).is_err(), "{header:?}");
+        }
+    }
+
+    #[test]
+    fn parses_responses() {
+        let response = parse_response(
+            b"HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\n\
+              X-Empty:\r\n\r\nno such page\r\n",
+        );
+        assert_eq!(
+            response,
+            Ok(Response {
+                status: 404,
+                headers: vec![
+                    ("content-type".to_string(), "text/plain".to_string()),
+                    ("x-empty".to_string(), String::new()),
+                ],
+                body: "no such page\r\n".to_string(),
+            })
+        );
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+        assert!(parse_response(b"garbage\r\n\r\n").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::class::LoxClass;
+use crate::error_reporter::LoxError;
+use crate::literal::Literal;
+use crate::token::Token;
+
+#[derive(Debug)]
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: RefCell<HashMap<Rc<str>, Literal>>,
+    frozen: Cell<bool>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> LoxInstance {
+        LoxInstance {
+            class,
+            fields: RefCell::new(HashMap::new()),
+            frozen: Cell::new(false),
+        }
+    }
+
+    pub fn class(&self) -> &Rc<LoxClass> {
+        &self.class
+    }
+
+    // Fields shadow methods, so a method can be replaced per instance.
+    pub fn get(self: &Rc<Self>, name: &Token) -> Result<Literal, LoxError> {
+        if let Some(value) = self.fields.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        if let Some(method) = self.class.find_method(&name.lexeme) {
+            let method = method.bind(self.clone());
+            return Ok(Literal::Function(Rc::new(method)));
+        }
+
+        Err(LoxError::runtime_error(
+            name.clone(),
+            format!("Undefined property '{}'.", name.lexeme),
+        ))
+    }
+
+    // A field's value, leaving out methods.
+    pub fn field(&self, name: &str) -> Option<Literal> {
+        self.fields.borrow().get(name).cloned()
+    }
+
+    // Sorted, so listing them doesn't depend on hashing order.
+    pub fn field_names(&self) -> Vec<Rc<str>> {
+        let mut names: Vec<Rc<str>> =
+            self.fields.borrow().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // Every field with its value, sorted by name like field_names().
+    pub fn fields(&self) -> Vec<(Rc<str>, Literal)> {
+        let mut fields: Vec<(Rc<str>, Literal)> = self
+            .fields
+            .borrow()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        fields
+    }
+
+    // A frozen instance can't have any of its fields set.
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.get()
+    }
+
+    pub fn set(&self, name: &Token, value: Literal) -> Result<(), LoxError> {
+        if self.is_frozen() {
+            return Err(LoxError::runtime_error(
+                name.clone(),
+                format!(
+                    "Can't set '{}' on a frozen {} instance.",
+                    name.lexeme,
+                    self.class.name()
+                ),
+            ));
+        }
+
+        if self.class.is_frozen()
+            && self.class.find_method(&name.lexeme).is_some()
+        {
+            return Err(LoxError::runtime_error(
+                name.clone(),
+                format!(
+                    "Can't replace method '{}' of frozen class {}.",
+                    name.lexeme,
+                    self.class.name()
+                ),
+            ));
+        }
+
+        self.fields.borrow_mut().insert(name.lexeme.clone(), value);
+        Ok(())
+    }
+}
+
+impl Display for LoxInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} instance>", self.class.name())
+    }
+}
@@ -1,25 +1,164 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::{Rc, Weak};
+use std::sync::Once;
+use std::time::{Duration, Instant};
 
+use crate::callable::{arity_mismatch, LoxCallable};
+use crate::class::LoxClass;
+use crate::control_flow::ControlFlow;
+use crate::debugger::{self, Debugger, Pause, PauseReason};
 use crate::environment::Environment;
 use crate::error_reporter::LoxError;
 use crate::expr::{
-    AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GroupingExpr,
-    LiteralExpr, LogicalExpr, UnaryExpr, VariableExpr,
+    AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr,
+    LiteralExpr, LogicalExpr, MatchArm, MatchExpr, SetExpr, ThisExpr,
+    UnaryExpr, VariableExpr,
 };
+use crate::features::LanguageFeatures;
+use crate::file_system::{FileSystem, RealFileSystem};
+use crate::foreign::Foreign;
 use crate::function::LoxFunction;
+use crate::interrupt;
 use crate::literal::Literal;
-use crate::native_functions::Clock;
+use crate::namespace::{self, LoxNamespace};
+use crate::native_functions::{
+    Accept, ApproxEq, Args, Assert, At, AtExit, BigIntFn, CharCase, CharTest,
+    ClassName, Clock, Close, EnumFn, Exit, ExpectEq, Fetch, Fields, FileExists,
+    Floor, FormatTime, Freeze, Len, List, ListSet, MapGet, MapHas, MapKeys,
+    MapNew, MapRemove, MapSet, Max, Methods, Min, Now, OnInterrupt, Open,
+    ParseInt, ParseTime, Pop, Push, RangeFn, ReadFile, ReadLine, Recv, SendFn,
+    Slice, Sort, Sqrt, StatsFn, Sum, TcpConnect, TcpListen, TcpPort, Template,
+    TestFn, ToBin, ToBool, ToFixed, ToHex, ToPrecision, Write, WriteFile,
+};
+use crate::prelude;
+use crate::recorder::{RecordKind, Recorder};
+use crate::sandbox::SandboxPolicy;
+use crate::scanner;
+use crate::stats::{self, Stats};
 use crate::stmt::{
-    BlockStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt, ReturnStmt,
-    Stmt, StmtVisitor, VarStmt, WhileStmt,
+    BlockStmt, BreakStmt, ClassStmt, ContinueStmt, ExpressionStmt, ForStmt,
+    FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt, StmtVisitor, VarStmt,
+    WhileStmt,
 };
+use crate::symbol::Symbol;
+use crate::test_runner::TestResults;
 use crate::token::Token;
 use crate::token_type::TokenType;
+use crate::tracer::Tracer;
+use crate::value::LoxValue;
+use crate::value_printer;
+
+// Enough for deeply nested blocks and recursion without holding on to an
+// unbounded number of empty environments afterwards.
+const ENVIRONMENT_POOL_SIZE: usize = 64;
+
+// Each Lox call recurses on the Rust stack, so runaway recursion is cut off
+// here instead of overflowing it. Debug builds need more than the 8 MiB a
+// main thread usually gets for this many calls; see main.rs.
+pub const MAX_CALL_DEPTH: usize = 2048;
+
+thread_local! {
+    // How many call_guarded() calls are running on this thread. A panic in
+    // one is caught and reported by the caller, so the hook keeps quiet.
+    static GUARDED_CALLS: Cell<usize> = const { Cell::new(0) };
+}
+
+// Replaces the panic hook, once, with one that says nothing while the
+// panicking thread is in call_guarded() and otherwise does what the hook it
+// replaced did. The hook is shared by every thread, so swapping it in and
+// out around each call would race with the other threads running tests.
+fn quiet_guarded_panics() {
+    static REPLACED: Once = Once::new();
+    REPLACED.call_once(|| {
+        let unguarded = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if GUARDED_CALLS.with(Cell::get) == 0 {
+                unguarded(info);
+            }
+        }));
+    });
+}
+
+// Limits and checks applied while running, for embedders and the CLI to
+// adjust. The defaults are what plain Lox does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InterpreterOptions {
+    // How many Lox calls may be running at once. Going past MAX_CALL_DEPTH
+    // needs a bigger Rust stack than the CLI's.
+    pub max_call_depth: usize,
+    // Whether if/while/for conditions and the operands of '!', 'and' and
+    // 'or' must be booleans instead of being converted implicitly.
+    pub strict_booleans: bool,
+    // How many times any one loop may run its body before it is stopped as
+    // a runaway, or None to let loops run forever.
+    pub max_loop_iterations: Option<usize>,
+    // Which extensions to the book's semantics are allowed.
+    pub features: LanguageFeatures,
+    // What the natives may reach outside the interpreter.
+    pub sandbox: SandboxPolicy,
+    // How long each test() body may run before it fails, or None to let
+    // tests run forever.
+    pub test_timeout: Option<Duration>,
+    // Whether the natives in a namespace, like io.readFile, are globals as
+    // well, for scripts written before the namespaces existed. Turning it
+    // on defines them; turning it off again doesn't take them away.
+    pub global_natives: bool,
+}
+
+impl Default for InterpreterOptions {
+    fn default() -> Self {
+        InterpreterOptions {
+            max_call_depth: MAX_CALL_DEPTH,
+            strict_booleans: false,
+            max_loop_iterations: None,
+            features: LanguageFeatures::default(),
+            sandbox: SandboxPolicy::default(),
+            test_timeout: Some(Duration::from_secs(10)),
+            global_natives: false,
+        }
+    }
+}
 
 pub struct Interpreter {
+    natives: Vec<(String, Literal)>,
     globals: Rc<RefCell<Environment>>,
     environment: Rc<RefCell<Environment>>,
+    environment_pool: Vec<Rc<RefCell<Environment>>>,
+    recorder: Option<Recorder>,
+    executed_statements: usize,
+    calls: usize,
+    call_depth: usize,
+    // The closing paren of the call being made from Lox code, so errors a
+    // native raises about the call itself are reported on its line.
+    call_site: Option<Token>,
+    strings: usize,
+    options: InterpreterOptions,
+    // Callbacks registered with atExit(), in the order they were added.
+    exit_hooks: Vec<Literal>,
+    // Set by onInterrupt() and cleared once it has handled a Ctrl-C.
+    interrupt_handler: Option<Literal>,
+    // How many globals the natives and the prelude define, which come
+    // before any the scripts define.
+    builtin_globals: usize,
+    // Where the io natives read and write files.
+    file_system: Rc<dyn FileSystem>,
+    // Where print statements write.
+    output: Rc<RefCell<dyn io::Write>>,
+    // What natives opened, such as files, to close when the program ends.
+    // Values a script keeps in its globals are never dropped, so they would
+    // otherwise never be flushed.
+    open_handles: Vec<Weak<dyn Foreign>>,
+    // What the test() calls so far came to.
+    tests: TestResults,
+    // When the running call_guarded() call has to be done by, and the limit
+    // that was given, for the error.
+    deadline: Option<(Instant, Duration)>,
+    // Taken out while it handles a pause, so code run from the handler
+    // doesn't stop again.
+    debugger: Option<Debugger>,
+    tracer: Tracer,
 }
 
 impl ExprVisitor<Result<Literal, LoxError>> for Interpreter {
@@ -31,100 +170,140 @@ impl ExprVisitor<Result<Literal, LoxError>> for Interpreter {
         let right = self.evaluate(&expr.right)?;
 
         let operator = &expr.operator.token_type;
+        if *operator == TokenType::Is {
+            return self.is_instance_of(&left, &right, &expr.operator);
+        }
 
         match (left, right) {
-            (Literal::Number(left), Literal::Number(right)) => match operator {
-                TokenType::Minus => Ok(Literal::Number(left - right)),
-                TokenType::Slash => Ok(Literal::Number(left / right)),
-                TokenType::Star => Ok(Literal::Number(left * right)),
-                TokenType::Plus => Ok(Literal::Number(left + right)),
-                TokenType::Greater => Ok(Literal::Bool(left > right)),
-                TokenType::GreaterEqual => Ok(Literal::Bool(left >= right)),
-                TokenType::Less => Ok(Literal::Bool(left < right)),
-                TokenType::LessEqual => Ok(Literal::Bool(left <= right)),
-                TokenType::BangEqual => Ok(Literal::Bool(left != right)),
-                TokenType::EqualEqual => Ok(Literal::Bool(left == right)),
+            (left @ Literal::BigInt(_), right)
+            | (left, right @ Literal::BigInt(_)) => {
+                self.bigint_binary(left, right, &expr.operator)
+            }
+            (Literal::Integer(left), Literal::Integer(right)) => {
+                self.integer_binary(left, right, &expr.operator)
+            }
+            (Literal::Number(left), Literal::Number(right)) => {
+                self.number_binary(left, right, &expr.operator)
+            }
+            // An integer and a float: equal only if the float is that exact
+            // integer, otherwise worked out as floats.
+            (
+                left @ (Literal::Integer(_) | Literal::Number(_)),
+                right @ (Literal::Integer(_) | Literal::Number(_)),
+            ) => match (operator, left.as_number(), right.as_number()) {
+                (TokenType::EqualEqual, _, _) => {
+                    Ok(Literal::Bool(left == right))
+                }
+                (TokenType::BangEqual, _, _) => {
+                    Ok(Literal::Bool(left != right))
+                }
+                (_, Some(left), Some(right)) => {
+                    self.number_binary(left, right, &expr.operator)
+                }
                 _ => {
                     Err(self
                         .error(&expr.operator, "Invalid operation".to_string()))
                 }
             },
-            (Literal::Number(left), Literal::String(right)) => match operator {
-                TokenType::Plus => {
-                    Ok(Literal::String(format!("{left}{right}")))
+            (
+                left @ (Literal::Number(_) | Literal::Integer(_)),
+                right @ Literal::String(_),
+            )
+            | (
+                left @ Literal::String(_),
+                right @ (Literal::Number(_) | Literal::Integer(_)),
+            ) => match operator {
+                TokenType::Plus if self.coerces_strings() => {
+                    Ok(self.new_string(format!("{left}{right}")))
                 }
-                TokenType::BangEqual => Ok(Literal::Bool(true)),
-                TokenType::EqualEqual => Ok(Literal::Bool(false)),
-                _ => Err(self.error(
+                TokenType::Plus => Err(self.operand_error(
                     &expr.operator,
-                    "Operands must be numbers".to_string(),
+                    "Operands must be two numbers or two strings",
+                    &left,
+                    &right,
                 )),
-            },
-            (Literal::String(left), Literal::Number(right)) => match operator {
-                TokenType::Plus => {
-                    Ok(Literal::String(format!("{left}{right}")))
-                }
                 TokenType::BangEqual => Ok(Literal::Bool(true)),
                 TokenType::EqualEqual => Ok(Literal::Bool(false)),
-                _ => Err(self.error(
+                _ => Err(self.operand_error(
                     &expr.operator,
-                    "Operands must be numbers".to_string(),
+                    "Operands must be numbers",
+                    &left,
+                    &right,
                 )),
             },
-            (Literal::String(left), Literal::String(right)) => match operator {
-                TokenType::Plus => {
-                    Ok(Literal::String(format!("{left}{right}")))
+            (left @ Literal::String(_), right @ Literal::String(_)) => {
+                match operator {
+                    TokenType::Plus => {
+                        Ok(self.new_string(format!("{left}{right}")))
+                    }
+                    TokenType::BangEqual => Ok(Literal::Bool(left != right)),
+                    TokenType::EqualEqual => Ok(Literal::Bool(left == right)),
+                    _ => Err(self.operand_error(
+                        &expr.operator,
+                        "Operands must be numbers",
+                        &left,
+                        &right,
+                    )),
                 }
+            }
+            (left @ Literal::Bool(_), right @ Literal::Bool(_))
+            | (left @ Literal::String(_), right @ Literal::Bool(_))
+            | (left @ Literal::Bool(_), right @ Literal::String(_)) => {
+                match operator {
+                    TokenType::BangEqual => Ok(Literal::Bool(left != right)),
+                    TokenType::EqualEqual => Ok(Literal::Bool(left == right)),
+                    _ => Err(self.operand_error(
+                        &expr.operator,
+                        "Operands must be numbers",
+                        &left,
+                        &right,
+                    )),
+                }
+            }
+            (
+                left @ (Literal::Number(_) | Literal::Integer(_)),
+                right @ Literal::Bool(_),
+            )
+            | (
+                left @ Literal::Bool(_),
+                right @ (Literal::Number(_) | Literal::Integer(_)),
+            )
+            | (left @ Literal::Nil, right)
+            | (left, right @ Literal::Nil) => match operator {
                 TokenType::BangEqual => Ok(Literal::Bool(left != right)),
                 TokenType::EqualEqual => Ok(Literal::Bool(left == right)),
-                _ => Err(self.error(
-                    &expr.operator,
-                    "Operands must be numbers".to_string(),
-                )),
-            },
-            (Literal::Bool(left), Literal::Bool(right)) => match operator {
-                TokenType::BangEqual => Ok(Literal::Bool(left != right)),
-                TokenType::EqualEqual => Ok(Literal::Bool(left == right)),
-                _ => Err(self.error(
+                _ => Err(self.operand_error(
                     &expr.operator,
-                    "Operands must be numbers".to_string(),
+                    "Operands must be two numbers or two strings",
+                    &left,
+                    &right,
                 )),
             },
-            (Literal::String(_), Literal::Bool(_))
-            | (Literal::Bool(_), Literal::String(_)) => match operator {
-                TokenType::BangEqual => Ok(Literal::Bool(true)),
-                TokenType::EqualEqual => Ok(Literal::Bool(false)),
-                _ => Err(self.error(
-                    &expr.operator,
-                    "Operands must be numbers".to_string(),
-                )),
-            },
-            (Literal::Number(_), Literal::Bool(_))
-            | (Literal::Bool(_), Literal::Number(_)) => match operator {
-                TokenType::BangEqual => Ok(Literal::Bool(true)),
-                TokenType::EqualEqual => Ok(Literal::Bool(false)),
-                _ => Err(self.error(
-                    &expr.operator,
-                    "Operands must be two numbers or two strings.".to_string(),
-                )),
-            },
-            (Literal::Nil, Literal::Nil) => match operator {
-                TokenType::BangEqual => Ok(Literal::Bool(false)),
-                TokenType::EqualEqual => Ok(Literal::Bool(true)),
-                _ => Err(self.error(
-                    &expr.operator,
-                    "Operands must be two numbers or two strings.".to_string(),
-                )),
-            },
-            (Literal::Nil, _) | (_, Literal::Nil) => match operator {
-                TokenType::BangEqual => Ok(Literal::Bool(true)),
-                TokenType::EqualEqual => Ok(Literal::Bool(false)),
-                _ => Err(self.error(
-                    &expr.operator,
-                    "Operands must be two numbers or two strings.".to_string(),
-                )),
-            },
-            _ => unreachable!(),
+            (Literal::String(left), right @ Literal::Instance(_))
+                if *operator == TokenType::Plus && self.coerces_strings() =>
+            {
+                let right = self.stringify(&right)?;
+                Ok(self.new_string(format!("{left}{right}")))
+            }
+            (left @ Literal::Instance(_), Literal::String(right))
+                if *operator == TokenType::Plus && self.coerces_strings() =>
+            {
+                let left = self.stringify(&left)?;
+                Ok(self.new_string(format!("{left}{right}")))
+            }
+            (left, right) if *operator == TokenType::EqualEqual => {
+                Ok(Literal::Bool(left == right))
+            }
+            (left, right) if *operator == TokenType::BangEqual => {
+                Ok(Literal::Bool(left != right))
+            }
+            (left, right) => {
+                let message = format!(
+                    "Unsupported operand types for '{}'",
+                    expr.operator.lexeme
+                );
+                Err(self.operand_error(&expr.operator, &message, &left, &right))
+            }
         }
     }
 
@@ -133,29 +312,29 @@ impl ExprVisitor<Result<Literal, LoxError>> for Interpreter {
         expr: &CallExpr,
     ) -> Result<Literal, LoxError> {
         let callee = self.evaluate(&expr.callee)?;
+        if expr.optional && matches!(callee, Literal::Nil) {
+            return Ok(Literal::Nil);
+        }
         let mut arguments: Vec<Literal> = Vec::new();
         for argument in &expr.arguments {
             arguments.push(self.evaluate(argument)?);
         }
 
-        let function = match callee {
-            Literal::Function(f) => Some(f),
-            _ => None,
-        };
-
-        if let Some(function) = function {
-            if arguments.len() != function.arity() {
+        if let Some(function) = callee.as_callable() {
+            if let Some(mismatch) =
+                arity_mismatch(function.as_ref(), arguments.len())
+            {
                 return Err(LoxError::runtime_error(
                     expr.paren.to_owned(),
-                    format!(
-                        "Expected {} arguments but got {}.",
-                        function.arity(),
-                        arguments.len()
-                    ),
+                    format!("{mismatch}."),
                 ));
             }
 
-            Ok(function.call(self, arguments)?)
+            self.calls += 1;
+            let outer = self.call_site.replace(expr.paren.clone());
+            let result = function.call_at(self, arguments, expr);
+            self.call_site = outer;
+            Ok(result?)
         } else {
             Err(LoxError::runtime_error(
                 expr.paren.to_owned(),
@@ -172,10 +351,10 @@ impl ExprVisitor<Result<Literal, LoxError>> for Interpreter {
     }
 
     fn visit_literal_expr(
-        &self,
+        &mut self,
         expr: &LiteralExpr,
     ) -> Result<Literal, LoxError> {
-        Ok(expr.value.clone().unwrap())
+        Ok(expr.value.clone().unwrap_or(Literal::Nil))
     }
 
     fn visit_logical_exp(
@@ -184,17 +363,62 @@ impl ExprVisitor<Result<Literal, LoxError>> for Interpreter {
     ) -> Result<Literal, LoxError> {
         let left = self.evaluate(&expr.left)?;
 
-        if expr.operator.token_type == TokenType::Or {
-            if self.is_truthy(&left) {
-                return Ok(left);
+        match expr.operator.token_type {
+            TokenType::Or => {
+                if self.is_truthy(&left, &expr.operator)? {
+                    return Ok(left);
+                }
             }
-        } else {
-            if !self.is_truthy(&left) {
-                return Ok(left);
+            TokenType::QuestionQuestion => {
+                if !matches!(left, Literal::Nil) {
+                    return Ok(left);
+                }
+            }
+            _ => {
+                if !self.is_truthy(&left, &expr.operator)? {
+                    return Ok(left);
+                }
             }
         }
 
-        Ok(self.evaluate(&expr.right)?)
+        self.evaluate(&expr.right)
+    }
+
+    fn visit_match_expr(
+        &mut self,
+        expr: &MatchExpr,
+    ) -> Result<Literal, LoxError> {
+        let subject = self.evaluate(&expr.subject)?;
+        for arm in &expr.arms {
+            if let Some(pattern) = &arm.pattern {
+                let pattern = self.evaluate(pattern)?;
+                if !matches_pattern(&subject, &pattern) {
+                    continue;
+                }
+            }
+
+            // The binding gets a scope of its own, as the block the match
+            // statement desugars to would give it.
+            let value = match &arm.binding {
+                Some(binding) => {
+                    let scope = self.new_scope(self.environment.clone());
+                    let previous =
+                        std::mem::replace(&mut self.environment, scope);
+                    let value = self
+                        .define(binding, subject.clone())
+                        .and_then(|()| self.case_value(arm, &expr.keyword));
+                    let scope =
+                        std::mem::replace(&mut self.environment, previous);
+                    self.release_scope(scope);
+                    value
+                }
+                None => self.case_value(arm, &expr.keyword),
+            };
+            if let Some(value) = value? {
+                return Ok(value);
+            }
+        }
+        Ok(Literal::Nil)
     }
 
     fn visit_unary_expr(
@@ -206,21 +430,36 @@ impl ExprVisitor<Result<Literal, LoxError>> for Interpreter {
         match expr.operator.token_type {
             TokenType::Minus => match right {
                 Literal::Number(v) => Ok(Literal::Number(-v)),
-                _ => Err(self.error(
+                Literal::Integer(v) => Ok(match v.checked_neg() {
+                    Some(v) => Literal::Integer(v),
+                    None => Literal::Number(-(v as f64)),
+                }),
+                Literal::BigInt(v) => Ok(Literal::BigInt(Rc::new(-&*v))),
+                other => Err(self.error(
                     &expr.operator,
-                    "Operand must be a number.".to_string(),
+                    format!(
+                        "Operand must be a number, got {}.",
+                        value_printer::describe(&other)
+                    ),
                 )),
             },
-            TokenType::Bang => Ok(Literal::Bool(!self.is_truthy(&right))),
-            _ => unreachable!(),
+            TokenType::Bang => {
+                Ok(Literal::Bool(!self.is_truthy(&right, &expr.operator)?))
+            }
+            _ => Err(self
+                .error(&expr.operator, "Invalid unary operator.".to_string())),
         }
     }
 
     fn visit_variable_expr(
-        &self,
+        &mut self,
         expr: &VariableExpr,
     ) -> Result<Literal, LoxError> {
-        let value = self.environment.borrow().get(expr.name.clone())?;
+        let value = if expr.global.is_global() {
+            self.globals.borrow().get_cached(&expr.name, &expr.global)?
+        } else {
+            self.environment.borrow().get(&expr.name)?
+        };
         match value {
             Literal::NilImplicit => {
                 let error = self.error(
@@ -233,43 +472,113 @@ impl ExprVisitor<Result<Literal, LoxError>> for Interpreter {
         }
     }
 
+    fn visit_get_expr(&mut self, expr: &GetExpr) -> Result<Literal, LoxError> {
+        match self.evaluate(&expr.object)? {
+            Literal::Instance(instance) => instance.get(&expr.name),
+            Literal::Namespace(namespace) => namespace.get(&expr.name),
+            Literal::Nil
+                if expr.optional || expr.object.in_optional_chain() =>
+            {
+                Ok(Literal::Nil)
+            }
+            _ => Err(self.error(
+                &expr.name,
+                "Only instances and namespaces have properties.".to_string(),
+            )),
+        }
+    }
+
+    fn visit_set_expr(&mut self, expr: &SetExpr) -> Result<Literal, LoxError> {
+        let instance = match self.evaluate(&expr.object)? {
+            Literal::Instance(instance) => instance,
+            _ => {
+                return Err(self.error(
+                    &expr.name,
+                    "Only instances have fields.".to_string(),
+                ))
+            }
+        };
+
+        let value = self.evaluate(&expr.value)?;
+        instance.set(&expr.name, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_this_expr(
+        &mut self,
+        expr: &ThisExpr,
+    ) -> Result<Literal, LoxError> {
+        self.environment.borrow().get(&expr.keyword)
+    }
+
     fn visit_assignment_expr(
         &mut self,
         expr: &AssignExpr,
     ) -> Result<Literal, LoxError> {
         let value = self.evaluate(&expr.value)?;
+        let old = if self.tracer.traces(&expr.name.lexeme) {
+            self.environment.borrow().get(&expr.name).ok()
+        } else {
+            None
+        };
         self.environment
             .borrow_mut()
-            .assign(expr.name.clone(), value.clone())?;
+            .assign(&expr.name, value.clone())?;
+        self.record(RecordKind::Assign, &expr.name, &value);
+        if let Some(old) = old {
+            eprintln!(
+                "{}",
+                self.tracer.describe(&expr.name, Some(&old), &value)
+            );
+        }
         Ok(value)
     }
 }
 
-impl StmtVisitor<Result<(), LoxError>> for Interpreter {
+impl StmtVisitor<Result<(), ControlFlow>> for Interpreter {
     fn visit_expression_stmt(
         &mut self,
         stmt: &ExpressionStmt,
-    ) -> Result<(), LoxError> {
+    ) -> Result<(), ControlFlow> {
         self.evaluate(&stmt.expression)?;
         Ok(())
     }
 
+    fn visit_class_stmt(
+        &mut self,
+        stmt: &ClassStmt,
+    ) -> Result<(), ControlFlow> {
+        let methods = stmt
+            .methods
+            .iter()
+            .map(|method| {
+                let function =
+                    LoxFunction::new_method(method, self.environment.clone());
+                (method.name.lexeme.clone(), Rc::new(function))
+            })
+            .collect();
+
+        let class = Literal::Class(LoxClass::new(
+            stmt.name.lexeme.to_string(),
+            methods,
+        ));
+        Ok(self.define(&stmt.name, class)?)
+    }
+
     fn visit_function_stmt(
         &mut self,
         stmt: &FunctionStmt,
-    ) -> Result<(), LoxError> {
-        let function = LoxFunction::new(stmt, self.environment.clone());
-        self.environment.borrow_mut().define(
-            stmt.name.lexeme.to_owned(),
-            Literal::Function(Rc::new(function)),
-        );
-
-        Ok(())
+    ) -> Result<(), ControlFlow> {
+        let function = Literal::Function(Rc::new(LoxFunction::new(
+            stmt,
+            self.environment.clone(),
+        )));
+        Ok(self.define(&stmt.name, function)?)
     }
 
-    fn visit_if_stmt(&mut self, stmt: &IfStmt) -> Result<(), LoxError> {
+    fn visit_if_stmt(&mut self, stmt: &IfStmt) -> Result<(), ControlFlow> {
         let literal = self.evaluate(&stmt.condition)?;
-        if self.is_truthy(&literal) {
+        if self.is_truthy(&literal, &stmt.keyword)? {
             self.execute(&stmt.then_branch)
         } else if let Some(else_branch) = stmt.else_branch.clone() {
             self.execute(&else_branch)
@@ -278,115 +587,1284 @@ impl StmtVisitor<Result<(), LoxError>> for Interpreter {
         }
     }
 
-    fn visit_print_stmt(&mut self, stmt: &PrintStmt) -> Result<(), LoxError> {
+    fn visit_print_stmt(
+        &mut self,
+        stmt: &PrintStmt,
+    ) -> Result<(), ControlFlow> {
         let value = self.evaluate(&stmt.expression)?;
-        println!("{}", value);
+        let text = self.stringify(&value)?;
+        writeln!(self.output.borrow_mut(), "{text}").map_err(|e| {
+            LoxError::system_error(format!("Unable to print: {e}"))
+        })?;
         Ok(())
     }
 
-    fn visit_return_stmt(&mut self, stmt: &ReturnStmt) -> Result<(), LoxError> {
+    fn visit_return_stmt(
+        &mut self,
+        stmt: &ReturnStmt,
+    ) -> Result<(), ControlFlow> {
         if let Some(value) = &stmt.value {
-            Err(LoxError::return_value(self.evaluate(value)?))
+            Err(ControlFlow::Return(self.evaluate(value)?))
         } else {
-            Err(LoxError::return_value(Literal::Nil))
+            Err(ControlFlow::Return(Literal::Nil))
         }
     }
 
-    fn visit_var_stmt(&mut self, stmt: &VarStmt) -> Result<(), LoxError> {
+    fn visit_var_stmt(&mut self, stmt: &VarStmt) -> Result<(), ControlFlow> {
         let value = if let Some(initializer) = &stmt.initializer {
-            self.evaluate(&initializer)?
+            self.evaluate(initializer)?
         } else {
             Literal::NilImplicit
         };
 
-        self.environment
-            .borrow_mut()
-            .define(stmt.name.lexeme.clone(), value);
-        Ok(())
+        Ok(self.define(&stmt.name, value)?)
     }
 
-    fn visit_while_stmt(&mut self, stmt: &WhileStmt) -> Result<(), LoxError> {
-        let mut literal = self.evaluate(&stmt.condition)?;
-        while self.is_truthy(&literal) {
-            self.execute(&stmt.body)?;
-            literal = self.evaluate(&stmt.condition)?;
+    fn visit_while_stmt(
+        &mut self,
+        stmt: &WhileStmt,
+    ) -> Result<(), ControlFlow> {
+        if Self::is_constant(&stmt.condition) {
+            if self.loop_condition(&stmt.condition, &stmt.keyword)? {
+                let mut passes = 0;
+                while self.execute_loop_body(
+                    &stmt.body,
+                    &stmt.label,
+                    &stmt.keyword,
+                    &mut passes,
+                )? {}
+            }
+            return Ok(());
+        }
+
+        let mut passes = 0;
+        while self.loop_condition(&stmt.condition, &stmt.keyword)? {
+            if !self.execute_loop_body(
+                &stmt.body,
+                &stmt.label,
+                &stmt.keyword,
+                &mut passes,
+            )? {
+                break;
+            }
         }
         Ok(())
     }
 
-    fn visit_block_stmt(&mut self, stmt: &BlockStmt) -> Result<(), LoxError> {
-        self.execute_block(
-            &stmt.statements,
-            Environment::new_with_enclosing(self.environment.clone()),
-        )
+    fn visit_for_stmt(&mut self, stmt: &ForStmt) -> Result<(), ControlFlow> {
+        // The initializer's variable is scoped to the loop.
+        let scope = self.new_scope(self.environment.clone());
+        let previous = std::mem::replace(&mut self.environment, scope);
+
+        let result = self.execute_for_loop(stmt);
+
+        let scope = std::mem::replace(&mut self.environment, previous);
+        self.release_scope(scope);
+
+        result
+    }
+
+    fn visit_break_stmt(
+        &mut self,
+        stmt: &BreakStmt,
+    ) -> Result<(), ControlFlow> {
+        let label = stmt.label.as_ref().map(|l| l.lexeme.clone());
+        Err(ControlFlow::Break(label))
+    }
+
+    fn visit_continue_stmt(
+        &mut self,
+        stmt: &ContinueStmt,
+    ) -> Result<(), ControlFlow> {
+        let label = stmt.label.as_ref().map(|l| l.lexeme.clone());
+        Err(ControlFlow::Continue(label))
+    }
+
+    fn visit_block_stmt(
+        &mut self,
+        stmt: &BlockStmt,
+    ) -> Result<(), ControlFlow> {
+        let scope = self.new_scope(self.environment.clone());
+        self.execute_block(&stmt.statements, scope)
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Interpreter {
     pub fn new() -> Interpreter {
+        let natives = vec![
+            ("clock".to_string(), Literal::Function(Rc::new(Clock))),
+            ("now".to_string(), Literal::Function(Rc::new(Now))),
+            (
+                "formatTime".to_string(),
+                Literal::Function(Rc::new(FormatTime)),
+            ),
+            (
+                "parseTime".to_string(),
+                Literal::Function(Rc::new(ParseTime)),
+            ),
+            ("exit".to_string(), Literal::Function(Rc::new(Exit))),
+            ("atExit".to_string(), Literal::Function(Rc::new(AtExit))),
+            (
+                "onInterrupt".to_string(),
+                Literal::Function(Rc::new(OnInterrupt)),
+            ),
+            (
+                "args".to_string(),
+                Literal::Function(Rc::new(Args::new(Vec::new()))),
+            ),
+            ("stats".to_string(), Literal::Function(Rc::new(StatsFn))),
+            ("assert".to_string(), Literal::Function(Rc::new(Assert))),
+            ("test".to_string(), Literal::Function(Rc::new(TestFn))),
+            ("expectEq".to_string(), Literal::Function(Rc::new(ExpectEq))),
+            ("enum".to_string(), Literal::Function(Rc::new(EnumFn))),
+            ("bool".to_string(), Literal::Function(Rc::new(ToBool))),
+            (
+                "className".to_string(),
+                Literal::Function(Rc::new(ClassName)),
+            ),
+            ("fields".to_string(), Literal::Function(Rc::new(Fields))),
+            ("methods".to_string(), Literal::Function(Rc::new(Methods))),
+            ("freeze".to_string(), Literal::Function(Rc::new(Freeze))),
+            ("range".to_string(), Literal::Function(Rc::new(RangeFn))),
+            ("len".to_string(), Literal::Function(Rc::new(Len))),
+            ("at".to_string(), Literal::Function(Rc::new(At))),
+            ("slice".to_string(), Literal::Function(Rc::new(Slice))),
+            ("list".to_string(), Literal::Function(Rc::new(List))),
+            ("listSet".to_string(), Literal::Function(Rc::new(ListSet))),
+            ("push".to_string(), Literal::Function(Rc::new(Push))),
+            ("pop".to_string(), Literal::Function(Rc::new(Pop))),
+            ("mapNew".to_string(), Literal::Function(Rc::new(MapNew))),
+            ("mapGet".to_string(), Literal::Function(Rc::new(MapGet))),
+            ("mapSet".to_string(), Literal::Function(Rc::new(MapSet))),
+            ("mapHas".to_string(), Literal::Function(Rc::new(MapHas))),
+            (
+                "mapRemove".to_string(),
+                Literal::Function(Rc::new(MapRemove)),
+            ),
+            ("mapKeys".to_string(), Literal::Function(Rc::new(MapKeys))),
+            ("template".to_string(), Literal::Function(Rc::new(Template))),
+            ("sort".to_string(), Literal::Function(Rc::new(Sort))),
+            ("min".to_string(), Literal::Function(Rc::new(Min))),
+            ("max".to_string(), Literal::Function(Rc::new(Max))),
+            ("sum".to_string(), Literal::Function(Rc::new(Sum))),
+            ("approxEq".to_string(), Literal::Function(Rc::new(ApproxEq))),
+            ("bigint".to_string(), Literal::Function(Rc::new(BigIntFn))),
+            ("toHex".to_string(), Literal::Function(Rc::new(ToHex))),
+            ("toBin".to_string(), Literal::Function(Rc::new(ToBin))),
+            ("parseInt".to_string(), Literal::Function(Rc::new(ParseInt))),
+            (
+                "isDigit".to_string(),
+                Literal::Function(Rc::new(CharTest::DIGIT)),
+            ),
+            (
+                "isAlpha".to_string(),
+                Literal::Function(Rc::new(CharTest::ALPHA)),
+            ),
+            (
+                "isSpace".to_string(),
+                Literal::Function(Rc::new(CharTest::SPACE)),
+            ),
+            (
+                "toUpperChar".to_string(),
+                Literal::Function(Rc::new(CharCase::UPPER)),
+            ),
+            (
+                "toLowerChar".to_string(),
+                Literal::Function(Rc::new(CharCase::LOWER)),
+            ),
+            ("toFixed".to_string(), Literal::Function(Rc::new(ToFixed))),
+            (
+                "toPrecision".to_string(),
+                Literal::Function(Rc::new(ToPrecision)),
+            ),
+            ("math.sqrt".to_string(), Literal::Function(Rc::new(Sqrt))),
+            ("math.floor".to_string(), Literal::Function(Rc::new(Floor))),
+            ("open".to_string(), Literal::Function(Rc::new(Open))),
+            ("readLine".to_string(), Literal::Function(Rc::new(ReadLine))),
+            ("write".to_string(), Literal::Function(Rc::new(Write))),
+            ("close".to_string(), Literal::Function(Rc::new(Close))),
+            ("readFile".to_string(), Literal::Function(Rc::new(ReadFile))),
+            (
+                "writeFile".to_string(),
+                Literal::Function(Rc::new(WriteFile)),
+            ),
+            (
+                "fileExists".to_string(),
+                Literal::Function(Rc::new(FileExists)),
+            ),
+            (
+                "tcpConnect".to_string(),
+                Literal::Function(Rc::new(TcpConnect)),
+            ),
+            (
+                "tcpListen".to_string(),
+                Literal::Function(Rc::new(TcpListen)),
+            ),
+            ("tcpPort".to_string(), Literal::Function(Rc::new(TcpPort))),
+            ("accept".to_string(), Literal::Function(Rc::new(Accept))),
+            ("send".to_string(), Literal::Function(Rc::new(SendFn))),
+            ("recv".to_string(), Literal::Function(Rc::new(Recv))),
+            ("fetch".to_string(), Literal::Function(Rc::new(Fetch))),
+        ];
+
+        Interpreter::with_natives(natives)
+    }
+
+    fn with_natives(natives: Vec<(String, Literal)>) -> Interpreter {
         let globals = Rc::new(RefCell::new(Environment::new()));
-        globals
-            .borrow_mut()
-            .define("clock".to_string(), Literal::Function(Rc::new(Clock)));
+        for (name, value) in &natives {
+            install_native(&globals, name, value, false);
+        }
 
         let environment = globals.clone();
 
-        Interpreter {
+        let mut interpreter = Interpreter {
+            natives,
             globals,
             environment,
+            environment_pool: Vec::new(),
+            recorder: None,
+            debugger: None,
+            tracer: Tracer::default(),
+            executed_statements: 0,
+            calls: 0,
+            call_depth: 0,
+            call_site: None,
+            strings: 0,
+            options: InterpreterOptions::default(),
+            exit_hooks: Vec::new(),
+            interrupt_handler: None,
+            builtin_globals: 0,
+            file_system: Rc::new(RealFileSystem),
+            output: Rc::new(RefCell::new(io::stdout())),
+            open_handles: Vec::new(),
+            tests: TestResults::default(),
+            deadline: None,
+        };
+
+        // The prelude only declares functions, so it can't fail at runtime.
+        // Its statements aren't the script's and aren't counted.
+        if interpreter.interpret(&prelude::compile()).is_err() {
+            panic!("the bundled prelude failed to run");
         }
+        interpreter.executed_statements = 0;
+        interpreter.builtin_globals =
+            interpreter.globals.borrow().defined_count();
+        interpreter
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        for statement in statements {
+            match self.execute(statement) {
+                Ok(()) => {}
+                Err(ControlFlow::Error(error)) => return Err(error),
+                // The resolver rejects return, break and continue outside a
+                // function or loop, so these never reach the top level.
+                Err(_) => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    // Drops everything the scripts defined and starts over with only the
+    // natives. The options, file system and output are kept. Recording stays enabled
+    // if it was, but with an empty log.
+    pub fn reset(&mut self) {
+        // Functions stored in the globals keep their closure, the globals
+        // themselves, alive. Clearing the values breaks those Rc cycles.
+        self.globals.borrow_mut().clear();
+
+        let recording = self.recorder.is_some();
+        let options = self.options;
+        let file_system = self.file_system.clone();
+        let output = self.output.clone();
+        *self = Interpreter::with_natives(std::mem::take(&mut self.natives));
+        self.set_options(options);
+        self.file_system = file_system;
+        self.output = output;
+        if recording {
+            self.start_recording();
+        }
+    }
+
+    // Creates an interpreter sharing this one's natives, options, file
+    // system and output but with its own global environment, so snippets run there
+    // can't touch this session.
+    pub fn fork_isolated(&self) -> Interpreter {
+        let mut fork = Interpreter::with_natives(self.natives.clone());
+        fork.set_options(self.options);
+        fork.file_system = self.file_system.clone();
+        fork.output = self.output.clone();
+        fork
     }
 
-    pub fn interpret(
+    // Makes the globals defined so far, the natives and whatever the preludes
+    // defined, read-only. Scripts then get an error instead of silently
+    // replacing a standard library function.
+    pub fn freeze_globals(&mut self) {
+        self.globals.borrow_mut().freeze();
+    }
+
+    // In strict mode if/while/for conditions and the operands of '!', 'and'
+    // and 'or' must be booleans instead of being converted implicitly.
+    pub fn set_strict_booleans(&mut self, strict: bool) {
+        self.options.strict_booleans = strict;
+    }
+
+    pub fn options(&self) -> InterpreterOptions {
+        self.options
+    }
+
+    pub fn call_site(&self) -> Option<&Token> {
+        self.call_site.as_ref()
+    }
+
+    pub fn set_options(&mut self, options: InterpreterOptions) {
+        let define_globals =
+            options.global_natives && !self.options.global_natives;
+        self.options = options;
+        if define_globals {
+            let mut globals = self.globals.borrow_mut();
+            for (name, value) in &self.natives {
+                if namespace::builtin_namespace(name).is_some() {
+                    globals.define(Symbol::intern(name), value.clone());
+                }
+            }
+            // Still builtins, as hosts set options before running scripts.
+            self.builtin_globals = globals.defined_count();
+        }
+    }
+
+    pub fn test_results(&self) -> &TestResults {
+        &self.tests
+    }
+
+    pub fn record_test(&mut self, passed: bool) {
+        if passed {
+            self.tests.passed += 1;
+        } else {
+            self.tests.failed += 1;
+        }
+    }
+
+    pub fn file_system(&self) -> Rc<dyn FileSystem> {
+        self.file_system.clone()
+    }
+
+    pub fn set_file_system(&mut self, file_system: Rc<dyn FileSystem>) {
+        self.file_system = file_system;
+    }
+
+    pub fn output(&self) -> Rc<RefCell<dyn io::Write>> {
+        self.output.clone()
+    }
+
+    pub fn set_output(&mut self, output: Rc<RefCell<dyn io::Write>>) {
+        self.output = output;
+    }
+
+    // Whether '+' turns a number or instance into a string to join it with
+    // one.
+    fn coerces_strings(&self) -> bool {
+        self.options
+            .features
+            .contains(LanguageFeatures::STRING_COERCION)
+    }
+
+    pub fn set_script_args(&mut self, args: Vec<String>) {
+        self.define_native(
+            "args".to_string(),
+            Literal::Function(Rc::new(Args::new(args))),
+        );
+    }
+
+    // Lets a host seed a global before running a script, or replace one.
+    // Natives can't be overwritten, so scripts always see the built-ins.
+    pub fn set_global(
         &mut self,
-        statements: &Vec<Stmt>,
+        name: &str,
+        value: LoxValue,
     ) -> Result<(), LoxError> {
-        for statement in statements {
-            self.execute(statement)?;
+        if !scanner::is_identifier(name) {
+            let message = format!("'{name}' is not a valid identifier.");
+            return Err(LoxError::system_error(message));
+        }
+        if self.natives.iter().any(|(n, _)| n == name) {
+            let message = format!("Can't overwrite native function '{name}'.");
+            return Err(LoxError::system_error(message));
+        }
+        if self.is_namespace(name) {
+            let message = format!("Can't overwrite namespace '{name}'.");
+            return Err(LoxError::system_error(message));
+        }
+        if self.globals.borrow().is_frozen(Symbol::intern(name)) {
+            let message = format!("Can't overwrite builtin '{name}'.");
+            return Err(LoxError::system_error(message));
         }
 
+        self.globals
+            .borrow_mut()
+            .define(Symbol::intern(name), value.into_literal());
         Ok(())
     }
 
+    // Adds a host native to a namespace, creating the namespace if needed, so
+    // embedders can group theirs as game.spawn without taking global names
+    // scripts may want. Like natives, members can't be replaced.
+    pub fn set_namespaced(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        value: LoxValue,
+    ) -> Result<(), LoxError> {
+        for part in [namespace, name] {
+            if !scanner::is_identifier(part) {
+                let message = format!("'{part}' is not a valid identifier.");
+                return Err(LoxError::system_error(message));
+            }
+        }
+        // Natives in a namespace may be globals too, so a namespace can't
+        // take a native's name even when that isn't a global.
+        if self.natives.iter().any(|(n, _)| n == namespace) {
+            let message = format!("'{namespace}' is a native function.");
+            return Err(LoxError::system_error(message));
+        }
+        let message = match self
+            .globals
+            .borrow()
+            .get_defined(Symbol::intern(namespace))
+        {
+            Some(Literal::Namespace(existing)) => existing
+                .member(name)
+                .map(|_| format!("'{namespace}.{name}' is already defined.")),
+            Some(_) => Some(format!(
+                "'{namespace}' is already defined and isn't a namespace."
+            )),
+            None => None,
+        };
+        if let Some(message) = message {
+            return Err(LoxError::system_error(message));
+        }
+
+        self.define_native(format!("{namespace}.{name}"), value.into_literal());
+        Ok(())
+    }
+
+    // Whether `name` is a built-in namespace or one the host added to,
+    // whatever the global of that name holds now.
+    fn is_namespace(&self, name: &str) -> bool {
+        namespace::BUILTIN.iter().any(|&(n, _)| n == name)
+            || self.natives.iter().any(|(n, _)| {
+                n.split_once('.')
+                    .is_some_and(|(namespace, _)| namespace == name)
+            })
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LoxValue> {
+        self.globals
+            .borrow()
+            .get_defined(Symbol::intern(name))
+            .map(LoxValue::from)
+    }
+
+    // The globals the scripts defined, in the order they were first defined.
+    // A native or prelude function a script redefines keeps its place among
+    // the builtins, so it is left out too.
+    pub fn script_globals(&self) -> Vec<(Symbol, Literal)> {
+        let mut definitions = self.globals.borrow().definitions();
+        definitions.drain(..self.builtin_globals.min(definitions.len()));
+        definitions
+    }
+
+    // Calls a global function from the host, for callback-style embedding
+    // where the script defines hooks such as on_update() for Rust to invoke.
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        args: Vec<LoxValue>,
+    ) -> Result<LoxValue, LoxError> {
+        let value =
+            match self.globals.borrow().get_defined(Symbol::intern(name)) {
+                Some(value) => value,
+                None => {
+                    let message = format!("Undefined function '{name}'.");
+                    return Err(LoxError::system_error(message));
+                }
+            };
+
+        let function = match value.as_callable() {
+            Some(function) => function,
+            None => {
+                let message = format!(
+                    "Can't call '{name}', it is a {}.",
+                    value.type_name()
+                );
+                return Err(LoxError::system_error(message));
+            }
+        };
+
+        if let Some(mismatch) = arity_mismatch(function.as_ref(), args.len()) {
+            let message = format!("{mismatch} calling '{name}'.");
+            return Err(LoxError::system_error(message));
+        }
+
+        let arguments = args.into_iter().map(LoxValue::into_literal).collect();
+        self.calls += 1;
+        function.call(self, arguments).map(LoxValue::from)
+    }
+
+    // Calls a function or class value for a native, e.g. a callback it was
+    // passed. The callee may call natives again in turn; every Lox function
+    // in the chain counts towards MAX_CALL_DEPTH. Natives must not hold a
+    // borrow of a list or map across the call, since the callee may change
+    // it.
+    pub fn call_value(
+        &mut self,
+        callee: &Literal,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let function = match callee.as_callable() {
+            Some(function) => function,
+            None => {
+                let message = format!(
+                    "Can only call functions and classes, not a {}.",
+                    callee.type_name()
+                );
+                return Err(LoxError::system_error(message));
+            }
+        };
+
+        if let Some(mismatch) =
+            arity_mismatch(function.as_ref(), arguments.len())
+        {
+            return Err(LoxError::system_error(format!("{mismatch}.")));
+        }
+
+        self.calls += 1;
+        function.call(self, arguments)
+    }
+
+    // Calls `callee` with no arguments like call_value, but stops it with an
+    // error once it has run for `limit`, and catches a panic inside the
+    // interpreter rather than letting it end the program. A panic's message
+    // is the outer error rather than something the panic hook prints, and
+    // the interpreter is left in the scope it was in before the call.
+    pub fn call_guarded(
+        &mut self,
+        callee: &Literal,
+        limit: Option<Duration>,
+    ) -> Result<Result<Literal, LoxError>, String> {
+        let outer_deadline = self.deadline;
+        if let Some(limit) = limit {
+            let deadline = Instant::now() + limit;
+            if outer_deadline.is_none_or(|(outer, _)| deadline < outer) {
+                self.deadline = Some((deadline, limit));
+            }
+        }
+        let (environment, call_depth, call_site) = (
+            self.environment.clone(),
+            self.call_depth,
+            self.call_site.clone(),
+        );
+
+        quiet_guarded_panics();
+        GUARDED_CALLS.with(|calls| calls.set(calls.get() + 1));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.call_value(callee, Vec::new())
+        }));
+        GUARDED_CALLS.with(|calls| calls.set(calls.get() - 1));
+        self.deadline = outer_deadline;
+        result.map_err(|payload| {
+            self.environment = environment;
+            self.call_depth = call_depth;
+            self.call_site = call_site;
+            match payload.downcast::<String>() {
+                Ok(message) => *message,
+                Err(payload) => match payload.downcast::<&str>() {
+                    Ok(message) => message.to_string(),
+                    Err(_) => "unknown panic".to_string(),
+                },
+            }
+        })
+    }
+
+    pub fn add_exit_hook(&mut self, hook: Literal) {
+        self.exit_hooks.push(hook);
+    }
+
+    // The most recently registered atExit() callback, which runs first.
+    pub fn pop_exit_hook(&mut self) -> Option<Literal> {
+        self.exit_hooks.pop()
+    }
+
+    // Has `handle` closed when the program ends, if it is still around.
+    pub fn track_open(&mut self, handle: &Rc<dyn Foreign>) {
+        self.open_handles.retain(|h| h.strong_count() > 0);
+        self.open_handles.push(Rc::downgrade(handle));
+    }
+
+    // Closes everything natives opened that is still open, flushing what was
+    // written to it.
+    pub fn close_open_handles(&mut self) {
+        for handle in std::mem::take(&mut self.open_handles) {
+            if let Some(handle) = handle.upgrade() {
+                if let Err(e) = handle.close() {
+                    eprintln!("Unable to close {handle}: {e}");
+                }
+            }
+        }
+    }
+
+    pub fn set_interrupt_handler(&mut self, handler: Literal) {
+        self.interrupt_handler = Some(handler);
+    }
+
+    fn define_native(&mut self, name: String, value: Literal) {
+        match self.natives.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value.clone(),
+            None => self.natives.push((name.to_owned(), value.clone())),
+        }
+        install_native(
+            &self.globals,
+            &name,
+            &value,
+            self.options.global_natives,
+        );
+    }
+
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(Recorder::new());
+    }
+
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    pub fn record(&mut self, kind: RecordKind, name: &Token, value: &Literal) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(kind, self.executed_statements, name, value);
+        }
+        if self
+            .debugger
+            .as_ref()
+            .is_some_and(|d| d.watches(&name.lexeme))
+        {
+            self.check_watchpoint(name, value);
+        }
+        // Assignments are traced where they happen, which is the only place
+        // the old value is still there to show.
+        if matches!(kind, RecordKind::Define)
+            && self.tracer.traces(&name.lexeme)
+        {
+            eprintln!("{}", self.tracer.describe(name, None, value));
+        }
+    }
+
+    pub fn tracer(&mut self) -> &mut Tracer {
+        &mut self.tracer
+    }
+
+    pub fn set_debugger(&mut self, debugger: Option<Debugger>) {
+        self.debugger = debugger;
+    }
+
+    // Pauses if `stmt` starts a line with a breakpoint whose condition, if
+    // it has one, holds.
+    fn check_breakpoints(&mut self, stmt: &Stmt) {
+        let Some(token) = debugger::statement_token(stmt) else {
+            return;
+        };
+        let Some(mut debugger) = self.debugger.take() else {
+            return;
+        };
+        if let Some(breakpoint) = debugger.breakpoint_at(token) {
+            let stop = match breakpoint.condition() {
+                Some(condition) => condition
+                    .accept(self)
+                    .and_then(|value| self.is_truthy(&value, token))
+                    .unwrap_or(true),
+                None => true,
+            };
+            if stop {
+                let pause = Pause {
+                    reason: PauseReason::Breakpoint,
+                    token: token.clone(),
+                    environment: self.environment.clone(),
+                };
+                debugger.pause(self, &pause);
+            }
+        }
+        self.debugger = Some(debugger);
+    }
+
+    fn check_watchpoint(&mut self, name: &Token, value: &Literal) {
+        let Some(mut debugger) = self.debugger.take() else {
+            return;
+        };
+        if let Some(old) = debugger.changed(&name.lexeme, value) {
+            let pause = Pause {
+                reason: PauseReason::Watchpoint {
+                    old,
+                    new: value.clone(),
+                },
+                token: name.clone(),
+                environment: self.environment.clone(),
+            };
+            debugger.pause(self, &pause);
+        }
+        self.debugger = Some(debugger);
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            // Pooled environments are alive but don't belong to any scope.
+            environments: stats::live_environments()
+                - self.environment_pool.len(),
+            globals: self.globals.borrow().defined_count(),
+            strings: self.strings,
+            calls: self.calls,
+        }
+    }
+
+    pub fn interpret_expression(
+        &mut self,
+        expr: &Expr,
+    ) -> Result<Literal, LoxError> {
+        expr.accept(self)
+    }
+
+    // Evaluates `expr` as if it were written where `environment` is the
+    // innermost scope, like a debugger does for a frame stopped at a
+    // breakpoint. The interpreter's own scope is put back afterwards, even
+    // if evaluating fails, so the program carries on from where it was.
+    pub fn evaluate_in(
+        &mut self,
+        environment: Rc<RefCell<Environment>>,
+        expr: &Expr,
+    ) -> Result<Literal, LoxError> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let result = expr.accept(self);
+        self.environment = previous;
+        result
+    }
+
     pub fn globals(&self) -> Rc<RefCell<Environment>> {
         self.globals.clone()
     }
 
+    // The innermost scope of the code running now. Holding on to it keeps
+    // it from being reused once that code is done, so it can still be
+    // evaluated in later.
+    pub fn environment(&self) -> Rc<RefCell<Environment>> {
+        self.environment.clone()
+    }
+
     pub fn execute_block(
         &mut self,
-        statements: &Vec<Stmt>,
-        environment: Environment,
-    ) -> Result<(), LoxError> {
-        let previous = self.environment.clone();
-        self.environment = Rc::new(RefCell::new(environment));
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<(), ControlFlow> {
+        let previous = std::mem::replace(&mut self.environment, environment);
 
         let result = statements
             .iter()
-            .try_for_each(|statement| self.execute(statement.clone()));
+            .try_for_each(|statement| self.execute(statement));
 
-        self.environment = previous;
+        let scope = std::mem::replace(&mut self.environment, previous);
+        self.release_scope(scope);
 
         result
     }
 
+    // Runs the body of the function `name` like a block, failing instead once
+    // max_call_depth calls are already running. Gives the value of the return
+    // statement that ended it, if one did.
+    pub fn execute_call(
+        &mut self,
+        name: &Token,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Option<Literal>, LoxError> {
+        if self.call_depth >= self.options.max_call_depth {
+            self.release_scope(environment);
+            return Err(self.error(name, "Stack overflow.".to_string()));
+        }
+
+        self.call_depth += 1;
+        let result = self.execute_block(statements, environment);
+        self.call_depth -= 1;
+
+        match result {
+            Ok(()) => Ok(None),
+            Err(ControlFlow::Return(value)) => Ok(Some(value)),
+            Err(ControlFlow::Error(error)) => Err(error),
+            // Only allowed inside a loop, which is also inside the function.
+            Err(ControlFlow::Break(_) | ControlFlow::Continue(_)) => Ok(None),
+        }
+    }
+
+    // Hands out an empty environment for a block or call, reusing one from
+    // the pool when there is one instead of allocating.
+    pub fn new_scope(
+        &mut self,
+        enclosing: Rc<RefCell<Environment>>,
+    ) -> Rc<RefCell<Environment>> {
+        match self.environment_pool.pop() {
+            Some(scope) => {
+                scope.borrow_mut().reuse(Some(enclosing));
+                scope
+            }
+            None => Rc::new(RefCell::new(Environment::new_with_enclosing(
+                enclosing,
+            ))),
+        }
+    }
+
+    // Returns a finished scope to the pool unless something still refers to
+    // it, like a closure created inside it, which keeps it on the heap.
+    fn release_scope(&mut self, scope: Rc<RefCell<Environment>>) {
+        if Rc::strong_count(&scope) == 1
+            && self.environment_pool.len() < ENVIRONMENT_POOL_SIZE
+        {
+            scope.borrow_mut().reuse(None);
+            self.environment_pool.push(scope);
+        }
+    }
+
+    // The value of a match case whose pattern matched, or None if its guard
+    // doesn't hold.
+    fn case_value(
+        &mut self,
+        arm: &MatchArm,
+        keyword: &Token,
+    ) -> Result<Option<Literal>, LoxError> {
+        if let Some(guard) = &arm.guard {
+            let guard = self.evaluate(guard)?;
+            if !self.is_truthy(&guard, keyword)? {
+                return Ok(None);
+            }
+        }
+        self.evaluate(&arm.value).map(Some)
+    }
+
     fn evaluate(&mut self, expr: &Rc<Expr>) -> Result<Literal, LoxError> {
         expr.accept(self)
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> Result<(), LoxError> {
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), ControlFlow> {
+        self.executed_statements += 1;
+        if let Some((deadline, limit)) = self.deadline {
+            if Instant::now() >= deadline {
+                let message = format!(
+                    "Ran past its time limit of {}ms.",
+                    limit.as_millis()
+                );
+                return Err(LoxError::system_error(message).into());
+            }
+        }
+        // Only an interpreter waiting for Ctrl-C consumes it.
+        if self.interrupt_handler.is_some() && interrupt::take() {
+            if let Some(handler) = self.interrupt_handler.take() {
+                self.call_value(&handler, Vec::new())?;
+            }
+        }
+        if self.debugger.is_some() {
+            self.check_breakpoints(stmt);
+        }
         stmt.accept(self)
     }
 
-    fn is_truthy(&self, literal: &Literal) -> bool {
-        match literal {
-            Literal::Nil => false,
-            Literal::Bool(v) => *v,
-            _ => true,
+    fn new_string(&mut self, value: String) -> Literal {
+        self.strings += 1;
+        Literal::String(value.into())
+    }
+
+    // An unlabeled break or continue targets the innermost loop, a labeled
+    // one passes through loops until it reaches the loop with that label.
+    fn execute_for_loop(&mut self, stmt: &ForStmt) -> Result<(), ControlFlow> {
+        if let Some(initializer) = &stmt.initializer {
+            self.execute(initializer)?;
+        }
+
+        let mut condition = stmt.condition.as_ref();
+        if let Some(constant) = condition.filter(|c| Self::is_constant(c)) {
+            if !self.loop_condition(constant, &stmt.keyword)? {
+                return Ok(());
+            }
+            condition = None;
+        }
+
+        let mut passes = 0;
+        loop {
+            if let Some(condition) = condition {
+                if !self.loop_condition(condition, &stmt.keyword)? {
+                    break;
+                }
+            }
+
+            if !self.execute_loop_body(
+                &stmt.body,
+                &stmt.label,
+                &stmt.keyword,
+                &mut passes,
+            )? {
+                break;
+            }
+
+            // Also reached by `continue`, which must still run the increment.
+            if let Some(increment) = &stmt.increment {
+                self.evaluate(increment)?;
+            }
         }
+        Ok(())
+    }
+
+    // A literal condition like `while (true)` can't change between
+    // iterations, so loops check it once up front instead of on every pass.
+    fn is_constant(condition: &Expr) -> bool {
+        matches!(condition, Expr::Literal(_))
+    }
+
+    fn loop_condition(
+        &mut self,
+        condition: &Rc<Expr>,
+        keyword: &Token,
+    ) -> Result<bool, LoxError> {
+        let literal = self.evaluate(condition)?;
+        self.is_truthy(&literal, keyword)
+    }
+
+    // Runs one iteration, returning false when a break ends the loop.
+    // Jumps aimed at an enclosing loop's label keep propagating. `passes`
+    // counts the loop's iterations against max_loop_iterations.
+    fn execute_loop_body(
+        &mut self,
+        body: &Stmt,
+        loop_label: &Option<Token>,
+        keyword: &Token,
+        passes: &mut usize,
+    ) -> Result<bool, ControlFlow> {
+        if let Some(max) = self.options.max_loop_iterations {
+            if *passes >= max {
+                let message = format!("Loop ran more than {max} times.");
+                return Err(self.error(keyword, message).into());
+            }
+            *passes += 1;
+        }
+
+        match self.execute(body) {
+            Ok(()) => Ok(true),
+            Err(ControlFlow::Break(label))
+                if Self::is_jump_target(&label, loop_label) =>
+            {
+                Ok(false)
+            }
+            Err(ControlFlow::Continue(label))
+                if Self::is_jump_target(&label, loop_label) =>
+            {
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn is_jump_target(
+        label: &Option<Rc<str>>,
+        loop_label: &Option<Token>,
+    ) -> bool {
+        match (label, loop_label) {
+            (None, _) => true,
+            (Some(label), Some(loop_label)) => *label == loop_label.lexeme,
+            (Some(_), None) => false,
+        }
+    }
+
+    // What `name` holds where the code is running now, if it is defined and
+    // initialized. Nothing is reported when it isn't, so diagnostics about
+    // code that already ran can look values up freely.
+    pub fn variable_value(&self, name: &Token) -> Option<Literal> {
+        match self.environment.borrow().find(name.symbol)? {
+            Literal::NilImplicit => None,
+            value => Some(value),
+        }
+    }
+
+    // Instances whose class defines a toString() method are shown as what it
+    // returns, everything else as its Display form.
+    pub fn stringify(&mut self, value: &Literal) -> Result<String, LoxError> {
+        if let Literal::Instance(instance) = value {
+            if let Some(method) = instance.class().find_method("toString") {
+                if method.arity() == 0 {
+                    self.calls += 1;
+                    let method = method.bind(instance.clone());
+                    return Ok(method.call(self, Vec::new())?.to_string());
+                }
+            }
+        }
+
+        Ok(value.to_string())
+    }
+
+    fn number_binary(
+        &self,
+        left: f64,
+        right: f64,
+        operator: &Token,
+    ) -> Result<Literal, LoxError> {
+        match operator.token_type {
+            TokenType::Minus => Ok(Literal::Number(left - right)),
+            TokenType::Slash => Ok(Literal::Number(left / right)),
+            TokenType::Star => Ok(Literal::Number(left * right)),
+            TokenType::Percent => Ok(Literal::Number(left % right)),
+            TokenType::Plus => Ok(Literal::Number(left + right)),
+            TokenType::Greater => Ok(Literal::Bool(left > right)),
+            TokenType::GreaterEqual => Ok(Literal::Bool(left >= right)),
+            TokenType::Less => Ok(Literal::Bool(left < right)),
+            TokenType::LessEqual => Ok(Literal::Bool(left <= right)),
+            TokenType::BangEqual => Ok(Literal::Bool(left != right)),
+            TokenType::EqualEqual => Ok(Literal::Bool(left == right)),
+            _ => Err(self.error(operator, "Invalid operation".to_string())),
+        }
+    }
+
+    // Stays in integers while results are exact, and falls back to floats
+    // when they overflow or a division leaves a remainder.
+    fn integer_binary(
+        &self,
+        left: i64,
+        right: i64,
+        operator: &Token,
+    ) -> Result<Literal, LoxError> {
+        let exact = match operator.token_type {
+            TokenType::Minus => left.checked_sub(right),
+            TokenType::Star => left.checked_mul(right),
+            TokenType::Plus => left.checked_add(right),
+            TokenType::Slash => match left.checked_rem(right) {
+                Some(0) => left.checked_div(right),
+                _ => None,
+            },
+            TokenType::Percent => left.checked_rem(right),
+            TokenType::Greater => return Ok(Literal::Bool(left > right)),
+            TokenType::GreaterEqual => return Ok(Literal::Bool(left >= right)),
+            TokenType::Less => return Ok(Literal::Bool(left < right)),
+            TokenType::LessEqual => return Ok(Literal::Bool(left <= right)),
+            TokenType::BangEqual => return Ok(Literal::Bool(left != right)),
+            TokenType::EqualEqual => return Ok(Literal::Bool(left == right)),
+            _ => None,
+        };
+
+        match exact {
+            Some(result) => Ok(Literal::Integer(result)),
+            None => self.number_binary(left as f64, right as f64, operator),
+        }
+    }
+
+    // Bigints work with each other and with whole numbers, which are widened
+    // to bigints, and never turn into floats: `/` rounds toward zero and `%`
+    // has the sign of the left operand.
+    fn bigint_binary(
+        &mut self,
+        left: Literal,
+        right: Literal,
+        operator: &Token,
+    ) -> Result<Literal, LoxError> {
+        let (a, b) = match (left.as_bigint(), right.as_bigint()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => {
+                let has_string = matches!(left, Literal::String(_))
+                    || matches!(right, Literal::String(_));
+                return match operator.token_type {
+                    TokenType::Plus if has_string && self.coerces_strings() => {
+                        Ok(self.new_string(format!("{left}{right}")))
+                    }
+                    TokenType::EqualEqual => Ok(Literal::Bool(false)),
+                    TokenType::BangEqual => Ok(Literal::Bool(true)),
+                    _ => Err(self.operand_error(
+                        operator,
+                        "Operands must be bigints or whole numbers",
+                        &left,
+                        &right,
+                    )),
+                };
+            }
+        };
+
+        let value = match operator.token_type {
+            TokenType::Plus => &a + &b,
+            TokenType::Minus => &a - &b,
+            TokenType::Star => &a * &b,
+            TokenType::Slash | TokenType::Percent => {
+                let result = match operator.token_type {
+                    TokenType::Slash => a.checked_div(&b),
+                    _ => a.checked_rem(&b),
+                };
+                result.ok_or_else(|| {
+                    self.error(operator, "Division by zero.".to_string())
+                })?
+            }
+            TokenType::Greater => return Ok(Literal::Bool(a > b)),
+            TokenType::GreaterEqual => return Ok(Literal::Bool(a >= b)),
+            TokenType::Less => return Ok(Literal::Bool(a < b)),
+            TokenType::LessEqual => return Ok(Literal::Bool(a <= b)),
+            TokenType::BangEqual => return Ok(Literal::Bool(a != b)),
+            TokenType::EqualEqual => return Ok(Literal::Bool(a == b)),
+            _ => {
+                return Err(
+                    self.error(operator, "Invalid operation".to_string())
+                )
+            }
+        };
+        Ok(Literal::BigInt(Rc::new(value)))
+    }
+
+    // `value is Class` holds for instances of exactly that class, and is
+    // false for any other kind of value.
+    fn is_instance_of(
+        &self,
+        value: &Literal,
+        class: &Literal,
+        operator: &Token,
+    ) -> Result<Literal, LoxError> {
+        let class = match class {
+            Literal::Class(class) => class,
+            other => {
+                return Err(self.error(
+                    operator,
+                    format!(
+                        "Right operand of 'is' must be a class but got {}.",
+                        value_printer::describe(other)
+                    ),
+                ))
+            }
+        };
+
+        let is_instance = match value {
+            Literal::Instance(instance) => Rc::ptr_eq(instance.class(), class),
+            _ => false,
+        };
+        Ok(Literal::Bool(is_instance))
+    }
+
+    // Every condition goes through here, so strict mode only has to check
+    // the value's type before falling back to the regular truthiness rules.
+    fn is_truthy(
+        &self,
+        literal: &Literal,
+        token: &Token,
+    ) -> Result<bool, LoxError> {
+        if self.options.strict_booleans && !matches!(literal, Literal::Bool(_))
+        {
+            return Err(self.error(
+                token,
+                format!(
+                    "Condition must be a boolean but got {}. \
+                     Use bool() to convert it explicitly.",
+                    value_printer::describe(literal)
+                ),
+            ));
+        }
+
+        Ok(literal.is_truthy())
+    }
+
+    // Defines `name` in the current scope, unless it names a frozen global.
+    fn define(&mut self, name: &Token, value: Literal) -> Result<(), LoxError> {
+        if self.environment.borrow().is_frozen(name.symbol) {
+            let message = format!("Can't redefine builtin '{}'.", name.lexeme);
+            return Err(self.error(name, message));
+        }
+
+        self.environment
+            .borrow_mut()
+            .define(name.symbol, value.clone());
+        self.record(RecordKind::Define, name, &value);
+        Ok(())
+    }
+
+    // A binary operator's operands didn't fit it. The message names what
+    // they actually were, e.g. "Operands must be numbers, got string ("hi")
+    // and nil."
+    fn operand_error(
+        &self,
+        operator: &Token,
+        problem: &str,
+        left: &Literal,
+        right: &Literal,
+    ) -> LoxError {
+        let message = format!(
+            "{problem}, got {} and {}.",
+            value_printer::describe(left),
+            value_printer::describe(right)
+        );
+        self.error(operator, message)
     }
 
     fn error(&self, token: &Token, message: String) -> LoxError {
-        let error = LoxError::runtime_error(token.clone(), message);
-        error
+        LoxError::runtime_error(token.clone(), message)
+    }
+}
+
+// Defines a native in `globals`. A name such as "math.sqrt" makes it only a
+// member of that namespace, as are natives listed in namespace::BUILTIN
+// unless `global_natives` makes them globals too.
+fn install_native(
+    globals: &RefCell<Environment>,
+    name: &str,
+    value: &Literal,
+    global_natives: bool,
+) {
+    let (namespace, member) = match name.split_once('.') {
+        Some((namespace, member)) => (Some(namespace), member),
+        None => {
+            if namespace::is_global_native(name, global_natives) {
+                globals
+                    .borrow_mut()
+                    .define(Symbol::intern(name), value.clone());
+            }
+            (namespace::builtin_namespace(name), name)
+        }
+    };
+
+    if let Some(namespace) = namespace {
+        namespace_global(globals, namespace).define(member, value.clone());
+    }
+}
+
+// The namespace defined as the global `name`, which is created if missing.
+fn namespace_global(
+    globals: &RefCell<Environment>,
+    name: &str,
+) -> Rc<LoxNamespace> {
+    let symbol = Symbol::intern(name);
+    if let Some(Literal::Namespace(namespace)) =
+        globals.borrow().get_defined(symbol)
+    {
+        return namespace;
+    }
+
+    let namespace = Rc::new(LoxNamespace::new(name));
+    globals
+        .borrow_mut()
+        .define(symbol, Literal::Namespace(namespace.clone()));
+    namespace
+}
+
+// Whether a match subject equals a case's pattern, the way `==` has it.
+fn matches_pattern(subject: &Literal, pattern: &Literal) -> bool {
+    match (subject, pattern) {
+        (Literal::BigInt(_), _) | (_, Literal::BigInt(_)) => {
+            match (subject.as_bigint(), pattern.as_bigint()) {
+                (Some(subject), Some(pattern)) => subject == pattern,
+                _ => false,
+            }
+        }
+        _ => subject == pattern,
     }
 }
@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Ctrl-C for scripts that asked for it with onInterrupt(). The signal
+// handler only sets a flag, which the interpreter checks between statements,
+// and puts the default action back, so a second Ctrl-C before the handler
+// is registered again still kills the process.
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::c_int;
+
+    pub const SIGINT: c_int = 2;
+    pub const SIG_DFL: usize = 0;
+
+    extern "C" {
+        pub fn signal(signum: c_int, handler: usize) -> usize;
+        #[cfg(test)]
+        pub fn raise(signum: c_int) -> c_int;
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signum: std::os::raw::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    // signal() is async-signal-safe, so it may be called from here.
+    unsafe {
+        sys::signal(sys::SIGINT, sys::SIG_DFL);
+    }
+}
+
+// Catches the next Ctrl-C instead of letting it end the process. Elsewhere
+// than on Unix this does nothing and Ctrl-C keeps its default action.
+pub fn arm() {
+    #[cfg(unix)]
+    unsafe {
+        sys::signal(sys::SIGINT, on_interrupt as *const () as usize);
+    }
+}
+
+// Whether a caught Ctrl-C is waiting to be handled, clearing it.
+pub fn take() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+// Sends this process a Ctrl-C, for tests. It must be armed first or the
+// process is killed.
+#[cfg(all(test, unix))]
+pub fn raise() {
+    unsafe {
+        sys::raise(sys::SIGINT);
+    }
+}
@@ -0,0 +1,57 @@
+pub mod analysis;
+pub mod ast;
+pub mod ast_printer;
+pub mod bigint;
+pub mod call_graph;
+pub mod callable;
+pub mod class;
+pub mod control_flow;
+pub mod debugger;
+pub mod disassembler;
+pub mod enumeration;
+pub mod environment;
+pub mod error_reporter;
+pub mod expr;
+pub mod features;
+pub mod file_system;
+pub mod foreign;
+pub mod function;
+pub mod grammar;
+pub mod http;
+pub mod instance;
+pub mod interpreter;
+pub mod interrupt;
+pub mod literal;
+pub mod lox;
+pub mod loxc;
+pub mod map;
+pub mod metrics;
+pub mod namespace;
+pub mod native_functions;
+pub mod optimizer;
+pub mod parser;
+pub mod prelude;
+pub mod range;
+pub mod recorder;
+pub mod rename;
+pub mod resolver;
+pub mod sandbox;
+pub mod scanner;
+pub mod snapshot;
+pub mod source;
+pub mod source_printer;
+pub mod stats;
+pub mod stmt;
+pub mod suggest;
+pub mod symbol;
+pub mod template;
+pub mod test_runner;
+pub mod time;
+pub mod token;
+pub mod token_type;
+pub mod tracer;
+pub mod type_checker;
+pub mod value;
+pub mod value_printer;
+pub mod walk;
+pub mod watch;
@@ -1,22 +1,210 @@
+use std::cell::RefCell;
 use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
+use crate::bigint::BigInt;
 use crate::callable::LoxCallable;
+use crate::class::LoxClass;
+use crate::foreign::Foreign;
+use crate::instance::LoxInstance;
+use crate::map::LoxMap;
+use crate::namespace::LoxNamespace;
+use crate::range::LoxRange;
+use crate::value_printer::ValuePrinter;
 
 #[derive(Clone, Debug)]
 pub enum Literal {
     Number(f64),
-    String(String),
+    // Numbers written and computed without a fraction. Lox has one number
+    // type, so an integer acts exactly like the same value as a Number; it
+    // only keeps arithmetic exact past 2^53 and becomes a Number when a
+    // result overflows or doesn't divide evenly.
+    Integer(i64),
+    // Integers of any size, written 123n or made by bigint(). Unlike
+    // Integer, a type of its own: arithmetic with one never turns into
+    // floats.
+    BigInt(Rc<BigInt>),
+    // Shared so that evaluating a string literal or copying a string value
+    // only bumps a reference count instead of copying the text.
+    String(Rc<str>),
     Bool(bool),
     Function(Rc<dyn LoxCallable>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<LoxInstance>),
+    List(Rc<RefCell<Vec<Literal>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    Range(Rc<LoxRange>),
+    Namespace(Rc<LoxNamespace>),
+    Foreign(Rc<dyn Foreign>),
     Nil,
     NilImplicit,
 }
 
+impl Literal {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Literal::Number(_) | Literal::Integer(_) => "number",
+            Literal::BigInt(_) => "bigint",
+            Literal::String(_) => "string",
+            Literal::Bool(_) => "boolean",
+            Literal::Function(_) => "function",
+            Literal::Class(_) => "class",
+            Literal::Instance(_) => "instance",
+            Literal::List(_) => "list",
+            Literal::Map(_) => "map",
+            Literal::Range(_) => "range",
+            Literal::Namespace(_) => "namespace",
+            Literal::Foreign(v) => v.type_name(),
+            Literal::Nil | Literal::NilImplicit => "nil",
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Literal::Number(v) => Some(*v),
+            Literal::Integer(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    // The number as an i64, if it is a whole number in range.
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Literal::Number(v) => whole_number(*v),
+            Literal::Integer(v) => Some(*v),
+            Literal::BigInt(v) => v.to_i64(),
+            _ => None,
+        }
+    }
+
+    // The value as a bigint, if it is one or a whole number an i64 holds.
+    pub fn as_bigint(&self) -> Option<BigInt> {
+        match self {
+            Literal::BigInt(v) => Some((**v).clone()),
+            other => other.as_integer().map(BigInt::from),
+        }
+    }
+
+    pub fn new_list(items: Vec<Literal>) -> Literal {
+        Literal::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn new_map() -> Literal {
+        Literal::Map(Rc::new(RefCell::new(LoxMap::new())))
+    }
+
+    // Functions and classes can be called, calling a class constructs it.
+    pub fn as_callable(&self) -> Option<Rc<dyn LoxCallable>> {
+        match self {
+            Literal::Function(f) => Some(f.clone()),
+            Literal::Class(c) => Some(c.clone()),
+            _ => None,
+        }
+    }
+
+    // Lox truthiness: only false and nil are falsey.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Literal::Nil => false,
+            Literal::Bool(v) => *v,
+            _ => true,
+        }
+    }
+}
+
+// The Integer equal to `v`, if it is a whole number in range.
+fn whole_number(v: f64) -> Option<i64> {
+    // -2^63 is exact as a float, 2^63 is the first value past i64::MAX.
+    let in_range = (-9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0)
+        .contains(&v);
+    if in_range && v.fract() == 0.0 {
+        Some(v as i64)
+    } else {
+        None
+    }
+}
+
+// Numbers, bigints, strings, booleans and ranges compare by value. Functions,
+// classes, instances, lists, maps, namespaces and foreign values are
+// references and only equal themselves.
+impl PartialEq for Literal {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Literal::Number(a), Literal::Number(b)) => a == b,
+            (Literal::Integer(a), Literal::Integer(b)) => a == b,
+            (Literal::Integer(a), Literal::Number(b))
+            | (Literal::Number(b), Literal::Integer(a)) => {
+                whole_number(*b) == Some(*a)
+            }
+            (Literal::BigInt(a), Literal::BigInt(b)) => a == b,
+            (Literal::String(a), Literal::String(b)) => a == b,
+            (Literal::Bool(a), Literal::Bool(b)) => a == b,
+            (Literal::Function(a), Literal::Function(b)) => {
+                std::ptr::addr_eq(Rc::as_ptr(a), Rc::as_ptr(b))
+            }
+            (Literal::Class(a), Literal::Class(b)) => Rc::ptr_eq(a, b),
+            (Literal::Instance(a), Literal::Instance(b)) => Rc::ptr_eq(a, b),
+            (Literal::List(a), Literal::List(b)) => Rc::ptr_eq(a, b),
+            (Literal::Map(a), Literal::Map(b)) => Rc::ptr_eq(a, b),
+            (Literal::Range(a), Literal::Range(b)) => a == b,
+            (Literal::Namespace(a), Literal::Namespace(b)) => Rc::ptr_eq(a, b),
+            (Literal::Foreign(a), Literal::Foreign(b)) => {
+                std::ptr::addr_eq(Rc::as_ptr(a), Rc::as_ptr(b))
+            }
+            (
+                Literal::Nil | Literal::NilImplicit,
+                Literal::Nil | Literal::NilImplicit,
+            ) => true,
+            _ => false,
+        }
+    }
+}
+
+// Maps need keys to be Eq. NaN is the one value not equal to itself, which
+// is why the map natives refuse it as a key.
+impl Eq for Literal {}
+
+// Agrees with PartialEq: references hash their address, so two instances with
+// the same fields are still different keys.
+impl Hash for Literal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_name().hash(state);
+        match self {
+            // Whole numbers hash like the Integer they equal, which also
+            // covers 0 and -0.
+            Literal::Number(v) => match whole_number(*v) {
+                Some(v) => v.hash(state),
+                None => v.to_bits().hash(state),
+            },
+            Literal::Integer(v) => v.hash(state),
+            Literal::BigInt(v) => v.hash(state),
+            Literal::String(v) => v.hash(state),
+            Literal::Bool(v) => v.hash(state),
+            Literal::Function(v) => (Rc::as_ptr(v) as *const ()).hash(state),
+            Literal::Class(v) => Rc::as_ptr(v).hash(state),
+            Literal::Instance(v) => Rc::as_ptr(v).hash(state),
+            Literal::List(v) => Rc::as_ptr(v).hash(state),
+            Literal::Map(v) => Rc::as_ptr(v).hash(state),
+            // Equal ranges hold the same numbers, so hash those up to the
+            // first two.
+            Literal::Range(v) => {
+                v.len().hash(state);
+                v.iter().take(2).for_each(|n| n.hash(state));
+            }
+            Literal::Namespace(v) => Rc::as_ptr(v).hash(state),
+            Literal::Foreign(v) => (Rc::as_ptr(v) as *const ()).hash(state),
+            Literal::Nil | Literal::NilImplicit => {}
+        }
+    }
+}
+
 impl Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Literal::Number(v) => write!(f, "{v}"),
+            Literal::Integer(v) => write!(f, "{v}"),
+            Literal::BigInt(v) => write!(f, "{v}"),
             Literal::String(v) => write!(f, "{v}"),
             Literal::Bool(v) => {
                 if *v {
@@ -28,6 +216,16 @@ impl Display for Literal {
             Literal::Nil => write!(f, "nil"),
             Literal::NilImplicit => write!(f, "nil_implicit"),
             Literal::Function(v) => write!(f, "{v}"),
+            Literal::Class(v) => write!(f, "{v}"),
+            Literal::Instance(v) => write!(f, "{v}"),
+            Literal::Range(v) => write!(f, "{v}"),
+            Literal::Namespace(v) => write!(f, "{v}"),
+            Literal::Foreign(v) => write!(f, "{v}"),
+            // Strings inside lists and maps are quoted, so ["a, b"] isn't
+            // ["a", "b"].
+            Literal::List(_) | Literal::Map(_) => {
+                write!(f, "{}", ValuePrinter::display().print(self))
+            }
         }
     }
 }
@@ -1,83 +1,3042 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, BufReader, Read};
-use std::path::Path;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::rc::Rc;
 
-use crate::interpreter::Interpreter;
-use crate::parser::Parser;
+use crate::ast_printer::AstPrinter;
+use crate::call_graph::{self, GraphFormat};
+use crate::debugger::Debugger;
+use crate::disassembler::Disassembler;
+use crate::environment::Environment;
+use crate::error_reporter::{self, report_line, report_warning, LoxError};
+use crate::expr::Expr;
+use crate::features::LanguageFeatures;
+use crate::file_system::FileSystem;
+use crate::interpreter::{Interpreter, InterpreterOptions};
+use crate::literal::Literal;
+use crate::loxc;
+use crate::metrics::{self, Thresholds};
+use crate::optimizer;
+use crate::parser::{Parser, ParserOptions};
+use crate::rename;
+use crate::resolver::{Mode, Resolver, Usage};
+use crate::sandbox::SandboxPolicy;
 use crate::scanner::Scanner;
+use crate::snapshot;
+use crate::source::Source;
+use crate::source_printer::SourcePrinter;
+use crate::stmt::Stmt;
+use crate::test_runner::TestResults;
+use crate::token::{Token, REPL};
+use crate::token_type::TokenType;
+use crate::type_checker::TypeChecker;
+use crate::value::LoxValue;
+use crate::value_printer::ValuePrinter;
 
 pub struct Lox {
+    interpreter: Interpreter,
+    record_path: Option<PathBuf>,
+    preludes: Vec<PathBuf>,
+    // Where the compiled preludes are kept between runs, if anywhere.
+    prelude_snapshot: Option<PathBuf>,
+    parser_options: ParserOptions,
+    print_stats: bool,
+    dump_scopes: bool,
+    mode: Mode,
+    optimize: bool,
+    // Whether calls to tiny functions are replaced by their bodies.
+    inline: bool,
+    // What the code compiled so far uses, kept while optimizing.
+    usage: Usage,
+    typed: bool,
+    // Whether the globals are frozen once the preludes have run.
+    freeze_globals: bool,
     had_error: bool,
     had_runtime_error: bool,
+    // Set once a script calls exit() or a file can't be read, which ends the
+    // run with this status.
+    exit_code: Option<i32>,
+    // The text of everything compiled, by name, so diagnostics can be
+    // traced back to it. Each REPL line replaces the one before.
+    sources: HashMap<Rc<str>, Rc<Source>>,
+    // The lines typed at the prompt that declared something and ran
+    // without an error, which :save writes out for :restore to replay.
+    session: Vec<String>,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Lox {
     pub fn new() -> Lox {
         Lox {
+            interpreter: Interpreter::new(),
+            record_path: None,
+            preludes: Vec::new(),
+            prelude_snapshot: None,
+            parser_options: ParserOptions::default(),
+            print_stats: false,
+            dump_scopes: false,
+            mode: Mode::Script,
+            optimize: false,
+            inline: false,
+            usage: Usage::default(),
+            typed: false,
+            freeze_globals: false,
             had_error: false,
             had_runtime_error: false,
+            exit_code: None,
+            sources: HashMap::new(),
+            session: Vec::new(),
+        }
+    }
+
+    pub fn set_script_args(&mut self, args: Vec<String>) {
+        self.interpreter.set_script_args(args);
+    }
+
+    pub fn set_global(
+        &mut self,
+        name: &str,
+        value: impl Into<LoxValue>,
+    ) -> Result<(), LoxError> {
+        self.interpreter.set_global(name, value.into())
+    }
+
+    pub fn set_namespaced(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        value: impl Into<LoxValue>,
+    ) -> Result<(), LoxError> {
+        self.interpreter
+            .set_namespaced(namespace, name, value.into())
+    }
+
+    // The source compiled under `name`, such as a script's path, to quote
+    // the line an error's location() points at.
+    pub fn source(&self, name: &str) -> Option<Rc<Source>> {
+        self.sources.get(name).cloned()
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LoxValue> {
+        self.interpreter.get_global(name)
+    }
+
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        args: Vec<LoxValue>,
+    ) -> Result<LoxValue, LoxError> {
+        self.interpreter.call_function(name, args)
+    }
+
+    // Queues a Lox file to run before the script or prompt, in the order
+    // added, so the helpers it defines are available as globals.
+    pub fn add_prelude(&mut self, path: PathBuf) {
+        self.preludes.push(path);
+    }
+
+    // Keeps the preludes compiled in `path` so later runs with the same
+    // preludes skip scanning, parsing, resolving and type checking them. The
+    // snapshot is rewritten whenever a prelude or the language features
+    // change.
+    pub fn set_prelude_snapshot(&mut self, path: PathBuf) {
+        self.prelude_snapshot = Some(path);
+    }
+
+    // Runs `source` into the globals. Any error is followed by a line naming
+    // the prelude, so it isn't mistaken for a problem in the script itself.
+    pub fn run_prelude(
+        &mut self,
+        name: &str,
+        source: &str,
+    ) -> Result<(), LoxError> {
+        self.run_named(name, source);
+
+        if self.had_error || self.had_runtime_error {
+            return Err(prelude_error(name));
+        }
+
+        Ok(())
+    }
+
+    // Scripts, preludes and the io natives all go through `file_system`,
+    // which is the real one unless this is changed.
+    pub fn set_file_system(&mut self, file_system: Rc<dyn FileSystem>) {
+        self.interpreter.set_file_system(file_system);
+    }
+
+    // Where print statements and the test summary go, stdout unless this is
+    // changed.
+    pub fn set_output(&mut self, output: Rc<RefCell<dyn Write>>) {
+        self.interpreter.set_output(output);
+    }
+
+    // Where errors and warnings go, stderr unless this is changed. They are
+    // reported as they are made, so this covers everything run on the
+    // calling thread, not only this Lox.
+    pub fn set_error_output(&mut self, output: Rc<RefCell<dyn Write>>) {
+        error_reporter::set_error_output(output);
+    }
+
+    // What the test() calls run so far came to.
+    pub fn test_results(&self) -> &TestResults {
+        self.interpreter.test_results()
+    }
+
+    fn read_file<P>(&mut self, path: &P) -> Option<Vec<u8>>
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        let path = path.as_ref();
+        match self.interpreter.file_system().read(path) {
+            Ok(buffer) => Some(buffer),
+            Err(e) => {
+                report_line(format!("Unable to read {}: {e}", path.display()));
+                self.exit_code = Some(66);
+                None
+            }
+        }
+    }
+
+    fn read_source<P>(&mut self, path: &P) -> Option<String>
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        let buffer = self.read_file(path)?;
+        self.source_text(path, buffer)
+    }
+
+    fn source_text<P>(&mut self, path: &P, buffer: Vec<u8>) -> Option<String>
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        match String::from_utf8(buffer) {
+            Ok(source) => Some(source),
+            Err(_) => {
+                let path = path.as_ref().display();
+                report_line(format!("{path} is not valid UTF-8."));
+                self.had_error = true;
+                None
+            }
+        }
+    }
+
+    // Runs the preludes, shutting down and returning false if one of them
+    // can't be read, fails or exits.
+    fn load_preludes(&mut self) -> bool {
+        if let Some(snapshot) = self.prelude_snapshot.take() {
+            if !self.load_preludes_through(&snapshot) {
+                self.shut_down();
+                return false;
+            }
+        }
+        for path in std::mem::take(&mut self.preludes) {
+            let ran = match self.read_source(&path) {
+                Some(source) => self
+                    .run_prelude(&path.display().to_string(), &source)
+                    .is_ok(),
+                None => false,
+            };
+            if !ran || self.exit_code.is_some() {
+                self.shut_down();
+                return false;
+            }
+        }
+
+        self.freeze_builtins();
+        true
+    }
+
+    // Runs the preludes from the snapshot at `path` if it was made from the
+    // same ones, and otherwise from source, writing a new snapshot of them.
+    // Returns false if one of them can't be read, fails or exits.
+    fn load_preludes_through(&mut self, path: &Path) -> bool {
+        let preludes: Option<Vec<(String, String)>> =
+            std::mem::take(&mut self.preludes)
+                .iter()
+                .map(|p| Some((p.display().to_string(), self.read_source(p)?)))
+                .collect();
+        let Some(preludes) = preludes else {
+            return false;
+        };
+        // Everything that changes how the preludes compile or what compiling
+        // them checks, as a loaded snapshot is neither resolved nor checked.
+        let settings = format!(
+            "{:?} {:?} typed={} optimize={} inline={} dump_scopes={}",
+            self.parser_options,
+            self.mode,
+            self.typed,
+            self.optimize,
+            self.inline,
+            self.dump_scopes
+        );
+        let key = snapshot::key(&preludes, &settings);
+        let file_system = self.interpreter.file_system();
+
+        let cached = file_system
+            .read(path)
+            .ok()
+            .and_then(|bytes| snapshot::read(&bytes, key));
+        if let Some(compiled) = cached {
+            for ((name, _), statements) in preludes.iter().zip(&compiled) {
+                self.note_usage(statements);
+                self.execute(statements);
+                if !self.end_prelude(name) {
+                    return false;
+                }
+            }
+            return true;
+        }
+
+        let mut compiled = Vec::new();
+        for (name, source) in &preludes {
+            let statements = self.compile(name, source).unwrap_or_default();
+            self.execute(&statements);
+            if !self.end_prelude(name) {
+                return false;
+            }
+            compiled.push(statements);
+        }
+        let units: Vec<(&str, &[Stmt])> = preludes
+            .iter()
+            .zip(&compiled)
+            .map(|((name, _), statements)| {
+                (name.as_str(), statements.as_slice())
+            })
+            .collect();
+        if let Err(e) = file_system.write(path, &snapshot::write(key, &units)) {
+            eprintln!("Unable to write {}: {e}", path.display());
+        }
+        true
+    }
+
+    // Whether the run goes on after the prelude `name`, which it doesn't if
+    // the prelude failed or exited.
+    fn end_prelude(&mut self, name: &str) -> bool {
+        if self.had_error || self.had_runtime_error {
+            prelude_error(name);
+        }
+        !(self.had_error || self.had_runtime_error || self.exit_code.is_some())
+    }
+
+    fn freeze_builtins(&mut self) {
+        if std::mem::take(&mut self.freeze_globals) {
+            self.interpreter.freeze_globals();
         }
     }
 
+    // Logs every definition and assignment, written to `path` when the run
+    // ends. A `.json` extension selects JSON output instead of plain text.
+    pub fn record_to(&mut self, path: PathBuf) {
+        self.interpreter.start_recording();
+        self.record_path = Some(path);
+    }
+
+    pub fn set_strict_booleans(&mut self, strict: bool) {
+        self.interpreter.set_strict_booleans(strict);
+    }
+
+    // Used for everything parsed from now on: scripts, preludes, the REPL
+    // and eval_expr. The bundled prelude is always parsed with the defaults.
+    pub fn set_parser_options(&mut self, options: ParserOptions) {
+        self.parser_options = options;
+    }
+
+    pub fn set_interpreter_options(&mut self, options: InterpreterOptions) {
+        self.interpreter.set_options(options);
+    }
+
+    // Which extensions to the book's Lox the parser and interpreter accept,
+    // LanguageFeatures::NONE being the book's Lox as it is.
+    pub fn set_language_features(&mut self, features: LanguageFeatures) {
+        self.parser_options.features = features;
+        let mut options = self.interpreter.options();
+        options.features = features;
+        self.interpreter.set_options(options);
+    }
+
+    // Which capabilities the natives may use, for running scripts that
+    // aren't trusted with files, the network or the process.
+    pub fn set_sandbox_policy(&mut self, policy: SandboxPolicy) {
+        let mut options = self.interpreter.options();
+        options.sandbox = policy;
+        self.interpreter.set_options(options);
+    }
+
+    // Checks the debugger's breakpoints and watchpoints as the scripts run,
+    // pausing in its handler when one is hit.
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.interpreter.set_debugger(Some(debugger));
+    }
+
+    // Logs every definition of and assignment to a variable called `name`,
+    // in any scope, to stderr as the scripts run.
+    pub fn trace_variable(&mut self, name: &str) {
+        self.interpreter.tracer().trace(name);
+    }
+
+    // Turns type annotation mismatches from warnings into errors that stop
+    // the script from running.
+    pub fn set_typed(&mut self, typed: bool) {
+        self.typed = typed;
+    }
+
+    // Makes the natives and everything the preludes define read-only before
+    // the script or prompt starts, so defining or assigning one of them is a
+    // runtime error rather than a quiet change to the standard library.
+    pub fn set_freeze_globals(&mut self, freeze: bool) {
+        self.freeze_globals = freeze;
+    }
+
+    // Whether the source passed to `run` is a script or lines typed at a
+    // prompt, where globals may be declared again. run_prompt selects Prompt
+    // by itself.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    // Drops the files' top-level functions that nothing calls before running,
+    // and lists them on stderr. Every file is then compiled before any of
+    // them runs, since a function may only be called by a later one. The
+    // preludes load as they otherwise would and are kept whole.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    // Replaces calls to functions that only return a simple expression with
+    // that expression, and lists the inlined functions on stderr.
+    pub fn set_inline(&mut self, inline: bool) {
+        self.inline = inline;
+    }
+
+    pub fn print_stats_at_exit(&mut self) {
+        self.print_stats = true;
+    }
+
+    // Prints every scope the resolver sees, with each variable's slot and the
+    // depth each reference resolves to, before the code runs.
+    pub fn dump_scopes(&mut self) {
+        self.dump_scopes = true;
+    }
+
     pub fn run(&mut self, source: String) {
-        let mut scanner = Scanner::new(&source);
+        self.run_named(REPL, &source);
+    }
+
+    // Like `run`, with diagnostics pointing into `name` instead of the REPL.
+    pub fn run_named(&mut self, name: &str, source: &str) {
+        if let Some(statements) = self.compile(name, source) {
+            self.execute(&statements);
+        }
+    }
+
+    // Evaluates a single expression against the current globals, so values
+    // defined by earlier `run` calls can be used from the host.
+    pub fn eval_expr(&mut self, source: &str) -> Result<LoxValue, LoxError> {
+        let mut scanner = Scanner::new(source);
+        scanner.set_features(self.parser_options.features);
         let tokens = scanner.scan_tokens();
 
-        let mut parser = Parser::new(tokens);
+        let expr = Parser::with_options(tokens, self.parser_options)
+            .parse_expression()?;
 
-        let statements = match parser.parse() {
-            Ok(s) => s,
-            Err(_) => {
+        self.interpreter
+            .interpret_expression(&expr)
+            .map(LoxValue::from)
+    }
+
+    // Like eval_expr, seeing the locals of `environment` as well, which is
+    // one captured with Interpreter::environment while code was running.
+    pub fn eval_expr_in(
+        &mut self,
+        environment: Rc<RefCell<Environment>>,
+        source: &str,
+    ) -> Result<LoxValue, LoxError> {
+        let mut scanner = Scanner::new(source);
+        scanner.set_features(self.parser_options.features);
+        let tokens = scanner.scan_tokens();
+
+        let expr = Parser::with_options(tokens, self.parser_options)
+            .parse_expression()?;
+
+        self.interpreter
+            .evaluate_in(environment, &expr)
+            .map(LoxValue::from)
+    }
+
+    fn compile(&mut self, name: &str, source: &str) -> Option<Vec<Stmt>> {
+        let source = Rc::new(Source::new(name, source));
+        self.sources.insert(source.shared_name(), source.clone());
+        // Tokens are parsed as they are scanned, so the whole list of them
+        // is never held at once.
+        let mut scanner = Scanner::from_source(&source);
+        scanner.set_features(self.parser_options.features);
+        let parsed =
+            Parser::from_tokens(scanner.iter(), self.parser_options).parse();
+
+        let mut statements = match parsed {
+            Ok(s) if !scanner.had_error() => s,
+            _ => {
                 self.had_error = true;
-                return;
+                return None;
             }
         };
 
-        let mut interpreter = Interpreter::new();
+        if self.inline {
+            let inlined = optimizer::inline_small_functions(&mut statements);
+            report_inlined(&inlined);
+        }
+
+        let mut resolver = if self.dump_scopes {
+            Resolver::with_scope_dump()
+        } else {
+            Resolver::new()
+        };
+        resolver.set_mode(self.mode);
+        let resolved = resolver.resolve(&statements);
+        for (token, message) in resolver.take_warnings() {
+            report_warning(&token, message);
+        }
+        if let Some(dump) = resolver.scope_dump() {
+            eprint!("{dump}");
+        }
+        if resolved.is_err() {
+            self.had_error = true;
+            return None;
+        }
+        if self.optimize {
+            self.usage.merge(resolver.take_usage());
+        }
+
+        if TypeChecker::new(self.typed).check(&statements).is_err() {
+            self.had_error = true;
+            return None;
+        }
+
+        Some(statements)
+    }
+
+    fn execute(&mut self, statements: &[Stmt]) {
+        let result = self.interpreter.interpret(statements);
+        self.record_outcome(result);
+    }
+
+    fn record_outcome(&mut self, result: Result<(), LoxError>) {
+        match result {
+            Ok(()) => {}
+            Err(LoxError::Exit { code }) => self.exit_code = Some(code),
+            Err(_) => self.had_runtime_error = true,
+        }
+    }
+
+    // Runs the atExit() callbacks, last registered first. Each one runs even
+    // if an earlier one failed or called exit(), and so do callbacks they
+    // register themselves.
+    fn run_exit_hooks(&mut self) {
+        while let Some(hook) = self.interpreter.pop_exit_hook() {
+            let result = self.interpreter.call_value(&hook, Vec::new());
+            self.record_outcome(result.map(|_| ()));
+        }
+    }
+
+    // Ends the run: the atExit() callbacks, then the files left open, the
+    // record and stats, then the test summary. What is left is to exit with
+    // status().
+    fn shut_down(&mut self) {
+        self.run_exit_hooks();
+        self.interpreter.close_open_handles();
+        self.finish();
+        let tests = self.interpreter.test_results();
+        if tests.ran() {
+            let output = self.interpreter.output();
+            let _ = writeln!(output.borrow_mut(), "{}", tests.summary());
+        }
+    }
+
+    pub fn run_file<P>(&mut self, path: &P)
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        self.run_files(&[path]);
+    }
+
+    // Runs each file in turn in the same interpreter, so later files see the
+    // globals defined by earlier ones. The first failing file stops the run,
+    // and the process exits with its status.
+    pub fn run_files<P>(&mut self, paths: &[&P])
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        self.run_files_to_end(paths);
+        self.exit_on_error();
+    }
+
+    // Like run_files, but returns the status the process would have exited
+    // with instead of exiting, so one process can run many programs. This is
+    // how `rlox test` runs each test file.
+    pub fn run_files_for_status<P>(&mut self, paths: &[&P]) -> i32
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        self.run_files_to_end(paths);
+        self.status()
+    }
+
+    fn run_files_to_end<P>(&mut self, paths: &[&P])
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        if self.optimize {
+            self.run_files_optimized(paths);
+            return;
+        }
+
+        if !self.load_preludes() {
+            return;
+        }
+
+        for path in paths {
+            if let Some(statements) = self.load_file(path) {
+                self.execute(&statements);
+            }
+
+            if self.had_error
+                || self.had_runtime_error
+                || self.exit_code.is_some()
+            {
+                break;
+            }
+        }
+
+        self.shut_down();
+    }
+
+    // Like run_files, but compiles every file up front so that what any of
+    // them or the preludes uses is known before unused functions are dropped.
+    fn run_files_optimized<P>(&mut self, paths: &[&P])
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        if !self.load_preludes() {
+            return;
+        }
+
+        let mut units = Vec::new();
+        for path in paths {
+            if self.had_error || self.exit_code.is_some() {
+                break;
+            }
+            if let Some(statements) = self.load_file(path) {
+                units.push(statements);
+            }
+        }
+
+        if !self.had_error && self.exit_code.is_none() {
+            let reachable = self.usage.reachable();
+            let units: Vec<_> = units
+                .into_iter()
+                .map(|statements| {
+                    let (statements, removed) =
+                        optimizer::remove_unused_functions(
+                            statements, &reachable,
+                        );
+                    report_removed(&removed);
+                    statements
+                })
+                .collect();
+
+            for statements in units {
+                self.execute(&statements);
+                if self.had_runtime_error || self.exit_code.is_some() {
+                    break;
+                }
+            }
+        }
+
+        self.shut_down();
+    }
+
+    // Reads either Lox source or a file produced by `rlox compile`, which is
+    // recognised by its header and loaded without scanning or parsing.
+    fn load_file<P>(&mut self, path: &P) -> Option<Vec<Stmt>>
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        let buffer = self.read_file(path)?;
+
+        if loxc::is_compiled(&buffer) {
+            match loxc::load(&buffer) {
+                Ok(statements) => {
+                    self.note_usage(&statements);
+                    Some(statements)
+                }
+                Err(_) => {
+                    self.had_error = true;
+                    None
+                }
+            }
+        } else {
+            let source = self.source_text(path, buffer)?;
+            self.compile(&path.as_ref().display().to_string(), &source)
+        }
+    }
+
+    // Adds what `statements`, which were compiled elsewhere and so never
+    // resolved here, use to what is kept while optimizing.
+    fn note_usage(&mut self, statements: &[Stmt]) {
+        if self.optimize {
+            let mut resolver = Resolver::new();
+            let _ = resolver.resolve(statements);
+            self.usage.merge(resolver.take_usage());
+        }
+    }
+
+    // Scans, parses, resolves and type checks each file without running
+    // anything, reporting the problems in every file rather than stopping at
+    // the first, and exits with 65 if there were any.
+    pub fn check_files<P>(&mut self, paths: &[&P])
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        for path in paths {
+            self.load_file(path);
+        }
+
+        self.exit_on_error();
+    }
 
-        if let Err(_) = interpreter.interpret(&statements) {
-            self.had_runtime_error = true;
+    // Parses and checks `path` and writes the resulting program to `output`
+    // in the .loxc format.
+    pub fn compile_file<P>(&mut self, path: &P, output: &Path)
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        let name = path.as_ref().display().to_string();
+        let Some(source) = self.read_source(path) else {
+            self.exit_on_error();
             return;
+        };
+
+        if let Some(mut statements) = self.compile(&name, &source) {
+            if self.optimize {
+                let reachable = std::mem::take(&mut self.usage).reachable();
+                let removed;
+                (statements, removed) =
+                    optimizer::remove_unused_functions(statements, &reachable);
+                report_removed(&removed);
+            }
+            let bytes = loxc::compile(&name, &statements);
+            let result = self.interpreter.file_system().write(output, &bytes);
+            if let Err(e) = result {
+                eprintln!("Unable to write {}: {e}", output.display());
+                process::exit(74);
+            }
         }
+
+        self.exit_on_error();
     }
 
-    pub fn run_file<P: ?Sized>(&mut self, path: &P)
+    // Prints the .loxc form of `path`, compiling it first if it is source.
+    pub fn disassemble_file<P>(&mut self, path: &P)
     where
-        P: AsRef<Path>,
+        P: AsRef<Path> + ?Sized,
+    {
+        let name = path.as_ref().display().to_string();
+        let Some(buffer) = self.read_file(path) else {
+            self.exit_on_error();
+            return;
+        };
+
+        let bytes = if loxc::is_compiled(&buffer) {
+            buffer
+        } else {
+            let Some(source) = self.source_text(path, buffer) else {
+                process::exit(65);
+            };
+            match self.compile(&name, &source) {
+                Some(statements) => loxc::compile(&name, &statements),
+                None => process::exit(65),
+            }
+        };
+
+        match Disassembler::new(&bytes).and_then(|d| d.disassemble()) {
+            Ok(listing) => print!("{listing}"),
+            Err(_) => process::exit(65),
+        }
+    }
+
+    // Checks each file like check_files, then warns about the functions in
+    // it that are over `thresholds`, printing every function's metrics
+    // first if `print_metrics` is set.
+    pub fn lint_files<P>(
+        &mut self,
+        paths: &[&P],
+        thresholds: Thresholds,
+        print_metrics: bool,
+    ) where
+        P: AsRef<Path> + ?Sized,
     {
-        let f = File::open(path).expect("Unable to open the file");
+        for path in paths {
+            let Some(statements) = self.load_file(path) else {
+                continue;
+            };
+            let functions = metrics::measure(&statements);
+            if print_metrics {
+                println!("{}:", path.as_ref().display());
+                print!("{}", metrics::report(&functions));
+            }
+            for function in &functions {
+                for warning in function.warnings(thresholds) {
+                    report_warning(&function.token, warning);
+                }
+            }
+        }
 
-        let mut reader = BufReader::new(f);
-        let mut buffer = Vec::new();
+        self.exit_on_error();
+    }
 
-        reader.read_to_end(&mut buffer).unwrap();
+    // Prints which functions in `path` call which, in `format`.
+    pub fn graph_file<P>(&mut self, path: &P, format: GraphFormat)
+    where
+        P: AsRef<Path> + ?Sized,
+    {
+        let name = path.as_ref().display().to_string();
+        let Some(source) = self.read_source(path) else {
+            self.exit_on_error();
+            return;
+        };
+        match call_graph::call_graph(&name, &source) {
+            Some(graph) => print!("{}", graph.render(format)),
+            None => process::exit(65),
+        }
+    }
 
-        let source = String::from_utf8(buffer).unwrap();
+    // Renames the name written at `line` and `column` of `path`, and every
+    // reference to it, rewriting the file or printing a diff of the change.
+    pub fn rename_in_file<P>(
+        &mut self,
+        path: &P,
+        (line, column): (u32, u32),
+        new_name: &str,
+        print_diff: bool,
+    ) where
+        P: AsRef<Path> + ?Sized,
+    {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let Some(source) = self.read_source(path) else {
+            self.exit_on_error();
+            return;
+        };
 
-        self.run(source);
+        let renamed =
+            match rename::rename(&name, &source, line, column, new_name) {
+                Ok(renamed) => renamed,
+                Err(message) => {
+                    eprintln!("{message}");
+                    process::exit(65);
+                }
+            };
+        if print_diff {
+            print!("{}", rename::diff(&name, &source, &renamed));
+            return;
+        }
+        let result = self
+            .interpreter
+            .file_system()
+            .write(path, renamed.as_bytes());
+        if let Err(e) = result {
+            eprintln!("Unable to write {name}: {e}");
+            process::exit(74);
+        }
+    }
 
-        if self.had_error {
-            process::exit(65);
+    // The status the run ends with: the one given to exit(), 65 after a
+    // compile error, 70 after a runtime error, 1 if a test failed and 0
+    // otherwise.
+    fn status(&self) -> i32 {
+        if let Some(code) = self.exit_code {
+            code
+        } else if self.had_error {
+            65
+        } else if self.had_runtime_error {
+            70
+        } else if self.interpreter.test_results().failed > 0 {
+            1
+        } else {
+            0
         }
-        if self.had_runtime_error {
-            process::exit(70);
+    }
+
+    // Exits with status() if the run failed or called exit().
+    fn exit_on_error(&self) {
+        let status = self.status();
+        if status != 0 || self.exit_code.is_some() {
+            process::exit(status);
         }
     }
 
     pub fn run_prompt(&mut self) {
+        if !self.load_preludes() {
+            self.exit_on_error();
+            return;
+        }
+        self.mode = Mode::Prompt;
+
         loop {
             let mut input = String::new();
             println!("Enter your code:");
-            io::stdin().read_line(&mut input).unwrap();
+            if let Err(e) = io::stdin().read_line(&mut input) {
+                eprintln!("Unable to read input: {e}");
+                break;
+            }
 
             if input.trim().is_empty() {
                 break;
             }
 
-            self.run(input);
+            if input.trim_start().starts_with(':') {
+                self.run_command(input.trim_start());
+            } else {
+                self.run_line(&input);
+            }
 
             self.had_error = false;
+            self.had_runtime_error = false;
+            if self.exit_code.is_some() {
+                break;
+            }
+        }
+
+        self.shut_down();
+        self.exit_on_error();
+    }
+
+    // Runs a line typed at the prompt. When it is a single expression
+    // statement its value is echoed as `=> value`, unless it is nil, so
+    // calls made for their effect stay quiet.
+    fn run_line(&mut self, source: &str) {
+        let Some(statements) = self.compile(REPL, source) else {
+            return;
+        };
+
+        let [Stmt::Expression(stmt)] = statements.as_slice() else {
+            self.execute(&statements);
+            if !self.had_runtime_error && statements.iter().any(declares) {
+                self.session.push(source.trim_end().to_string());
+            }
+            return;
+        };
+        let result = self.interpreter.interpret_expression(&stmt.expression);
+        if let Ok(value) = &result {
+            if !matches!(value, Literal::Nil | Literal::NilImplicit) {
+                println!("=> {}", ValuePrinter::new().print(value));
+            }
         }
+        self.record_outcome(result.map(|_| ()));
+    }
+
+    fn finish(&self) {
+        self.write_record();
+
+        if self.print_stats {
+            eprintln!("[stats] {}", self.interpreter.stats());
+        }
+    }
+
+    fn write_record(&self) {
+        let (Some(path), Some(recorder)) =
+            (&self.record_path, self.interpreter.recorder())
+        else {
+            return;
+        };
+
+        let result = File::create(path).and_then(|f| {
+            let mut writer = BufWriter::new(f);
+            if path.extension().is_some_and(|e| e == "json") {
+                recorder.write_json(&mut writer)
+            } else {
+                recorder.write_text(&mut writer)
+            }
+        });
+
+        if let Err(e) = result {
+            eprintln!("Unable to write the record to {}: {e}", path.display());
+        }
+    }
+
+    fn run_command(&mut self, input: &str) {
+        let (command, source) =
+            input.split_once(char::is_whitespace).unwrap_or((input, ""));
+
+        match command {
+            ":ast" if starts_with_statement(source) => {
+                let mut scanner = Scanner::new(source);
+                scanner.set_features(self.parser_options.features);
+                let parsed =
+                    Parser::from_tokens(scanner.iter(), self.parser_options)
+                        .parse();
+
+                if let Ok(statements) = parsed {
+                    for statement in &statements {
+                        println!("{}", AstPrinter::new().print_stmt(statement));
+                    }
+                }
+            }
+            ":ast" => {
+                if let Some(expr) = self.parse_expression(source) {
+                    println!("{}", AstPrinter::new().print(&expr));
+                }
+            }
+            ":fmt" => {
+                if let Some(expr) = self.parse_expression(source) {
+                    println!("{}", SourcePrinter::new().print(&expr));
+                }
+            }
+            ":type" => {
+                if let Some(expr) = self.parse_expression(source) {
+                    if let Ok(value) =
+                        self.interpreter.interpret_expression(&expr)
+                    {
+                        println!("{}", value.type_name());
+                    }
+                }
+            }
+            ":env" => {
+                for (name, value) in self.interpreter.script_globals() {
+                    println!("{name} = {value}");
+                }
+            }
+            // Starts tracing the variable, or stops if it already is. With
+            // no name, lists the ones being traced.
+            ":trace" => {
+                let name = source.trim();
+                let tracer = self.interpreter.tracer();
+                if name.is_empty() {
+                    for name in tracer.names() {
+                        println!("{name}");
+                    }
+                } else if tracer.untrace(name) {
+                    println!("Stopped tracing {name}.");
+                } else {
+                    tracer.trace(name);
+                    println!("Tracing {name}.");
+                }
+            }
+            ":save" => self.save_session(&session_path(source)),
+            ":restore" => self.restore_session(&session_path(source)),
+            _ => {
+                eprintln!(
+                    "Unknown command '{}'. Available: :ast, :env, :fmt, :restore, :save, :trace, :type",
+                    command.trim()
+                )
+            }
+        }
+    }
+
+    fn save_session(&self, path: &Path) {
+        let mut text = self.session.join("\n");
+        text.push('\n');
+        let file_system = self.interpreter.file_system();
+        match file_system.write(path, text.as_bytes()) {
+            Ok(()) => println!(
+                "Saved {} lines to {}.",
+                self.session.len(),
+                path.display()
+            ),
+            Err(e) => eprintln!("Unable to write {}: {e}", path.display()),
+        }
+    }
+
+    // Replays a saved session as if it were typed again. Unlike a script
+    // given on the command line, a file that can't be read doesn't end the
+    // session.
+    fn restore_session(&mut self, path: &Path) {
+        let file_system = self.interpreter.file_system();
+        let text = match file_system.read(path).map(String::from_utf8) {
+            Ok(Ok(text)) => text,
+            Ok(Err(_)) => {
+                eprintln!("{} is not valid UTF-8.", path.display());
+                return;
+            }
+            Err(e) => {
+                eprintln!("Unable to read {}: {e}", path.display());
+                return;
+            }
+        };
+
+        self.run_named(&path.display().to_string(), &text);
+        if !self.had_error && !self.had_runtime_error {
+            self.session.push(text.trim_end().to_string());
+        }
+    }
+
+    fn parse_expression(&mut self, source: &str) -> Option<Expr> {
+        let mut scanner = Scanner::new(source);
+        scanner.set_features(self.parser_options.features);
+        let parsed = Parser::from_tokens(scanner.iter(), self.parser_options)
+            .parse_expression();
+        parsed.ok()
+    }
+}
+
+// Whether a line typed at the prompt is worth keeping for :save.
+fn declares(statement: &Stmt) -> bool {
+    matches!(statement, Stmt::Class(_) | Stmt::Function(_) | Stmt::Var(_))
+}
+
+// The file :save and :restore use, session.lox unless one is named.
+fn session_path(argument: &str) -> PathBuf {
+    match argument.trim() {
+        "" => PathBuf::from("session.lox"),
+        path => PathBuf::from(path),
+    }
+}
+
+// Whether `:ast` input is a statement rather than a bare expression.
+fn starts_with_statement(source: &str) -> bool {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens();
+
+    // A label, `outer: for ...`, also starts a statement.
+    if tokens.len() > 1
+        && tokens[0].token_type == TokenType::Identifier
+        && tokens[1].token_type == TokenType::Colon
+    {
+        return true;
+    }
+
+    matches!(
+        tokens[0].token_type,
+        TokenType::Break
+            | TokenType::Class
+            | TokenType::Continue
+            | TokenType::Enum
+            | TokenType::For
+            | TokenType::Fun
+            | TokenType::If
+            | TokenType::LeftBrace
+            | TokenType::Print
+            | TokenType::Return
+            | TokenType::Var
+            | TokenType::While
+    )
+}
+
+// Reported after any error in a prelude.
+fn prelude_error(name: &str) -> LoxError {
+    LoxError::system_error(format!("Error in prelude '{name}'."))
+}
+
+fn report_inlined(inlined: &[(Token, usize)]) {
+    for (name, calls) in inlined {
+        eprintln!(
+            "[optimize] inlined {calls} call{} to {} ({})",
+            if *calls == 1 { "" } else { "s" },
+            name.lexeme,
+            name.location()
+        );
+    }
+}
+
+fn report_removed(removed: &[Token]) {
+    for name in removed {
+        eprintln!(
+            "[optimize] removed unused function {} ({})",
+            name.lexeme,
+            name.location()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callable::LoxCallable;
+    use crate::debugger::{Pause, PauseHandler};
+    use crate::file_system::MemoryFileSystem;
+    use crate::literal::Literal;
+    use crate::sandbox::Capability;
+    use crate::value_printer;
+    use std::hash::{BuildHasher, RandomState};
+    use std::thread;
+    use std::time::Duration;
+
+    // Runs `f` on a thread with the stack main.rs gives the CLI, which debug
+    // builds need for the deepest nesting and recursion the limits allow.
+    fn with_cli_stack<T, F>(f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        thread::Builder::new()
+            .stack_size(256 * 1024 * 1024)
+            .spawn(f)
+            .unwrap()
+            .join()
+            .unwrap_or_else(|_| panic!("the test thread panicked"))
+    }
+
+    // Whether running `source` reported a compile error and a runtime error.
+    fn run(source: &str) -> (bool, bool) {
+        let source = source.to_string();
+        with_cli_stack(move || {
+            let mut lox = Lox::new();
+            lox.run(source);
+            (lox.had_error, lox.had_runtime_error)
+        })
+    }
+
+    fn assert_compile_error(source: &str) {
+        assert_eq!(run(source), (true, false), "source: {source:?}");
+    }
+
+    // The message the resolver rejects `source` with.
+    fn resolve_error(source: &str) -> String {
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let statements = Parser::new(&tokens).parse().unwrap_or_default();
+        match Resolver::new().resolve(&statements) {
+            Err(LoxError::ParseError { message, .. }) => message,
+            _ => panic!("{source} should not resolve"),
+        }
+    }
+
+    #[test]
+    fn scans_non_ascii_source() {
+        assert_eq!(run("print \"héllo\";"), (false, false));
+        assert_eq!(run("// ünïcode comment\nprint 1;"), (false, false));
+        assert_eq!(run("/* ✓ */ print 1;"), (false, false));
+    }
+
+    // Inputs that used to panic in the scanner, reduced from fuzzing.
+    #[test]
+    fn reports_errors_for_fuzzed_scanner_input() {
+        for source in [
+            "\u{4}ÃnØ\u{e}Ďqàýěw°vpë",
+            "!= class nil < A className \"é\" className fun",
+            "\n ü or \"s\" \" ! .5 bool = : stats",
+            "( super bool var @ stats é false and ?",
+            "\"é",
+            "/* é",
+            "é",
+        ] {
+            assert_compile_error(source);
+        }
+    }
+
+    #[test]
+    fn reports_errors_for_input_ending_early() {
+        assert_eq!(run(""), (false, false));
+        for source in [
+            "print",
+            "(",
+            "1 +",
+            "class",
+            "class A {",
+            "fun f(",
+            "a.",
+            "var",
+            "}",
+            ")",
+            "for (;;",
+            "if (true)",
+            "outer:",
+            "\"",
+            "?",
+        ] {
+            assert_compile_error(source);
+        }
+    }
+
+    #[test]
+    fn reports_errors_for_deep_nesting() {
+        let depth = 100_000;
+        for source in [
+            format!("print {}1{};", "(".repeat(depth), ")".repeat(depth)),
+            format!("print {}1;", "-".repeat(depth)),
+            format!("print 1{};", "+1".repeat(depth)),
+            format!("print a{};", ".b".repeat(depth)),
+            format!("f{};", "()".repeat(depth)),
+            format!("{}{}", "{".repeat(depth), "}".repeat(depth)),
+            format!("{}print 1;", "if (true) ".repeat(depth)),
+            format!("{}{}", "fun f() {".repeat(depth), "}".repeat(depth)),
+        ] {
+            assert_compile_error(&source);
+        }
+    }
+
+    #[test]
+    fn reports_runaway_recursion_as_a_runtime_error() {
+        assert_eq!(run("fun f() { f(); } f();"), (false, true));
+
+        let source =
+            "class A { toString() { return \"\" + this; } } print A();";
+        assert_eq!(run(source), (false, true));
+    }
+
+    #[test]
+    fn allows_recursion_below_the_call_limit() {
+        let source = "fun f(n) { if (n > 0) f(n - 1); } f(2000);";
+        assert_eq!(run(source), (false, false));
+    }
+
+    #[test]
+    fn parses_token_lists_without_eof() {
+        assert!(Parser::new(&Vec::new()).parse().is_ok());
+
+        let mut tokens = Scanner::new("print 1;").scan_tokens().clone();
+        tokens.pop();
+        assert!(Parser::new(&tokens).parse().is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_compiled_files() {
+        let source = "class A { m(x) { return x + 1; } } print A().m(1);";
+        let statements = Parser::new(Scanner::new(source).scan_tokens())
+            .parse()
+            .unwrap_or_else(|_| panic!("test source should parse"));
+        let bytes = loxc::compile(REPL, &statements);
+
+        assert!(loxc::load(&bytes).is_ok());
+        for len in 0..bytes.len() {
+            assert!(loxc::load(&bytes[..len]).is_err(), "length {len}");
+            let listing =
+                Disassembler::new(&bytes[..len]).and_then(|d| d.disassemble());
+            assert!(listing.is_err(), "length {len}");
+        }
+    }
+
+    #[test]
+    fn rejects_deeply_nested_compiled_files() {
+        with_cli_stack(deeply_nested_compiled_file);
+    }
+
+    fn deeply_nested_compiled_file() {
+        let statements = Parser::new(Scanner::new("print (1);").scan_tokens())
+            .parse()
+            .unwrap_or_else(|_| panic!("test source should parse"));
+        let bytes = loxc::compile(REPL, &statements);
+
+        // Repeat the GROUPING opcode that follows PRINT.
+        let at = bytes
+            .windows(2)
+            .rposition(|w| w == [loxc::op::PRINT, loxc::op::GROUPING])
+            .unwrap_or_else(|| panic!("expected PRINT GROUPING"));
+        let grouping = vec![loxc::op::GROUPING; 100_000];
+        let bytes = [&bytes[..=at], &grouping, &bytes[at + 1..]].concat();
+
+        assert!(loxc::load(&bytes).is_err());
+        let listing = Disassembler::new(&bytes).and_then(|d| d.disassemble());
+        assert!(listing.is_err());
+    }
+
+    fn eval(source: &str) -> String {
+        match Lox::new().eval_expr(source) {
+            Ok(value) => value.to_string(),
+            Err(_) => panic!("{source} should evaluate"),
+        }
+    }
+
+    #[test]
+    fn keeps_whole_number_arithmetic_exact() {
+        assert_eq!(eval("9007199254740992 + 1"), "9007199254740993");
+        assert_eq!(eval("3037000499 * 3037000499"), "9223372030926249001");
+        assert_eq!(eval("-9007199254740993 - 2"), "-9007199254740995");
+        assert_eq!(eval("12 / 4"), "3");
+    }
+
+    #[test]
+    fn falls_back_to_floats() {
+        assert_eq!(eval("7 / 2"), "3.5");
+        assert_eq!(eval("1 / 0"), "inf");
+        assert_eq!(eval("0.5 + 1"), "1.5");
+        assert_eq!(eval("9223372036854775807 + 1"), "9223372036854776000");
+        assert_eq!(eval("-(-9223372036854775807 - 1)"), "9223372036854776000");
+    }
+
+    #[test]
+    fn integers_equal_the_same_float() {
+        assert_eq!(eval("3 == 3.0"), "true");
+        assert_eq!(eval("0 == -0.0"), "true");
+        assert_eq!(eval("9007199254740993 == 9007199254740992.0"), "false");
+        assert_eq!(eval("2 < 2.5"), "true");
+
+        let state = RandomState::new();
+        assert_eq!(
+            state.hash_one(Literal::Integer(3)),
+            state.hash_one(Literal::Number(3.0))
+        );
+    }
+
+    #[test]
+    fn ranges_count_without_listing_their_numbers() {
+        assert_eq!(eval("range(3)"), "range(0, 3)");
+        assert_eq!(eval("len(range(0, 1000000000000, 7))"), "142857142858");
+        assert_eq!(eval("at(range(10, 0, -3), 2)"), "4");
+        assert_eq!(eval("slice(range(0, 100, 5), 1, 3)"), "range(5, 15, 5)");
+        assert_eq!(eval("range(1, 4) == slice(range(5), 1, 4)"), "true");
+
+        let mut lox = Lox::new();
+        assert!(lox.eval_expr("range(1, 2, 0)").is_err());
+        assert!(lox.eval_expr("range(1.5)").is_err());
+        assert!(lox.eval_expr("range(1, 2, 3, 4)").is_err());
+        assert!(lox.eval_expr("at(range(3), 3)").is_err());
+    }
+
+    #[test]
+    fn slices_count_negative_indexes_from_the_end() {
+        assert_eq!(eval("slice(list(1, 2, 3, 4), -2, 10)"), "[3, 4]");
+        assert_eq!(eval("slice(list(1, 2, 3, 4), -10, -3)"), "[1]");
+        assert_eq!(eval("slice(list(1, 2, 3, 4), 3, 1)"), "[]");
+        assert_eq!(eval("slice(\"hello\", 1, -1)"), "ell");
+        assert_eq!(eval("slice(range(0, 100, 5), -3, -1)"), "range(85, 95, 5)");
+
+        let mut lox = Lox::new();
+        assert!(lox.eval_expr("slice(1, 0, 1)").is_err());
+        assert!(lox.eval_expr("slice(list(1), 0.5, 1)").is_err());
+    }
+
+    // The global `result` after running `source`.
+    fn result_of(source: &str) -> String {
+        let mut lox = Lox::new();
+        lox.run(source.to_string());
+        assert!(!lox.had_error && !lox.had_runtime_error, "{source}");
+        match lox.get_global("result") {
+            Some(value) => value.to_string(),
+            None => panic!("{source} should set result"),
+        }
+    }
+
+    #[test]
+    fn prelude_helpers_take_lox_callbacks() {
+        let source = "fun square(n) { return n * n; }
+            var result = map(range(4), square);";
+        assert_eq!(result_of(source), "[0, 1, 4, 9]");
+
+        let source = "fun big(n) { return n > 5; }
+            fun add(a, b) { return a + b; }
+            var result = reduce(filter(range(10), big), add, 0);";
+        assert_eq!(result_of(source), "30");
+    }
+
+    #[test]
+    fn sort_is_stable() {
+        let source = "fun byLength(a, b) { return len(a) < len(b); }
+            var words = list(\"ccc\", \"a\", \"bb\", \"b\", \"aaa\", \"c\");
+            var result = sort(words, byLength);";
+        assert_eq!(
+            result_of(source),
+            "[\"a\", \"b\", \"c\", \"bb\", \"ccc\", \"aaa\"]"
+        );
+
+        let source = "var result = sort(list(3, 1.5, 9007199254740993, 2));";
+        assert_eq!(result_of(source), "[1.5, 2, 3, 9007199254740993]");
+        let source = "var result = sort(list(\"b\", \"a\", \"c\"));";
+        assert_eq!(result_of(source), "[\"a\", \"b\", \"c\"]");
+    }
+
+    #[test]
+    fn min_max_and_sum_take_a_key() {
+        let source = "fun negate(n) { return -n; }
+            var result = list(math.min(range(5, 10)),
+                math.max(range(5, 10), negate), math.sum(range(101)),
+                math.sum(range(4), negate), math.sum(list()));";
+        assert_eq!(result_of(source), "[5, 5, 5050, -6, 0]");
+
+        let source = "fun length(s) { return len(s); }
+            var words = list(\"bb\", \"a\", \"cc\", \"d\");
+            var result = list(math.min(words, length),
+                math.max(words, length));";
+        assert_eq!(result_of(source), "[\"a\", \"bb\"]");
+    }
+
+    #[test]
+    fn formats_and_compares_numbers() {
+        let source = "var result = list(math.approxEq(0.1 + 0.2, 0.3),
+            0.1 + 0.2 == 0.3, math.approxEq(1, 1.5, 0.5), math.approxEq(1, 1.5),
+            str.toFixed(2 / 3, 2), str.toFixed(7, 1), str.toFixed(-1.005, 0),
+            str.toPrecision(3.14159, 3), str.toPrecision(9.99, 2),
+            str.toPrecision(1234.5, 2), str.toPrecision(0.000123, 2));";
+        assert_eq!(
+            result_of(source),
+            "[true, false, true, false, \"0.67\", \"7.0\", \"-1\", \"3.14\", \
+             \"10\", \"1.2e3\", \"0.00012\"]"
+        );
+
+        let mut lox = Lox::new();
+        for source in [
+            "str.toFixed(1, -1)",
+            "str.toFixed(1, 1.5)",
+            "str.toPrecision(1, 0)",
+            "str.toFixed(\"1\", 2)",
+            "math.approxEq(1, 2, -1)",
+        ] {
+            assert!(lox.eval_expr(source).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn bigints_stay_exact() {
+        let source = "var f = 1n;
+            for (var i = 2; i <= 25; i = i + 1) f = f * i;
+            var result = list(f, f / 1000000n, f % 1000007, -f + 1 < 0,
+                2n == 2, 2n == \"2\", bigint(\"-99999999999999999999\") - 1,
+                7 % 3, -7.5 % 2, math.max(list(3n, 4, -5n)));";
+        assert_eq!(
+            result_of(source),
+            "[15511210043330985984000000, 15511210043330985984, 913534, \
+             true, true, false, -100000000000000000000, 1, -1.5, 4]"
+        );
+
+        let mut lox = Lox::new();
+        for source in ["1n / 0", "1n + 1.5", "bigint(\"1e9\")", "bigint(0.5)"] {
+            assert!(lox.eval_expr(source).is_err(), "{source}");
+        }
+        lox.set_language_features(LanguageFeatures::NONE);
+        lox.run("print 12n;".to_string());
+        assert!(lox.had_error);
+        lox.had_error = false;
+        lox.run("print 7 % 2;".to_string());
+        assert!(lox.had_error);
+    }
+
+    #[test]
+    fn formats_and_parses_other_bases() {
+        let source = "var result = list(str.toHex(255), str.toHex(-4096),
+            str.toBin(5), str.toBin(0), str.parseInt(\"ff\", 16),
+            str.parseInt(\"-0x1F\", 16), str.parseInt(\"0b101\", 2),
+            str.parseInt(\" 42 \"), str.parseInt(\"zz\", 36),
+            str.parseInt(str.toHex(-9223372036854775807 - 1), 16));";
+        assert_eq!(
+            result_of(source),
+            "[\"ff\", \"-1000\", \"101\", \"0\", 255, -31, 5, 42, 1295, \
+             -9223372036854775808]"
+        );
+
+        let mut lox = Lox::new();
+        for source in [
+            "str.toHex(1.5)",
+            "str.toBin(\"1\")",
+            "str.parseInt(\"12\", 1)",
+            "str.parseInt(\"12\", 37)",
+            "str.parseInt(\"19\", 8)",
+            "str.parseInt(\"\", 10)",
+            "str.parseInt(\"--1\")",
+            "str.parseInt(\"0x\", 16)",
+            "str.parseInt(\"9223372036854775808\")",
+        ] {
+            assert!(lox.eval_expr(source).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn classifies_characters() {
+        let source = "fun words(text) {
+              var count = 0;
+              var inWord = false;
+              for (var i = 0; i < len(text); i = i + 1) {
+                var c = slice(text, i, i + 1);
+                if (str.isAlpha(c) and !inWord) count = count + 1;
+                inWord = str.isAlpha(c) or str.isDigit(c);
+              }
+              return count;
+            }
+            var result = list(words(\"let x1 = 42; done\"), str.isDigit(\"7\"),
+                str.isDigit(\"x\"), str.isSpace(\" \"), str.isAlpha(\"é\"),
+                str.toUpperChar(\"a\"), str.toLowerChar(\"Q\"),
+                str.toUpperChar(\"1\"));";
+        assert_eq!(
+            result_of(source),
+            "[3, true, false, true, true, \"A\", \"q\", \"1\"]"
+        );
+
+        let mut lox = Lox::new();
+        for source in [
+            "str.isDigit(\"12\")",
+            "str.isAlpha(\"\")",
+            "str.toUpperChar(1)",
+        ] {
+            assert!(lox.eval_expr(source).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn refuses_to_order_mixed_types() {
+        let mut lox = Lox::new();
+        for source in [
+            "sort(list(1, \"a\"))",
+            "sort(list(true, false))",
+            "math.min(list(nil, 1))",
+            "math.max(list())",
+            "math.sum(list(1, \"2\"))",
+            "sort(list(1, 0 / 0))",
+        ] {
+            assert!(lox.eval_expr(source).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn sort_survives_inconsistent_comparators() {
+        // Every element claims to go before every other one.
+        let source = "fun always(a, b) { return true; }
+            var result = len(sort(range(100), always));";
+        assert_eq!(result_of(source), "100");
+
+        let source = "fun broken(a, b) { return a.missing; }
+            sort(range(3), broken);";
+        assert_eq!(run(source), (false, true));
+    }
+
+    #[test]
+    fn scripts_can_replace_prelude_helpers() {
+        let source = "fun map(a, b) { return \"mine\"; }
+            var result = map(1, 2);";
+        assert_eq!(result_of(source), "mine");
+    }
+
+    #[test]
+    fn lists_and_maps_change_in_place() {
+        let source = "var result = list(1, 2);
+            push(result, 3);
+            listSet(result, 0, pop(result));";
+        assert_eq!(result_of(source), "[3, 2]");
+
+        let source = "var result = mapNew();
+            mapSet(result, \"a\", 1);
+            mapSet(result, 2.0, nil);
+            mapSet(result, \"a\", mapHas(result, 2) and mapGet(result, 2) == nil);
+            mapRemove(result, 2);";
+        assert_eq!(result_of(source), "{\"a\": true}");
+
+        let source = "var result = mapNew();
+            for (var i = 9; i > 0; i = i - 2) mapSet(result, i, i * i);
+            mapRemove(result, 5);
+            mapSet(result, 9, 0);
+            mapSet(result, \"z\", mapKeys(result));";
+        assert_eq!(
+            result_of(source),
+            "{9: 0, 7: 49, 3: 9, 1: 1, \"z\": [9, 7, 3, 1]}"
+        );
+
+        let mut lox = Lox::new();
+        assert!(lox.eval_expr("pop(list())").is_err());
+        assert!(lox.eval_expr("listSet(list(1), 1, 0)").is_err());
+        assert!(lox.eval_expr("mapSet(mapNew(), 0 / 0, 1)").is_err());
+    }
+
+    #[test]
+    fn fills_templates_from_maps_and_instances() {
+        let source =
+            "class User { toString() { return \"<\" + this.name + \">\"; } }
+            var user = User();
+            user.name = \"Ann\";
+            var values = mapNew();
+            mapSet(values, \"user\", user);
+            mapSet(values, \"n\", 3);
+            var result =
+                str.template(\"{{ user.name }} {{user}} x{{n}}\", values);";
+        assert_eq!(result_of(source), "Ann <Ann> x3");
+
+        let source = "var values = mapNew();
+            mapSet(values, \"a\", \"<b>\");
+            var result = str.template(\"{{a}}{{b.c}}\", values, \"html,keep\")
+                + str.template(\"{{b}}|\", values, \"empty\");";
+        assert_eq!(result_of(source), "&lt;b&gt;{{b.c}}|");
+
+        let mut lox = Lox::new();
+        assert!(lox.eval_expr("str.template(\"{{a}}\", mapNew())").is_err());
+        assert!(lox.eval_expr("str.template(\"{{a\", mapNew())").is_err());
+        assert!(lox
+            .eval_expr("str.template(\"\", mapNew(), \"xml\")")
+            .is_err());
+    }
+
+    #[test]
+    fn matches_the_first_case_that_fits() {
+        let source = "fun describe(x) {
+                match (x) {
+                    case 0: return \"zero\";
+                    case -1: return \"minus one\";
+                    case nil: return \"nothing\";
+                    case n if n > 3: return \"big \" + n;
+                    case _ if x == 2: return \"two\";
+                    case other: return \"other \" + other;
+                }
+            }
+            var result = describe(0) + \", \" + describe(-1) + \", \"
+                + describe(nil) + \", \" + describe(7) + \", \"
+                + describe(2) + \", \" + describe(3);";
+        assert_eq!(
+            result_of(source),
+            "zero, minus one, nothing, big 7, two, other 3"
+        );
+
+        // A binding only shadows names inside its own case.
+        let source = "var n = \"outer\";
+            var result;
+            match (n + \"!\") {
+                case n if n == \"nope\": result = n;
+                case \"outer!\": result = n;
+            }";
+        assert_eq!(result_of(source), "outer");
+
+        let source = "var calls = 0;
+            fun subject() { calls = calls + 1; return calls; }
+            var result;
+            match (subject()) { case 2: result = 2; case 1: result = calls; }";
+        assert_eq!(result_of(source), "1");
+
+        assert_compile_error("match (1) { print 1; }");
+        assert_compile_error("match (1) { case a(): print 1; }");
+        assert_compile_error("match (1) { case 1 print 1; }");
+    }
+
+    #[test]
+    fn type_mismatches_only_stop_typed_programs() {
+        let source = "var n: Number = \"one\"; var result = n;";
+        assert_eq!(result_of(source), "one");
+
+        let mut lox = Lox::new();
+        lox.set_typed(true);
+        lox.run(source.to_string());
+        assert!(lox.had_error);
+        assert!(lox.get_global("result").is_none());
+    }
+
+    #[test]
+    fn match_expressions_have_the_value_of_the_case_that_fits() {
+        assert_eq!(result_of("var result = match (1) { case _: 2 };"), "2");
+
+        let source = "enum Color { Red, Green }
+            var n = \"outer\";
+            fun describe(x) {
+                return match (x) {
+                    case 0: \"zero\"
+                    case -1: \"minus one\"
+                    case Color.Red: \"red\"
+                    case 12n: \"twelve\"
+                    case s if s == \"text\": s + \"!\"
+                    case n if n > 3: \"big \" + n
+                };
+            }
+            var result = list(describe(0), describe(-1), describe(Color.Red),
+                describe(12), describe(7), describe(\"text\"), describe(2), n);";
+        assert_eq!(
+            result_of(source),
+            "[\"zero\", \"minus one\", \"red\", \"twelve\", \"big 7\", \
+             \"text!\", nil, \"outer\"]"
+        );
+
+        // Kept through a round trip through a compiled file.
+        let source = "print match (x) { case 1: \"one\" case n if n > 1: n };";
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let statements = Parser::new(&tokens).parse().unwrap_or_default();
+        let compiled = loxc::compile("match.lox", &statements);
+        let printed = match loxc::load(&compiled).ok().as_deref() {
+            Some([Stmt::Print(ps)]) => {
+                SourcePrinter::new().print(&ps.expression)
+            }
+            _ => String::new(),
+        };
+        assert_eq!(printed, "match (x) { case 1: \"one\" case n if n > 1: n }");
+
+        assert_compile_error("var a = match (1) { case 1: print 1; };");
+        assert_compile_error("var a = match (1) { 1 };");
+    }
+
+    #[test]
+    fn script_args_are_a_list_of_strings() {
+        let mut lox = Lox::new();
+        lox.set_script_args(vec!["two words".to_string(), "2".to_string()]);
+        lox.run(
+            "var result = list(len(sys.args()), at(sys.args(), 0) + \"!\", \
+                 at(sys.args(), 1) == \"2\");"
+                .into(),
+        );
+        assert_eq!(
+            lox.get_global("result").map(|v| v.to_string()).as_deref(),
+            Some("[2, \"two words!\", true]")
+        );
+        assert_eq!(result_of("var result = sys.args();"), "[]");
+    }
+
+    #[test]
+    fn closures_keep_their_scopes_out_of_the_pool() {
+        let source = "var captured = list();
+            for (var i = 0; i < 3; i = i + 1) {
+                var j = i * 10;
+                fun f() { return j; }
+                push(captured, f);
+            }
+            // Enough scopes to take every environment back out of the pool.
+            for (var k = 0; k < 100; k = k + 1) { var j = -1; { var l = k; } }
+            fun call(f) { var j = -2; return f(); }
+            var result = list(at(captured, 0)(), call(at(captured, 1)),
+                at(captured, 2)());";
+        assert_eq!(result_of(source), "[0, 10, 20]");
+    }
+
+    #[test]
+    fn names_resolve_across_separately_run_sources() {
+        let mut lox = Lox::new();
+        lox.run("var shared = 1; fun bump() { shared = shared + 1; }".into());
+        lox.run("bump(); { var local = shared; bump(); }".into());
+        lox.run("var result = shared;".into());
+        assert!(!lox.had_error && !lox.had_runtime_error);
+        assert_eq!(
+            lox.get_global("result").map(|v| v.to_string()).as_deref(),
+            Some("3")
+        );
+    }
+
+    #[test]
+    fn binds_methods_to_their_instance() {
+        let source = "class Counter {
+                init(n) { this.n = n; }
+                add() { this.n = this.n + 1; return this.n; }
+            }
+            var a = Counter(1);
+            var b = Counter(10);
+            var add = a.add;
+            b.add = a.add;
+            var result = list(add(), b.add(), a.n, b.n);";
+        assert_eq!(result_of(source), "[2, 3, 3, 10]");
+
+        // Methods belong to instances, not to the class itself.
+        assert_eq!(
+            run("class A { m() { return 1; } } print A.m();"),
+            (false, true)
+        );
+        assert_eq!(
+            run("class A { m() { return this; } } var m = A().m; print m();"),
+            (false, false)
+        );
+    }
+
+    #[test]
+    fn initializers_return_the_instance() {
+        let source = "class Point {
+                init(x) { this.x = x; if (x < 0) return; this.y = x; }
+            }
+            var p = Point(1);
+            var again = p.init(2);
+            var early = Point(-1);
+            var result = list(again == p, p.x, p.y, early.x);";
+        assert_eq!(result_of(source), "[true, 2, 2, -1]");
+
+        assert_compile_error("class A { init() { return 1; } }");
+        assert_eq!(
+            resolve_error("class A { init() { if (true) return nil; } }"),
+            "Can't return a value from an initializer."
+        );
+        assert_eq!(
+            run("class A { init() { fun f() { return 1; } f(); } } A();"),
+            (false, false)
+        );
+    }
+
+    #[test]
+    fn only_methods_can_use_this() {
+        assert_compile_error("print this;");
+        for source in ["fun f() { return this; }", "class A {} print this.x;"] {
+            assert_eq!(
+                resolve_error(source),
+                "Can't use 'this' outside of a class."
+            );
+        }
+        assert_eq!(
+            run("class A { m() { fun f() { return this; } return f(); } } \
+                 print A().m();"),
+            (false, false)
+        );
+    }
+
+    #[test]
+    fn optional_access_gives_nil_on_nil() {
+        let source = "class Node { init(next) { this.next = next; } }
+            var chain = Node(Node(nil));
+            var missing = nil;
+            var result = list(missing?.next, chain?.next?.next,
+                chain.next?.next?.next, missing?.next ?? \"none\");";
+        assert_eq!(result_of(source), "[nil, nil, nil, \"none\"]");
+
+        // A nil receiver makes the rest of the chain nil, calls included,
+        // without evaluating the arguments.
+        let source = "class Box { get() { return this; } }
+            var a = nil;
+            var calls = 0;
+            fun count() { calls = calls + 1; return calls; }
+            var result = list(a?.get(), a?.b.c, a?.get().get(count()),
+                Box()?.get().get(), calls);";
+        assert_eq!(result_of(source), "[nil, nil, nil, <Box instance>, 0]");
+
+        // Only nil is skipped, and only in a chain with a '?.'.
+        assert_eq!(run("var a = nil; print a.b?.c;"), (false, true));
+        assert_eq!(run("var a = nil; print (a?.b).c;"), (false, true));
+        assert_eq!(run("var a = nil; print a();"), (false, true));
+        assert_eq!(run("print 1?.b;"), (false, true));
+
+        // Kept through a round trip through a compiled file.
+        let tokens = Scanner::new("print a?.b.c;").scan_tokens().clone();
+        let statements = Parser::new(&tokens).parse().unwrap_or_default();
+        let compiled = loxc::compile("optional.lox", &statements);
+        let printed = match loxc::load(&compiled).ok().as_deref() {
+            Some([Stmt::Print(ps)]) => {
+                SourcePrinter::new().print(&ps.expression)
+            }
+            _ => String::new(),
+        };
+        assert_eq!(printed, "a?.b.c");
+    }
+
+    #[test]
+    fn ast_tells_declarations_from_expressions() {
+        for statement in
+            ["enum Color { Red }", "class A {}", "outer: for (;;) {}"]
+        {
+            assert!(starts_with_statement(statement), "{statement}");
+        }
+        assert!(!starts_with_statement("Color.Red"));
+        assert!(!starts_with_statement("1 + 2"));
+    }
+
+    #[test]
+    fn enum_variants_are_distinct_constants() {
+        let source = "enum Color { Red, Green, Blue, }
+            enum Other { Red }
+            fun name(color) {
+                match (color) {
+                    case Color.Red: return \"red\";
+                    case Color.Green: return \"green\";
+                    case _: return \"other\";
+                }
+            }
+            var result = list(Color.Blue, fields(Color),
+                Color.Red == Color.Red, Color.Red == Other.Red,
+                name(Color.Green), name(Other.Red));";
+        assert_eq!(
+            result_of(source),
+            "[Color.Blue, [\"Red\", \"Green\", \"Blue\"], true, false, \
+             \"green\", \"other\"]"
+        );
+
+        assert_eq!(run("enum Color { Red } Color.Red = 1;"), (false, true));
+        assert_compile_error("enum Color { Red, Red }");
+        assert_compile_error("enum Color { Red Green }");
+    }
+
+    #[test]
+    fn lists_script_globals_in_definition_order() {
+        let mut lox = Lox::new();
+        lox.run(
+            "var b = 1; var a = 2; fun map(f, xs) { return xs; } b = 3;"
+                .to_string(),
+        );
+        assert!(!lox.had_error && !lox.had_runtime_error);
+        let globals: Vec<String> = lox
+            .interpreter
+            .script_globals()
+            .iter()
+            .map(|(name, value)| format!("{name} = {value}"))
+            .collect();
+        assert_eq!(globals, ["b = 3", "a = 2"]);
+    }
+
+    #[test]
+    fn reset_and_forked_interpreters_keep_the_file_system() {
+        let files =
+            Rc::new(MemoryFileSystem::new().with_file("in.txt", "in memory"));
+        let mut lox = Lox::new();
+        lox.set_file_system(files);
+        let read = |lox: &mut Lox| {
+            lox.run("var text = io.readFile(\"in.txt\");".to_string());
+            assert!(!lox.had_error && !lox.had_runtime_error);
+            lox.get_global("text").map(|text| text.to_string())
+        };
+
+        lox.interpreter.reset();
+        assert_eq!(read(&mut lox).as_deref(), Some("in memory"));
+        lox.interpreter = lox.interpreter.fork_isolated();
+        assert_eq!(read(&mut lox).as_deref(), Some("in memory"));
+    }
+
+    #[test]
+    fn reuses_prelude_snapshots() {
+        let files = Rc::new(
+            MemoryFileSystem::new()
+                .with_file("helpers.lox", "fun double(n) { return n * 2; }"),
+        );
+        let snapshot_path = Path::new("preludes.snapshot");
+        let run = |source: &str| {
+            let mut lox = Lox::new();
+            lox.set_file_system(files.clone());
+            lox.set_prelude_snapshot(snapshot_path.to_path_buf());
+            lox.add_prelude(PathBuf::from("helpers.lox"));
+            lox.load_preludes();
+            lox.run(source.to_string());
+            assert!(!lox.had_error && !lox.had_runtime_error);
+            lox.sources.contains_key("helpers.lox")
+        };
+
+        // Compiled the first time, then loaded without compiling.
+        assert!(run("assert(double(2) == 4);"));
+        let snapshot = files.contents(snapshot_path);
+        assert!(snapshot.is_some());
+        assert!(!run("assert(double(3) == 6);"));
+        assert_eq!(files.contents(snapshot_path), snapshot);
+
+        files
+            .write(Path::new("helpers.lox"), b"fun double(n) { return n + n; }")
+            .unwrap_or_else(|e| panic!("{e}"));
+        assert!(run("assert(double(4) == 8);"));
+        assert_ne!(files.contents(snapshot_path), snapshot);
+    }
+
+    #[test]
+    fn prelude_snapshots_are_remade_for_other_settings() {
+        let files = Rc::new(
+            MemoryFileSystem::new()
+                .with_file("typed.lox", "var n: Number = 1;"),
+        );
+        let snapshot_path = Path::new("preludes.snapshot");
+        let run = |typed: bool| {
+            let mut lox = Lox::new();
+            lox.set_file_system(files.clone());
+            lox.set_typed(typed);
+            lox.set_prelude_snapshot(snapshot_path.to_path_buf());
+            lox.add_prelude(PathBuf::from("typed.lox"));
+            lox.load_preludes();
+            assert!(!lox.had_error && !lox.had_runtime_error);
+            files.contents(snapshot_path)
+        };
+
+        let untyped = run(false);
+        let typed = run(true);
+        assert!(untyped.is_some() && typed.is_some());
+        assert_ne!(untyped, typed);
+        assert_eq!(run(true), typed);
+    }
+
+    #[test]
+    fn frozen_globals_refuse_redefinition() {
+        let mut lox = Lox::new();
+        lox.set_freeze_globals(true);
+        lox.load_preludes();
+        lox.run("var total = math.sum(list(1, 2));".to_string());
+        assert!(!lox.had_error && !lox.had_runtime_error);
+
+        for source in [
+            "var len = 1;",
+            "fun map(f, xs) {}",
+            "class sort {}",
+            "clock = nil;",
+        ] {
+            lox.run(source.to_string());
+            assert!(lox.had_runtime_error, "{source}");
+            lox.had_runtime_error = false;
+        }
+        lox.run("total = 4; { var len = total; total = len + 1; }".to_string());
+        assert!(!lox.had_runtime_error);
+        assert_eq!(lox.get_global("total").unwrap().to_string(), "5");
+        assert!(lox.set_global("map", 1.0).is_err());
+        assert!(lox.set_global("mine", 1.0).is_ok());
+    }
+
+    #[test]
+    fn natives_are_grouped_in_namespaces() {
+        let source = "var result = list(math.sqrt(9), math.floor(-1.5),
+            fields(math), str.toHex(255), time.clock == clock);";
+        assert_eq!(
+            result_of(source),
+            "[3, -2, [\"min\", \"max\", \"sum\", \"approxEq\", \"sqrt\", \
+             \"floor\"], \"ff\", true]"
+        );
+        assert_eq!(run("print math.cbrt;"), (false, true));
+        assert_eq!(run("math.sqrt = nil;"), (false, true));
+        assert_eq!(run("print readFile;"), (false, true));
+
+        // Older scripts can have them as globals too.
+        let mut lox = Lox::new();
+        lox.set_interpreter_options(InterpreterOptions {
+            global_natives: true,
+            ..InterpreterOptions::default()
+        });
+        lox.run(
+            "var result = io.readFile == readFile and toHex(1) == \"1\";"
+                .to_string(),
+        );
+        assert_eq!(lox.get_global("result").unwrap().to_string(), "true");
+
+        let mut lox = Lox::new();
+        let apply = LoxValue::from(Literal::Function(Rc::new(Apply)));
+        assert!(lox.set_namespaced("game", "apply", apply.clone()).is_ok());
+        assert!(lox.set_namespaced("game", "apply", apply.clone()).is_err());
+        assert!(lox.set_namespaced("math", "apply", apply.clone()).is_ok());
+        assert!(lox.set_namespaced("len", "apply", apply.clone()).is_err());
+        // A native that is only a namespace member still has its name.
+        assert!(lox.set_namespaced("readFile", "apply", apply).is_err());
+        assert!(lox.set_global("game", 1.0).is_err());
+        assert!(lox.set_global("str", 1.0).is_err());
+        lox.run("var result = game.apply(math.sqrt, 4);".to_string());
+        assert_eq!(lox.get_global("result").unwrap().to_string(), "2");
+        // Even once a script has put something else there.
+        lox.run("var io = 1;".to_string());
+        assert!(lox.set_global("io", 2.0).is_err());
+    }
+
+    #[test]
+    fn only_the_prompt_lets_globals_be_declared_again() {
+        let mut script = Lox::new();
+        script.run("var a = 1;\nvar a = 2;".to_string());
+        assert!(script.had_error);
+
+        let mut prompt = Lox::new();
+        prompt.set_mode(Mode::Prompt);
+        prompt.run("var a = 1;\nvar a = 2;".to_string());
+        prompt.run("fun a() { return 3; }".to_string());
+        prompt.run("var result = a();".to_string());
+        assert!(!prompt.had_error && !prompt.had_runtime_error);
+        assert_eq!(prompt.get_global("result").unwrap().to_string(), "3");
+
+        // Locals follow the same rule either way.
+        prompt.run("{ var b; var b; }".to_string());
+        assert!(prompt.had_error);
+    }
+
+    #[test]
+    fn saves_and_restores_prompt_sessions() {
+        let files = Rc::new(MemoryFileSystem::new());
+        let mut lox = Lox::new();
+        lox.set_file_system(files.clone());
+        lox.set_mode(Mode::Prompt);
+        for line in [
+            "var a = 1;\n",
+            "fun f() { return a + 1; }\n",
+            "print f();\n",
+            "var broken = nil - 1;\n",
+            "var oops = ;\n",
+            "a = 5; var b = a;\n",
+        ] {
+            lox.run_line(line);
+            lox.had_error = false;
+            lox.had_runtime_error = false;
+        }
+        lox.run_command(":save saved.lox\n");
+        assert_eq!(
+            files.contents(Path::new("saved.lox")),
+            Some(
+                b"var a = 1;\nfun f() { return a + 1; }\na = 5; var b = a;\n"
+                    .to_vec()
+            )
+        );
+
+        let mut restored = Lox::new();
+        restored.set_file_system(files.clone());
+        restored.set_mode(Mode::Prompt);
+        restored.run_command(":restore saved.lox");
+        assert!(!restored.had_error && !restored.had_runtime_error);
+        assert_eq!(
+            restored.eval_expr("f() + b").map(|v| v.to_string()).ok(),
+            Some("11".to_string())
+        );
+
+        // What was restored is saved again along with anything new.
+        restored.run_line("var c = 3;");
+        restored.run_command(":save");
+        let saved = files.contents(Path::new("session.lox"));
+        assert!(saved
+            .is_some_and(|text| text.ends_with(b"var b = a;\nvar c = 3;\n")));
+    }
+
+    #[test]
+    fn keeps_the_source_of_what_it_compiled() {
+        let mut lox = Lox::new();
+        lox.run_named("script.lox", "var a = 1;\nprint a;\n");
+        let source = lox.source("script.lox");
+        assert_eq!(source.as_ref().and_then(|s| s.line(2)), Some("print a;"));
+        assert!(lox.source("other.lox").is_none());
+
+        let error = lox.eval_expr("1 +");
+        let location = error.as_ref().err().and_then(|e| e.location());
+        assert_eq!(location, Some((REPL, 1)));
+    }
+
+    #[test]
+    fn book_lox_turns_the_extensions_off() {
+        let extended = [
+            "while (true) break;",
+            "outer: for (;;) { break outer; }",
+            "print nil ?? 1;",
+            "class A {} print A() is A;",
+            "match (1) { case _: print 1; }",
+            "var a = match (1) { case _: 1 };",
+            "enum Color { Red }",
+            "print nil?.a;",
+        ];
+        for source in extended {
+            let mut lox = Lox::new();
+            lox.run(source.to_string());
+            assert!(!lox.had_error, "{source}");
+
+            let mut lox = Lox::new();
+            lox.set_language_features(LanguageFeatures::NONE);
+            lox.run(source.to_string());
+            assert!(lox.had_error, "{source}");
+        }
+
+        let mut lox = Lox::new();
+        lox.set_language_features(
+            LanguageFeatures::ALL.without(LanguageFeatures::STRING_COERCION),
+        );
+        lox.run("print \"a\" + \"b\"; print nil ?? 1;".to_string());
+        assert!(!lox.had_error && !lox.had_runtime_error);
+        lox.run("print \"a\" + 1;".to_string());
+        assert!(lox.had_runtime_error);
+    }
+
+    #[test]
+    fn limits_come_from_the_options() {
+        let mut lox = Lox::new();
+        lox.set_parser_options(ParserOptions {
+            max_nesting: 8,
+            ..ParserOptions::default()
+        });
+        lox.run("print (((1)));".to_string());
+        assert!(!lox.had_error);
+        lox.run("print ((((((((1))))))));".to_string());
+        assert!(lox.had_error);
+
+        let mut lox = Lox::new();
+        lox.set_interpreter_options(InterpreterOptions {
+            max_call_depth: 10,
+            max_loop_iterations: Some(5),
+            ..InterpreterOptions::default()
+        });
+        lox.run("for (var i = 0; i < 5; i = i + 1) {}".to_string());
+        lox.run("fun down(n) { if (n > 0) down(n - 1); } down(9);".to_string());
+        assert!(!lox.had_runtime_error);
+        lox.run("var i = 0; while (i < 6) i = i + 1;".to_string());
+        assert!(lox.had_runtime_error);
+        lox.had_runtime_error = false;
+        lox.run("down(10);".to_string());
+        assert!(lox.had_runtime_error);
+    }
+
+    #[test]
+    fn pretty_prints_nested_values_to_a_depth() {
+        let mut lox = Lox::new();
+        lox.run(
+            "class Point {}
+            var p = Point();
+            p.x = 1;
+            p.tags = list(\"a\", list(list(2)));
+            var m = mapNew();
+            mapSet(m, \"p\", p);
+            var result = list(m, Point(), nil, \"s\");"
+                .to_string(),
+        );
+        let result = lox.get_global("result").unwrap();
+        assert_eq!(
+            ValuePrinter::new().print(result.as_literal()),
+            "[{\"p\": Point { tags: [\"a\", [...]], x: 1 }}, Point {}, nil, \"s\"]"
+        );
+        assert_eq!(
+            ValuePrinter::new()
+                .with_max_depth(2)
+                .print(result.as_literal()),
+            "[{\"p\": Point {...}}, Point {}, nil, \"s\"]"
+        );
+
+        assert_eq!(
+            ValuePrinter::new()
+                .with_max_length(1)
+                .print(result.as_literal()),
+            "[{\"p\": Point { tags: [\"a\", ...], ... }}, ...]"
+        );
+    }
+
+    #[test]
+    fn prints_cycles_without_recursing_forever() {
+        let source = "var result = list(1);
+            var m = mapNew();
+            push(result, m);
+            mapSet(m, \"back\", result);
+            mapSet(m, \"self\", m);";
+        assert_eq!(
+            result_of(source),
+            "[1, {\"back\": [...], \"self\": {...}}]"
+        );
+
+        let mut lox = Lox::new();
+        lox.run(source.to_string());
+        let result = lox.get_global("result").unwrap();
+        assert_eq!(
+            value_printer::describe(result.as_literal()),
+            "list ([1, {...}])"
+        );
+        assert_eq!(value_printer::describe(&Literal::Nil), "nil");
+    }
+
+    #[test]
+    fn operand_errors_name_what_the_operands_were() {
+        let mut lox = Lox::new();
+        for (source, expected) in [
+            (
+                "\"hi\" * 2",
+                "Operands must be numbers, got string (\"hi\") and number (2).",
+            ),
+            (
+                "\"hi\" * nil",
+                "Operands must be two numbers or two strings, \
+                 got string (\"hi\") and nil.",
+            ),
+            (
+                "1 < true",
+                "Operands must be two numbers or two strings, \
+                 got number (1) and boolean (true).",
+            ),
+            (
+                "list(1, 2, 3, 4) - mapNew()",
+                "Unsupported operand types for '-', \
+                 got list ([1, 2, 3, ...]) and map ({}).",
+            ),
+            ("-\"a\"", "Operand must be a number, got string (\"a\")."),
+        ] {
+            match lox.eval_expr(source) {
+                Err(LoxError::RuntimeError { message, .. }) => {
+                    assert_eq!(message, expected)
+                }
+                _ => panic!("{source} should fail at runtime"),
+            }
+        }
+    }
+
+    #[test]
+    fn returns_unwind_loops_but_not_callers() {
+        let source = "fun first(xs, limit) {
+                for (var i = 0; i < len(xs); i = i + 1) {
+                    while (true) {
+                        if (at(xs, i) > limit) return at(xs, i);
+                        break;
+                    }
+                }
+                return nil;
+            }
+            fun over2(x) { return first(list(x), 2) != nil; }
+            var result = list(first(list(1, 5, 9), 4), len(filter(list(1, 3, 4), over2)));";
+        assert_eq!(result_of(source), "[5, 2]");
+    }
+
+    // apply(f, x) calls f(x) from native code.
+    #[derive(Debug)]
+    struct Apply;
+
+    impl LoxCallable for Apply {
+        fn arity(&self) -> usize {
+            2
+        }
+
+        fn call(
+            &self,
+            interpreter: &mut Interpreter,
+            mut arguments: Vec<Literal>,
+        ) -> Result<Literal, LoxError> {
+            let argument = arguments.pop().unwrap_or(Literal::Nil);
+            interpreter.call_value(&arguments[0], vec![argument])
+        }
+    }
+
+    impl std::fmt::Display for Apply {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "native apply function")
+        }
+    }
+
+    // Like run, with the apply native defined. Also returns `result`.
+    fn run_with_apply(source: &str) -> (bool, bool, String) {
+        let source = source.to_string();
+        with_cli_stack(move || {
+            let mut lox = Lox::new();
+            let apply = LoxValue::from(Literal::Function(Rc::new(Apply)));
+            assert!(lox.set_global("apply", apply).is_ok());
+            lox.run(source);
+            let result = lox.get_global("result").map(|r| r.to_string());
+            (
+                lox.had_error,
+                lox.had_runtime_error,
+                result.unwrap_or_default(),
+            )
+        })
+    }
+
+    #[test]
+    fn natives_call_back_into_lox() {
+        let source = "fun inc(n) { return n + 1; }
+            fun twice(n) { return apply(inc, apply(inc, n)); }
+            class Box { init(v) { this.v = v; } }
+            var result = apply(twice, 1) + apply(Box, 2).v;";
+        assert_eq!(run_with_apply(source), (false, false, "5".to_string()));
+    }
+
+    // crash() panics like a bug in a native would.
+    #[derive(Debug)]
+    struct Crash;
+
+    impl LoxCallable for Crash {
+        fn arity(&self) -> usize {
+            0
+        }
+
+        fn call(
+            &self,
+            _interpreter: &mut Interpreter,
+            _arguments: Vec<Literal>,
+        ) -> Result<Literal, LoxError> {
+            panic!("crashed on purpose");
+        }
+    }
+
+    impl std::fmt::Display for Crash {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "native crash function")
+        }
+    }
+
+    #[test]
+    fn failing_tests_leave_the_rest_running() {
+        let mut lox = Lox::new();
+        let crash = LoxValue::from(Literal::Function(Rc::new(Crash)));
+        assert!(lox.set_global("crash", crash).is_ok());
+        lox.set_interpreter_options(InterpreterOptions {
+            test_timeout: Some(Duration::from_millis(50)),
+            ..InterpreterOptions::default()
+        });
+        lox.run(
+            "fun crashes() { var local = 1; { crash(); } }
+            fun spins() { while (true) {} }
+            fun fails() { expectEq(list(1), list(2)); }
+            fun passes() { expectEq(list(1, nil), list(1, nil)); }
+            var after = 0;
+            var result = list(test(\"crashes\", crashes), test(\"spins\", spins),
+                test(\"fails\", fails), test(\"passes\", passes));
+            fun later() { after = after + 1; return after; }
+            later();"
+                .to_string(),
+        );
+        assert!(!lox.had_error && !lox.had_runtime_error);
+        let result = lox.get_global("result").map(|r| r.to_string());
+        assert_eq!(result.as_deref(), Some("[false, false, false, true]"));
+        let after = lox.get_global("after").map(|r| r.to_string());
+        assert_eq!(after.as_deref(), Some("1"));
+        let tests = lox.interpreter.test_results();
+        assert_eq!((tests.passed, tests.failed), (1, 3));
+    }
+
+    // breakpoint() keeps the scope it was called from, as a debugger
+    // stopping there would.
+    #[derive(Debug, Default)]
+    struct Breakpoint(RefCell<Option<Rc<RefCell<Environment>>>>);
+
+    impl LoxCallable for Breakpoint {
+        fn arity(&self) -> usize {
+            0
+        }
+
+        fn call(
+            &self,
+            interpreter: &mut Interpreter,
+            _arguments: Vec<Literal>,
+        ) -> Result<Literal, LoxError> {
+            *self.0.borrow_mut() = Some(interpreter.environment());
+            Ok(Literal::Nil)
+        }
+    }
+
+    impl std::fmt::Display for Breakpoint {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "native breakpoint function")
+        }
+    }
+
+    // Notes each pause, with the value of `i` where it stopped.
+    struct Pauses(Rc<RefCell<Vec<String>>>);
+
+    impl PauseHandler for Pauses {
+        fn paused(&mut self, interpreter: &mut Interpreter, pause: &Pause) {
+            let i = Parser::new(Scanner::new("i").scan_tokens())
+                .parse_expression()
+                .and_then(|i| {
+                    interpreter.evaluate_in(pause.environment.clone(), &i)
+                })
+                .map(|i| i.to_string())
+                .unwrap_or_default();
+            self.0.borrow_mut().push(format!("{pause} i={i}"));
+        }
+    }
+
+    #[test]
+    fn pauses_at_breakpoints_and_watched_changes() {
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        let mut debugger = Debugger::new(Box::new(Pauses(pauses.clone())));
+        let breakpoint =
+            crate::debugger::Breakpoint::parse("3 if i == 2 or i == 4");
+        debugger.add_breakpoint(breakpoint.unwrap_or_else(|e| panic!("{e}")));
+        debugger.watch("total");
+
+        let mut lox = Lox::new();
+        lox.set_debugger(debugger);
+        lox.run(
+            "var total = 0;
+            for (var i = 1; i < 6; i = i + 1) {
+              total = total + i - i;
+              if (i > 3) total = total + i;
+            }"
+            .to_string(),
+        );
+        assert!(!lox.had_error && !lox.had_runtime_error);
+        assert_eq!(
+            *pauses.borrow(),
+            [
+                "Paused at line 1: total defined as 0. i=",
+                "Paused at line 3. i=2",
+                "Paused at line 3. i=4",
+                "Paused at line 4: total changed from 0 to 4. i=4",
+                "Paused at line 4: total changed from 4 to 9. i=5",
+            ]
+        );
+    }
+
+    #[test]
+    fn evaluates_expressions_in_a_stopped_frame() {
+        let mut lox = Lox::new();
+        let breakpoint = Rc::new(Breakpoint::default());
+        let native = LoxValue::from(Literal::Function(breakpoint.clone()));
+        assert!(lox.set_global("breakpoint", native).is_ok());
+        lox.run(
+            "var shadowed = \"global\";
+            fun f(n) { var shadowed = n * 2; { var inner = 1; breakpoint(); } }
+            f(21);"
+                .to_string(),
+        );
+        let Some(frame) = breakpoint.0.borrow_mut().take() else {
+            panic!("breakpoint() should have been called");
+        };
+
+        let eval = |lox: &mut Lox, source| {
+            lox.eval_expr_in(frame.clone(), source)
+                .map(|value| value.to_string())
+                .ok()
+        };
+        assert_eq!(
+            eval(&mut lox, "shadowed + inner + n").as_deref(),
+            Some("64")
+        );
+        assert_eq!(eval(&mut lox, "inner = inner + 1").as_deref(), Some("2"));
+        assert_eq!(eval(&mut lox, "inner").as_deref(), Some("2"));
+        assert_eq!(eval(&mut lox, "missing"), None);
+        assert_eq!(
+            lox.eval_expr("shadowed")
+                .map(|v| v.to_string())
+                .ok()
+                .as_deref(),
+            Some("global")
+        );
+    }
+
+    #[test]
+    fn callback_errors_reach_the_script() {
+        for source in [
+            "fun bad(n) { return nil - n; } apply(bad, 1);",
+            "fun two(a, b) {} apply(two, 1);",
+            "apply(1, 2);",
+            "fun f(n) { return apply(f, n); } apply(f, 1);",
+        ] {
+            let (had_error, had_runtime_error, _) = run_with_apply(source);
+            assert!(!had_error && had_runtime_error, "{source}");
+        }
+    }
+
+    #[test]
+    fn inlined_calls_give_the_same_results() {
+        let source = "fun square(x) { return x * x; }
+            fun both(a, b) { return a and b; }
+            var result = list();
+            for (var i = 0; i < 3; i = i + 1) {
+                fun local() { return square(i) + 1; }
+                push(result, local());
+                push(result, both(i, nil));
+            }";
+        let mut lox = Lox::new();
+        lox.set_inline(true);
+        lox.run(source.to_string());
+        assert!(!lox.had_error && !lox.had_runtime_error);
+        assert_eq!(
+            lox.get_global("result").map(|v| v.to_string()),
+            Some(result_of(source))
+        );
+    }
+
+    #[test]
+    fn optimized_runs_load_preludes_through_their_snapshot() {
+        let files = Rc::new(
+            MemoryFileSystem::new()
+                .with_file("helpers.lox", "fun start() { main(); }")
+                .with_file(
+                    "main.lox",
+                    "fun main() { print \"main\"; } fun unused() {} start();",
+                ),
+        );
+        let snapshot_path = Path::new("preludes.snapshot");
+        let run = || {
+            let output = Rc::new(RefCell::new(Vec::new()));
+            let mut lox = Lox::new();
+            lox.set_file_system(files.clone());
+            lox.set_output(output.clone());
+            lox.set_optimize(true);
+            lox.set_prelude_snapshot(snapshot_path.to_path_buf());
+            lox.add_prelude(PathBuf::from("helpers.lox"));
+            let status = lox.run_files_for_status(&["main.lox"]);
+            let output = String::from_utf8_lossy(&output.borrow()).into_owned();
+            (status, output)
+        };
+
+        // main() is only called from the prelude, which the second run loads
+        // from the snapshot.
+        assert_eq!(run(), (0, "main\n".to_string()));
+        assert!(files.contents(snapshot_path).is_some());
+        assert_eq!(run(), (0, "main\n".to_string()));
+    }
+
+    #[test]
+    fn reads_back_written_lines() {
+        let path = std::env::temp_dir()
+            .join(format!("rlox-lines-{}.txt", std::process::id()));
+        let path = path.display().to_string();
+        let source = format!(
+            "var f = io.open(\"{path}\", \"w\");
+            io.write(f, \"one\ntwo\r\n\");
+            io.close(f);
+            f = io.open(\"{path}\", \"a\");
+            io.write(f, \"three\");
+            io.close(f);
+
+            var result = list();
+            f = io.open(\"{path}\", \"r\");
+            for (var l = io.readLine(f); l != nil; l = io.readLine(f)) {{
+                push(result, l);
+            }}
+            io.close(f);
+            push(result, len(io.readFile(\"{path}\")));"
+        );
+        let result = result_of(&source);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(result, "[\"one\", \"two\", \"three\", 14]");
+    }
+
+    #[test]
+    fn refuses_misused_file_handles() {
+        let path = std::env::temp_dir()
+            .join(format!("rlox-handles-{}.txt", std::process::id()));
+        let path = path.display().to_string();
+        let mut lox = Lox::new();
+        let open = format!("var f = io.open(\"{path}\", \"w\");");
+        lox.run(open);
+        assert!(!lox.had_error && !lox.had_runtime_error);
+
+        assert!(lox.eval_expr("io.readLine(f)").is_err());
+        assert!(lox.eval_expr("io.write(f, 1)").is_err());
+        assert!(lox.eval_expr("io.close(f)").is_ok());
+        assert!(lox.eval_expr("io.close(f)").is_ok());
+        assert!(lox.eval_expr("io.write(f, \"x\")").is_err());
+        assert!(lox.eval_expr("io.readLine(range(1))").is_err());
+        assert!(lox
+            .eval_expr(&format!("io.open(\"{path}\", \"rw\")"))
+            .is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn reads_and_writes_through_its_file_system() {
+        let files = Rc::new(
+            MemoryFileSystem::new()
+                .with_file("main.lox", "var result = run();")
+                .with_file("lib/in.txt", "input")
+                .with_file("log.txt", "one"),
+        );
+        let mut lox = Lox::new();
+        lox.set_file_system(files.clone());
+        lox.run(
+            "fun run() {
+                var text = io.readFile(\"lib/../lib/in.txt\");
+                io.writeFile(\"out.txt\", text + \"!\");
+                var log = io.open(\"log.txt\", \"a\");
+                io.write(log, \" two\");
+                io.close(log);
+                return list(
+                    io.fileExists(\"out.txt\"), io.fileExists(\"in.txt\"));
+            }"
+            .to_string(),
+        );
+
+        let statements = lox.load_file("main.lox");
+        assert!(statements.is_some());
+        lox.execute(&statements.unwrap_or_default());
+        assert!(!lox.had_error && !lox.had_runtime_error);
+        assert_eq!(
+            lox.get_global("result").map(|v| v.to_string()),
+            Some("[true, false]".to_string())
+        );
+        assert_eq!(
+            files.contents(Path::new("out.txt")),
+            Some(b"input!".to_vec())
+        );
+        assert_eq!(
+            files.contents(Path::new("log.txt")),
+            Some(b"one two".to_vec())
+        );
+        assert!(lox.source("main.lox").is_some());
+    }
+
+    #[test]
+    fn flushes_files_left_open_when_the_program_ends() {
+        let files = Rc::new(MemoryFileSystem::new());
+        let mut lox = Lox::new();
+        lox.set_file_system(files.clone());
+        lox.run(
+            "var kept = io.open(\"kept.txt\", \"w\");
+            io.write(kept, \"hello\");
+            fun log() {
+                var f = io.open(\"log.txt\", \"a\");
+                io.write(f, \"dropped\");
+            }
+            log();
+            var closed = io.open(\"closed.txt\", \"w\");
+            io.write(closed, \"closed\");
+            io.close(closed);"
+                .to_string(),
+        );
+        assert!(!lox.had_error && !lox.had_runtime_error);
+        assert_eq!(files.contents(Path::new("kept.txt")), Some(Vec::new()));
+        assert_eq!(
+            files.contents(Path::new("log.txt")),
+            Some(b"dropped".to_vec())
+        );
+
+        lox.shut_down();
+        for (path, contents) in [
+            ("kept.txt", "hello"),
+            ("log.txt", "dropped"),
+            ("closed.txt", "closed"),
+        ] {
+            assert_eq!(
+                files.contents(Path::new(path)),
+                Some(contents.as_bytes().to_vec()),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn denied_capabilities_stop_their_natives() {
+        let mut lox = Lox::new();
+        lox.set_file_system(Rc::new(
+            MemoryFileSystem::new().with_file("in.txt", "text"),
+        ));
+        assert!(lox.eval_expr("io.readFile(\"in.txt\")").is_ok());
+
+        lox.set_sandbox_policy(
+            SandboxPolicy::allow_all()
+                .deny(Capability::FileSystem)
+                .deny(Capability::Process),
+        );
+        for denied in [
+            "io.readFile(\"in.txt\")",
+            "io.writeFile(\"out.txt\", \"\")",
+            "io.fileExists(\"in.txt\")",
+            "io.open(\"in.txt\", \"r\")",
+            "sys.exit(0)",
+            "sys.args()",
+        ] {
+            assert!(lox.eval_expr(denied).is_err(), "{denied}");
+        }
+        assert!(lox.eval_expr("clock()").is_ok());
+        assert!(lox.eval_expr("len(\"allowed\")").is_ok());
+
+        lox.set_sandbox_policy(SandboxPolicy::deny_all());
+        assert!(lox.eval_expr("time.now()").is_err());
+        assert!(lox.eval_expr("net.tcpListen(0)").is_err());
+        assert!(lox.eval_expr("time.formatTime(0, \"%Y\")").is_ok());
+
+        // Reported at the call, like the runtime error it is.
+        let mut script = Lox::new();
+        script.set_sandbox_policy(SandboxPolicy::deny_all());
+        script.run("clock();".to_string());
+        assert!(script.had_runtime_error && !script.had_error);
+        match lox.eval_expr("\n\nclock(\n)") {
+            Err(LoxError::RuntimeError { token, message }) => {
+                assert_eq!(token.line, 4);
+                assert_eq!(
+                    message,
+                    "clock() needs the 'clock' capability, which the sandbox \
+                     denies."
+                );
+            }
+            _ => panic!("clock() should be denied"),
+        }
+        // A call from the host has no line to report.
+        assert!(matches!(
+            lox.call_function("clock", Vec::new()),
+            Err(LoxError::SystemError { .. })
+        ));
+    }
+
+    #[test]
+    fn talks_to_itself_over_tcp() {
+        let source = "var server = net.tcpListen(0);
+            var client = net.tcpConnect(\"127.0.0.1\", net.tcpPort(server));
+            var connection = net.accept(server);
+            net.send(client, \"ping\");
+            var result = list(net.recv(connection));
+            net.send(connection, \"pong\");
+            io.close(connection);
+            push(result, net.recv(client));
+            push(result, net.recv(client));
+            io.close(client);
+            io.close(server);";
+        assert_eq!(result_of(source), "[\"ping\", \"pong\", nil]");
+
+        let mut lox = Lox::new();
+        lox.run("var server = net.tcpListen(0); io.close(server);".to_string());
+        assert!(lox.eval_expr("net.accept(server)").is_err());
+        assert!(lox.eval_expr("net.tcpListen(65536)").is_err());
+        assert!(lox.eval_expr("net.send(server, \"x\")").is_err());
+    }
+
+    #[test]
+    fn fetches_over_http() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap_or_else(|e| panic!("can't listen: {e}"));
+        let port = listener
+            .local_addr()
+            .unwrap_or_else(|e| panic!("no address: {e}"))
+            .port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener
+                .accept()
+                .unwrap_or_else(|e| panic!("can't accept: {e}"));
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"hi") {
+                match stream.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buffer[..n]),
+                }
+            }
+            let response =
+                "HTTP/1.0 201 Created\r\nContent-Type: text/plain\r\n\r\nmade";
+            let _ = stream.write_all(response.as_bytes());
+            String::from_utf8_lossy(&request).into_owned()
+        });
+
+        let source = format!(
+            "var headers = mapNew();
+            mapSet(headers, \"X-Test\", \"yes\");
+            var options = mapNew();
+            mapSet(options, \"method\", \"PUT\");
+            mapSet(options, \"headers\", headers);
+            mapSet(options, \"body\", \"hi\");
+            var url = \"http://127.0.0.1:{port}/thing\";
+            var response = net.fetch(url, options);
+            var result = list(mapGet(response, \"status\"),
+                mapGet(mapGet(response, \"headers\"), \"content-type\"),
+                mapGet(response, \"body\"));"
+        );
+        assert_eq!(result_of(&source), "[201, \"text/plain\", \"made\"]");
+
+        let request = server
+            .join()
+            .unwrap_or_else(|_| panic!("the server thread panicked"));
+        assert!(request.starts_with("PUT /thing HTTP/1.0\r\n"), "{request}");
+        assert!(request.contains("\r\nX-Test: yes\r\n"), "{request}");
+        assert!(request.ends_with("\r\n\r\nhi"), "{request}");
+
+        let mut lox = Lox::new();
+        assert!(lox.eval_expr("net.fetch(\"https://example.com\")").is_err());
+    }
+
+    #[test]
+    fn formats_and_parses_times() {
+        assert_eq!(
+            eval("time.formatTime(1700000000.75, \"%Y-%m-%d %H:%M:%S\")"),
+            "2023-11-14 22:13:20"
+        );
+        assert_eq!(
+            eval("time.parseTime(\"14/11/2023 22:13\", \"%d/%m/%Y %H:%M\")"),
+            "1699999980"
+        );
+        assert_eq!(eval("time.now() > 1700000000"), "true");
+
+        let mut lox = Lox::new();
+        assert!(lox.eval_expr("time.formatTime(0, \"%Q\")").is_err());
+        assert!(lox
+            .eval_expr("time.parseTime(\"2023\", \"%Y-%m\")")
+            .is_err());
+        assert!(lox.eval_expr("time.formatTime(\"now\", \"%Y\")").is_err());
+    }
+
+    #[test]
+    fn runs_exit_hooks_last_first() {
+        let mut lox = Lox::new();
+        lox.run(
+            "var result = list();
+            fun first() { push(result, \"first\"); }
+            fun second() { push(result, \"second\"); }
+            sys.atExit(first);
+            sys.atExit(second);
+            push(result, \"main\");"
+                .to_string(),
+        );
+        assert_eq!(lox.exit_code, None);
+        lox.run_exit_hooks();
+        let result = lox.get_global("result").map(|r| r.to_string());
+        assert_eq!(
+            result.as_deref(),
+            Some("[\"main\", \"second\", \"first\"]")
+        );
+    }
+
+    #[test]
+    fn exit_unwinds_and_still_runs_exit_hooks() {
+        let mut lox = Lox::new();
+        lox.run(
+            "var result = list();
+            fun broken() { push(result, \"broken\"); nil(); }
+            fun again() { push(result, \"again\"); sys.exit(4); }
+            fun cleanup() { push(result, \"cleanup\"); }
+            sys.atExit(cleanup);
+            sys.atExit(again);
+            sys.atExit(broken);
+            fun stop() { while (true) { sys.exit(3); } }
+            stop();
+            push(result, \"unreachable\");"
+                .to_string(),
+        );
+        assert!(!lox.had_runtime_error);
+        assert_eq!(lox.exit_code, Some(3));
+
+        lox.run_exit_hooks();
+        assert!(lox.had_runtime_error);
+        assert_eq!(lox.exit_code, Some(4));
+        let result = lox.get_global("result").map(|r| r.to_string());
+        assert_eq!(
+            result.as_deref(),
+            Some("[\"broken\", \"again\", \"cleanup\"]")
+        );
+    }
+
+    #[test]
+    fn runs_files_for_their_status_without_exiting() {
+        let files = Rc::new(
+            MemoryFileSystem::new()
+                .with_file("exits.lox", "print \"before\"; sys.exit(3);")
+                .with_file("broken.lox", "print \"before\"; nil();"),
+        );
+        let run = |path: &str| {
+            let output = Rc::new(RefCell::new(Vec::new()));
+            let mut lox = Lox::new();
+            lox.set_file_system(files.clone());
+            lox.set_output(output.clone());
+            lox.set_error_output(output.clone());
+            let status = lox.run_files_for_status(&[path]);
+            let output = String::from_utf8_lossy(&output.borrow()).into_owned();
+            (status, output)
+        };
+
+        assert_eq!(run("exits.lox"), (3, "before\n".to_string()));
+        let (status, output) = run("broken.lox");
+        assert_eq!(status, 70);
+        assert!(output.contains("before\nCan only call"), "{output}");
+        let (status, output) = run("missing.lox");
+        assert_eq!(status, 66);
+        assert!(output.starts_with("Unable to read missing.lox"), "{output}");
+    }
+
+    #[test]
+    fn refuses_bad_exit_arguments() {
+        for source in [
+            "sys.atExit(1);",
+            "fun f(a) {} sys.atExit(f);",
+            "sys.exit(256);",
+            "sys.exit(1.5);",
+        ] {
+            assert_eq!(run(source), (false, true), "{source}");
+        }
+    }
+
+    // Sends the process a real Ctrl-C, so onInterrupt() must have been called.
+    #[cfg(unix)]
+    #[derive(Debug)]
+    struct RaiseInterrupt;
+
+    #[cfg(unix)]
+    impl LoxCallable for RaiseInterrupt {
+        fn arity(&self) -> usize {
+            0
+        }
+
+        fn call(
+            &self,
+            _interpreter: &mut Interpreter,
+            _arguments: Vec<Literal>,
+        ) -> Result<Literal, LoxError> {
+            crate::interrupt::raise();
+            Ok(Literal::Nil)
+        }
+    }
+
+    #[cfg(unix)]
+    impl std::fmt::Display for RaiseInterrupt {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "native raiseInterrupt function")
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn calls_the_interrupt_handler_instead_of_stopping() {
+        let mut lox = Lox::new();
+        let raise = LoxValue::from(Literal::Function(Rc::new(RaiseInterrupt)));
+        assert!(lox.set_global("raiseInterrupt", raise).is_ok());
+        lox.run(
+            "var result = list();
+            fun handler() { push(result, \"interrupted\"); }
+            sys.onInterrupt(handler);
+            push(result, \"before\");
+            raiseInterrupt();
+            push(result, \"after\");"
+                .to_string(),
+        );
+        assert!(!lox.had_error && !lox.had_runtime_error);
+        let result = lox.get_global("result").map(|r| r.to_string());
+        assert_eq!(
+            result.as_deref(),
+            Some("[\"before\", \"interrupted\", \"after\"]")
+        );
+
+        lox.run(
+            "sys.onInterrupt(handler); raiseInterrupt(); sys.exit(3);"
+                .to_string(),
+        );
+        assert_eq!(lox.exit_code, Some(3));
     }
 }
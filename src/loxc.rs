@@ -0,0 +1,999 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::bigint::BigInt;
+use crate::error_reporter::LoxError;
+use crate::expr::{
+    AssignExpr, BinaryExpr, CallExpr, Expr, GetExpr, GroupingExpr, LiteralExpr,
+    LogicalExpr, MatchArm, MatchExpr, SetExpr, ThisExpr, UnaryExpr,
+    VariableExpr,
+};
+use crate::literal::Literal;
+use crate::parser::MAX_NESTING;
+use crate::stmt::{
+    BlockStmt, BreakStmt, ClassStmt, ContinueStmt, ExpressionStmt, ForStmt,
+    FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt, VarStmt, WhileStmt,
+};
+use crate::token::{Token, REPL};
+use crate::token_type::TokenType;
+
+/* .loxc layout, all integers little endian
+file       → MAGIC VERSION constants u32(source name constant)
+             u32(statement count) stmt* ;
+constants  → u32(count) ( 0x00 f64 | 0x01 u32(len) utf8 | 0x02 i64 )* ;
+stmt, expr → opcode operands ;
+token      → u8(token type) u32(lexeme constant) u32(line) literal ;
+literal    → 0 none | 1 nil | 2 false | 3 true | 4 u32(constant)
+             | 5 u32(string constant of a bigint's digits) ;
+option     → 0 | 1 value ;
+list       → u32(count) value* ;
+*/
+
+pub const MAGIC: &[u8; 4] = b"LOXC";
+pub const VERSION: u8 = 7;
+
+pub mod op {
+    pub const BLOCK: u8 = 0x01;
+    pub const BREAK: u8 = 0x02;
+    pub const CONTINUE: u8 = 0x03;
+    pub const EXPRESSION: u8 = 0x04;
+    pub const FUNCTION: u8 = 0x05;
+    pub const IF: u8 = 0x06;
+    pub const PRINT: u8 = 0x07;
+    pub const RETURN: u8 = 0x08;
+    pub const VAR: u8 = 0x09;
+    pub const WHILE: u8 = 0x0a;
+    pub const FOR: u8 = 0x0b;
+    pub const CLASS: u8 = 0x0c;
+
+    pub const ASSIGN: u8 = 0x20;
+    pub const BINARY: u8 = 0x21;
+    pub const CALL: u8 = 0x22;
+    pub const GROUPING: u8 = 0x23;
+    pub const LITERAL: u8 = 0x24;
+    pub const LOGICAL: u8 = 0x25;
+    pub const UNARY: u8 = 0x26;
+    pub const VARIABLE: u8 = 0x27;
+    pub const GET: u8 = 0x28;
+    pub const OPTIONAL_GET: u8 = 0x29;
+    pub const SET: u8 = 0x2a;
+    pub const THIS: u8 = 0x2b;
+    pub const MATCH: u8 = 0x2c;
+}
+
+// Token types are stored as their position in this list, so reordering the
+// TokenType enum doesn't silently change the meaning of existing files.
+// New token types must only ever be appended.
+const TOKEN_TYPES: [TokenType; 49] = [
+    TokenType::LeftParen,
+    TokenType::RightParen,
+    TokenType::LeftBrace,
+    TokenType::RightBrace,
+    TokenType::Colon,
+    TokenType::Comma,
+    TokenType::Dot,
+    TokenType::Minus,
+    TokenType::Plus,
+    TokenType::Semicolon,
+    TokenType::Slash,
+    TokenType::Star,
+    TokenType::Bang,
+    TokenType::BangEqual,
+    TokenType::Equal,
+    TokenType::EqualEqual,
+    TokenType::Greater,
+    TokenType::GreaterEqual,
+    TokenType::Less,
+    TokenType::LessEqual,
+    TokenType::QuestionQuestion,
+    TokenType::QuestionDot,
+    TokenType::Identifier,
+    TokenType::String,
+    TokenType::Number,
+    TokenType::False,
+    TokenType::True,
+    TokenType::Nil,
+    TokenType::And,
+    TokenType::Break,
+    TokenType::Class,
+    TokenType::Continue,
+    TokenType::Else,
+    TokenType::Fun,
+    TokenType::For,
+    TokenType::If,
+    TokenType::Or,
+    TokenType::Print,
+    TokenType::Return,
+    TokenType::Super,
+    TokenType::This,
+    TokenType::Var,
+    TokenType::While,
+    TokenType::EOF,
+    TokenType::Is,
+    TokenType::Match,
+    TokenType::Case,
+    TokenType::Enum,
+    TokenType::Percent,
+];
+
+pub fn token_type_code(token_type: TokenType) -> u8 {
+    TOKEN_TYPES
+        .iter()
+        .position(|t| *t == token_type)
+        .expect("every token type has a .loxc code") as u8
+}
+
+pub fn token_type_from_code(code: u8) -> Option<TokenType> {
+    TOKEN_TYPES.get(code as usize).copied()
+}
+
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub enum Constant {
+    Number(f64),
+    String(Rc<str>),
+    Integer(i64),
+}
+
+// `source_name` is restored into every loaded token so diagnostics still
+// name the original file.
+pub fn compile(source_name: &str, statements: &[Stmt]) -> Vec<u8> {
+    let mut writer = Writer::new();
+    let source = writer.string(source_name);
+    writer.u32(statements.len() as u32);
+    for statement in statements {
+        writer.stmt(statement);
+    }
+
+    let mut bytes = Vec::with_capacity(writer.code.len() + 64);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&(writer.constants.len() as u32).to_le_bytes());
+    for constant in &writer.constants {
+        match constant {
+            Constant::Number(n) => {
+                bytes.push(0);
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            Constant::String(s) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(s.as_bytes());
+            }
+            Constant::Integer(n) => {
+                bytes.push(2);
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+        }
+    }
+    bytes.extend_from_slice(&source.to_le_bytes());
+    bytes.extend_from_slice(&writer.code);
+
+    bytes
+}
+
+pub fn load(bytes: &[u8]) -> Result<Vec<Stmt>, LoxError> {
+    let mut reader = Reader::new(bytes)?;
+    let count = reader.u32()?;
+    let mut statements = Vec::new();
+    for _ in 0..count {
+        statements.push(reader.stmt()?);
+    }
+
+    if !reader.is_at_end() {
+        return Err(reader.error("trailing bytes after the last statement"));
+    }
+
+    Ok(statements)
+}
+
+struct Writer {
+    constants: Vec<Constant>,
+    numbers: HashMap<u64, u32>,
+    integers: HashMap<i64, u32>,
+    strings: HashMap<String, u32>,
+    code: Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer {
+            constants: Vec::new(),
+            numbers: HashMap::new(),
+            integers: HashMap::new(),
+            strings: HashMap::new(),
+            code: Vec::new(),
+        }
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.code.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.code.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn number(&mut self, value: f64) -> u32 {
+        let next = self.constants.len() as u32;
+        let index = *self.numbers.entry(value.to_bits()).or_insert(next);
+        if index == next {
+            self.constants.push(Constant::Number(value));
+        }
+        index
+    }
+
+    fn integer(&mut self, value: i64) -> u32 {
+        let next = self.constants.len() as u32;
+        let index = *self.integers.entry(value).or_insert(next);
+        if index == next {
+            self.constants.push(Constant::Integer(value));
+        }
+        index
+    }
+
+    fn string(&mut self, value: &str) -> u32 {
+        if let Some(index) = self.strings.get(value) {
+            return *index;
+        }
+
+        let index = self.constants.len() as u32;
+        self.constants.push(Constant::String(value.into()));
+        self.strings.insert(value.to_owned(), index);
+        index
+    }
+
+    fn literal(&mut self, literal: &Option<Literal>) {
+        match literal {
+            None => self.u8(0),
+            Some(Literal::Nil) | Some(Literal::NilImplicit) => self.u8(1),
+            Some(Literal::Bool(false)) => self.u8(2),
+            Some(Literal::Bool(true)) => self.u8(3),
+            Some(Literal::Number(n)) => {
+                let index = self.number(*n);
+                self.u8(4);
+                self.u32(index);
+            }
+            Some(Literal::Integer(n)) => {
+                let index = self.integer(*n);
+                self.u8(4);
+                self.u32(index);
+            }
+            Some(Literal::String(s)) => {
+                let index = self.string(s);
+                self.u8(4);
+                self.u32(index);
+            }
+            Some(Literal::BigInt(n)) => {
+                let index = self.string(&n.to_string());
+                self.u8(5);
+                self.u32(index);
+            }
+            // Functions, classes, instances, lists, maps, ranges, namespaces
+            // and foreign values only exist at runtime and never appear in
+            // the AST.
+            Some(
+                Literal::Function(_)
+                | Literal::Class(_)
+                | Literal::Instance(_)
+                | Literal::List(_)
+                | Literal::Map(_)
+                | Literal::Range(_)
+                | Literal::Namespace(_)
+                | Literal::Foreign(_),
+            ) => self.u8(1),
+        }
+    }
+
+    fn token(&mut self, token: &Token) {
+        let lexeme = self.string(&token.lexeme);
+        self.u8(token_type_code(token.token_type));
+        self.u32(lexeme);
+        self.u32(token.line);
+        self.literal(&token.literal);
+    }
+
+    fn option_token(&mut self, token: &Option<Token>) {
+        match token {
+            None => self.u8(0),
+            Some(t) => {
+                self.u8(1);
+                self.token(t);
+            }
+        }
+    }
+
+    fn option_expr(&mut self, expr: &Option<Rc<Expr>>) {
+        match expr {
+            None => self.u8(0),
+            Some(e) => {
+                self.u8(1);
+                self.expr(e);
+            }
+        }
+    }
+
+    fn stmts(&mut self, statements: &[Stmt]) {
+        self.u32(statements.len() as u32);
+        for statement in statements {
+            self.stmt(statement);
+        }
+    }
+
+    fn function(&mut self, function: &FunctionStmt) {
+        self.token(&function.name);
+        self.u32(function.params.len() as u32);
+        for (param, param_type) in
+            function.params.iter().zip(&function.param_types)
+        {
+            self.token(param);
+            self.option_token(param_type);
+        }
+        self.option_token(&function.return_type);
+        self.stmts(&function.body);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(bs) => {
+                self.u8(op::BLOCK);
+                self.stmts(&bs.statements);
+            }
+            Stmt::Break(bs) => {
+                self.u8(op::BREAK);
+                self.token(&bs.keyword);
+                self.option_token(&bs.label);
+            }
+            Stmt::Continue(cs) => {
+                self.u8(op::CONTINUE);
+                self.token(&cs.keyword);
+                self.option_token(&cs.label);
+            }
+            Stmt::Expression(es) => {
+                self.u8(op::EXPRESSION);
+                self.expr(&es.expression);
+            }
+            Stmt::Function(fs) => {
+                self.u8(op::FUNCTION);
+                self.function(fs);
+            }
+            Stmt::Class(cs) => {
+                self.u8(op::CLASS);
+                self.token(&cs.name);
+                self.u32(cs.methods.len() as u32);
+                for method in &cs.methods {
+                    self.function(method);
+                }
+            }
+            Stmt::If(ifs) => {
+                self.u8(op::IF);
+                self.token(&ifs.keyword);
+                self.expr(&ifs.condition);
+                self.stmt(&ifs.then_branch);
+                match &ifs.else_branch {
+                    None => self.u8(0),
+                    Some(else_branch) => {
+                        self.u8(1);
+                        self.stmt(else_branch);
+                    }
+                }
+            }
+            Stmt::Print(ps) => {
+                self.u8(op::PRINT);
+                self.expr(&ps.expression);
+            }
+            Stmt::Return(rs) => {
+                self.u8(op::RETURN);
+                self.token(&rs.keyword);
+                self.option_expr(&rs.value);
+            }
+            Stmt::Var(vs) => {
+                self.u8(op::VAR);
+                self.token(&vs.name);
+                self.option_token(&vs.type_annotation);
+                self.option_expr(&vs.initializer);
+            }
+            Stmt::While(ws) => {
+                self.u8(op::WHILE);
+                self.token(&ws.keyword);
+                self.option_token(&ws.label);
+                self.expr(&ws.condition);
+                self.stmt(&ws.body);
+            }
+            Stmt::For(fs) => {
+                self.u8(op::FOR);
+                self.token(&fs.keyword);
+                self.option_token(&fs.label);
+                match &fs.initializer {
+                    None => self.u8(0),
+                    Some(initializer) => {
+                        self.u8(1);
+                        self.stmt(initializer);
+                    }
+                }
+                self.option_expr(&fs.condition);
+                self.option_expr(&fs.increment);
+                self.stmt(&fs.body);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign(ae) => {
+                self.u8(op::ASSIGN);
+                self.token(&ae.name);
+                self.expr(&ae.value);
+            }
+            Expr::Binary(be) => {
+                self.u8(op::BINARY);
+                self.token(&be.operator);
+                self.expr(&be.left);
+                self.expr(&be.right);
+            }
+            Expr::Call(ce) => {
+                self.u8(op::CALL);
+                self.token(&ce.paren);
+                self.expr(&ce.callee);
+                self.u32(ce.arguments.len() as u32);
+                for argument in &ce.arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Get(ge) => {
+                self.u8(if ge.optional {
+                    op::OPTIONAL_GET
+                } else {
+                    op::GET
+                });
+                self.token(&ge.name);
+                self.expr(&ge.object);
+            }
+            Expr::Grouping(ge) => {
+                self.u8(op::GROUPING);
+                self.expr(&ge.expression);
+            }
+            Expr::Literal(le) => {
+                self.u8(op::LITERAL);
+                self.literal(&le.value);
+            }
+            Expr::Logical(le) => {
+                self.u8(op::LOGICAL);
+                self.token(&le.operator);
+                self.expr(&le.left);
+                self.expr(&le.right);
+            }
+            Expr::Unary(ue) => {
+                self.u8(op::UNARY);
+                self.token(&ue.operator);
+                self.expr(&ue.right);
+            }
+            Expr::Variable(ve) => {
+                self.u8(op::VARIABLE);
+                self.token(&ve.name);
+            }
+            Expr::Set(se) => {
+                self.u8(op::SET);
+                self.token(&se.name);
+                self.expr(&se.object);
+                self.expr(&se.value);
+            }
+            Expr::This(te) => {
+                self.u8(op::THIS);
+                self.token(&te.keyword);
+            }
+            Expr::Match(me) => {
+                self.u8(op::MATCH);
+                self.token(&me.keyword);
+                self.expr(&me.subject);
+                self.u32(me.arms.len() as u32);
+                for arm in &me.arms {
+                    self.option_token(&arm.binding);
+                    self.option_expr(&arm.pattern);
+                    self.option_expr(&arm.guard);
+                    self.expr(&arm.value);
+                }
+            }
+        }
+    }
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    current: usize,
+    constants: Vec<Constant>,
+    source_name: Rc<str>,
+    depth: usize,
+}
+
+impl Reader<'_> {
+    pub fn new(bytes: &[u8]) -> Result<Reader<'_>, LoxError> {
+        let mut reader = Reader {
+            bytes,
+            current: 0,
+            constants: Vec::new(),
+            source_name: Rc::from(REPL),
+            depth: 0,
+        };
+
+        if !is_compiled(bytes) {
+            return Err(reader.error("missing LOXC header"));
+        }
+        reader.current = MAGIC.len();
+
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(reader.error(&format!(
+                "unsupported version {version}, expected {VERSION}"
+            )));
+        }
+
+        let count = reader.u32()?;
+        for _ in 0..count {
+            let constant = match reader.u8()? {
+                0 => Constant::Number(f64::from_le_bytes(reader.array()?)),
+                2 => Constant::Integer(i64::from_le_bytes(reader.array()?)),
+                1 => {
+                    let len = reader.u32()? as usize;
+                    let bytes = reader.take(len)?;
+                    match String::from_utf8(bytes.to_vec()) {
+                        Ok(s) => Constant::String(s.into()),
+                        Err(_) => {
+                            return Err(reader.error("invalid UTF-8 string"))
+                        }
+                    }
+                }
+                tag => {
+                    return Err(
+                        reader.error(&format!("unknown constant tag {tag}"))
+                    )
+                }
+            };
+            reader.constants.push(constant);
+        }
+        reader.source_name = reader.string()?;
+
+        Ok(reader)
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    pub fn source_name(&self) -> &str {
+        &self.source_name
+    }
+
+    pub fn offset(&self) -> usize {
+        self.current
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.current >= self.bytes.len()
+    }
+
+    pub fn error(&self, message: &str) -> LoxError {
+        LoxError::system_error(format!(
+            "Invalid compiled file at byte {}: {message}.",
+            self.current
+        ))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8], LoxError> {
+        if self.current + len > self.bytes.len() {
+            return Err(self.error("unexpected end of file"));
+        }
+
+        let slice = &self.bytes[self.current..self.current + len];
+        self.current += len;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoxError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, LoxError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, LoxError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn constant(&self, index: u32) -> Result<&Constant, LoxError> {
+        match self.constants.get(index as usize) {
+            Some(c) => Ok(c),
+            None => Err(self.error(&format!("unknown constant {index}"))),
+        }
+    }
+
+    fn string(&mut self) -> Result<Rc<str>, LoxError> {
+        let index = self.u32()?;
+        match self.constant(index)? {
+            Constant::String(s) => Ok(s.clone()),
+            Constant::Number(_) | Constant::Integer(_) => {
+                Err(self.error("expected a string constant"))
+            }
+        }
+    }
+
+    pub fn literal(&mut self) -> Result<Option<Literal>, LoxError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(Literal::Nil)),
+            2 => Ok(Some(Literal::Bool(false))),
+            3 => Ok(Some(Literal::Bool(true))),
+            4 => {
+                let index = self.u32()?;
+                Ok(Some(match self.constant(index)? {
+                    Constant::Number(n) => Literal::Number(*n),
+                    Constant::Integer(n) => Literal::Integer(*n),
+                    Constant::String(s) => Literal::String(s.clone()),
+                }))
+            }
+            5 => match BigInt::parse(&self.string()?) {
+                Some(n) => Ok(Some(Literal::BigInt(Rc::new(n)))),
+                None => Err(self.error("invalid bigint constant")),
+            },
+            tag => Err(self.error(&format!("unknown literal tag {tag}"))),
+        }
+    }
+
+    pub fn token(&mut self) -> Result<Token, LoxError> {
+        let code = self.u8()?;
+        let token_type = match token_type_from_code(code) {
+            Some(t) => t,
+            None => {
+                return Err(self.error(&format!("unknown token type {code}")))
+            }
+        };
+        let lexeme = self.string()?;
+        let line = self.u32()?;
+        let literal = self.literal()?;
+
+        Ok(Token::new(
+            token_type,
+            lexeme,
+            literal,
+            line,
+            self.source_name.clone(),
+        ))
+    }
+
+    fn option_token(&mut self) -> Result<Option<Token>, LoxError> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.token()?)),
+        }
+    }
+
+    fn option_expr(&mut self) -> Result<Option<Rc<Expr>>, LoxError> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(Rc::new(self.expr()?))),
+        }
+    }
+
+    fn stmts(&mut self) -> Result<Vec<Stmt>, LoxError> {
+        let count = self.u32()?;
+        let mut statements = Vec::new();
+        for _ in 0..count {
+            statements.push(self.stmt()?);
+        }
+        Ok(statements)
+    }
+
+    fn function(&mut self) -> Result<FunctionStmt, LoxError> {
+        let name = self.token()?;
+        let count = self.u32()?;
+        let mut params = Vec::new();
+        let mut param_types = Vec::new();
+        for _ in 0..count {
+            params.push(self.token()?);
+            param_types.push(self.option_token()?);
+        }
+        let return_type = self.option_token()?;
+        let body = self.stmts()?;
+        Ok(FunctionStmt::new(
+            name,
+            params,
+            param_types,
+            return_type,
+            Rc::new(body),
+        ))
+    }
+
+    // Statements and expressions nest no deeper than the parser allows, so a
+    // crafted file can't overflow the stack while it is read.
+    pub fn nested<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, LoxError>,
+    ) -> Result<T, LoxError> {
+        if self.depth == MAX_NESTING {
+            return Err(self.error("nesting is too deep"));
+        }
+
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, LoxError> {
+        self.nested(Self::stmt_kind)
+    }
+
+    fn stmt_kind(&mut self) -> Result<Stmt, LoxError> {
+        let stmt = match self.u8()? {
+            op::BLOCK => Stmt::Block(BlockStmt::new(self.stmts()?)),
+            op::BREAK => {
+                let keyword = self.token()?;
+                Stmt::Break(BreakStmt::new(keyword, self.option_token()?))
+            }
+            op::CONTINUE => {
+                let keyword = self.token()?;
+                Stmt::Continue(ContinueStmt::new(keyword, self.option_token()?))
+            }
+            op::EXPRESSION => {
+                Stmt::Expression(ExpressionStmt::new(Rc::new(self.expr()?)))
+            }
+            op::FUNCTION => Stmt::Function(self.function()?),
+            op::CLASS => {
+                let name = self.token()?;
+                let count = self.u32()?;
+                let mut methods = Vec::new();
+                for _ in 0..count {
+                    methods.push(self.function()?);
+                }
+                Stmt::Class(ClassStmt::new(name, methods))
+            }
+            op::IF => {
+                let keyword = self.token()?;
+                let condition = Rc::new(self.expr()?);
+                let then_branch = Rc::new(self.stmt()?);
+                let else_branch = match self.u8()? {
+                    0 => None,
+                    _ => Some(Rc::new(self.stmt()?)),
+                };
+                Stmt::If(IfStmt::new(
+                    keyword,
+                    condition,
+                    then_branch,
+                    else_branch,
+                ))
+            }
+            op::PRINT => Stmt::Print(PrintStmt::new(Rc::new(self.expr()?))),
+            op::RETURN => {
+                let keyword = self.token()?;
+                Stmt::Return(ReturnStmt::new(keyword, self.option_expr()?))
+            }
+            op::VAR => {
+                let name = self.token()?;
+                let type_annotation = self.option_token()?;
+                let initializer = self.option_expr()?;
+                Stmt::Var(VarStmt::new(name, type_annotation, initializer))
+            }
+            op::WHILE => {
+                let keyword = self.token()?;
+                let label = self.option_token()?;
+                let condition = Rc::new(self.expr()?);
+                let body = Rc::new(self.stmt()?);
+                Stmt::While(WhileStmt::new(keyword, label, condition, body))
+            }
+            op::FOR => {
+                let keyword = self.token()?;
+                let label = self.option_token()?;
+                let initializer = match self.u8()? {
+                    0 => None,
+                    _ => Some(Rc::new(self.stmt()?)),
+                };
+                let condition = self.option_expr()?;
+                let increment = self.option_expr()?;
+                let body = Rc::new(self.stmt()?);
+                Stmt::For(ForStmt::new(
+                    keyword,
+                    label,
+                    initializer,
+                    condition,
+                    increment,
+                    body,
+                ))
+            }
+            code => {
+                return Err(self
+                    .error(&format!("unknown statement opcode {code:#04x}")))
+            }
+        };
+
+        Ok(stmt)
+    }
+
+    fn expr(&mut self) -> Result<Expr, LoxError> {
+        self.nested(Self::expr_kind)
+    }
+
+    fn expr_kind(&mut self) -> Result<Expr, LoxError> {
+        let expr = match self.u8()? {
+            op::ASSIGN => {
+                let name = self.token()?;
+                Expr::Assign(AssignExpr::new(name, Rc::new(self.expr()?)))
+            }
+            op::BINARY => {
+                let operator = self.token()?;
+                let left = Rc::new(self.expr()?);
+                let right = Rc::new(self.expr()?);
+                Expr::Binary(BinaryExpr::new(left, operator, right))
+            }
+            op::CALL => {
+                let paren = self.token()?;
+                let callee = Rc::new(self.expr()?);
+                let count = self.u32()?;
+                let mut arguments = Vec::new();
+                for _ in 0..count {
+                    arguments.push(Rc::new(self.expr()?));
+                }
+                // Follows from the callee, so it isn't stored.
+                let optional = callee.in_optional_chain();
+                Expr::Call(CallExpr::new(callee, paren, arguments, optional))
+            }
+            code @ (op::GET | op::OPTIONAL_GET) => {
+                let name = self.token()?;
+                let object = Rc::new(self.expr()?);
+                Expr::Get(GetExpr::new(object, name, code == op::OPTIONAL_GET))
+            }
+            op::GROUPING => {
+                Expr::Grouping(GroupingExpr::new(Rc::new(self.expr()?)))
+            }
+            op::LITERAL => Expr::Literal(LiteralExpr::new(self.literal()?)),
+            op::LOGICAL => {
+                let operator = self.token()?;
+                let left = Rc::new(self.expr()?);
+                let right = Rc::new(self.expr()?);
+                Expr::Logical(LogicalExpr::new(left, operator, right))
+            }
+            op::UNARY => {
+                let operator = self.token()?;
+                Expr::Unary(UnaryExpr::new(operator, Rc::new(self.expr()?)))
+            }
+            op::VARIABLE => Expr::Variable(VariableExpr::new(self.token()?)),
+            op::SET => {
+                let name = self.token()?;
+                let object = Rc::new(self.expr()?);
+                let value = Rc::new(self.expr()?);
+                Expr::Set(SetExpr::new(object, name, value))
+            }
+            op::THIS => Expr::This(ThisExpr::new(self.token()?)),
+            op::MATCH => {
+                let keyword = self.token()?;
+                let subject = Rc::new(self.expr()?);
+                let count = self.u32()?;
+                let mut arms = Vec::new();
+                for _ in 0..count {
+                    arms.push(MatchArm {
+                        binding: self.option_token()?,
+                        pattern: self.option_expr()?,
+                        guard: self.option_expr()?,
+                        value: Rc::new(self.expr()?),
+                    });
+                }
+                Expr::Match(MatchExpr::new(keyword, subject, arms))
+            }
+            code => {
+                return Err(self
+                    .error(&format!("unknown expression opcode {code:#04x}")))
+            }
+        };
+
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast_printer::AstPrinter;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    // Every kind of statement and expression, with the optional parts both
+    // there and left out.
+    const EVERY_KIND: &str = "\
+var a: Number = 1;
+var b;
+{ var c = -a; }
+class Point {
+  init(x, y) { this.x = x; this.y = y; }
+  sum(): Number { return this.x + this.y; }
+}
+fun typed(n: Number, s): String { return s + n; }
+fun nothing() { return; }
+outer: for (var i = 0; i < 3; i = i + 1) {
+  if (i == 1) continue outer; else break outer;
+}
+for (;;) break;
+inner: while (a > 0 and !false or nil ?? true) { a = a - 1; continue; }
+print (1.5 * 2) / 3 % 4;
+print Point(1, 2).sum();
+print Point(1, 2)?.x;
+Point(1, 2).x = \"text\";
+print 12n;
+print match (a) {
+  case 1: \"one\"
+  case -2: \"minus two\"
+  case Point.x: nil
+  case n if n > 3: n
+  case _: true
+};
+";
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let parsed = Parser::new(&tokens).parse();
+        parsed.unwrap_or_else(|_| panic!("test source should parse"))
+    }
+
+    fn load_error(bytes: &[u8]) -> String {
+        match load(bytes) {
+            Err(LoxError::SystemError { message }) => message,
+            _ => panic!("loading should fail"),
+        }
+    }
+
+    fn printed(statements: &[Stmt]) -> Vec<String> {
+        let mut printer = AstPrinter::new();
+        statements.iter().map(|s| printer.print_stmt(s)).collect()
+    }
+
+    #[test]
+    fn loads_what_it_compiles() {
+        let statements = parse(EVERY_KIND);
+        let compiled = compile("every.lox", &statements);
+        let loaded = load(&compiled)
+            .unwrap_or_else(|_| panic!("the compiled file should load"));
+
+        assert_eq!(printed(&loaded), printed(&statements));
+        // Compiling again gives the same bytes, so nothing the printer
+        // leaves out, like token lines or types, was lost either.
+        assert_eq!(compile("every.lox", &loaded), compiled);
+        let names: Vec<&str> = loaded
+            .iter()
+            .filter_map(|s| match s {
+                Stmt::Var(vs) => Some(&*vs.name.source),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["every.lox", "every.lox"]);
+    }
+
+    #[test]
+    fn rejects_files_it_did_not_write() {
+        let mut compiled = compile("empty.lox", &[]);
+        assert!(load(&compiled).is_ok_and(|s| s.is_empty()));
+
+        let mut wrong_magic = compiled.clone();
+        wrong_magic[0] = b'X';
+        assert_eq!(
+            load_error(&wrong_magic),
+            "Invalid compiled file at byte 0: missing LOXC header."
+        );
+        assert_eq!(
+            load_error(b"print 1;"),
+            "Invalid compiled file at byte 0: missing LOXC header."
+        );
+
+        compiled[MAGIC.len()] = VERSION + 1;
+        assert_eq!(
+            load_error(&compiled),
+            format!(
+                "Invalid compiled file at byte 5: unsupported version {}, \
+                 expected {VERSION}.",
+                VERSION + 1
+            )
+        );
+    }
+}
@@ -1,37 +1,421 @@
 use std::env;
-use std::path::Path;
-use std::process;
-
-mod ast_printer;
-mod callable;
-mod environment;
-mod error_reporter;
-mod expr;
-mod function;
-mod interpreter;
-mod literal;
-mod lox;
-mod native_functions;
-mod parser;
-mod scanner;
-mod stmt;
-mod token;
-mod token_type;
-
-use lox::Lox;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::thread;
+use std::time::Duration;
+
+use rlox::call_graph::GraphFormat;
+use rlox::debugger::{Breakpoint, ConsoleDebugger, Debugger};
+use rlox::features::LanguageFeatures;
+use rlox::interpreter::InterpreterOptions;
+use rlox::lox::Lox;
+use rlox::metrics::Thresholds;
+use rlox::parser::ParserOptions;
+use rlox::sandbox::SandboxPolicy;
+use rlox::test_runner;
+use rlox::watch::{self, Watcher};
+
+// Room for MAX_CALL_DEPTH nested Lox calls even in debug builds, whose stack
+// frames are several times larger than release ones.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 
 fn main() {
+    let cli = thread::Builder::new().stack_size(STACK_SIZE).spawn(run);
+    match cli {
+        Ok(cli) => {
+            // A panic has already been printed by the thread itself.
+            if cli.join().is_err() {
+                process::exit(101);
+            }
+        }
+        Err(_) => run(),
+    }
+}
+
+// What the command line asked for, with the Lox its options set up.
+struct Cli<'a> {
+    lox: Lox,
+    paths: Vec<&'a String>,
+    output: Option<PathBuf>,
+    watch: bool,
+    print_diff: bool,
+    graph_format: Option<GraphFormat>,
+    print_metrics: bool,
+    thresholds: Option<Thresholds>,
+    jobs: Option<usize>,
+    // The preludes, which a watched script is run again for too.
+    preludes: Vec<PathBuf>,
+}
+
+fn run() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // Everything after `--` belongs to the script, which gets it as the list
+    // sys.args() returns.
+    let (options, script_args) = match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (&args[..], &[][..]),
+    };
+
+    let Cli {
+        mut lox,
+        mut paths,
+        output,
+        watch,
+        print_diff,
+        graph_format,
+        print_metrics,
+        thresholds,
+        jobs,
+        mut preludes,
+    } = parse(options, script_args);
+
+    // `rlox test` runs each test file with the options it was given.
+    let positional = paths.clone();
+    let command = match paths.first().map(|p| p.as_str()) {
+        Some("check") | Some("compile") | Some("disasm") | Some("graph")
+        | Some("lint") | Some("rename") | Some("run") | Some("test") => {
+            Some(paths.remove(0).as_str())
+        }
+        _ => None,
+    };
+
+    match (command, paths.as_slice()) {
+        (Some("compile"), [path]) => {
+            let path = Path::new(path);
+            let output = output.unwrap_or_else(|| path.with_extension("loxc"));
+            lox.compile_file(path, &output);
+        }
+        (Some("rename"), [path, position, new_name]) => {
+            lox.rename_in_file(
+                Path::new(path),
+                line_and_column(position),
+                new_name,
+                print_diff,
+            );
+        }
+        (Some("graph"), [path]) => {
+            lox.graph_file(Path::new(path), graph_format.unwrap_or_default());
+        }
+        (Some("lint"), [_, ..]) => {
+            let paths: Vec<&Path> = paths.iter().map(Path::new).collect();
+            lox.lint_files(
+                &paths,
+                thresholds.unwrap_or_default(),
+                print_metrics,
+            );
+        }
+        (Some("test"), [_, ..]) if !watch => {
+            // --jobs is the runner's own; everything else goes to each file.
+            let mut forwarded = Vec::new();
+            let mut options = options
+                .iter()
+                .filter(|a| !positional.iter().any(|p| std::ptr::eq(*p, *a)));
+            while let Some(option) = options.next() {
+                match option.as_str() {
+                    "--jobs" => {
+                        options.next();
+                    }
+                    _ => forwarded.push(option.clone()),
+                }
+            }
+            run_tests(&paths, &forwarded, script_args, jobs);
+        }
+        (_, _)
+            if output.is_some()
+                || jobs.is_some()
+                || print_diff
+                || graph_format.is_some()
+                || print_metrics
+                || thresholds.is_some() =>
+        {
+            usage()
+        }
+        (Some("run") | None, [_, ..]) if watch => {
+            preludes.extend(paths.iter().map(PathBuf::from));
+            watch_files(&args, &preludes);
+        }
+        (_, _) if watch => usage(),
+        (Some("disasm"), [path]) => lox.disassemble_file(Path::new(path)),
+        (Some("check"), [_, ..]) => {
+            let paths: Vec<&Path> = paths.iter().map(Path::new).collect();
+            lox.check_files(&paths);
+        }
+        (None, []) => lox.run_prompt(),
+        (Some("run") | None, [_, ..]) => {
+            let paths: Vec<&Path> = paths.iter().map(Path::new).collect();
+            lox.run_files(&paths);
+        }
+        _ => usage(),
+    }
+}
+
+// Reads the options before `--`, setting up a Lox to run with them and
+// `script_args`. Anything that isn't an option is one of the paths.
+fn parse<'a>(options: &'a [String], script_args: &[String]) -> Cli<'a> {
     let mut lox = Lox::new();
+    let mut paths = Vec::new();
+    let mut output = None;
+    let mut watch = false;
+    let mut print_diff = false;
+    let mut graph_format = None;
+    let mut print_metrics = false;
+    let mut thresholds = None;
+    let mut jobs = None;
+    let mut debugger = Debugger::new(Box::new(ConsoleDebugger));
+    let mut debugging = false;
+    let mut preludes = Vec::new();
+    let mut parser_options = ParserOptions::default();
+    let mut interpreter_options = InterpreterOptions::default();
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--record" => match options.next() {
+                Some(path) => lox.record_to(PathBuf::from(path)),
+                None => usage(),
+            },
+            "-o" => match options.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => usage(),
+            },
+            "--prelude" => match options.next() {
+                Some(path) => {
+                    preludes.push(PathBuf::from(path));
+                    lox.add_prelude(PathBuf::from(path));
+                }
+                None => usage(),
+            },
+            "--prelude-snapshot" => match options.next() {
+                Some(path) => lox.set_prelude_snapshot(PathBuf::from(path)),
+                None => usage(),
+            },
+            "--watch" => watch = true,
+            "--diff" => print_diff = true,
+            "--metrics" => print_metrics = true,
+            "--break" => {
+                let breakpoint =
+                    match options.next().map(|b| Breakpoint::parse(b)) {
+                        Some(Ok(breakpoint)) => breakpoint,
+                        Some(Err(message)) => {
+                            eprintln!("{message}");
+                            usage()
+                        }
+                        None => usage(),
+                    };
+                debugger.add_breakpoint(breakpoint);
+                debugging = true;
+            }
+            "--watch-var" => match options.next() {
+                Some(name) => {
+                    debugger.watch(name);
+                    debugging = true;
+                }
+                None => usage(),
+            },
+            "--trace-var" => match options.next() {
+                Some(name) => lox.trace_variable(name),
+                None => usage(),
+            },
+            "--thresholds" => {
+                thresholds = match options.next().map(|l| Thresholds::parse(l))
+                {
+                    Some(Ok(thresholds)) => Some(thresholds),
+                    Some(Err(message)) => {
+                        eprintln!("{message}");
+                        usage()
+                    }
+                    None => usage(),
+                };
+            }
+            "--stats" => lox.print_stats_at_exit(),
+            "--dump-scopes" => lox.dump_scopes(),
+            "--strict-bool" => interpreter_options.strict_booleans = true,
+            "--global-natives" => interpreter_options.global_natives = true,
+            "--jobs" => jobs = Some(count(options.next()).max(1)),
+            "--max-args" => {
+                parser_options.max_arguments = count(options.next());
+            }
+            "--max-nesting" => {
+                parser_options.max_nesting = count(options.next());
+            }
+            "--max-call-depth" => {
+                interpreter_options.max_call_depth = count(options.next());
+            }
+            "--max-loop-iterations" => {
+                interpreter_options.max_loop_iterations =
+                    Some(count(options.next()));
+            }
+            // In milliseconds, where 0 lets tests run forever.
+            "--test-timeout" => {
+                interpreter_options.test_timeout = match count(options.next()) {
+                    0 => None,
+                    ms => Some(Duration::from_millis(ms as u64)),
+                };
+            }
+            "--features" => {
+                let features = match options
+                    .next()
+                    .map(|list| LanguageFeatures::parse(list))
+                {
+                    Some(Ok(features)) => features,
+                    Some(Err(message)) => {
+                        eprintln!("{message}");
+                        usage()
+                    }
+                    None => usage(),
+                };
+                parser_options.features = features;
+                interpreter_options.features = features;
+            }
+            "--deny" => {
+                interpreter_options.sandbox = match options
+                    .next()
+                    .map(|list| SandboxPolicy::parse_denied(list))
+                {
+                    Some(Ok(policy)) => policy,
+                    Some(Err(message)) => {
+                        eprintln!("{message}");
+                        usage()
+                    }
+                    None => usage(),
+                };
+            }
+            "--typed" => lox.set_typed(true),
+            "--freeze-globals" => lox.set_freeze_globals(true),
+            "--optimize" => lox.set_optimize(true),
+            "--inline" => lox.set_inline(true),
+            _ if option.starts_with("--format=") => {
+                graph_format = option
+                    .strip_prefix("--format=")
+                    .and_then(GraphFormat::named);
+                if graph_format.is_none() {
+                    usage();
+                }
+            }
+            _ if option.starts_with('-') => usage(),
+            _ => paths.push(option),
+        }
+    }
+
+    lox.set_parser_options(parser_options);
+    lox.set_interpreter_options(interpreter_options);
+    lox.set_script_args(script_args.to_vec());
+    if debugging {
+        lox.set_debugger(debugger);
+    }
+
+    Cli {
+        lox,
+        paths,
+        output,
+        watch,
+        print_diff,
+        graph_format,
+        print_metrics,
+        thresholds,
+        jobs,
+        preludes,
+    }
+}
 
-    let args: Vec<String> = env::args().collect();
-    let args_len = args.len();
+// Runs rlox again with the same arguments, minus --watch, whenever one of
+// `files` changes, stopping the previous run if it is still going. Each run
+// is a separate process, so it starts from fresh globals and exit() or an
+// error ends only that run.
+fn watch_files(args: &[String], files: &[PathBuf]) -> ! {
+    let program = env::current_exe().unwrap_or_else(|e| {
+        eprintln!("Unable to find the rlox executable: {e}");
+        process::exit(70);
+    });
+    let script_args = args.iter().position(|a| a == "--");
+    let args: Vec<&String> = args
+        .iter()
+        .enumerate()
+        .filter(|&(i, a)| a != "--watch" || script_args.is_some_and(|s| i > s))
+        .map(|(_, a)| a)
+        .collect();
+
+    let mut watcher = Watcher::new(files);
+    loop {
+        let mut child = match Command::new(&program).args(&args).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                eprintln!("Unable to run rlox: {e}");
+                process::exit(70);
+            }
+        };
+
+        let mut running = true;
+        let changed = loop {
+            thread::sleep(watch::POLL_INTERVAL);
+            if running && matches!(child.try_wait(), Ok(Some(_))) {
+                running = false;
+            }
+            let changed = watcher.changed();
+            if !changed.is_empty() {
+                break changed
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+            }
+        };
+
+        if running {
+            let _ = child.kill();
+        }
+        let _ = child.wait();
+        println!("\n--- {changed} changed, running again ---\n");
+    }
+}
 
-    if args_len > 2 {
-        println!("Usage: rlox [script]");
-        process::exit(64);
-    } else if args_len == 2 {
-        lox.run_file(Path::new(&args[1]));
-    } else {
-        lox.run_prompt();
+// Runs the test files on `jobs` threads, or one per core by default, each
+// in a Lox set up by `options` and `script_args`.
+fn run_tests(
+    paths: &[&String],
+    options: &[String],
+    script_args: &[String],
+    jobs: Option<usize>,
+) -> ! {
+    let paths: Vec<&Path> = paths.iter().map(Path::new).collect();
+    let files = test_runner::discover(&paths).unwrap_or_else(|e| {
+        eprintln!("Unable to find the test files: {e}");
+        process::exit(66);
+    });
+    if files.is_empty() {
+        println!("No *{} files found.", test_runner::TEST_FILE_SUFFIX);
+        process::exit(0);
     }
+
+    let jobs = jobs.unwrap_or_else(|| {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    });
+    let passed = test_runner::run(&files, jobs, STACK_SIZE, || {
+        parse(options, script_args).lox
+    });
+    process::exit(if passed { 0 } else { 1 });
+}
+
+// The number given after an option like --max-args.
+fn count(value: Option<&String>) -> usize {
+    value
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| usage())
+}
+
+// A position given as `line:column`.
+fn line_and_column(position: &str) -> (u32, u32) {
+    position
+        .split_once(':')
+        .and_then(|(line, column)| {
+            Some((line.parse().ok()?, column.parse().ok()?))
+        })
+        .unwrap_or_else(|| usage())
+}
+
+fn usage() -> ! {
+    println!(
+        "Usage: rlox [--record log[.json]] [--stats] [--strict-bool] [--typed]\n            [--dump-scopes] [--optimize] [--inline] [--freeze-globals]\n            [--global-natives]\n            [--max-args n] [--max-nesting n] [--max-call-depth n]\n            [--max-loop-iterations n] [--test-timeout ms]\n            [--features list|none]\n            [--deny fs,net,process,clock|all]\n            [--prelude file.lox]... [--prelude-snapshot file]\n            [--watch]\n            [--break [file:]line[ if condition]]... [--watch-var name]...\n            [--trace-var name]...\n            [script...] [-- args...]\n       rlox compile [--optimize] [--inline] script.lox [-o script.loxc]\n       rlox run script[.loxc]... [-- args...]\n       rlox check [--typed] script[.loxc]...\n       rlox disasm script[.loxc]\n       rlox graph [--format=dot|text] script.lox\n       rlox lint [--metrics] [--thresholds statements=n,nesting=n,params=n]\n                 script[.loxc]...\n       rlox rename [--diff] script.lox line:column new-name\n       rlox test [--jobs n] dir-or-file...\n\nA script gets the args after `--` as a list of strings from sys.args()."
+    );
+    process::exit(64);
 }
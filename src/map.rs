@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+use crate::literal::Literal;
+
+// The entries of a Lox map in the order their keys were first added, so
+// printing a map or listing its keys gives the same result on every run.
+// Setting a key again keeps its place. Removed entries leave a gap that is
+// only closed up once gaps outnumber entries, so removing stays cheap.
+#[derive(Debug, Default)]
+pub struct LoxMap {
+    indices: HashMap<Literal, usize>,
+    entries: Vec<Option<(Literal, Literal)>>,
+}
+
+impl LoxMap {
+    pub fn new() -> LoxMap {
+        LoxMap::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn get(&self, key: &Literal) -> Option<&Literal> {
+        let index = *self.indices.get(key)?;
+        self.entries[index].as_ref().map(|(_, value)| value)
+    }
+
+    pub fn contains_key(&self, key: &Literal) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    // Stores `value` under `key`, returning the value it replaces.
+    pub fn insert(&mut self, key: Literal, value: Literal) -> Option<Literal> {
+        if let Some(&index) = self.indices.get(&key) {
+            if let Some((_, old)) = &mut self.entries[index] {
+                return Some(std::mem::replace(old, value));
+            }
+        }
+
+        self.indices.insert(key.clone(), self.entries.len());
+        self.entries.push(Some((key, value)));
+        None
+    }
+
+    pub fn remove(&mut self, key: &Literal) -> Option<Literal> {
+        let index = self.indices.remove(key)?;
+        let (_, value) = self.entries[index].take()?;
+
+        if self.entries.len() > 2 * self.indices.len() + 8 {
+            self.entries.retain(Option::is_some);
+            for (index, (key, _)) in self.entries.iter().flatten().enumerate() {
+                self.indices.insert(key.clone(), index);
+            }
+        }
+
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Literal, &Literal)> {
+        self.entries
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Literal> {
+        self.iter().map(|(key, _)| key)
+    }
+}
+
+impl FromIterator<(Literal, Literal)> for LoxMap {
+    fn from_iter<I: IntoIterator<Item = (Literal, Literal)>>(iter: I) -> Self {
+        let mut map = LoxMap::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(map: &LoxMap) -> Vec<i64> {
+        map.keys().filter_map(Literal::as_integer).collect()
+    }
+
+    #[test]
+    fn keeps_keys_in_insertion_order() {
+        let mut map: LoxMap = [5, 3, 9, 1]
+            .into_iter()
+            .map(|n| (Literal::Integer(n), Literal::Nil))
+            .collect();
+        assert_eq!(keys(&map), [5, 3, 9, 1]);
+
+        assert!(map
+            .insert(Literal::Number(3.0), Literal::Bool(true))
+            .is_some());
+        assert_eq!(map.remove(&Literal::Integer(9)), Some(Literal::Nil));
+        map.insert(Literal::Integer(9), Literal::Nil);
+        assert_eq!(keys(&map), [5, 3, 1, 9]);
+        assert_eq!(map.get(&Literal::Integer(3)), Some(&Literal::Bool(true)));
+        assert_eq!(map.len(), 4);
+    }
+
+    #[test]
+    fn closes_gaps_left_by_removals() {
+        let mut map = LoxMap::new();
+        for n in 0..100 {
+            map.insert(Literal::Integer(n), Literal::Integer(n * n));
+        }
+        for n in (0..100).filter(|n| n % 10 != 0) {
+            assert_eq!(
+                map.remove(&Literal::Integer(n)),
+                Some(Literal::Integer(n * n))
+            );
+        }
+        assert!(map.entries.len() < 30);
+        assert_eq!(keys(&map), [0, 10, 20, 30, 40, 50, 60, 70, 80, 90]);
+        assert_eq!(
+            map.get(&Literal::Integer(90)),
+            Some(&Literal::Integer(8100))
+        );
+        assert_eq!(map.remove(&Literal::Integer(1)), None);
+    }
+}
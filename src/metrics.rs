@@ -0,0 +1,267 @@
+use std::fmt::Write;
+
+use crate::stmt::{FunctionStmt, Stmt};
+use crate::token::Token;
+use crate::walk::{walk_function, walk_stmt, walk_stmts, Walk};
+
+// How big a function can get before `rlox lint` warns about it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Thresholds {
+    pub statements: usize,
+    pub nesting: usize,
+    pub params: usize,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            statements: 30,
+            nesting: 4,
+            params: 5,
+        }
+    }
+}
+
+impl Thresholds {
+    // Reads a comma-separated list of `name=limit`, as given to
+    // --thresholds, where the limits not listed keep their defaults.
+    pub fn parse(list: &str) -> Result<Thresholds, String> {
+        let mut thresholds = Thresholds::default();
+        for setting in list.split(',').map(str::trim) {
+            let (name, limit) = setting.split_once('=').ok_or_else(|| {
+                format!("Expected name=limit, not '{setting}'.")
+            })?;
+            let limit = limit
+                .parse()
+                .map_err(|_| format!("Invalid limit for '{name}'."))?;
+            match name {
+                "statements" => thresholds.statements = limit,
+                "nesting" => thresholds.nesting = limit,
+                "params" => thresholds.params = limit,
+                _ => return Err(format!("Unknown threshold '{name}'.")),
+            }
+        }
+        Ok(thresholds)
+    }
+}
+
+#[derive(Debug)]
+pub struct FunctionMetrics {
+    // `name`, or `Class.name` for a method.
+    pub name: String,
+    pub token: Token,
+    // Every statement in the body, however deeply nested, but not the ones
+    // in the bodies of functions declared inside it, which count on their
+    // own.
+    pub statements: usize,
+    // How many ifs and loops deep the body goes. An `else if` is no deeper
+    // than the `if` it follows.
+    pub nesting: usize,
+    pub params: usize,
+}
+
+impl FunctionMetrics {
+    // What about the function is over `thresholds`.
+    pub fn warnings(&self, thresholds: Thresholds) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.statements > thresholds.statements {
+            warnings.push(format!(
+                "Function '{}' has {} statements, more than {}.",
+                self.name, self.statements, thresholds.statements
+            ));
+        }
+        if self.nesting > thresholds.nesting {
+            warnings.push(format!(
+                "Function '{}' nests {} deep, more than {}.",
+                self.name, self.nesting, thresholds.nesting
+            ));
+        }
+        if self.params > thresholds.params {
+            warnings.push(format!(
+                "Function '{}' takes {} parameters, more than {}.",
+                self.name, self.params, thresholds.params
+            ));
+        }
+        warnings
+    }
+}
+
+// The metrics of every function and method in `statements`, in the order
+// they are declared.
+pub fn measure(statements: &[Stmt]) -> Vec<FunctionMetrics> {
+    let mut measure = Measure::default();
+    walk_stmts(&mut measure, statements);
+    measure.functions
+}
+
+// The metrics as a table, a row for each function.
+pub fn report(functions: &[FunctionMetrics]) -> String {
+    let width = functions
+        .iter()
+        .map(|f| f.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("function".len());
+    let mut report =
+        format!("{:width$}  line  statements  nesting  params\n", "function");
+    for f in functions {
+        let _ = writeln!(
+            report,
+            "{:width$}  {:>4}  {:>10}  {:>7}  {:>6}",
+            f.name, f.token.line, f.statements, f.nesting, f.params
+        );
+    }
+    report
+}
+
+#[derive(Default)]
+struct Measure {
+    functions: Vec<FunctionMetrics>,
+    // The function being measured, innermost last, by index in functions.
+    open: Vec<usize>,
+    nesting: usize,
+}
+
+impl Measure {
+    fn function(&mut self, name: String, function: &FunctionStmt) {
+        self.functions.push(FunctionMetrics {
+            name,
+            token: function.name.clone(),
+            statements: 0,
+            nesting: 0,
+            params: function.params.len(),
+        });
+        self.open.push(self.functions.len() - 1);
+        let nesting = std::mem::take(&mut self.nesting);
+        walk_function(self, function);
+        self.nesting = nesting;
+        self.open.pop();
+    }
+
+    fn nested<F: FnOnce(&mut Self)>(&mut self, walk: F) {
+        self.nesting += 1;
+        if let Some(&index) = self.open.last() {
+            let deepest = &mut self.functions[index].nesting;
+            *deepest = (*deepest).max(self.nesting);
+        }
+        walk(self);
+        self.nesting -= 1;
+    }
+}
+
+impl Walk for Measure {
+    fn stmt(&mut self, stmt: &Stmt) {
+        // Blocks only group statements, they aren't statements of their own.
+        if !matches!(stmt, Stmt::Block(_)) {
+            if let Some(&index) = self.open.last() {
+                self.functions[index].statements += 1;
+            }
+        }
+
+        match stmt {
+            Stmt::Function(fs) => self.function(fs.name.lexeme.to_string(), fs),
+            Stmt::Class(cs) => {
+                for method in &cs.methods {
+                    let name =
+                        format!("{}.{}", cs.name.lexeme, method.name.lexeme);
+                    self.function(name, method);
+                }
+            }
+            Stmt::If(is) => {
+                self.nested(|m| m.stmt(&is.then_branch));
+                // Counted as a statement of its own, at the same depth.
+                match &is.else_branch {
+                    Some(else_branch)
+                        if matches!(**else_branch, Stmt::If(_)) =>
+                    {
+                        self.stmt(else_branch)
+                    }
+                    Some(else_branch) => self.nested(|m| m.stmt(else_branch)),
+                    None => {}
+                }
+            }
+            Stmt::While(ws) => self.nested(|m| m.stmt(&ws.body)),
+            Stmt::For(fs) => {
+                if let Some(initializer) = &fs.initializer {
+                    self.stmt(initializer);
+                }
+                self.nested(|m| m.stmt(&fs.body));
+            }
+            stmt => walk_stmt(self, stmt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn metrics(source: &str) -> Vec<(String, usize, usize, usize)> {
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let statements = Parser::new(&tokens)
+            .parse()
+            .unwrap_or_else(|_| panic!("test source should parse"));
+        measure(&statements)
+            .into_iter()
+            .map(|f| (f.name, f.statements, f.nesting, f.params))
+            .collect()
+    }
+
+    #[test]
+    fn measures_each_function_on_its_own() {
+        let source = "
+            fun outer(a, b) {
+              var total = 0;
+              for (var i = 0; i < a; i = i + 1) {
+                if (i > b) {
+                  while (total < 10) total = total + 1;
+                } else if (i == b) {
+                  print i;
+                } else {
+                  print b;
+                }
+              }
+              fun inner() { return 1; }
+              return total + inner();
+            }
+            class Box { size() { return 0; } }";
+        assert_eq!(
+            metrics(source),
+            [
+                ("outer".to_string(), 11, 3, 2),
+                ("inner".to_string(), 1, 0, 0),
+                ("Box.size".to_string(), 1, 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_above_the_thresholds() {
+        let thresholds = Thresholds::parse("statements=1, params=1");
+        assert_eq!(
+            thresholds,
+            Ok(Thresholds {
+                statements: 1,
+                nesting: 4,
+                params: 1,
+            })
+        );
+        assert!(Thresholds::parse("lines=3").is_err());
+        assert!(Thresholds::parse("params").is_err());
+
+        let source = "fun f(a, b) { print a; print b; }";
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let statements = Parser::new(&tokens).parse().unwrap_or_default();
+        let warnings =
+            measure(&statements)[0].warnings(thresholds.unwrap_or_default());
+        assert_eq!(
+            warnings,
+            [
+                "Function 'f' has 2 statements, more than 1.",
+                "Function 'f' takes 2 parameters, more than 1.",
+            ]
+        );
+    }
+}
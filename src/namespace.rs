@@ -0,0 +1,136 @@
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+use crate::error_reporter::LoxError;
+use crate::literal::Literal;
+use crate::token::Token;
+
+// The natives grouped under each built-in namespace, so scripts write
+// io.readFile(path). They are only globals too with
+// InterpreterOptions::global_natives, for scripts written before the
+// namespaces existed.
+pub const BUILTIN: &[(&str, &[&str])] = &[
+    ("math", &["min", "max", "sum", "approxEq"]),
+    (
+        "str",
+        &[
+            "template",
+            "isDigit",
+            "isAlpha",
+            "isSpace",
+            "toUpperChar",
+            "toLowerChar",
+            "toHex",
+            "toBin",
+            "parseInt",
+            "toFixed",
+            "toPrecision",
+        ],
+    ),
+    (
+        "io",
+        &[
+            "open",
+            "readLine",
+            "write",
+            "close",
+            "readFile",
+            "writeFile",
+            "fileExists",
+        ],
+    ),
+    (
+        "net",
+        &[
+            "tcpConnect",
+            "tcpListen",
+            "tcpPort",
+            "accept",
+            "send",
+            "recv",
+            "fetch",
+        ],
+    ),
+    ("time", &["clock", "now", "formatTime", "parseTime"]),
+    ("sys", &["args", "exit", "atExit", "onInterrupt", "stats"]),
+];
+
+// The built-in namespace a native belongs to, if any.
+pub fn builtin_namespace(native: &str) -> Option<&'static str> {
+    BUILTIN
+        .iter()
+        .find(|(_, members)| members.contains(&native))
+        .map(|&(namespace, _)| namespace)
+}
+
+// Whether the native `name` is a global, which those in a namespace only
+// are with `global_natives`. clock() is the book's, so it always is.
+pub fn is_global_native(name: &str, global_natives: bool) -> bool {
+    global_natives || name == "clock" || builtin_namespace(name).is_none()
+}
+
+// A named group of natives reached with a dot, as in math.sqrt(2). Only the
+// host adds members; scripts can read them but not set them.
+#[derive(Debug)]
+pub struct LoxNamespace {
+    name: Rc<str>,
+    // In the order they were added, which is the order listed by fields().
+    members: RefCell<Vec<(Rc<str>, Literal)>>,
+}
+
+impl LoxNamespace {
+    pub fn new(name: &str) -> LoxNamespace {
+        LoxNamespace {
+            name: name.into(),
+            members: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Literal, LoxError> {
+        self.member(&name.lexeme).ok_or_else(|| {
+            LoxError::runtime_error(
+                name.clone(),
+                format!(
+                    "Namespace '{}' has no member '{}'.",
+                    self.name, name.lexeme
+                ),
+            )
+        })
+    }
+
+    pub fn member(&self, name: &str) -> Option<Literal> {
+        self.members
+            .borrow()
+            .iter()
+            .find(|(n, _)| &**n == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    // Adds `name`, or replaces it in place if it is already a member.
+    pub fn define(&self, name: &str, value: Literal) {
+        let mut members = self.members.borrow_mut();
+        match members.iter_mut().find(|(n, _)| &**n == name) {
+            Some((_, v)) => *v = value,
+            None => members.push((name.into(), value)),
+        }
+    }
+
+    pub fn member_names(&self) -> Vec<Rc<str>> {
+        self.members
+            .borrow()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+impl Display for LoxNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<namespace {}>", self.name)
+    }
+}
@@ -1,11 +1,32 @@
-use std::fmt::Display;
+use std::any::Any;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write as _};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::Path;
+use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::error_reporter::LoxError;
+use crate::bigint::BigInt;
+use crate::enumeration;
+use crate::error_reporter::{report_line, LoxError};
+use crate::expr::{CallExpr, Expr};
+use crate::foreign::Foreign;
+use crate::http;
 use crate::interpreter::Interpreter;
+use crate::interrupt;
 use crate::literal::Literal;
+use crate::map::LoxMap;
+use crate::range::LoxRange;
+use crate::sandbox::Capability;
+use crate::source_printer::SourcePrinter;
+use crate::template::{self, Missing, Part, TemplateOptions};
+use crate::time::DateTime;
+use crate::value_printer::{self, ValuePrinter};
+use crate::walk::{walk_expr, Walk};
 
-use crate::callable::LoxCallable;
+use crate::callable::{arity_mismatch, LoxCallable};
 
 #[derive(Debug)]
 pub struct Clock;
@@ -17,9 +38,10 @@ impl LoxCallable for Clock {
 
     fn call(
         &self,
-        _interpreter: &mut Interpreter,
+        interpreter: &mut Interpreter,
         _arguments: Vec<Literal>,
     ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::Clock, "clock")?;
         match SystemTime::now().duration_since(UNIX_EPOCH) {
             Ok(d) => Ok(Literal::Number(d.as_micros() as f64)),
             Err(e) => Err(LoxError::system_error(format!(
@@ -35,3 +57,2975 @@ impl Display for Clock {
         write!(f, "native clock function")
     }
 }
+
+// Seconds since the Unix epoch, with the fraction of the current second.
+#[derive(Debug)]
+pub struct Now;
+
+impl LoxCallable for Now {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        _arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::Clock, "now")?;
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => Ok(Literal::Number(d.as_secs_f64())),
+            Err(e) => Err(LoxError::system_error(format!(
+                "Invalid duration: {:?}",
+                e.duration()
+            ))),
+        }
+    }
+}
+
+impl Display for Now {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native now function")
+    }
+}
+
+// formatTime(epoch, format) writes a time in UTC, see time.rs for the
+// directives. Fractions of a second are dropped.
+#[derive(Debug)]
+pub struct FormatTime;
+
+impl LoxCallable for FormatTime {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let epoch = match arguments[0].as_number() {
+            Some(epoch) if epoch.is_finite() => epoch.floor() as i64,
+            _ => {
+                return Err(expected(
+                    interpreter,
+                    "formatTime",
+                    "a time",
+                    &arguments[0],
+                ))
+            }
+        };
+        let format = string_argument(interpreter, "formatTime", &arguments[1])?;
+        match DateTime::from_epoch(epoch).format(&format) {
+            Ok(text) => Ok(Literal::String(text.into())),
+            Err(e) => Err(LoxError::system_error(format!("formatTime() {e}."))),
+        }
+    }
+}
+
+impl Display for FormatTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native formatTime function")
+    }
+}
+
+// parseTime(text, format) reads a UTC time written as `format` and returns
+// its epoch seconds.
+#[derive(Debug)]
+pub struct ParseTime;
+
+impl LoxCallable for ParseTime {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let text = string_argument(interpreter, "parseTime", &arguments[0])?;
+        let format = string_argument(interpreter, "parseTime", &arguments[1])?;
+        match DateTime::parse(&text, &format) {
+            Ok(date) => Ok(Literal::Integer(date.to_epoch())),
+            Err(e) => Err(LoxError::system_error(format!("parseTime() {e}."))),
+        }
+    }
+}
+
+impl Display for ParseTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native parseTime function")
+    }
+}
+
+// exit([code]) ends the program once the atExit() callbacks have run.
+#[derive(Debug)]
+pub struct Exit;
+
+impl LoxCallable for Exit {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn max_arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::Process, "exit")?;
+        let code = match arguments.first() {
+            Some(code) => whole_number(interpreter, "exit", code)?,
+            None => 0,
+        };
+        if !(0..=255).contains(&code) {
+            return Err(LoxError::system_error(format!(
+                "exit() code must be between 0 and 255, not {code}."
+            )));
+        }
+
+        Err(LoxError::exit(code as i32))
+    }
+}
+
+impl Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native exit function")
+    }
+}
+
+// atExit(fn) registers a callback to run when the program ends, after its
+// last statement or at exit(). Callbacks run last registered first.
+#[derive(Debug)]
+pub struct AtExit;
+
+impl LoxCallable for AtExit {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::Process, "atExit")?;
+        let callback = callback_argument(interpreter, "atExit", &arguments[0])?;
+        interpreter.add_exit_hook(callback);
+        Ok(Literal::Nil)
+    }
+}
+
+impl Display for AtExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native atExit function")
+    }
+}
+
+// onInterrupt(fn) calls `fn` at the next Ctrl-C instead of ending the
+// program, which then carries on unless the handler calls exit(). Each call
+// catches a single Ctrl-C; another one before the next onInterrupt() ends
+// the program as usual.
+#[derive(Debug)]
+pub struct OnInterrupt;
+
+impl LoxCallable for OnInterrupt {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::Process, "onInterrupt")?;
+        let handler =
+            callback_argument(interpreter, "onInterrupt", &arguments[0])?;
+        interpreter.set_interrupt_handler(handler);
+        interrupt::arm();
+        Ok(Literal::Nil)
+    }
+}
+
+impl Display for OnInterrupt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native onInterrupt function")
+    }
+}
+
+// args() lists the arguments given to the script after `--`, as strings.
+#[derive(Debug)]
+pub struct Args {
+    values: Vec<String>,
+}
+
+impl Args {
+    pub fn new(values: Vec<String>) -> Args {
+        Args { values }
+    }
+}
+
+impl LoxCallable for Args {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        _arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::Process, "args")?;
+        let values = self
+            .values
+            .iter()
+            .map(|value| Literal::String(value.as_str().into()))
+            .collect();
+        Ok(Literal::new_list(values))
+    }
+}
+
+impl Display for Args {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native args function")
+    }
+}
+
+#[derive(Debug)]
+pub struct StatsFn;
+
+impl LoxCallable for StatsFn {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        _arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        Ok(Literal::String(interpreter.stats().to_string().into()))
+    }
+}
+
+impl Display for StatsFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native stats function")
+    }
+}
+
+#[derive(Debug)]
+pub struct ToBool;
+
+impl LoxCallable for ToBool {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        Ok(Literal::Bool(arguments[0].is_truthy()))
+    }
+}
+
+impl Display for ToBool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native bool function")
+    }
+}
+
+// The class name of an instance, or the type name of any other value.
+#[derive(Debug)]
+pub struct ClassName;
+
+impl LoxCallable for ClassName {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let name = match &arguments[0] {
+            Literal::Instance(instance) => instance.class().name(),
+            other => other.type_name(),
+        };
+        Ok(Literal::String(name.into()))
+    }
+}
+
+impl Display for ClassName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native className function")
+    }
+}
+
+#[derive(Debug)]
+pub struct Fields;
+
+impl LoxCallable for Fields {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        match &arguments[0] {
+            Literal::Instance(instance) => {
+                Ok(names_list(instance.field_names()))
+            }
+            Literal::Namespace(namespace) => {
+                Ok(names_list(namespace.member_names()))
+            }
+            other => Err(expected(
+                interpreter,
+                "fields",
+                "an instance or namespace",
+                other,
+            )),
+        }
+    }
+}
+
+impl Display for Fields {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native fields function")
+    }
+}
+
+// Takes a class, or an instance to list the methods of its class.
+#[derive(Debug)]
+pub struct Methods;
+
+impl LoxCallable for Methods {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        match &arguments[0] {
+            Literal::Class(class) => Ok(names_list(class.method_names())),
+            Literal::Instance(instance) => {
+                Ok(names_list(instance.class().method_names()))
+            }
+            other => Err(expected(interpreter, "methods", "a class", other)),
+        }
+    }
+}
+
+impl Display for Methods {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native methods function")
+    }
+}
+
+// What an `enum` declaration calls to make its enum, see
+// Parser::enum_declaration: enum(name, variant...). `enum` is a keyword, so
+// scripts can't call it themselves.
+#[derive(Debug)]
+pub struct EnumFn;
+
+impl LoxCallable for EnumFn {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        usize::MAX
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let names = arguments
+            .iter()
+            .map(|name| string_argument(interpreter, "enum", name))
+            .collect::<Result<Vec<_>, _>>()?;
+        let enumeration = enumeration::new_enum(&names[0], &names[1..]);
+        Ok(Literal::Namespace(Rc::new(enumeration)))
+    }
+}
+
+impl Display for EnumFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native enum function")
+    }
+}
+
+// assert(condition) or assert(condition, message) fails with a runtime
+// error when `condition` is false or nil. Called from Lox code the error
+// shows the condition as written and the variables it reads:
+//
+//     Assertion failed: x > limit (x=5, limit=3).
+#[derive(Debug)]
+pub struct Assert;
+
+impl LoxCallable for Assert {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        if arguments[0].is_truthy() {
+            return Ok(Literal::Nil);
+        }
+        let message = assertion_failed(None, arguments.get(1));
+        Err(LoxError::system_error(message))
+    }
+
+    fn call_at(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+        call: &CallExpr,
+    ) -> Result<Literal, LoxError> {
+        if arguments[0].is_truthy() {
+            return Ok(Literal::Nil);
+        }
+
+        let condition = &call.arguments[0];
+        let mut asserted = SourcePrinter::new().print(condition);
+        let mut reads = VariablesRead {
+            interpreter,
+            values: Vec::new(),
+        };
+        reads.expr(condition);
+        if !reads.values.is_empty() {
+            let values: Vec<String> = reads
+                .values
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect();
+            asserted.push_str(&format!(" ({})", values.join(", ")));
+        }
+        let message = assertion_failed(Some(&asserted), arguments.get(1));
+        Err(LoxError::runtime_error(call.paren.clone(), message))
+    }
+}
+
+impl Display for Assert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native assert function")
+    }
+}
+
+fn assertion_failed(
+    asserted: Option<&str>,
+    message: Option<&Literal>,
+) -> String {
+    match (asserted, message) {
+        (Some(asserted), Some(message)) => {
+            format!("Assertion failed: {asserted}: {message}")
+        }
+        (Some(asserted), None) => format!("Assertion failed: {asserted}."),
+        (None, Some(message)) => format!("Assertion failed: {message}"),
+        (None, None) => "Assertion failed.".to_string(),
+    }
+}
+
+// The value of each variable an expression reads, once each and in the
+// order they first appear. Functions and classes are left out, their names
+// say as much as their values would.
+struct VariablesRead<'i> {
+    interpreter: &'i Interpreter,
+    values: Vec<(Rc<str>, String)>,
+}
+
+impl Walk for VariablesRead<'_> {
+    fn expr(&mut self, expr: &Expr) {
+        if let Expr::Variable(ve) = expr {
+            let name = &ve.name.lexeme;
+            let seen = self.values.iter().any(|(n, _)| n == name);
+            match self.interpreter.variable_value(&ve.name) {
+                Some(Literal::Function(_) | Literal::Class(_)) => {}
+                Some(value) if !seen => {
+                    let value = ValuePrinter::brief().print(&value);
+                    self.values.push((name.clone(), value));
+                }
+                _ => {}
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+// test(name, body) calls body() as a test. A test fails when its body
+// raises an error, which is reported as usual, runs past the test timeout
+// or panics the interpreter, and the rest of the script still runs. Returns
+// whether the test passed. See test_runner.rs for how results are counted.
+#[derive(Debug)]
+pub struct TestFn;
+
+impl LoxCallable for TestFn {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let name = string_argument(interpreter, "test", &arguments[0])?;
+        let body = callback_argument(interpreter, "test", &arguments[1])?;
+        let timeout = interpreter.options().test_timeout;
+        let passed = match interpreter.call_guarded(&body, timeout) {
+            Ok(Ok(_)) => true,
+            Ok(Err(exit @ LoxError::Exit { .. })) => return Err(exit),
+            Ok(Err(_)) => {
+                report_line(format!("Test '{name}' failed."));
+                false
+            }
+            Err(panic) => {
+                report_line(format!("Test '{name}' panicked: {panic}"));
+                false
+            }
+        };
+        interpreter.record_test(passed);
+        Ok(Literal::Bool(passed))
+    }
+}
+
+impl Display for TestFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native test function")
+    }
+}
+
+// expectEq(actual, expected) fails unless the two are equal. Unlike `==`,
+// lists and maps are equal when their contents are.
+#[derive(Debug)]
+pub struct ExpectEq;
+
+impl ExpectEq {
+    fn mismatch(actual: &Literal, expected: &Literal) -> Option<String> {
+        if same_contents(actual, expected, 0) {
+            return None;
+        }
+        let printer = ValuePrinter::new();
+        Some(format!(
+            "Expected {} but got {}.",
+            printer.print(expected),
+            printer.print(actual)
+        ))
+    }
+}
+
+impl LoxCallable for ExpectEq {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        match ExpectEq::mismatch(&arguments[0], &arguments[1]) {
+            Some(message) => Err(LoxError::system_error(message)),
+            None => Ok(Literal::Nil),
+        }
+    }
+
+    // Called from Lox code, the error points at the failed expectation.
+    fn call_at(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+        call: &CallExpr,
+    ) -> Result<Literal, LoxError> {
+        match ExpectEq::mismatch(&arguments[0], &arguments[1]) {
+            Some(message) => {
+                Err(LoxError::runtime_error(call.paren.clone(), message))
+            }
+            None => Ok(Literal::Nil),
+        }
+    }
+}
+
+impl Display for ExpectEq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native expectEq function")
+    }
+}
+
+// Whether two values are `==`, or are lists or maps holding equal values.
+// Past a few levels of nesting only `==` counts, so cyclic lists still
+// compare.
+fn same_contents(a: &Literal, b: &Literal, depth: usize) -> bool {
+    if a == b {
+        return true;
+    }
+    if depth == 16 {
+        return false;
+    }
+    match (a, b) {
+        (Literal::List(a), Literal::List(b)) => {
+            let (a, b) = (a.borrow(), b.borrow());
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b.iter())
+                    .all(|(a, b)| same_contents(a, b, depth + 1))
+        }
+        (Literal::Map(a), Literal::Map(b)) => {
+            let (a, b) = (a.borrow(), b.borrow());
+            a.len() == b.len()
+                && a.iter().all(|(key, a)| {
+                    b.get(key).is_some_and(|b| same_contents(a, b, depth + 1))
+                })
+        }
+        _ => false,
+    }
+}
+
+// Returns its argument, so `var p = freeze(Point(1, 2));` works.
+#[derive(Debug)]
+pub struct Freeze;
+
+impl LoxCallable for Freeze {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        match &arguments[0] {
+            Literal::Instance(instance) => instance.freeze(),
+            Literal::Class(class) => class.freeze(),
+            other => {
+                return Err(expected(
+                    interpreter,
+                    "freeze",
+                    "an instance or a class",
+                    other,
+                ))
+            }
+        }
+        Ok(arguments[0].clone())
+    }
+}
+
+impl Display for Freeze {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native freeze function")
+    }
+}
+
+// range(end), range(start, end) or range(start, end, step).
+#[derive(Debug)]
+pub struct RangeFn;
+
+impl LoxCallable for RangeFn {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let mut numbers = Vec::new();
+        for argument in &arguments {
+            numbers.push(whole_number(interpreter, "range", argument)?);
+        }
+
+        let (start, end, step) = match numbers[..] {
+            [end] => (0, end, 1),
+            [start, end] => (start, end, 1),
+            [start, end, step] => (start, end, step),
+            _ => unreachable!("arity is checked before calling"),
+        };
+        match LoxRange::new(start, end, step) {
+            Some(range) => Ok(Literal::Range(Rc::new(range))),
+            None => Err(LoxError::system_error(
+                "range() step can't be 0.".to_string(),
+            )),
+        }
+    }
+}
+
+impl Display for RangeFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native range function")
+    }
+}
+
+// The number of characters in a string, elements in a list or range, or
+// entries in a map.
+#[derive(Debug)]
+pub struct Len;
+
+impl LoxCallable for Len {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let len = match &arguments[0] {
+            Literal::String(s) => s.chars().count(),
+            Literal::List(items) => items.borrow().len(),
+            Literal::Map(entries) => entries.borrow().len(),
+            Literal::Range(range) => range.len(),
+            other => {
+                return Err(expected(
+                    interpreter,
+                    "len",
+                    "a string, list, map or range",
+                    other,
+                ))
+            }
+        };
+        Ok(Literal::Integer(len.try_into().unwrap_or(i64::MAX)))
+    }
+}
+
+impl Display for Len {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native len function")
+    }
+}
+
+// at(sequence, index) is the element of a list or range at a zero-based
+// index.
+#[derive(Debug)]
+pub struct At;
+
+impl LoxCallable for At {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let index = whole_number(interpreter, "at", &arguments[1])?;
+        let (element, len) = match &arguments[0] {
+            Literal::List(items) => {
+                let items = items.borrow();
+                let element = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| items.get(index).cloned());
+                (element, items.len())
+            }
+            Literal::Range(range) => {
+                let element = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| range.get(index))
+                    .map(Literal::Integer);
+                (element, range.len())
+            }
+            other => {
+                return Err(expected(
+                    interpreter,
+                    "at",
+                    "a list or range",
+                    other,
+                ))
+            }
+        };
+
+        element.ok_or_else(|| {
+            out_of_bounds(interpreter, index, &arguments[0], len)
+        })
+    }
+}
+
+impl Display for At {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native at function")
+    }
+}
+
+// slice(sequence, from, to) copies the part of a string or list from index
+// `from` up to `to`, or narrows a range without listing its numbers. Like in
+// Python, a negative index counts back from the end, and indexes are then
+// clamped to the sequence.
+#[derive(Debug)]
+pub struct Slice;
+
+impl LoxCallable for Slice {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let len = match &arguments[0] {
+            Literal::String(s) => s.chars().count(),
+            Literal::List(items) => items.borrow().len(),
+            Literal::Range(range) => range.len(),
+            other => {
+                return Err(expected(
+                    interpreter,
+                    "slice",
+                    "a string, list or range",
+                    other,
+                ))
+            }
+        };
+        let index = |argument: &Literal| -> Result<usize, LoxError> {
+            let index = whole_number(interpreter, "slice", argument)?;
+            let len = i64::try_from(len).unwrap_or(i64::MAX);
+            let index = if index < 0 { index + len } else { index };
+            Ok(index.clamp(0, len) as usize)
+        };
+        let (from, to) = (index(&arguments[1])?, index(&arguments[2])?);
+        let to = to.max(from);
+
+        match &arguments[0] {
+            Literal::String(s) => {
+                let part: String =
+                    s.chars().skip(from).take(to - from).collect();
+                Ok(Literal::String(part.into()))
+            }
+            Literal::List(items) => {
+                Ok(Literal::new_list(items.borrow()[from..to].to_vec()))
+            }
+            Literal::Range(range) => {
+                Ok(Literal::Range(Rc::new(range.slice(from, to))))
+            }
+            _ => unreachable!("checked above"),
+        }
+    }
+}
+
+impl Display for Slice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native slice function")
+    }
+}
+
+// list(a, b, ...) is a new list of its arguments.
+#[derive(Debug)]
+pub struct List;
+
+impl LoxCallable for List {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn max_arity(&self) -> usize {
+        usize::MAX
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        Ok(Literal::new_list(arguments))
+    }
+}
+
+impl Display for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native list function")
+    }
+}
+
+// listSet(list, index, value) replaces an element and returns the value.
+#[derive(Debug)]
+pub struct ListSet;
+
+impl LoxCallable for ListSet {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let value = arguments.pop().unwrap_or(Literal::Nil);
+        let index = whole_number(interpreter, "listSet", &arguments[1])?;
+        let items = list_argument(interpreter, "listSet", &arguments[0])?;
+        let mut items = items.borrow_mut();
+
+        let len = items.len();
+        match usize::try_from(index).ok().and_then(|i| items.get_mut(i)) {
+            Some(element) => *element = value.clone(),
+            None => {
+                return Err(out_of_bounds(
+                    interpreter,
+                    index,
+                    &arguments[0],
+                    len,
+                ))
+            }
+        }
+        Ok(value)
+    }
+}
+
+impl Display for ListSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native listSet function")
+    }
+}
+
+// push(list, value) appends to the end of the list.
+#[derive(Debug)]
+pub struct Push;
+
+impl LoxCallable for Push {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let value = arguments.pop().unwrap_or(Literal::Nil);
+        list_argument(interpreter, "push", &arguments[0])?
+            .borrow_mut()
+            .push(value);
+        Ok(Literal::Nil)
+    }
+}
+
+impl Display for Push {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native push function")
+    }
+}
+
+// Removes and returns the last element of a list.
+#[derive(Debug)]
+pub struct Pop;
+
+impl LoxCallable for Pop {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        match list_argument(interpreter, "pop", &arguments[0])?
+            .borrow_mut()
+            .pop()
+        {
+            Some(value) => Ok(value),
+            None => Err(LoxError::system_error(
+                "pop() called on an empty list.".to_string(),
+            )),
+        }
+    }
+}
+
+impl Display for Pop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native pop function")
+    }
+}
+
+// A new, empty map. Keys are compared like `==` compares values, so
+// instances and lists are keyed by identity.
+#[derive(Debug)]
+pub struct MapNew;
+
+impl LoxCallable for MapNew {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(
+        &self,
+        _interpreter: &mut Interpreter,
+        _arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        Ok(Literal::new_map())
+    }
+}
+
+impl Display for MapNew {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native mapNew function")
+    }
+}
+
+// mapGet(map, key) is the value stored under `key`, or nil.
+#[derive(Debug)]
+pub struct MapGet;
+
+impl LoxCallable for MapGet {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let entries = map_argument(interpreter, "mapGet", &arguments[0])?;
+        let key = map_key(interpreter, "mapGet", &arguments[1])?;
+        let value = entries.borrow().get(key).cloned();
+        Ok(value.unwrap_or(Literal::Nil))
+    }
+}
+
+impl Display for MapGet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native mapGet function")
+    }
+}
+
+// mapSet(map, key, value) stores `value` under `key` and returns it.
+#[derive(Debug)]
+pub struct MapSet;
+
+impl LoxCallable for MapSet {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let value = arguments.pop().unwrap_or(Literal::Nil);
+        let entries = map_argument(interpreter, "mapSet", &arguments[0])?;
+        let key = map_key(interpreter, "mapSet", &arguments[1])?;
+        entries.borrow_mut().insert(key.clone(), value.clone());
+        Ok(value)
+    }
+}
+
+impl Display for MapSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native mapSet function")
+    }
+}
+
+// mapHas(map, key) tells a key stored with a nil value from a missing one.
+#[derive(Debug)]
+pub struct MapHas;
+
+impl LoxCallable for MapHas {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let entries = map_argument(interpreter, "mapHas", &arguments[0])?;
+        let key = map_key(interpreter, "mapHas", &arguments[1])?;
+        let has = entries.borrow().contains_key(key);
+        Ok(Literal::Bool(has))
+    }
+}
+
+impl Display for MapHas {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native mapHas function")
+    }
+}
+
+// mapRemove(map, key) deletes `key` and returns its value, or nil.
+#[derive(Debug)]
+pub struct MapRemove;
+
+impl LoxCallable for MapRemove {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let entries = map_argument(interpreter, "mapRemove", &arguments[0])?;
+        let key = map_key(interpreter, "mapRemove", &arguments[1])?;
+        let value = entries.borrow_mut().remove(key);
+        Ok(value.unwrap_or(Literal::Nil))
+    }
+}
+
+impl Display for MapRemove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native mapRemove function")
+    }
+}
+
+// The keys of a map as a new list.
+#[derive(Debug)]
+pub struct MapKeys;
+
+impl LoxCallable for MapKeys {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let entries = map_argument(interpreter, "mapKeys", &arguments[0])?;
+        let keys = entries.borrow().keys().cloned().collect();
+        Ok(Literal::new_list(keys))
+    }
+}
+
+impl Display for MapKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native mapKeys function")
+    }
+}
+
+// template(text, values) fills each `{{path}}` placeholder in `text`, see
+// template.rs, with the value the path leads to. Each name along the path
+// is a key of a map or a field of an instance. Values are written as print
+// writes them. template(text, values, options) takes a list of options:
+// `html` escapes the values, and `empty` or `keep` replace or keep
+// placeholders with no value instead of failing.
+#[derive(Debug)]
+pub struct Template;
+
+impl LoxCallable for Template {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn max_arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let text = string_argument(interpreter, "template", &arguments[0])?;
+        let options = match arguments.get(2) {
+            Some(options) => {
+                let list = string_argument(interpreter, "template", options)?;
+                TemplateOptions::parse(&list)
+                    .map_err(|message| template_error(interpreter, message))?
+            }
+            None => TemplateOptions::default(),
+        };
+
+        let mut filled = String::new();
+        for part in template::parse(&text)
+            .map_err(|message| template_error(interpreter, message))?
+        {
+            let path = match part {
+                Part::Text(text) => {
+                    filled.push_str(text);
+                    continue;
+                }
+                Part::Placeholder(path) => path,
+            };
+            match lookup(&arguments[1], &path) {
+                Some(value) => {
+                    let value = interpreter.stringify(&value)?;
+                    filled.push_str(&template::escape(&value, options.escape));
+                }
+                None => match options.missing {
+                    Missing::Error => {
+                        return Err(template_error(
+                            interpreter,
+                            format!("has no value for '{}'", path.join(".")),
+                        ))
+                    }
+                    Missing::Empty => {}
+                    Missing::Keep => {
+                        filled.push_str(&format!("{{{{{}}}}}", path.join(".")))
+                    }
+                },
+            }
+        }
+        Ok(Literal::String(filled.into()))
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native template function")
+    }
+}
+
+// The value `path` leads to from `values`, if every step finds something.
+fn lookup(values: &Literal, path: &[&str]) -> Option<Literal> {
+    let mut value = values.clone();
+    for &name in path {
+        value = match &value {
+            Literal::Map(entries) => {
+                entries.borrow().get(&Literal::String(name.into())).cloned()
+            }
+            Literal::Instance(instance) => instance.field(name),
+            _ => None,
+        }?;
+    }
+    Some(value)
+}
+
+fn template_error(interpreter: &Interpreter, message: String) -> LoxError {
+    call_error(interpreter, format!("template() {message}."))
+}
+
+// sort(sequence) or sort(sequence, before) is a sorted list of the
+// elements. Without `before` numbers and strings sort in their natural
+// order; before(a, b) returns true when a belongs before b. Elements that
+// aren't before one another keep their order.
+#[derive(Debug)]
+pub struct Sort;
+
+impl LoxCallable for Sort {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let items = elements(interpreter, "sort", &arguments[0])?;
+        let sorted = match arguments.get(1) {
+            Some(before) => merge_sort(items, &mut |a, b| {
+                let result = interpreter
+                    .call_value(before, vec![a.clone(), b.clone()])?;
+                Ok(result.is_truthy())
+            })?,
+            None => merge_sort(items, &mut |a, b| {
+                Ok(compare("sort", a, b)? == Ordering::Less)
+            })?,
+        };
+        Ok(Literal::new_list(sorted))
+    }
+}
+
+impl Display for Sort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native sort function")
+    }
+}
+
+// math.sqrt(x), only reachable through the math namespace.
+#[derive(Debug)]
+pub struct Sqrt;
+
+impl LoxCallable for Sqrt {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let x = number_argument(interpreter, "sqrt", &arguments[0])?;
+        Ok(Literal::Number(x.sqrt()))
+    }
+}
+
+impl Display for Sqrt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native sqrt function")
+    }
+}
+
+// math.floor(x) is the largest whole number not above x.
+#[derive(Debug)]
+pub struct Floor;
+
+impl LoxCallable for Floor {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        match &arguments[0] {
+            Literal::Integer(n) => Ok(Literal::Integer(*n)),
+            other => {
+                let x = number_argument(interpreter, "floor", other)?;
+                Ok(Literal::Number(x.floor()))
+            }
+        }
+    }
+}
+
+impl Display for Floor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native floor function")
+    }
+}
+
+// bigint(x) is x as a bigint, where x is a whole number, a bigint or a
+// string of decimal digits with an optional sign: bigint("-123").
+#[derive(Debug)]
+pub struct BigIntFn;
+
+impl LoxCallable for BigIntFn {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let n = match &arguments[0] {
+            Literal::String(s) => BigInt::parse(s.trim()),
+            other => other.as_bigint(),
+        };
+        match n {
+            Some(n) => Ok(Literal::BigInt(Rc::new(n))),
+            None => Err(expected(
+                interpreter,
+                "bigint",
+                "a whole number or a string of digits",
+                &arguments[0],
+            )),
+        }
+    }
+}
+
+impl Display for BigIntFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native bigint function")
+    }
+}
+
+// approxEq(a, b) or approxEq(a, b, eps) is whether a and b are at most eps
+// apart, 1e-9 unless given, for comparing the results of float arithmetic.
+#[derive(Debug)]
+pub struct ApproxEq;
+
+impl LoxCallable for ApproxEq {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn max_arity(&self) -> usize {
+        3
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let a = number_argument(interpreter, "approxEq", &arguments[0])?;
+        let b = number_argument(interpreter, "approxEq", &arguments[1])?;
+        let eps = match arguments.get(2) {
+            Some(eps) => number_argument(interpreter, "approxEq", eps)?,
+            None => 1e-9,
+        };
+        if eps.is_nan() || eps < 0.0 {
+            return Err(expected(
+                interpreter,
+                "approxEq",
+                "a tolerance of 0 or more",
+                &arguments[2],
+            ));
+        }
+        // Equal infinities are equal, though their difference is NaN.
+        Ok(Literal::Bool(a == b || (a - b).abs() <= eps))
+    }
+}
+
+impl Display for ApproxEq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native approxEq function")
+    }
+}
+
+// toFixed(n, digits) is n as a string with exactly `digits` digits after the
+// point, rounded: toFixed(2 / 3, 2) is "0.67".
+#[derive(Debug)]
+pub struct ToFixed;
+
+impl LoxCallable for ToFixed {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let n = number_argument(interpreter, "toFixed", &arguments[0])?;
+        let digits = digits_argument(interpreter, "toFixed", &arguments[1], 0)?;
+        Ok(Literal::String(format!("{n:.digits$}").into()))
+    }
+}
+
+impl Display for ToFixed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native toFixed function")
+    }
+}
+
+// toPrecision(n, digits) is n as a string rounded to `digits` significant
+// digits: toPrecision(3.14159, 3) is "3.14" and toPrecision(1234.5, 2) is
+// "1.2e3", as numbers too large or small for that many digits are written
+// with an exponent.
+#[derive(Debug)]
+pub struct ToPrecision;
+
+impl LoxCallable for ToPrecision {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let n = number_argument(interpreter, "toPrecision", &arguments[0])?;
+        let digits =
+            digits_argument(interpreter, "toPrecision", &arguments[1], 1)?;
+        Ok(Literal::String(to_precision(n, digits).into()))
+    }
+}
+
+impl Display for ToPrecision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native toPrecision function")
+    }
+}
+
+fn to_precision(n: f64, digits: usize) -> String {
+    if !n.is_finite() {
+        return n.to_string();
+    }
+    // Formatted with an exponent first, so the exponent is the one after
+    // rounding, where 9.99 to two digits is 1.0e1.
+    let scientific = format!("{n:.*e}", digits - 1);
+    let exponent: i64 = scientific
+        .split_once('e')
+        .and_then(|(_, exponent)| exponent.parse().ok())
+        .unwrap_or(0);
+    if exponent < -6 || exponent >= digits as i64 {
+        return scientific;
+    }
+    let decimals = (digits as i64 - 1 - exponent) as usize;
+    format!("{n:.decimals$}")
+}
+
+// A count of digits for toFixed and toPrecision, from `least` up to 100.
+fn digits_argument(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &Literal,
+    least: i64,
+) -> Result<usize, LoxError> {
+    match whole_number(interpreter, function, value)? {
+        digits if (least..=100).contains(&digits) => Ok(digits as usize),
+        _ => Err(expected(
+            interpreter,
+            function,
+            &format!("between {least} and 100 digits"),
+            value,
+        )),
+    }
+}
+
+// toHex(n) is the whole number n in lowercase hexadecimal, with a minus sign
+// rather than two's complement when it's negative: toHex(255) is "ff".
+#[derive(Debug)]
+pub struct ToHex;
+
+impl LoxCallable for ToHex {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let n = whole_number(interpreter, "toHex", &arguments[0])?;
+        let sign = if n < 0 { "-" } else { "" };
+        Ok(Literal::String(
+            format!("{sign}{:x}", n.unsigned_abs()).into(),
+        ))
+    }
+}
+
+impl Display for ToHex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native toHex function")
+    }
+}
+
+// toBin(n) is like toHex in binary: toBin(5) is "101".
+#[derive(Debug)]
+pub struct ToBin;
+
+impl LoxCallable for ToBin {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let n = whole_number(interpreter, "toBin", &arguments[0])?;
+        let sign = if n < 0 { "-" } else { "" };
+        Ok(Literal::String(
+            format!("{sign}{:b}", n.unsigned_abs()).into(),
+        ))
+    }
+}
+
+impl Display for ToBin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native toBin function")
+    }
+}
+
+// parseInt(text) or parseInt(text, base) reads a whole number written in
+// base 2 to 36, 10 unless given, with an optional sign. In base 16 and 2 the
+// number may start with 0x or 0b, so toHex's and toBin's output and the
+// usual literals both read back. Anything else in the text is an error.
+#[derive(Debug)]
+pub struct ParseInt;
+
+impl LoxCallable for ParseInt {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let text = string_argument(interpreter, "parseInt", &arguments[0])?;
+        let base = match arguments.get(1) {
+            Some(base) => match whole_number(interpreter, "parseInt", base)? {
+                base @ 2..=36 => base as u32,
+                _ => {
+                    return Err(expected(
+                        interpreter,
+                        "parseInt",
+                        "a base from 2 to 36",
+                        base,
+                    ))
+                }
+            },
+            None => 10,
+        };
+        match parse_int(text.trim(), base) {
+            Some(n) => Ok(Literal::Integer(n)),
+            None => Err(LoxError::system_error(format!(
+                "parseInt() can't read \"{text}\" as a whole number in base \
+                 {base}."
+            ))),
+        }
+    }
+}
+
+impl Display for ParseInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native parseInt function")
+    }
+}
+
+fn parse_int(text: &str, base: u32) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let prefix = match base {
+        16 => ["0x", "0X"].iter().find_map(|p| digits.strip_prefix(p)),
+        2 => ["0b", "0B"].iter().find_map(|p| digits.strip_prefix(p)),
+        _ => None,
+    };
+    let digits = prefix.unwrap_or(digits);
+    // from_str_radix takes a sign of its own, which mustn't follow ours.
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    let magnitude = u64::from_str_radix(digits, base).ok()?;
+    if negative {
+        0i64.checked_sub_unsigned(magnitude)
+    } else {
+        i64::try_from(magnitude).ok()
+    }
+}
+
+// isDigit(ch), isAlpha(ch) and isSpace(ch): whether the single character
+// string `ch` is a digit from 0 to 9, a letter, or whitespace. Letters and
+// whitespace go by Unicode, so isAlpha("é") is true.
+#[derive(Debug)]
+pub struct CharTest {
+    name: &'static str,
+    test: fn(char) -> bool,
+}
+
+impl CharTest {
+    pub const DIGIT: CharTest = CharTest {
+        name: "isDigit",
+        test: |c| c.is_ascii_digit(),
+    };
+    pub const ALPHA: CharTest = CharTest {
+        name: "isAlpha",
+        test: char::is_alphabetic,
+    };
+    pub const SPACE: CharTest = CharTest {
+        name: "isSpace",
+        test: char::is_whitespace,
+    };
+}
+
+impl LoxCallable for CharTest {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let c = char_argument(interpreter, self.name, &arguments[0])?;
+        Ok(Literal::Bool((self.test)(c)))
+    }
+}
+
+impl Display for CharTest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native {} function", self.name)
+    }
+}
+
+// toUpperChar(ch) and toLowerChar(ch) are the single character string `ch`
+// in the other case, or unchanged when it has none. A few characters change
+// length, as toUpperChar("ß") is "SS".
+#[derive(Debug)]
+pub struct CharCase {
+    name: &'static str,
+    upper: bool,
+}
+
+impl CharCase {
+    pub const UPPER: CharCase = CharCase {
+        name: "toUpperChar",
+        upper: true,
+    };
+    pub const LOWER: CharCase = CharCase {
+        name: "toLowerChar",
+        upper: false,
+    };
+}
+
+impl LoxCallable for CharCase {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let c = char_argument(interpreter, self.name, &arguments[0])?;
+        let converted: String = match self.upper {
+            true => c.to_uppercase().collect(),
+            false => c.to_lowercase().collect(),
+        };
+        Ok(Literal::String(converted.into()))
+    }
+}
+
+impl Display for CharCase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native {} function", self.name)
+    }
+}
+
+// min(sequence) or min(sequence, key) is the first smallest element, by
+// key(element) when a key function is given.
+#[derive(Debug)]
+pub struct Min;
+
+impl LoxCallable for Min {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        extreme("min", Ordering::Less, interpreter, &arguments)
+    }
+}
+
+impl Display for Min {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native min function")
+    }
+}
+
+// Like min, the first largest element.
+#[derive(Debug)]
+pub struct Max;
+
+impl LoxCallable for Max {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        extreme("max", Ordering::Greater, interpreter, &arguments)
+    }
+}
+
+impl Display for Max {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native max function")
+    }
+}
+
+// sum(sequence) adds up numbers, sum(sequence, key) adds key(element) for
+// each element. An empty sequence sums to 0.
+#[derive(Debug)]
+pub struct Sum;
+
+impl LoxCallable for Sum {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let mut total = Literal::Integer(0);
+        for item in elements(interpreter, "sum", &arguments[0])? {
+            let item = match arguments.get(1) {
+                Some(key) => interpreter.call_value(key, vec![item])?,
+                None => item,
+            };
+            total = match (&total, &item) {
+                (Literal::Integer(a), Literal::Integer(b)) => a
+                    .checked_add(*b)
+                    .map(Literal::Integer)
+                    .unwrap_or(Literal::Number(*a as f64 + *b as f64)),
+                _ => match (total.as_number(), item.as_number()) {
+                    (Some(a), Some(b)) => Literal::Number(a + b),
+                    _ => {
+                        return Err(expected(
+                            interpreter,
+                            "sum",
+                            "numbers",
+                            &item,
+                        ))
+                    }
+                },
+            };
+        }
+        Ok(total)
+    }
+}
+
+impl Display for Sum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native sum function")
+    }
+}
+
+// An open file, as returned by open(). Lines are read and text written
+// through buffers, so the file is never held in memory whole. What is
+// written is flushed by close(), and otherwise when the program ends.
+#[derive(Debug)]
+pub struct FileHandle {
+    path: String,
+    file: RefCell<OpenFile>,
+}
+
+enum OpenFile {
+    Reading(BufReader<Box<dyn Read>>),
+    Writing(BufWriter<Box<dyn io::Write>>),
+    Closed,
+}
+
+impl Debug for OpenFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenFile::Reading(_) => write!(f, "Reading"),
+            OpenFile::Writing(_) => write!(f, "Writing"),
+            OpenFile::Closed => write!(f, "Closed"),
+        }
+    }
+}
+
+impl Foreign for FileHandle {
+    fn type_name(&self) -> &'static str {
+        "file"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn close(&self) -> io::Result<()> {
+        match self.file.replace(OpenFile::Closed) {
+            OpenFile::Writing(mut writer) => writer.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for FileHandle {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+impl Display for FileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self.file.borrow() {
+            OpenFile::Closed => write!(f, "<closed file {}>", self.path),
+            _ => write!(f, "<file {}>", self.path),
+        }
+    }
+}
+
+// open(path, mode) opens a file for reading with "r", or for writing with
+// "w" to replace it or "a" to append to it.
+#[derive(Debug)]
+pub struct Open;
+
+impl LoxCallable for Open {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::FileSystem, "open")?;
+        let path = string_argument(interpreter, "open", &arguments[0])?;
+        let mode = string_argument(interpreter, "open", &arguments[1])?;
+        let file_system = interpreter.file_system();
+        let file = Path::new(&*path);
+        let opened = match &*mode {
+            "r" => file_system
+                .open_read(file)
+                .map(|r| OpenFile::Reading(BufReader::new(r))),
+            "w" => file_system
+                .open_write(file)
+                .map(|w| OpenFile::Writing(BufWriter::new(w))),
+            "a" => file_system
+                .open_append(file)
+                .map(|w| OpenFile::Writing(BufWriter::new(w))),
+            _ => {
+                return Err(LoxError::system_error(format!(
+                    "open() mode must be \"r\", \"w\" or \"a\", not \"{mode}\"."
+                )))
+            }
+        };
+
+        let handle: Rc<dyn Foreign> = match opened {
+            Ok(opened) => Rc::new(FileHandle {
+                path: path.to_string(),
+                file: RefCell::new(opened),
+            }),
+            Err(e) => return Err(io_error("open", &path, e)),
+        };
+        interpreter.track_open(&handle);
+        Ok(Literal::Foreign(handle))
+    }
+}
+
+impl Display for Open {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native open function")
+    }
+}
+
+// The next line of a file opened for reading, without its line ending, or
+// nil at the end of the file.
+#[derive(Debug)]
+pub struct ReadLine;
+
+impl LoxCallable for ReadLine {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let handle = file_argument(interpreter, "readLine", &arguments[0])?;
+        let mut file = handle.file.borrow_mut();
+        let reader = match &mut *file {
+            OpenFile::Reading(reader) => reader,
+            OpenFile::Writing(_) => {
+                return Err(file_error(
+                    "readLine",
+                    handle,
+                    "isn't open for reading",
+                ))
+            }
+            OpenFile::Closed => {
+                return Err(file_error("readLine", handle, "is closed"))
+            }
+        };
+
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => Ok(Literal::Nil),
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Ok(Literal::String(line.into()))
+            }
+            Err(e) => Err(io_error("readLine", &handle.path, e)),
+        }
+    }
+}
+
+impl Display for ReadLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native readLine function")
+    }
+}
+
+// write(file, text) writes a string to a file opened for writing, adding no
+// line ending of its own.
+#[derive(Debug)]
+pub struct Write;
+
+impl LoxCallable for Write {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let handle = file_argument(interpreter, "write", &arguments[0])?;
+        let text = string_argument(interpreter, "write", &arguments[1])?;
+        let mut file = handle.file.borrow_mut();
+        let writer = match &mut *file {
+            OpenFile::Writing(writer) => writer,
+            OpenFile::Reading(_) => {
+                return Err(file_error(
+                    "write",
+                    handle,
+                    "isn't open for writing",
+                ))
+            }
+            OpenFile::Closed => {
+                return Err(file_error("write", handle, "is closed"))
+            }
+        };
+
+        match writer.write_all(text.as_bytes()) {
+            Ok(()) => Ok(Literal::Nil),
+            Err(e) => Err(io_error("write", &handle.path, e)),
+        }
+    }
+}
+
+impl Display for Write {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native write function")
+    }
+}
+
+// Closes a file or socket, reporting any error flushing a file. Closing it
+// again does nothing.
+#[derive(Debug)]
+pub struct Close;
+
+impl LoxCallable for Close {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let foreign = match &arguments[0] {
+            Literal::Foreign(foreign) => foreign,
+            other => {
+                return Err(expected(
+                    interpreter,
+                    "close",
+                    "a file or socket",
+                    other,
+                ))
+            }
+        };
+
+        let name = foreign.to_string();
+        match foreign.close() {
+            Ok(()) => Ok(Literal::Nil),
+            Err(e) => Err(io_error("close", &name, e)),
+        }
+    }
+}
+
+impl Display for Close {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native close function")
+    }
+}
+
+// readFile(path) is the whole text of a file.
+#[derive(Debug)]
+pub struct ReadFile;
+
+impl LoxCallable for ReadFile {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::FileSystem, "readFile")?;
+        let path = string_argument(interpreter, "readFile", &arguments[0])?;
+        let text = interpreter.file_system().read(Path::new(&*path)).and_then(
+            |contents| {
+                String::from_utf8(contents)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            },
+        );
+        match text {
+            Ok(text) => Ok(Literal::String(text.into())),
+            Err(e) => Err(io_error("readFile", &path, e)),
+        }
+    }
+}
+
+impl Display for ReadFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native readFile function")
+    }
+}
+
+// writeFile(path, text) replaces a file with `text`.
+#[derive(Debug)]
+pub struct WriteFile;
+
+impl LoxCallable for WriteFile {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::FileSystem, "writeFile")?;
+        let path = string_argument(interpreter, "writeFile", &arguments[0])?;
+        let text = string_argument(interpreter, "writeFile", &arguments[1])?;
+        let file_system = interpreter.file_system();
+        match file_system.write(Path::new(&*path), text.as_bytes()) {
+            Ok(()) => Ok(Literal::Nil),
+            Err(e) => Err(io_error("writeFile", &path, e)),
+        }
+    }
+}
+
+impl Display for WriteFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native writeFile function")
+    }
+}
+
+// fileExists(path) is whether there is a file at `path`.
+#[derive(Debug)]
+pub struct FileExists;
+
+impl LoxCallable for FileExists {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::FileSystem, "fileExists")?;
+        let path = string_argument(interpreter, "fileExists", &arguments[0])?;
+        let exists = interpreter.file_system().exists(Path::new(&*path));
+        Ok(Literal::Bool(exists))
+    }
+}
+
+impl Display for FileExists {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native fileExists function")
+    }
+}
+
+// A connection made by tcpConnect() or accept().
+#[derive(Debug)]
+pub struct SocketHandle {
+    peer: String,
+    stream: RefCell<Option<TcpStream>>,
+}
+
+impl SocketHandle {
+    fn new(stream: TcpStream) -> SocketHandle {
+        let peer = match stream.peer_addr() {
+            Ok(address) => address.to_string(),
+            Err(_) => "unknown peer".to_string(),
+        };
+        SocketHandle {
+            peer,
+            stream: RefCell::new(Some(stream)),
+        }
+    }
+}
+
+impl Foreign for SocketHandle {
+    fn type_name(&self) -> &'static str {
+        "socket"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.stream.replace(None);
+        Ok(())
+    }
+}
+
+impl Display for SocketHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self.stream.borrow() {
+            Some(_) => write!(f, "<socket {}>", self.peer),
+            None => write!(f, "<closed socket {}>", self.peer),
+        }
+    }
+}
+
+// A socket waiting for connections, made by tcpListen().
+#[derive(Debug)]
+pub struct ListenerHandle {
+    address: String,
+    listener: RefCell<Option<TcpListener>>,
+}
+
+impl Foreign for ListenerHandle {
+    fn type_name(&self) -> &'static str {
+        "listener"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.listener.replace(None);
+        Ok(())
+    }
+}
+
+impl Display for ListenerHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self.listener.borrow() {
+            Some(_) => write!(f, "<listener {}>", self.address),
+            None => write!(f, "<closed listener {}>", self.address),
+        }
+    }
+}
+
+// tcpConnect(host, port) opens a connection, waiting until it is made.
+#[derive(Debug)]
+pub struct TcpConnect;
+
+impl LoxCallable for TcpConnect {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::Network, "tcpConnect")?;
+        let host = string_argument(interpreter, "tcpConnect", &arguments[0])?;
+        let port = port_argument(interpreter, "tcpConnect", &arguments[1])?;
+        match TcpStream::connect((&*host, port)) {
+            Ok(stream) => {
+                Ok(Literal::Foreign(Rc::new(SocketHandle::new(stream))))
+            }
+            Err(e) => Err(io_error("tcpConnect", &format!("{host}:{port}"), e)),
+        }
+    }
+}
+
+impl Display for TcpConnect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native tcpConnect function")
+    }
+}
+
+// tcpListen(port) listens on the loopback interface only, so a toy server
+// isn't reachable from other machines. Port 0 picks a free port, which
+// tcpPort() then tells.
+#[derive(Debug)]
+pub struct TcpListen;
+
+impl LoxCallable for TcpListen {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::Network, "tcpListen")?;
+        let port = port_argument(interpreter, "tcpListen", &arguments[0])?;
+        let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, port)) {
+            Ok(listener) => listener,
+            Err(e) => return Err(io_error("tcpListen", &port.to_string(), e)),
+        };
+
+        let address = match listener.local_addr() {
+            Ok(address) => address.to_string(),
+            Err(_) => format!("{}:{port}", Ipv4Addr::LOCALHOST),
+        };
+        Ok(Literal::Foreign(Rc::new(ListenerHandle {
+            address,
+            listener: RefCell::new(Some(listener)),
+        })))
+    }
+}
+
+impl Display for TcpListen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native tcpListen function")
+    }
+}
+
+// The local port of a listener or socket.
+#[derive(Debug)]
+pub struct TcpPort;
+
+impl LoxCallable for TcpPort {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let address = match &arguments[0] {
+            Literal::Foreign(foreign) => {
+                let any = foreign.as_any();
+                if let Some(listener) = any.downcast_ref::<ListenerHandle>() {
+                    listener.listener.borrow().as_ref().map(|l| l.local_addr())
+                } else if let Some(socket) = any.downcast_ref::<SocketHandle>()
+                {
+                    socket.stream.borrow().as_ref().map(|s| s.local_addr())
+                } else {
+                    return Err(expected(
+                        interpreter,
+                        "tcpPort",
+                        "a listener or socket",
+                        &arguments[0],
+                    ));
+                }
+            }
+            other => {
+                return Err(expected(
+                    interpreter,
+                    "tcpPort",
+                    "a listener or socket",
+                    other,
+                ))
+            }
+        };
+
+        match address {
+            Some(Ok(address)) => Ok(Literal::Integer(address.port().into())),
+            Some(Err(e)) => {
+                Err(io_error("tcpPort", &arguments[0].to_string(), e))
+            }
+            None => Err(LoxError::system_error(format!(
+                "tcpPort() can't use {}, it is closed.",
+                arguments[0]
+            ))),
+        }
+    }
+}
+
+impl Display for TcpPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native tcpPort function")
+    }
+}
+
+// accept(listener) waits for the next connection and returns its socket.
+#[derive(Debug)]
+pub struct Accept;
+
+impl LoxCallable for Accept {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let handle: &ListenerHandle = foreign_argument(
+            interpreter,
+            "accept",
+            "a listener",
+            &arguments[0],
+        )?;
+        let listener = handle.listener.borrow();
+        let listener = match &*listener {
+            Some(listener) => listener,
+            None => {
+                return Err(LoxError::system_error(format!(
+                    "accept() can't use {}, it is closed.",
+                    handle.address
+                )))
+            }
+        };
+
+        match listener.accept() {
+            Ok((stream, _)) => {
+                Ok(Literal::Foreign(Rc::new(SocketHandle::new(stream))))
+            }
+            Err(e) => Err(io_error("accept", &handle.address, e)),
+        }
+    }
+}
+
+impl Display for Accept {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native accept function")
+    }
+}
+
+// send(socket, text) writes all of a string to a connection.
+#[derive(Debug)]
+pub struct SendFn;
+
+impl LoxCallable for SendFn {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let handle = socket_argument(interpreter, "send", &arguments[0])?;
+        let text = string_argument(interpreter, "send", &arguments[1])?;
+        let mut stream = handle.stream.borrow_mut();
+        let stream = open_socket("send", handle, &mut stream)?;
+        match stream.write_all(text.as_bytes()) {
+            Ok(()) => Ok(Literal::Nil),
+            Err(e) => Err(io_error("send", &handle.peer, e)),
+        }
+    }
+}
+
+impl Display for SendFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native send function")
+    }
+}
+
+// recv(socket) waits for data and returns what arrived, or nil once the
+// other end has closed the connection. Bytes that aren't UTF-8 are replaced.
+#[derive(Debug)]
+pub struct Recv;
+
+impl LoxCallable for Recv {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        let handle = socket_argument(interpreter, "recv", &arguments[0])?;
+        let mut stream = handle.stream.borrow_mut();
+        let stream = open_socket("recv", handle, &mut stream)?;
+
+        let mut buffer = [0; 4096];
+        match stream.read(&mut buffer) {
+            Ok(0) => Ok(Literal::Nil),
+            Ok(n) => {
+                let text = String::from_utf8_lossy(&buffer[..n]);
+                Ok(Literal::String(text.into()))
+            }
+            Err(e) => Err(io_error("recv", &handle.peer, e)),
+        }
+    }
+}
+
+impl Display for Recv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native recv function")
+    }
+}
+
+// fetch(url) or fetch(url, options) makes an HTTP request and returns a map
+// of its "status", "headers" and "body". Options is a map that may set the
+// "method", the request "headers" as a map and a "body" string.
+#[derive(Debug)]
+pub struct Fetch;
+
+impl LoxCallable for Fetch {
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn max_arity(&self) -> usize {
+        2
+    }
+
+    fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, LoxError> {
+        require(interpreter, Capability::Network, "fetch")?;
+        let mut request = http::Request {
+            method: "GET".to_string(),
+            url: string_argument(interpreter, "fetch", &arguments[0])?
+                .to_string(),
+            headers: Vec::new(),
+            body: String::new(),
+        };
+
+        if let Some(options) = arguments.get(1) {
+            let options = map_argument(interpreter, "fetch", options)?.borrow();
+            let option =
+                |name: &str| options.get(&Literal::String(name.into()));
+            if let Some(method) = option("method") {
+                request.method =
+                    string_argument(interpreter, "fetch", method)?.to_string();
+            }
+            if let Some(body) = option("body") {
+                request.body =
+                    string_argument(interpreter, "fetch", body)?.to_string();
+            }
+            if let Some(headers) = option("headers") {
+                for (name, value) in
+                    map_argument(interpreter, "fetch", headers)?.borrow().iter()
+                {
+                    request.headers.push((
+                        string_argument(interpreter, "fetch", name)?
+                            .to_string(),
+                        string_argument(interpreter, "fetch", value)?
+                            .to_string(),
+                    ));
+                }
+            }
+        }
+
+        let response = match http::fetch(&request) {
+            Ok(response) => response,
+            Err(e) => {
+                return Err(LoxError::system_error(format!("fetch() {e}.")))
+            }
+        };
+
+        let headers = response
+            .headers
+            .into_iter()
+            .map(|(name, value)| (name, Literal::String(value.into())))
+            .collect();
+        Ok(map_of(vec![
+            (
+                "status".to_string(),
+                Literal::Integer(response.status.into()),
+            ),
+            ("headers".to_string(), map_of(headers)),
+            ("body".to_string(), Literal::String(response.body.into())),
+        ]))
+    }
+}
+
+impl Display for Fetch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "native fetch function")
+    }
+}
+
+fn extreme(
+    function: &str,
+    wanted: Ordering,
+    interpreter: &mut Interpreter,
+    arguments: &[Literal],
+) -> Result<Literal, LoxError> {
+    let mut best: Option<(Literal, Literal)> = None;
+    for item in elements(interpreter, function, &arguments[0])? {
+        let key = match arguments.get(1) {
+            Some(key) => interpreter.call_value(key, vec![item.clone()])?,
+            None => item.clone(),
+        };
+        best = match best {
+            Some((best_item, best_key))
+                if compare(function, &key, &best_key)? != wanted =>
+            {
+                Some((best_item, best_key))
+            }
+            _ => Some((item, key)),
+        };
+    }
+
+    match best {
+        Some((item, _)) => Ok(item),
+        None => Err(LoxError::system_error(format!(
+            "{function}() of an empty sequence."
+        ))),
+    }
+}
+
+// The natural order of numbers and of strings. Anything else, such as a
+// number and a string, has no order.
+fn compare(
+    function: &str,
+    a: &Literal,
+    b: &Literal,
+) -> Result<Ordering, LoxError> {
+    let ordering = match (a, b) {
+        (Literal::Integer(a), Literal::Integer(b)) => Some(a.cmp(b)),
+        (Literal::String(a), Literal::String(b)) => Some(a.cmp(b)),
+        (Literal::BigInt(_), _) | (_, Literal::BigInt(_)) => {
+            match (a.as_bigint(), b.as_bigint()) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => None,
+            }
+        }
+        _ => match (a.as_number(), b.as_number()) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => None,
+        },
+    };
+
+    ordering.ok_or_else(|| {
+        LoxError::system_error(format!(
+            "{function}() can't compare {} {a} with {} {b}.",
+            a.type_name(),
+            b.type_name()
+        ))
+    })
+}
+
+// A bottom-up merge sort that stops at the first error from `before`. The
+// standard library's sorts may panic when the order isn't consistent, which
+// a Lox comparator can't promise.
+fn merge_sort(
+    items: Vec<Literal>,
+    before: &mut dyn FnMut(&Literal, &Literal) -> Result<bool, LoxError>,
+) -> Result<Vec<Literal>, LoxError> {
+    let n = items.len();
+    let mut from = items;
+    let mut width = 1;
+    while width < n {
+        let mut to = Vec::with_capacity(n);
+        for start in (0..n).step_by(2 * width) {
+            let middle = (start + width).min(n);
+            let end = (middle + width).min(n);
+            let (mut left, mut right) = (start, middle);
+            while left < middle || right < end {
+                // Taking from the right only when it is strictly before the
+                // left keeps equal elements in order.
+                if left == middle
+                    || (right < end && before(&from[right], &from[left])?)
+                {
+                    to.push(from[right].clone());
+                    right += 1;
+                } else {
+                    to.push(from[left].clone());
+                    left += 1;
+                }
+            }
+        }
+        from = to;
+        width *= 2;
+    }
+
+    Ok(from)
+}
+
+// A map from the given names to values.
+fn map_of(entries: Vec<(String, Literal)>) -> Literal {
+    let entries = entries
+        .into_iter()
+        .map(|(name, value)| (Literal::String(name.into()), value))
+        .collect();
+    Literal::Map(Rc::new(RefCell::new(entries)))
+}
+
+fn names_list(names: Vec<Rc<str>>) -> Literal {
+    Literal::new_list(names.into_iter().map(Literal::String).collect())
+}
+
+fn expected(
+    interpreter: &Interpreter,
+    function: &str,
+    expected: &str,
+    found: &Literal,
+) -> LoxError {
+    call_error(
+        interpreter,
+        format!(
+            "{function}() expects {expected} but got {}.",
+            value_printer::describe(found)
+        ),
+    )
+}
+
+fn number_argument(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &Literal,
+) -> Result<f64, LoxError> {
+    value
+        .as_number()
+        .ok_or_else(|| expected(interpreter, function, "a number", value))
+}
+
+fn whole_number(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &Literal,
+) -> Result<i64, LoxError> {
+    value
+        .as_integer()
+        .ok_or_else(|| expected(interpreter, function, "whole numbers", value))
+}
+
+// A function or class called later with no arguments.
+fn callback_argument(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &Literal,
+) -> Result<Literal, LoxError> {
+    match value.as_callable() {
+        Some(callback) if arity_mismatch(callback.as_ref(), 0).is_none() => {
+            Ok(value.clone())
+        }
+        Some(_) => Err(call_error(
+            interpreter,
+            format!("{function}() callbacks must take no arguments."),
+        )),
+        None => Err(expected(interpreter, function, "a function", value)),
+    }
+}
+
+// A copy of the elements of a list or range, so callbacks run over them can
+// change the list.
+fn elements(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &Literal,
+) -> Result<Vec<Literal>, LoxError> {
+    match value {
+        Literal::List(items) => Ok(items.borrow().clone()),
+        Literal::Range(range) => {
+            Ok(range.iter().map(Literal::Integer).collect())
+        }
+        other => Err(expected(interpreter, function, "a list or range", other)),
+    }
+}
+
+fn string_argument(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &Literal,
+) -> Result<Rc<str>, LoxError> {
+    match value {
+        Literal::String(s) => Ok(s.clone()),
+        other => Err(expected(interpreter, function, "a string", other)),
+    }
+}
+
+fn char_argument(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &Literal,
+) -> Result<char, LoxError> {
+    let mut chars = match value {
+        Literal::String(s) => s.chars(),
+        other => {
+            return Err(expected(interpreter, function, "a character", other))
+        }
+    };
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(expected(interpreter, function, "a single character", value)),
+    }
+}
+
+// The foreign value of type T that `value` holds, e.g. the FileHandle of an
+// open file.
+fn foreign_argument<'a, T: 'static>(
+    interpreter: &Interpreter,
+    function: &str,
+    description: &str,
+    value: &'a Literal,
+) -> Result<&'a T, LoxError> {
+    match value {
+        Literal::Foreign(foreign) => foreign.as_any().downcast_ref::<T>(),
+        _ => None,
+    }
+    .ok_or_else(|| expected(interpreter, function, description, value))
+}
+
+fn file_argument<'a>(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &'a Literal,
+) -> Result<&'a FileHandle, LoxError> {
+    foreign_argument(interpreter, function, "a file", value)
+}
+
+fn socket_argument<'a>(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &'a Literal,
+) -> Result<&'a SocketHandle, LoxError> {
+    foreign_argument(interpreter, function, "a socket", value)
+}
+
+fn open_socket<'a>(
+    function: &str,
+    handle: &SocketHandle,
+    stream: &'a mut Option<TcpStream>,
+) -> Result<&'a mut TcpStream, LoxError> {
+    stream.as_mut().ok_or_else(|| {
+        LoxError::system_error(format!(
+            "{function}() can't use {}, it is closed.",
+            handle.peer
+        ))
+    })
+}
+
+fn port_argument(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &Literal,
+) -> Result<u16, LoxError> {
+    value
+        .as_integer()
+        .and_then(|port| u16::try_from(port).ok())
+        .ok_or_else(|| {
+            expected(interpreter, function, "a port from 0 to 65535", value)
+        })
+}
+
+fn file_error(function: &str, handle: &FileHandle, problem: &str) -> LoxError {
+    LoxError::system_error(format!(
+        "{function}() can't use {}, it {problem}.",
+        handle.path
+    ))
+}
+
+// Fails unless the sandbox lets natives use `capability`.
+fn require(
+    interpreter: &Interpreter,
+    capability: Capability,
+    function: &str,
+) -> Result<(), LoxError> {
+    if interpreter.options().sandbox.allows(capability) {
+        return Ok(());
+    }
+
+    Err(call_error(
+        interpreter,
+        format!(
+            "{function}() needs the '{capability}' capability, which the \
+             sandbox denies."
+        ),
+    ))
+}
+
+// A runtime error at the call when it was made from Lox code, and one
+// without a line when it came from the host.
+fn call_error(interpreter: &Interpreter, message: String) -> LoxError {
+    match interpreter.call_site() {
+        Some(paren) => LoxError::runtime_error(paren.clone(), message),
+        None => LoxError::system_error(message),
+    }
+}
+
+// `target` names what failed, such as a path or an address.
+fn io_error(function: &str, target: &str, error: io::Error) -> LoxError {
+    LoxError::system_error(format!("{function}() failed on {target}: {error}."))
+}
+
+fn list_argument<'a>(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &'a Literal,
+) -> Result<&'a Rc<RefCell<Vec<Literal>>>, LoxError> {
+    match value {
+        Literal::List(items) => Ok(items),
+        other => Err(expected(interpreter, function, "a list", other)),
+    }
+}
+
+fn map_argument<'a>(
+    interpreter: &Interpreter,
+    function: &str,
+    value: &'a Literal,
+) -> Result<&'a Rc<RefCell<LoxMap>>, LoxError> {
+    match value {
+        Literal::Map(entries) => Ok(entries),
+        other => Err(expected(interpreter, function, "a map", other)),
+    }
+}
+
+// Any value can be a key except NaN, which no lookup would ever find.
+fn map_key<'a>(
+    interpreter: &Interpreter,
+    function: &str,
+    key: &'a Literal,
+) -> Result<&'a Literal, LoxError> {
+    match key.as_number() {
+        Some(n) if n.is_nan() => Err(call_error(
+            interpreter,
+            format!("{function}() can't use NaN as a key."),
+        )),
+        _ => Ok(key),
+    }
+}
+
+fn out_of_bounds(
+    interpreter: &Interpreter,
+    index: i64,
+    sequence: &Literal,
+    len: usize,
+) -> LoxError {
+    call_error(
+        interpreter,
+        format!(
+            "Index {index} is out of bounds for a {} of length {len}.",
+            sequence.type_name()
+        ),
+    )
+}
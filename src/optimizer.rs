@@ -0,0 +1,585 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::expr::{
+    BinaryExpr, Expr, GetExpr, GroupingExpr, LiteralExpr, LogicalExpr,
+    UnaryExpr, VariableExpr,
+};
+use crate::stmt::{FunctionStmt, Stmt};
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::walk::{walk_expr, walk_function, walk_stmt, walk_stmts, Walk};
+
+// Drops the top-level function declarations `reachable` doesn't name, and
+// returns what is left along with the names of the dropped functions.
+// Functions declared in blocks, other functions or classes are always kept.
+pub fn remove_unused_functions(
+    statements: Vec<Stmt>,
+    reachable: &HashSet<Symbol>,
+) -> (Vec<Stmt>, Vec<Token>) {
+    let mut removed = Vec::new();
+    let kept = statements
+        .into_iter()
+        .filter(|statement| match statement {
+            Stmt::Function(function)
+                if !reachable.contains(&function.name.symbol) =>
+            {
+                removed.push(function.name.clone());
+                false
+            }
+            _ => true,
+        })
+        .collect();
+
+    (kept, removed)
+}
+
+// Replaces calls to tiny top-level functions with what they return, the
+// arguments put in place of the parameters, so a hot loop doesn't pay for a
+// call and an environment each time round. Returns each inlined function's
+// name with how many calls were replaced. Runs before resolving, as the
+// copied expressions still need their variables resolved.
+//
+// Only functions whose body is a single `return` of an expression without
+// calls or assignments qualify, so inlining can't change what runs or in
+// which order. Arguments must be literals or variables, and a variable
+// argument's parameter must be read whether or not any `and` or `or`
+// short-circuits, so it's read as often as the call would have read it.
+// A function is left alone if its name, or a global its body reads, is
+// ever assigned or declared anywhere but the top level, or if it has type
+// annotations for the type checker to check calls against.
+pub fn inline_small_functions(statements: &mut [Stmt]) -> Vec<(Token, usize)> {
+    let mut bindings = Bindings::default();
+    walk_stmts(&mut bindings, statements);
+
+    let mut inliner = Inliner {
+        bindings,
+        candidates: HashMap::new(),
+        inlined: Vec::new(),
+    };
+    // Calls are only inlined after the declaration, where the call would
+    // have found the function too. A function is looked at after its own
+    // body, so one that only calls another inlined one can qualify itself.
+    for statement in statements.iter_mut() {
+        inliner.stmt(statement);
+        if let Stmt::Function(function) = statement {
+            inliner.consider(function);
+        }
+    }
+
+    inliner.inlined.retain(|(_, count)| *count > 0);
+    inliner.inlined
+}
+
+// The names declared anywhere but the top level, the names assigned
+// anywhere, and how many times each top-level name is declared.
+#[derive(Default)]
+struct Bindings {
+    depth: usize,
+    locals: HashSet<Rc<str>>,
+    assigned: HashSet<Rc<str>>,
+    top_level: HashMap<Rc<str>, usize>,
+}
+
+impl Bindings {
+    fn declare(&mut self, name: &Token) {
+        if self.depth == 0 {
+            *self.top_level.entry(name.lexeme.clone()).or_default() += 1;
+        } else {
+            self.locals.insert(name.lexeme.clone());
+        }
+    }
+
+    fn function(&mut self, function: &FunctionStmt) {
+        self.depth += 1;
+        for param in &function.params {
+            self.declare(param);
+        }
+        walk_function(self, function);
+        self.depth -= 1;
+    }
+
+    // Whether `name` always means the one top-level declaration of it.
+    fn is_fixed_global(&self, name: &str) -> bool {
+        !self.locals.contains(name)
+            && !self.assigned.contains(name)
+            && self.top_level.get(name).is_none_or(|&count| count <= 1)
+    }
+}
+
+impl Walk for Bindings {
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Var(vs) => self.declare(&vs.name),
+            Stmt::Function(fs) => {
+                self.declare(&fs.name);
+                self.function(fs);
+                return;
+            }
+            Stmt::Class(cs) => {
+                self.declare(&cs.name);
+                for method in &cs.methods {
+                    self.function(method);
+                }
+                return;
+            }
+            Stmt::Block(_) | Stmt::For(_) => {
+                self.depth += 1;
+                walk_stmt(self, stmt);
+                self.depth -= 1;
+                return;
+            }
+            _ => {}
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign(ae) => {
+                self.assigned.insert(ae.name.lexeme.clone());
+            }
+            // A case's binding is local to it, wherever the match is.
+            Expr::Match(me) => {
+                for binding in me.arms.iter().filter_map(|a| a.binding.as_ref())
+                {
+                    self.locals.insert(binding.lexeme.clone());
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+// A function calls to which can be replaced by its returned expression.
+struct Candidate {
+    params: Vec<Rc<str>>,
+    // Whether each parameter is read however the body short-circuits.
+    always_read: Vec<bool>,
+    body: Rc<Expr>,
+    // Where its count is in Inliner::inlined.
+    index: usize,
+}
+
+struct Inliner {
+    bindings: Bindings,
+    candidates: HashMap<Symbol, Candidate>,
+    inlined: Vec<(Token, usize)>,
+}
+
+impl Inliner {
+    fn consider(&mut self, function: &FunctionStmt) {
+        let [Stmt::Return(rs)] = function.body.as_slice() else {
+            return;
+        };
+        let Some(body) = &rs.value else {
+            return;
+        };
+        let annotated = function.return_type.is_some()
+            || function.param_types.iter().any(Option::is_some);
+        if annotated || !self.bindings.is_fixed_global(&function.name.lexeme) {
+            return;
+        }
+
+        let params: Vec<Rc<str>> =
+            function.params.iter().map(|p| p.lexeme.clone()).collect();
+        if !self.is_simple(body, &function.name.lexeme, &params) {
+            return;
+        }
+
+        let always_read =
+            params.iter().map(|p| always_reads(body, p)).collect();
+        self.candidates.insert(
+            function.name.symbol,
+            Candidate {
+                params,
+                always_read,
+                body: body.clone(),
+                index: self.inlined.len(),
+            },
+        );
+        self.inlined.push((function.name.clone(), 0));
+    }
+
+    // Whether `expr` only reads: no calls, no assignments, nothing that
+    // mentions the function itself, and no global that could mean
+    // something else where the function is called.
+    fn is_simple(
+        &self,
+        expr: &Expr,
+        function: &str,
+        params: &[Rc<str>],
+    ) -> bool {
+        let simple = |e: &Rc<Expr>| self.is_simple(e, function, params);
+        match expr {
+            Expr::Binary(be) => simple(&be.left) && simple(&be.right),
+            Expr::Get(ge) => simple(&ge.object),
+            Expr::Grouping(ge) => simple(&ge.expression),
+            Expr::Literal(_) => true,
+            Expr::Logical(le) => simple(&le.left) && simple(&le.right),
+            Expr::Unary(ue) => simple(&ue.right),
+            Expr::Variable(ve) => {
+                let name = &ve.name.lexeme;
+                params.contains(name)
+                    || (**name != *function
+                        && self.bindings.is_fixed_global(name))
+            }
+            Expr::Assign(_)
+            | Expr::Call(_)
+            | Expr::Match(_)
+            | Expr::Set(_)
+            | Expr::This(_) => false,
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Block(bs) => {
+                for statement in &mut bs.statements {
+                    self.stmt(statement);
+                }
+            }
+            Stmt::Break(_) | Stmt::Continue(_) => {}
+            Stmt::Class(cs) => {
+                for method in &mut cs.methods {
+                    self.function(method);
+                }
+            }
+            Stmt::Expression(es) => self.expr(&mut es.expression),
+            Stmt::For(fs) => {
+                if let Some(initializer) = &mut fs.initializer {
+                    self.shared_stmt(initializer);
+                }
+                if let Some(condition) = &mut fs.condition {
+                    self.expr(condition);
+                }
+                if let Some(increment) = &mut fs.increment {
+                    self.expr(increment);
+                }
+                self.shared_stmt(&mut fs.body);
+            }
+            Stmt::Function(fs) => self.function(fs),
+            Stmt::If(is) => {
+                self.expr(&mut is.condition);
+                self.shared_stmt(&mut is.then_branch);
+                if let Some(else_branch) = &mut is.else_branch {
+                    self.shared_stmt(else_branch);
+                }
+            }
+            Stmt::Print(ps) => self.expr(&mut ps.expression),
+            Stmt::Return(rs) => {
+                if let Some(value) = &mut rs.value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Var(vs) => {
+                if let Some(initializer) = &mut vs.initializer {
+                    self.expr(initializer);
+                }
+            }
+            Stmt::While(ws) => {
+                self.expr(&mut ws.condition);
+                self.shared_stmt(&mut ws.body);
+            }
+        }
+    }
+
+    // Nodes straight from the parser have one owner each, so they can be
+    // changed in place. Anything shared is left as it is.
+    fn shared_stmt(&mut self, stmt: &mut Rc<Stmt>) {
+        if let Some(stmt) = Rc::get_mut(stmt) {
+            self.stmt(stmt);
+        }
+    }
+
+    fn function(&mut self, function: &mut FunctionStmt) {
+        if let Some(body) = Rc::get_mut(&mut function.body) {
+            for statement in body {
+                self.stmt(statement);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Rc<Expr>) {
+        if let Some(inner) = Rc::get_mut(expr) {
+            match inner {
+                Expr::Assign(ae) => self.expr(&mut ae.value),
+                Expr::Binary(be) => {
+                    self.expr(&mut be.left);
+                    self.expr(&mut be.right);
+                }
+                Expr::Call(ce) => {
+                    self.expr(&mut ce.callee);
+                    for argument in &mut ce.arguments {
+                        self.expr(argument);
+                    }
+                }
+                Expr::Get(ge) => self.expr(&mut ge.object),
+                Expr::Grouping(ge) => self.expr(&mut ge.expression),
+                Expr::Literal(_) | Expr::This(_) | Expr::Variable(_) => {}
+                Expr::Logical(le) => {
+                    self.expr(&mut le.left);
+                    self.expr(&mut le.right);
+                }
+                Expr::Match(me) => {
+                    self.expr(&mut me.subject);
+                    for arm in &mut me.arms {
+                        arm.pattern.iter_mut().for_each(|p| self.expr(p));
+                        arm.guard.iter_mut().for_each(|g| self.expr(g));
+                        self.expr(&mut arm.value);
+                    }
+                }
+                Expr::Set(se) => {
+                    self.expr(&mut se.object);
+                    self.expr(&mut se.value);
+                }
+                Expr::Unary(ue) => self.expr(&mut ue.right),
+            }
+        }
+
+        if let Some(inlined) = self.inline_call(expr) {
+            *expr = inlined;
+        }
+    }
+
+    fn inline_call(&mut self, expr: &Expr) -> Option<Rc<Expr>> {
+        let Expr::Call(call) = expr else {
+            return None;
+        };
+        let Expr::Variable(callee) = &*call.callee else {
+            return None;
+        };
+        let candidate = self.candidates.get(&callee.name.symbol)?;
+        if call.arguments.len() != candidate.params.len() {
+            return None;
+        }
+        let arguments_fit =
+            call.arguments.iter().zip(&candidate.always_read).all(
+                |(argument, &always_read)| match &**argument {
+                    Expr::Literal(_) => true,
+                    Expr::Variable(_) => always_read,
+                    _ => false,
+                },
+            );
+        if !arguments_fit {
+            return None;
+        }
+
+        let inlined =
+            substitute(&candidate.body, &candidate.params, &call.arguments);
+        self.inlined[candidate.index].1 += 1;
+        Some(inlined)
+    }
+}
+
+// Whether evaluating `expr` always reads the variable `name`, which it
+// doesn't if the only reads are on the right of an `and` or `or`.
+fn always_reads(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::Binary(be) => {
+            always_reads(&be.left, name) || always_reads(&be.right, name)
+        }
+        Expr::Get(ge) => always_reads(&ge.object, name),
+        Expr::Grouping(ge) => always_reads(&ge.expression, name),
+        Expr::Logical(le) => always_reads(&le.left, name),
+        Expr::Match(me) => always_reads(&me.subject, name),
+        Expr::Unary(ue) => always_reads(&ue.right, name),
+        Expr::Variable(ve) => *ve.name.lexeme == *name,
+        _ => false,
+    }
+}
+
+// A copy of a simple function body with each parameter replaced by its
+// argument. The copy's variables get caches of their own for the resolver
+// to fill in.
+fn substitute(
+    expr: &Rc<Expr>,
+    params: &[Rc<str>],
+    arguments: &[Rc<Expr>],
+) -> Rc<Expr> {
+    let copy = |e: &Rc<Expr>| substitute(e, params, arguments);
+    let substituted = match &**expr {
+        Expr::Binary(be) => Expr::Binary(BinaryExpr::new(
+            copy(&be.left),
+            be.operator.clone(),
+            copy(&be.right),
+        )),
+        Expr::Get(ge) => Expr::Get(GetExpr::new(
+            copy(&ge.object),
+            ge.name.clone(),
+            ge.optional,
+        )),
+        Expr::Grouping(ge) => {
+            Expr::Grouping(GroupingExpr::new(copy(&ge.expression)))
+        }
+        Expr::Literal(le) => Expr::Literal(LiteralExpr::new(le.value.clone())),
+        Expr::Logical(le) => Expr::Logical(LogicalExpr::new(
+            copy(&le.left),
+            le.operator.clone(),
+            copy(&le.right),
+        )),
+        Expr::Unary(ue) => {
+            Expr::Unary(UnaryExpr::new(ue.operator.clone(), copy(&ue.right)))
+        }
+        Expr::Variable(ve) => {
+            match params.iter().position(|p| *p == ve.name.lexeme) {
+                // Parentheses end an optional chain, so the body's own
+                // property accesses don't join one the argument started.
+                Some(i) if arguments[i].in_optional_chain() => {
+                    Expr::Grouping(GroupingExpr::new(arguments[i].clone()))
+                }
+                Some(i) => return arguments[i].clone(),
+                None => Expr::Variable(VariableExpr::new(ve.name.clone())),
+            }
+        }
+        // Not in a body that qualifies for inlining.
+        _ => return expr.clone(),
+    };
+    Rc::new(substituted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::resolver::{Resolver, Usage};
+    use crate::scanner::Scanner;
+    use crate::source_printer::SourcePrinter;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let parsed = Parser::new(&tokens).parse();
+        parsed.unwrap_or_else(|_| panic!("test source should parse"))
+    }
+
+    fn usage(statements: &[Stmt]) -> Usage {
+        let mut resolver = Resolver::new();
+        assert!(resolver.resolve(statements).is_ok());
+        resolver.take_usage()
+    }
+
+    fn removed(source: &str) -> Vec<String> {
+        let statements = parse(source);
+        let reachable = usage(&statements).reachable();
+        let (_, removed) = remove_unused_functions(statements, &reachable);
+        removed.iter().map(|name| name.lexeme.to_string()).collect()
+    }
+
+    #[test]
+    fn removes_functions_nothing_calls() {
+        assert_eq!(removed("fun a() {} fun b() {} b();"), ["a"]);
+    }
+
+    #[test]
+    fn keeps_functions_reached_through_other_functions() {
+        let source = "fun a() {} fun b() { a(); } fun c() { b(); } c();";
+        assert!(removed(source).is_empty());
+    }
+
+    #[test]
+    fn removes_functions_only_reached_from_removed_ones() {
+        let source = "fun a() { b(); } fun b() { a(); } fun c() {} c();";
+        assert_eq!(removed(source), ["a", "b"]);
+    }
+
+    #[test]
+    fn keeps_functions_used_by_classes_and_values() {
+        let source = "fun a() {} fun b() {} fun c() {}
+            class C { m() { a(); } }
+            var f = b;
+            c = nil;";
+        assert!(removed(source).is_empty());
+    }
+
+    #[test]
+    fn shadowed_names_are_not_uses() {
+        let source = "fun a() {} fun b(a) { a(); } b(1);";
+        assert_eq!(removed(source), ["a"]);
+    }
+
+    #[test]
+    fn keeps_functions_used_by_a_later_unit() {
+        let prelude = parse("fun helper() {} fun unused() {}");
+        let script = parse("helper();");
+        let mut used = usage(&prelude);
+        used.merge(usage(&script));
+
+        let (_, removed) = remove_unused_functions(prelude, &used.reachable());
+        assert_eq!(removed.len(), 1);
+        assert_eq!(&*removed[0].lexeme, "unused");
+    }
+
+    // What each top-level print statement prints once calls are inlined,
+    // and how many calls to each function were.
+    fn inlined(source: &str) -> (Vec<String>, Vec<String>) {
+        let mut statements = parse(source);
+        let inlined = inline_small_functions(&mut statements);
+        let printed = statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Stmt::Print(ps) => {
+                    Some(SourcePrinter::new().print(&ps.expression))
+                }
+                _ => None,
+            })
+            .collect();
+        let counts = inlined
+            .iter()
+            .map(|(name, count)| format!("{} {count}", name.lexeme))
+            .collect();
+        (printed, counts)
+    }
+
+    #[test]
+    fn inlines_calls_to_functions_returning_an_expression() {
+        let (printed, counts) = inlined(
+            "fun square(x) { return x * x; }
+            fun half(n) { return n / 2; }
+            fun mean(a, b) { return half(a) + half(b); }
+            var y = 3;
+            print square(y) + 1;
+            print square(2 + y);
+            print mean(1, y);
+            print -square(1);",
+        );
+        assert_eq!(
+            printed,
+            ["y * y + 1", "square(2 + y)", "1 / 2 + y / 2", "-(1 * 1)"]
+        );
+        assert_eq!(counts, ["square 2", "half 2", "mean 1"]);
+    }
+
+    #[test]
+    fn leaves_functions_that_could_behave_differently() {
+        let (printed, counts) = inlined(
+            "fun early() { return 1; }
+            print early() + late();
+            fun late() { return 2; }
+            fun calls(x) { return clock() + x; }
+            fun recursive(n) { return n and recursive(n); }
+            fun reassigned() { return 3; }
+            reassigned = nil;
+            fun shadowed() { return z; }
+            var z = 1;
+            { var z = 2; print shadowed(); }
+            fun typed(n: Number) { return n; }
+            fun long(a) { print a; return a; }
+            print calls(1) + typed(1) + long(1);",
+        );
+        assert_eq!(printed, ["1 + late()", "calls(1) + typed(1) + long(1)"]);
+        assert_eq!(counts, ["early 1"]);
+    }
+
+    #[test]
+    fn only_substitutes_variables_that_are_always_read() {
+        let (printed, counts) = inlined(
+            "fun either(a, b) { return a or b; }
+            var x = 1;
+            print either(x, true);
+            print either(true, x);",
+        );
+        assert_eq!(printed, ["x or true", "either(true, x)"]);
+        assert_eq!(counts, ["either 1"]);
+    }
+}
@@ -1,67 +1,198 @@
+use std::collections::VecDeque;
 use std::rc::Rc;
 
-use crate::error_reporter::LoxError;
+use crate::error_reporter::{report_warning, LoxError};
 use crate::expr::{
-    AssignExpr, BinaryExpr, CallExpr, Expr, GroupingExpr, LiteralExpr,
-    LogicalExpr, UnaryExpr, VariableExpr,
+    AssignExpr, BinaryExpr, CallExpr, Expr, GetExpr, GroupingExpr, LiteralExpr,
+    LogicalExpr, MatchArm, MatchExpr, SetExpr, ThisExpr, UnaryExpr,
+    VariableExpr,
 };
+use crate::features::LanguageFeatures;
+use crate::grammar::{self, Operator, Precedence};
 use crate::literal::Literal;
 use crate::stmt::{
-    BlockStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt, ReturnStmt,
-    Stmt, VarStmt, WhileStmt,
+    BlockStmt, BreakStmt, ClassStmt, ContinueStmt, ExpressionStmt, ForStmt,
+    FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt, VarStmt, WhileStmt,
 };
-use crate::token::Token;
+use crate::token::{Token, REPL};
 use crate::token_type::TokenType;
 
 const MAX_ARGUMENTS_COUNT: usize = 255;
 
 /* expression grammar
 expression     → assignment ;
-assignment     → IDENTIFIER "=" assignment
+assignment     → ( call "." )? IDENTIFIER "=" assignment
                | logic_or ;
-logic_or       → logic_and ( "or" logic_and )* ;
+logic_or       → coalesce ( "or" coalesce )* ;
+coalesce       → logic_and ( "??" logic_and )* ;
 logic_and      → equality ( "and" equality )* ;
 equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+comparison     → term ( ( ">" | ">=" | "<" | "<=" | "is" ) term )* ;
 term           → factor ( ( "-" | "+" ) factor )* ;
 factor         → unary ( ( "/" | "*" ) unary )* ;
 unary          → ( "!" | "-" ) unary
                | call ;
-call           → primary ( "(" arguments? ")" )* ;
+call           → primary ( "(" arguments? ")" | ( "." | "?." ) IDENTIFIER )* ;
 arguments      → expression ( "," expression )* ;
-primary        → NUMBER | STRING | "true" | "false" | "nil"
-               | "(" expression ")" ;
+primary        → NUMBER | STRING | "true" | "false" | "nil" | "this"
+               | IDENTIFIER | "(" expression ")" | match ;
+match          → "match" "(" expression ")" "{" case* "}" ;
+case           → "case" pattern ( "if" expression )? ":" expression ;
+pattern        → "-"? NUMBER | STRING | "true" | "false" | "nil"
+               | IDENTIFIER ( "." IDENTIFIER )* ;
+
+Which operators belong to each level is read from the table in grammar.rs.
 */
 
+// How deeply statements and expressions may nest. The parser and everything
+// walking the tree after it recurse once per level, so without a limit a
+// long enough run of '(' overflows the stack instead of reporting an error.
+pub const MAX_NESTING: usize = 256;
+
+// Limits on what the parser accepts, for embedders and the CLI to tighten or
+// relax. The defaults are the book's.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParserOptions {
+    // The most parameters a function may declare and arguments a call may
+    // pass.
+    pub max_arguments: usize,
+    // How deeply statements and expressions may nest. Compiled files are
+    // still read with MAX_NESTING, so code nested deeper can't be compiled.
+    pub max_nesting: usize,
+    // Which extensions to the book's syntax are accepted.
+    pub features: LanguageFeatures,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            max_arguments: MAX_ARGUMENTS_COUNT,
+            max_nesting: MAX_NESTING,
+            features: LanguageFeatures::default(),
+        }
+    }
+}
+
 pub struct Parser<'a> {
-    tokens: &'a Vec<Token>,
-    current: usize,
+    tokens: Box<dyn Iterator<Item = Token> + 'a>,
+    // The current token and the one after it, which is as far ahead as the
+    // grammar looks. Past the end of the tokens they are EOF.
+    lookahead: VecDeque<Token>,
+    previous: Option<Token>,
+    options: ParserOptions,
+    // Labels of the loops enclosing the current statement, innermost last.
+    loops: Vec<Option<Token>>,
+    depth: usize,
+    // Stands in for the end of a token list that doesn't end in EOF, and
+    // for the token before the first.
+    eof: Token,
 }
 
-impl Parser<'_> {
-    pub fn new<'a>(tokens: &'a Vec<Token>) -> Parser {
-        Parser { tokens, current: 0 }
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &[Token]) -> Parser<'_> {
+        Parser::with_options(tokens, ParserOptions::default())
+    }
+
+    pub fn with_options(
+        tokens: &[Token],
+        options: ParserOptions,
+    ) -> Parser<'_> {
+        Parser::from_tokens(tokens.iter().cloned(), options)
+    }
+
+    // Parses tokens as they come, like those of Scanner::iter, keeping only
+    // the few around the current one.
+    pub fn from_tokens<I>(tokens: I, options: ParserOptions) -> Parser<'a>
+    where
+        I: IntoIterator<Item = Token>,
+        I::IntoIter: 'a,
+    {
+        let mut parser = Parser {
+            tokens: Box::new(tokens.into_iter()),
+            lookahead: VecDeque::new(),
+            previous: None,
+            options,
+            loops: Vec::new(),
+            depth: 0,
+            eof: Token::new(TokenType::EOF, "".into(), None, 1, REPL.into()),
+        };
+        parser.fill_lookahead();
+        parser
     }
 
     pub fn parse(&mut self) -> Result<Vec<Stmt>, LoxError> {
         let mut statements: Vec<Stmt> = vec![];
+        let mut first_error = None;
         while !self.is_at_end() {
-            if let Ok(s) = self.declaration() {
-                statements.push(s);
+            match self.declaration() {
+                Ok(s) => statements.push(s),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
             }
         }
-        Ok(statements)
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(statements),
+        }
+    }
+
+    // Parses the tokens as a single expression, used by the REPL inspectors.
+    // A trailing ';' is tolerated so statements can be pasted as they are.
+    pub fn parse_expression(&mut self) -> Result<Expr, LoxError> {
+        let expr = self.expression()?;
+        self.is_match(&[TokenType::Semicolon]);
+
+        if !self.is_at_end() {
+            let current_token = self.peek().clone();
+            return Err(self.error(
+                current_token,
+                "Expect end of expression.".to_string(),
+            ));
+        }
+
+        Ok(expr)
     }
 
     fn expression(&mut self) -> Result<Expr, LoxError> {
-        self.assignment()
+        self.nested(Self::assignment)
+    }
+
+    // Runs `parse` one nesting level deeper, failing past max_nesting.
+    fn nested<T>(
+        &mut self,
+        parse: fn(&mut Self) -> Result<T, LoxError>,
+    ) -> Result<T, LoxError> {
+        let depth = self.depth;
+        self.deepen()?;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    // Left-associative operators, calls and property accesses nest the tree
+    // without recursing, so their loops count every operand as a level too.
+    fn deepen(&mut self) -> Result<(), LoxError> {
+        if self.depth == self.options.max_nesting {
+            let token = self.peek().clone();
+            return Err(self.error(token, "Nesting is too deep.".to_string()));
+        }
+
+        self.depth += 1;
+        Ok(())
     }
 
     fn declaration(&mut self) -> Result<Stmt, LoxError> {
-        let result = if self.is_match(vec![TokenType::Var]) {
-            self.var_declaration()
-        } else if self.is_match(vec![TokenType::Fun]) {
-            self.function("function")
+        let result = if self.is_match(&[TokenType::Class]) {
+            self.nested(Self::class_declaration)
+        } else if self.is_match(&[TokenType::Enum]) {
+            self.nested(Self::enum_declaration)
+        } else if self.is_match(&[TokenType::Var]) {
+            self.nested(Self::var_declaration)
+        } else if self.is_match(&[TokenType::Fun]) {
+            self.nested(|parser| parser.function("function"))
+                .map(Stmt::Function)
         } else {
             self.statement()
         };
@@ -74,102 +205,184 @@ impl Parser<'_> {
     }
 
     fn statement(&mut self) -> Result<Stmt, LoxError> {
-        if self.is_match(vec![TokenType::For]) {
-            return self.for_statement();
+        self.nested(Self::statement_kind)
+    }
+
+    fn statement_kind(&mut self) -> Result<Stmt, LoxError> {
+        if self.check(TokenType::Identifier)
+            && self.check_next(TokenType::Colon)
+        {
+            return self.labeled_statement();
+        }
+        if self.is_match(&[TokenType::Break]) {
+            return self.break_statement();
+        }
+        if self.is_match(&[TokenType::Continue]) {
+            return self.continue_statement();
+        }
+        if self.is_match(&[TokenType::For]) {
+            return self.for_statement(None);
         }
-        if self.is_match(vec![TokenType::If]) {
+        if self.is_match(&[TokenType::If]) {
             return self.if_statement();
         }
-        if self.is_match(vec![TokenType::Print]) {
+        if self.is_match(&[TokenType::Match]) {
+            return self.match_statement();
+        }
+        if self.is_match(&[TokenType::Print]) {
             return self.print_statement();
         }
-        if self.is_match(vec![TokenType::Return]) {
+        if self.is_match(&[TokenType::Return]) {
             return self.return_statement();
         }
-        if self.is_match(vec![TokenType::While]) {
-            return self.while_statement();
+        if self.is_match(&[TokenType::While]) {
+            return self.while_statement(None);
         }
-        if self.is_match(vec![TokenType::LeftBrace]) {
+        if self.is_match(&[TokenType::LeftBrace]) {
             return Ok(Stmt::Block(BlockStmt::new(self.block()?)));
         }
 
         self.expression_statement()
     }
 
-    fn for_statement(&mut self) -> Result<Stmt, LoxError> {
-        self.consume(
-            TokenType::LeftParen,
-            "Expect '(' after 'for'.".to_string(),
-        )?;
+    fn labeled_statement(&mut self) -> Result<Stmt, LoxError> {
+        let label = self.advance().clone();
+        self.require(LanguageFeatures::LOOP_JUMPS, &label)?;
+        self.advance();
 
-        let initializer = if self.is_match(vec![TokenType::Semicolon]) {
+        if self
+            .loops
+            .iter()
+            .flatten()
+            .any(|l| l.lexeme == label.lexeme)
+        {
+            return Err(self.error(
+                label.clone(),
+                format!("Label '{}' is already in use.", label.lexeme),
+            ));
+        }
+
+        if self.is_match(&[TokenType::While]) {
+            self.while_statement(Some(label))
+        } else if self.is_match(&[TokenType::For]) {
+            self.for_statement(Some(label))
+        } else {
+            let current_token = self.peek().clone();
+            Err(self
+                .error(current_token, "Expect loop after label.".to_string()))
+        }
+    }
+
+    fn break_statement(&mut self) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        let label = self.jump_label(&keyword)?;
+        self.consume(TokenType::Semicolon, "Expect ';' after 'break'.")?;
+
+        Ok(Stmt::Break(BreakStmt::new(keyword, label)))
+    }
+
+    fn continue_statement(&mut self) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        let label = self.jump_label(&keyword)?;
+        self.consume(TokenType::Semicolon, "Expect ';' after 'continue'.")?;
+
+        Ok(Stmt::Continue(ContinueStmt::new(keyword, label)))
+    }
+
+    // Parses the optional label after 'break' or 'continue' and checks that
+    // there is an enclosing loop it can refer to.
+    fn jump_label(
+        &mut self,
+        keyword: &Token,
+    ) -> Result<Option<Token>, LoxError> {
+        self.require(LanguageFeatures::LOOP_JUMPS, keyword)?;
+        if self.loops.is_empty() {
+            return Err(self.error(
+                keyword.clone(),
+                format!("Can't use '{}' outside of a loop.", keyword.lexeme),
+            ));
+        }
+
+        if !self.is_match(&[TokenType::Identifier]) {
+            return Ok(None);
+        }
+
+        let label = self.previous().clone();
+        if !self
+            .loops
+            .iter()
+            .flatten()
+            .any(|l| l.lexeme == label.lexeme)
+        {
+            return Err(self.error(
+                label.clone(),
+                format!("No enclosing loop labeled '{}'.", label.lexeme),
+            ));
+        }
+
+        Ok(Some(label))
+    }
+
+    fn loop_body(&mut self, label: Option<Token>) -> Result<Stmt, LoxError> {
+        self.loops.push(label);
+        let body = self.statement();
+        self.loops.pop();
+        body
+    }
+
+    fn for_statement(
+        &mut self,
+        label: Option<Token>,
+    ) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.is_match(&[TokenType::Semicolon]) {
             None
-        } else if self.is_match(vec![TokenType::Var]) {
+        } else if self.is_match(&[TokenType::Var]) {
             Some(self.var_declaration()?)
         } else {
             Some(self.expression_statement()?)
         };
 
         let condition = if !self.check(TokenType::Semicolon) {
-            Some(self.expression()?)
+            let condition = self.expression()?;
+            self.warn_if_assignment(&condition);
+            Some(condition)
         } else {
             None
         };
-        self.consume(
-            TokenType::Semicolon,
-            "Expect ';' after loop condition.".to_string(),
-        )?;
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
 
         let increment = if !self.check(TokenType::RightParen) {
             Some(self.expression()?)
         } else {
             None
         };
-        self.consume(
-            TokenType::RightParen,
-            "Expect ')' after for clauses.".to_string(),
-        )?;
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
 
-        let mut body = self.statement()?;
+        let body = self.loop_body(label.clone())?;
 
-        if let Some(inc) = increment {
-            body = Stmt::Block(BlockStmt::new(vec![
-                body,
-                Stmt::Expression(ExpressionStmt::new(Rc::new(inc))),
-            ]))
-        };
-
-        let final_condition = if let Some(cond) = condition {
-            cond
-        } else {
-            Expr::Literal(LiteralExpr::new(Some(Literal::Bool(true))))
-        };
-        body = Stmt::While(WhileStmt::new(
-            Rc::new(final_condition),
+        Ok(Stmt::For(ForStmt::new(
+            keyword,
+            label,
+            initializer.map(Rc::new),
+            condition.map(Rc::new),
+            increment.map(Rc::new),
             Rc::new(body),
-        ));
-
-        if let Some(init) = initializer {
-            body = Stmt::Block(BlockStmt::new(vec![init, body]));
-        };
-
-        Ok(body)
+        )))
     }
 
     fn if_statement(&mut self) -> Result<Stmt, LoxError> {
-        self.consume(
-            TokenType::LeftParen,
-            "Expect '(' after 'if'.".to_string(),
-        )?;
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
-        self.consume(
-            TokenType::RightParen,
-            "Expect ')' after if condition.".to_string(),
-        )?;
+        self.warn_if_assignment(&condition);
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
 
         let then_branch = self.statement()?;
 
-        let else_branch = if self.is_match(vec![TokenType::Else]) {
+        let else_branch = if self.is_match(&[TokenType::Else]) {
             let stmt = self.statement()?;
             Some(Rc::new(stmt))
         } else {
@@ -177,18 +390,166 @@ impl Parser<'_> {
         };
 
         Ok(Stmt::If(IfStmt::new(
+            keyword,
             Rc::new(condition),
             Rc::new(then_branch),
             else_branch,
         )))
     }
 
+    // A match statement is desugared into ifs here, so nothing after the
+    // parser knows about it. The subject is evaluated once into a hidden
+    // variable, whose name has a space so no script can refer to it, and the
+    // cases are tried top-down until one matches:
+    //
+    //     match (subject) {
+    //         case 0: print "zero";
+    //         case n if n > 3: print n;
+    //         case _: print "other";
+    //     }
+    //
+    // A literal pattern, or a dotted name like `Color.Red`, matches a
+    // subject equal to it. A name matches anything and binds the subject to
+    // it for the guard and the body, and `_` matches anything without
+    // binding it.
+    fn match_statement(&mut self) -> Result<Stmt, LoxError> {
+        let (keyword, subject) = self.match_subject()?;
+        let cases = self.match_cases(|parser, mut case| {
+            while !parser.check(TokenType::Case)
+                && !parser.check(TokenType::RightBrace)
+                && !parser.is_at_end()
+            {
+                case.body.push(parser.declaration()?);
+            }
+            Ok(case)
+        })?;
+
+        let subject_name =
+            synthetic(&keyword, TokenType::Identifier, "match subject");
+        let mut statements = vec![Stmt::Var(VarStmt::new(
+            subject_name.clone(),
+            None,
+            Some(Rc::new(subject)),
+        ))];
+        let tried = cases.into_iter().rev().fold(None, |rest, case| {
+            Some(case.desugar(&keyword, &subject_name, rest))
+        });
+        statements.extend(tried);
+        Ok(Stmt::Block(BlockStmt::new(statements)))
+    }
+
+    // A match in an expression has the value of the first case that
+    // matches, or nil if none does:
+    //
+    //     var name = match (n) { case 0: "zero" case _: "many" };
+    //
+    // It can't be desugared like the statement, so it stays a node of its
+    // own.
+    fn match_expression(&mut self) -> Result<Expr, LoxError> {
+        let (keyword, subject) = self.match_subject()?;
+        let arms = self.match_cases(|parser, case| {
+            Ok(MatchArm {
+                pattern: case.value.map(Rc::new),
+                binding: case.binding,
+                guard: case.guard.map(Rc::new),
+                value: Rc::new(parser.expression()?),
+            })
+        })?;
+        Ok(Expr::Match(MatchExpr::new(keyword, Rc::new(subject), arms)))
+    }
+
+    // Everything from after 'match' up to the first case.
+    fn match_subject(&mut self) -> Result<(Token, Expr), LoxError> {
+        let keyword = self.previous().clone();
+        self.require(LanguageFeatures::MATCH, &keyword)?;
+        self.consume(TokenType::LeftParen, "Expect '(' after 'match'.")?;
+        let subject = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after match subject.")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before match cases.")?;
+        Ok((keyword, subject))
+    }
+
+    // The cases up to the closing '}', each read by `body` once its pattern
+    // and guard are.
+    fn match_cases<T>(
+        &mut self,
+        mut body: impl FnMut(&mut Self, MatchCase) -> Result<T, LoxError>,
+    ) -> Result<Vec<T>, LoxError> {
+        let mut cases = Vec::new();
+        let mut matched_already = false;
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            let case =
+                self.consume(TokenType::Case, "Expect 'case' in match.")?;
+            if matched_already {
+                report_warning(
+                    &case,
+                    "Unreachable case, an earlier one always matches."
+                        .to_string(),
+                );
+            }
+            let case = self.match_case()?;
+            matched_already |= case.always_matches();
+            cases.push(body(self, case)?);
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after match cases.")?;
+        Ok(cases)
+    }
+
+    fn match_case(&mut self) -> Result<MatchCase, LoxError> {
+        let mut case = MatchCase::default();
+        if self.check(TokenType::Identifier) && self.check_next(TokenType::Dot)
+        {
+            case.value = Some(self.constant_path()?);
+        } else if self.check(TokenType::Identifier) {
+            let name = self.advance().clone();
+            if &*name.lexeme != "_" {
+                case.binding = Some(name);
+            }
+        } else if self.check(TokenType::Minus)
+            && self.check_next(TokenType::Number)
+            || [
+                TokenType::Number,
+                TokenType::String,
+                TokenType::True,
+                TokenType::False,
+                TokenType::Nil,
+            ]
+            .contains(&self.peek().token_type)
+        {
+            case.value = Some(self.unary()?);
+        } else {
+            let token = self.peek().clone();
+            return Err(self.error(
+                token,
+                "Expect a literal, a name or '_' after 'case'.".to_string(),
+            ));
+        }
+
+        if self.is_match(&[TokenType::If]) {
+            case.guard = Some(self.expression()?);
+        }
+        self.consume(TokenType::Colon, "Expect ':' after case pattern.")?;
+        Ok(case)
+    }
+
+    // A dotted name like `Color.Red`, which a pattern compares the subject
+    // to rather than binding.
+    fn constant_path(&mut self) -> Result<Expr, LoxError> {
+        let name = self.advance().clone();
+        let mut path = Expr::Variable(VariableExpr::new(name));
+        while self.is_match(&[TokenType::Dot]) {
+            let name = self.consume(
+                TokenType::Identifier,
+                "Expect property name after '.'.",
+            )?;
+            path = Expr::Get(GetExpr::new(Rc::new(path), name, false));
+        }
+        Ok(path)
+    }
+
     fn print_statement(&mut self) -> Result<Stmt, LoxError> {
         let value = self.expression()?;
-        self.consume(
-            TokenType::Semicolon,
-            "Expect ';' after value.".to_string(),
-        )?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
         Ok(Stmt::Print(PrintStmt::new(Rc::new(value))))
     }
 
@@ -199,21 +560,18 @@ impl Parser<'_> {
             false => Some(Rc::new(self.expression()?)),
         };
 
-        self.consume(
-            TokenType::Semicolon,
-            "Expect ';' after return value.".to_string(),
-        )?;
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
 
         Ok(Stmt::Return(ReturnStmt::new(keyword, value)))
     }
 
     fn var_declaration(&mut self) -> Result<Stmt, LoxError> {
-        let name = self.consume(
-            TokenType::Identifier,
-            "Expect variable name.".to_string(),
-        )?;
+        let name =
+            self.consume(TokenType::Identifier, "Expect variable name.")?;
+
+        let type_annotation = self.type_annotation()?;
 
-        let initializer = if self.is_match(vec![TokenType::Equal]) {
+        let initializer = if self.is_match(&[TokenType::Equal]) {
             Some(Rc::new(self.expression()?))
         } else {
             None
@@ -221,25 +579,26 @@ impl Parser<'_> {
 
         self.consume(
             TokenType::Semicolon,
-            "Expect ';' after variable declaration.".to_string(),
+            "Expect ';' after variable declaration.",
         )?;
 
-        Ok(Stmt::Var(VarStmt::new(name, initializer)))
+        Ok(Stmt::Var(VarStmt::new(name, type_annotation, initializer)))
     }
 
-    fn while_statement(&mut self) -> Result<Stmt, LoxError> {
-        self.consume(
-            TokenType::LeftParen,
-            "Expect '(' after 'while'.".to_string(),
-        )?;
+    fn while_statement(
+        &mut self,
+        label: Option<Token>,
+    ) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
-        self.consume(
-            TokenType::RightParen,
-            "Expect ')' after condition.".to_string(),
-        )?;
-        let body = self.statement()?;
+        self.warn_if_assignment(&condition);
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = self.loop_body(label.clone())?;
 
         Ok(Stmt::While(WhileStmt::new(
+            keyword,
+            label,
             Rc::new(condition),
             Rc::new(body),
         )))
@@ -247,65 +606,143 @@ impl Parser<'_> {
 
     fn expression_statement(&mut self) -> Result<Stmt, LoxError> {
         let expr = self.expression()?;
-        self.consume(
-            TokenType::Semicolon,
-            "Expect ';' after value.".to_string(),
-        )?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
         Ok(Stmt::Expression(ExpressionStmt::new(Rc::new(expr))))
     }
 
-    fn function(&mut self, kind: &str) -> Result<Stmt, LoxError> {
-        let name =
-            self.consume(TokenType::Identifier, format!("Expect {kind} name"))?;
+    fn class_declaration(&mut self) -> Result<Stmt, LoxError> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?;
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.function("method")?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(Stmt::Class(ClassStmt::new(name, methods)))
+    }
+
+    // `enum Color { Red, Green }` is desugared into a variable holding what
+    // the `enum` native makes: `var Color = enum("Color", "Red", "Green");`.
+    fn enum_declaration(&mut self) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        self.require(LanguageFeatures::ENUMS, &keyword)?;
+        let name = self.consume(TokenType::Identifier, "Expect enum name.")?;
+        let brace =
+            self.consume(TokenType::LeftBrace, "Expect '{' before enum body.")?;
+
+        let mut variants: Vec<Token> = Vec::new();
+        while !self.check(TokenType::RightBrace) {
+            let variant =
+                self.consume(TokenType::Identifier, "Expect variant name.")?;
+            if variants.iter().any(|v| v.lexeme == variant.lexeme) {
+                return Err(self.error(
+                    variant.clone(),
+                    format!(
+                        "Variant '{}' is already declared.",
+                        variant.lexeme
+                    ),
+                ));
+            }
+            variants.push(variant);
+            if !self.is_match(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after enum body.")?;
+
+        let string = |token: &Token| {
+            let value = Literal::String(token.lexeme.clone());
+            Rc::new(Expr::Literal(LiteralExpr::new(Some(value))))
+        };
+        let callee = synthetic(&keyword, TokenType::Identifier, "enum");
+        let arguments = std::iter::once(&name).chain(&variants).map(string);
+        let call = Expr::Call(CallExpr::new(
+            Rc::new(Expr::Variable(VariableExpr::new(callee))),
+            brace,
+            arguments.collect(),
+            false,
+        ));
+        Ok(Stmt::Var(VarStmt::new(name, None, Some(Rc::new(call)))))
+    }
+
+    fn function(&mut self, kind: &str) -> Result<FunctionStmt, LoxError> {
+        let name = self
+            .consume(TokenType::Identifier, &format!("Expect {kind} name"))?;
 
         self.consume(
             TokenType::LeftParen,
-            format!("Expect '(' after {kind} name."),
+            &format!("Expect '(' after {kind} name."),
         )?;
 
         let mut parameters: Vec<Token> = Vec::new();
+        let mut param_types: Vec<Option<Token>> = Vec::new();
 
         if !self.check(TokenType::RightParen) {
-            parameters.push(self.consume(
-                TokenType::Identifier,
-                "Expect parameter name.".to_string(),
-            )?);
+            parameters.push(
+                self.consume(TokenType::Identifier, "Expect parameter name.")?,
+            );
+            param_types.push(self.type_annotation()?);
             loop {
-                if self.is_match(vec![TokenType::Comma]) {
-                    if parameters.len() >= MAX_ARGUMENTS_COUNT {
+                if self.is_match(&[TokenType::Comma]) {
+                    if parameters.len() >= self.options.max_arguments {
                         self.error(
                             self.peek().clone(),
-                            "Can't have more than 255 parameters.".to_string(),
+                            format!(
+                                "Can't have more than {} parameters.",
+                                self.options.max_arguments
+                            ),
                         );
                     }
 
                     parameters.push(self.consume(
                         TokenType::Identifier,
-                        "Expect parameter name.".to_string(),
+                        "Expect parameter name.",
                     )?);
+                    param_types.push(self.type_annotation()?);
                 } else {
                     break;
                 }
             }
         }
 
-        self.consume(
-            TokenType::RightParen,
-            "Expect ')' after parameters.".to_string(),
-        )?;
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+
+        let return_type = self.type_annotation()?;
 
         self.consume(
             TokenType::LeftBrace,
-            format!("Expect '{{' before {kind} body."),
+            &format!("Expect '{{' before {kind} body."),
         )?;
 
-        let body = self.block()?;
+        // A loop around the declaration can't be the target of a 'break'
+        // inside the function body.
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        let body = self.nested(Self::block);
+        self.loops = enclosing_loops;
+        let body = body?;
 
-        Ok(Stmt::Function(FunctionStmt::new(
+        Ok(FunctionStmt::new(
             name,
             parameters,
+            param_types,
+            return_type,
             Rc::new(body),
-        )))
+        ))
+    }
+
+    // Parses an optional `: Type` annotation. Annotations are only looked at
+    // by the type checker and are erased before interpretation.
+    fn type_annotation(&mut self) -> Result<Option<Token>, LoxError> {
+        if !self.is_match(&[TokenType::Colon]) {
+            return Ok(None);
+        }
+
+        let type_name =
+            self.consume(TokenType::Identifier, "Expect type name after ':'.")?;
+        Ok(Some(type_name))
     }
 
     fn block(&mut self) -> Result<Vec<Stmt>, LoxError> {
@@ -315,97 +752,117 @@ impl Parser<'_> {
             statements.push(self.declaration()?);
         }
 
-        self.consume(
-            TokenType::RightBrace,
-            "Expect '}' after block.".to_string(),
-        )?;
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
         Ok(statements)
     }
 
     fn assignment(&mut self) -> Result<Expr, LoxError> {
-        let expr = self.or()?;
+        self.infix(Precedence::Assignment)
+    }
 
-        if self.is_match(vec![TokenType::Equal]) {
-            let equals = self.previous().clone();
-            let value = self.assignment()?;
+    // Parses one level of the operator table: operands of the next tighter
+    // level joined by the operators at `precedence`. Unary and tighter
+    // levels have their own shapes and are parsed by their own methods.
+    fn infix(&mut self, precedence: Precedence) -> Result<Expr, LoxError> {
+        if precedence >= Precedence::Unary {
+            return self.unary();
+        }
 
-            match expr {
-                Expr::Variable(ve) => {
-                    let name = ve.name;
-                    return Ok(Expr::Assign(AssignExpr::new(
-                        name,
-                        Rc::new(value),
-                    )));
+        let mut expr = self.infix(precedence.next())?;
+
+        let depth = self.depth;
+        while let Some(operator) = self.match_infix(precedence) {
+            self.deepen()?;
+            let token = self.previous().clone();
+            match token.token_type {
+                TokenType::QuestionQuestion => {
+                    self.require(LanguageFeatures::COALESCE, &token)?;
                 }
-                _ => {
-                    self.error(
-                        equals,
-                        "Invalid assignment target.".to_string(),
-                    );
+                TokenType::Is => self.require(LanguageFeatures::IS, &token)?,
+                TokenType::Percent => {
+                    self.require(LanguageFeatures::REMAINDER, &token)?;
                 }
+                _ => {}
             }
+            let right = self.infix(operator.operand_precedence())?;
+            expr = self.combine(expr, token, right);
         }
 
+        self.depth = depth;
         Ok(expr)
     }
 
-    fn or(&mut self) -> Result<Expr, LoxError> {
-        let mut expr = self.and()?;
-
-        while self.is_match(vec![TokenType::Or]) {
-            let operator = self.previous().clone();
-            let right = self.and()?;
-            expr = Expr::Logical(LogicalExpr::new(
-                Rc::new(expr),
+    // Builds the node for `left operator right`. Only variables and
+    // properties can be assigned to; any other target is reported and the
+    // assignment dropped so parsing can go on.
+    fn combine(&mut self, left: Expr, operator: Token, right: Expr) -> Expr {
+        match operator.token_type {
+            TokenType::Equal => match left {
+                Expr::Variable(ve) => {
+                    Expr::Assign(AssignExpr::new(ve.name, Rc::new(right)))
+                }
+                Expr::Get(ge) if !ge.optional => {
+                    Expr::Set(SetExpr::new(ge.object, ge.name, Rc::new(right)))
+                }
+                _ => {
+                    self.error(
+                        operator,
+                        "Invalid assignment target.".to_string(),
+                    );
+                    left
+                }
+            },
+            TokenType::Or | TokenType::QuestionQuestion | TokenType::And => {
+                Expr::Logical(LogicalExpr::new(
+                    Rc::new(left),
+                    operator,
+                    Rc::new(right),
+                ))
+            }
+            _ => Expr::Binary(BinaryExpr::new(
+                Rc::new(left),
                 operator,
                 Rc::new(right),
-            ));
+            )),
         }
-
-        Ok(expr)
     }
 
-    fn and(&mut self) -> Result<Expr, LoxError> {
-        let mut expr = self.equality()?;
-
-        while self.is_match(vec![TokenType::And]) {
-            let operator = self.previous().clone();
-            let right = self.equality()?;
-            expr = Expr::Logical(LogicalExpr::new(
-                Rc::new(expr),
-                operator,
-                Rc::new(right),
-            ))
+    fn is_match(&mut self, types: &[TokenType]) -> bool {
+        for &token_type in types {
+            if self.check(token_type) {
+                self.advance();
+                return true;
+            }
         }
 
-        Ok(expr)
+        false
     }
 
-    fn equality(&mut self) -> Result<Expr, LoxError> {
-        let mut expr = self.comparison()?;
-
-        while self.is_match(vec![TokenType::BangEqual, TokenType::EqualEqual]) {
-            let operator = self.previous().clone();
-            let right = self.comparison()?;
-            expr = Expr::Binary(BinaryExpr::new(
-                Rc::new(expr),
-                operator,
-                Rc::new(right),
-            ));
+    // Consumes the current token if the operator table lists it as an infix
+    // operator at `precedence`.
+    fn match_infix(
+        &mut self,
+        precedence: Precedence,
+    ) -> Option<&'static Operator> {
+        let operator = grammar::infix(self.peek().token_type)?;
+        if operator.precedence != precedence {
+            return None;
         }
 
-        Ok(expr)
+        self.advance();
+        Some(operator)
     }
 
-    fn is_match(&mut self, types: Vec<TokenType>) -> bool {
-        for token_type in types {
-            if self.check(token_type) {
-                self.advance();
-                return true;
-            }
-        }
+    fn match_prefix(&mut self) -> Option<&'static Operator> {
+        let operator = grammar::prefix(self.peek().token_type)?;
+        self.advance();
+        Some(operator)
+    }
 
-        return false;
+    fn match_postfix(&mut self) -> Option<&'static Operator> {
+        let operator = grammar::postfix(self.peek().token_type)?;
+        self.advance();
+        Some(operator)
     }
 
     fn check(&self, token_type: TokenType) -> bool {
@@ -413,15 +870,44 @@ impl Parser<'_> {
             return false;
         }
 
-        return self.peek().token_type == token_type;
+        self.peek().token_type == token_type
+    }
+
+    fn check_next(&self, token_type: TokenType) -> bool {
+        self.lookahead[1].token_type == token_type
     }
 
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
-            self.current += 1;
+            self.previous = self.lookahead.pop_front();
+            self.fill_lookahead();
         }
 
-        return self.previous();
+        self.previous()
+    }
+
+    // Tops the lookahead back up to two tokens. Once the tokens run out it
+    // is padded with EOF, on the last token's line.
+    fn fill_lookahead(&mut self) {
+        while self.lookahead.len() < 2 {
+            match self.tokens.next() {
+                Some(token) => self.lookahead.push_back(token),
+                None => {
+                    if let Some(last) =
+                        self.lookahead.back().or(self.previous.as_ref())
+                    {
+                        self.eof = Token::new(
+                            TokenType::EOF,
+                            "".into(),
+                            None,
+                            last.line,
+                            last.source.clone(),
+                        );
+                    }
+                    self.lookahead.push_back(self.eof.clone());
+                }
+            }
+        }
     }
 
     fn is_at_end(&self) -> bool {
@@ -429,70 +915,17 @@ impl Parser<'_> {
     }
 
     fn peek(&self) -> &Token {
-        self.tokens.get(self.current).unwrap()
+        &self.lookahead[0]
     }
 
     fn previous(&self) -> &Token {
-        self.tokens.get(self.current - 1).unwrap()
-    }
-
-    fn comparison(&mut self) -> Result<Expr, LoxError> {
-        let mut expr = self.term()?;
-
-        while self.is_match(vec![
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::Less,
-            TokenType::LessEqual,
-        ]) {
-            let operator = self.previous().clone();
-            let right = self.term()?;
-            expr = Expr::Binary(BinaryExpr::new(
-                Rc::new(expr),
-                operator,
-                Rc::new(right),
-            ));
-        }
-
-        Ok(expr)
-    }
-
-    fn term(&mut self) -> Result<Expr, LoxError> {
-        let mut expr = self.factor()?;
-
-        while self.is_match(vec![TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous().clone();
-            let right = self.factor()?;
-            expr = Expr::Binary(BinaryExpr::new(
-                Rc::new(expr),
-                operator,
-                Rc::new(right),
-            ));
-        }
-
-        Ok(expr)
-    }
-
-    fn factor(&mut self) -> Result<Expr, LoxError> {
-        let mut expr = self.unary()?;
-
-        while self.is_match(vec![TokenType::Slash, TokenType::Star]) {
-            let operator = self.previous().clone();
-            let right = self.unary()?;
-            expr = Expr::Binary(BinaryExpr::new(
-                Rc::new(expr),
-                operator,
-                Rc::new(right),
-            ));
-        }
-
-        Ok(expr)
+        self.previous.as_ref().unwrap_or(&self.eof)
     }
 
     fn unary(&mut self) -> Result<Expr, LoxError> {
-        if self.is_match(vec![TokenType::Bang, TokenType::Minus]) {
+        if self.match_prefix().is_some() {
             let operator = self.previous().clone();
-            let right = self.unary()?;
+            let right = self.nested(Self::unary)?;
             return Ok(Expr::Unary(UnaryExpr::new(operator, Rc::new(right))));
         }
 
@@ -505,13 +938,13 @@ impl Parser<'_> {
         if !self.check(TokenType::RightParen) {
             arguments.push(Rc::new(self.expression()?));
             loop {
-                if self.is_match(vec![TokenType::Comma]) {
-                    if arguments.len() >= MAX_ARGUMENTS_COUNT {
+                if self.is_match(&[TokenType::Comma]) {
+                    if arguments.len() >= self.options.max_arguments {
                         self.error(
                             self.peek().clone(),
                             format!(
                                 "Can't have more than {} arguments.",
-                                MAX_ARGUMENTS_COUNT
+                                self.options.max_arguments
                             ),
                         );
                     }
@@ -522,46 +955,74 @@ impl Parser<'_> {
             }
         }
 
-        let paren = self.consume(
-            TokenType::RightParen,
-            "Expect ')' after arguments.".to_string(),
-        )?;
+        let paren =
+            self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
 
-        Ok(Expr::Call(CallExpr::new(Rc::new(callee), paren, arguments)))
+        let optional = callee.in_optional_chain();
+        Ok(Expr::Call(CallExpr::new(
+            Rc::new(callee),
+            paren,
+            arguments,
+            optional,
+        )))
     }
 
     fn call(&mut self) -> Result<Expr, LoxError> {
         let mut expr = self.primary()?;
 
-        loop {
-            if self.is_match(vec![TokenType::LeftParen]) {
+        let depth = self.depth;
+        while let Some(operator) = self.match_postfix() {
+            self.deepen()?;
+            if operator.token_type == TokenType::LeftParen {
                 expr = self.finish_call(expr)?;
             } else {
-                break;
+                let optional = operator.token_type == TokenType::QuestionDot;
+                if optional {
+                    let token = self.previous().clone();
+                    self.require(LanguageFeatures::OPTIONAL_CHAINING, &token)?;
+                }
+                let name = self.consume(
+                    TokenType::Identifier,
+                    &format!(
+                        "Expect property name after '{}'.",
+                        operator.symbol
+                    ),
+                )?;
+                expr = Expr::Get(GetExpr::new(Rc::new(expr), name, optional));
             }
         }
 
+        self.depth = depth;
         Ok(expr)
     }
 
     fn primary(&mut self) -> Result<Expr, LoxError> {
-        if self.is_match(vec![TokenType::False]) {
+        if self.is_match(&[TokenType::False]) {
             Ok(Expr::Literal(LiteralExpr::new(Some(Literal::Bool(false)))))
-        } else if self.is_match(vec![TokenType::True]) {
+        } else if self.is_match(&[TokenType::True]) {
             Ok(Expr::Literal(LiteralExpr::new(Some(Literal::Bool(true)))))
-        } else if self.is_match(vec![TokenType::Nil]) {
+        } else if self.is_match(&[TokenType::Nil]) {
             Ok(Expr::Literal(LiteralExpr::new(Some(Literal::Nil))))
-        } else if self.is_match(vec![TokenType::Number, TokenType::String]) {
+        } else if self.is_match(&[TokenType::Number, TokenType::String]) {
             Ok(Expr::Literal(LiteralExpr::new(
                 self.previous().literal.clone(),
             )))
-        } else if self.is_match(vec![TokenType::LeftParen]) {
+        } else if self.is_match(&[TokenType::LeftParen]) {
             let expr = self.expression()?;
+            self.consume(
+                TokenType::RightParen,
+                "Expect ')' after expression.",
+            )?;
 
             Ok(Expr::Grouping(GroupingExpr::new(Rc::new(expr))))
-        } else if self.is_match(vec![TokenType::Identifier]) {
+        } else if self.is_match(&[TokenType::This]) {
+            let keyword = self.previous().clone();
+            Ok(Expr::This(ThisExpr::new(keyword)))
+        } else if self.is_match(&[TokenType::Identifier]) {
             let name = self.previous().clone();
             Ok(Expr::Variable(VariableExpr::new(name)))
+        } else if self.is_match(&[TokenType::Match]) {
+            self.match_expression()
         } else {
             let current_token = self.peek().clone();
             Err(self.error(current_token, "Expression expected".to_string()))
@@ -571,19 +1032,51 @@ impl Parser<'_> {
     fn consume(
         &mut self,
         token_type: TokenType,
-        message: String,
+        message: &str,
     ) -> Result<Token, LoxError> {
         if self.check(token_type) {
             return Ok(self.advance().clone());
         }
 
         let current_token = self.peek().clone();
-        Err(self.error(current_token, message))
+        Err(self.error(current_token, message.to_string()))
     }
 
     fn error(&self, token: Token, message: String) -> LoxError {
-        let error = LoxError::parse_error(token, message);
-        error
+        LoxError::parse_error(token, message)
+    }
+
+    // Fails on `token` unless `feature` is enabled.
+    fn require(
+        &self,
+        feature: LanguageFeatures,
+        token: &Token,
+    ) -> Result<(), LoxError> {
+        if self.options.features.contains(feature) {
+            return Ok(());
+        }
+
+        Err(self.error(
+            token.clone(),
+            format!(
+                "'{}' needs the '{}' language feature.",
+                token.lexeme,
+                feature.name()
+            ),
+        ))
+    }
+
+    // An assignment as the whole condition is almost always a typo for '=='.
+    // Wrapping it in an extra pair of parentheses marks it as intentional.
+    fn warn_if_assignment(&self, condition: &Expr) {
+        if let Expr::Assign(ae) = condition {
+            report_warning(
+                &ae.name,
+                "Assignment used as a condition, did you mean '=='? \
+                 Wrap it in parentheses to silence this warning."
+                    .to_string(),
+            );
+        }
     }
 
     fn synchronize(&mut self) {
@@ -596,10 +1089,12 @@ impl Parser<'_> {
 
             match self.peek().token_type {
                 TokenType::Class
+                | TokenType::Enum
                 | TokenType::Fun
                 | TokenType::Var
                 | TokenType::For
                 | TokenType::If
+                | TokenType::Match
                 | TokenType::While
                 | TokenType::Print
                 | TokenType::Return => return,
@@ -610,3 +1105,371 @@ impl Parser<'_> {
         }
     }
 }
+
+// One case of a match, as written. Only a statement's cases have a body.
+#[derive(Default)]
+struct MatchCase {
+    // A literal or a dotted name the subject has to equal.
+    value: Option<Expr>,
+    binding: Option<Token>,
+    guard: Option<Expr>,
+    body: Vec<Stmt>,
+}
+
+impl MatchCase {
+    fn always_matches(&self) -> bool {
+        self.value.is_none() && self.guard.is_none()
+    }
+
+    // The statement that runs this case's body if it matches and `rest`,
+    // the cases after it, if it doesn't.
+    fn desugar(
+        self,
+        keyword: &Token,
+        subject: &Token,
+        rest: Option<Stmt>,
+    ) -> Stmt {
+        let body = Stmt::Block(BlockStmt::new(self.body));
+        let if_stmt = |condition: Expr, then: Stmt, rest: Option<Stmt>| {
+            Stmt::If(IfStmt::new(
+                synthetic(keyword, TokenType::If, "if"),
+                Rc::new(condition),
+                Rc::new(then),
+                rest.map(Rc::new),
+            ))
+        };
+        let subject = || Expr::Variable(VariableExpr::new(subject.clone()));
+
+        let Some(binding) = self.binding else {
+            let equal = self.value.map(|value| {
+                Expr::Binary(BinaryExpr::new(
+                    Rc::new(subject()),
+                    synthetic(keyword, TokenType::EqualEqual, "=="),
+                    Rc::new(value),
+                ))
+            });
+            let condition = match (equal, self.guard) {
+                (Some(equal), Some(guard)) => Expr::Logical(LogicalExpr::new(
+                    Rc::new(equal),
+                    synthetic(keyword, TokenType::And, "and"),
+                    Rc::new(guard),
+                )),
+                (Some(condition), None) | (None, Some(condition)) => condition,
+                (None, None) => return body,
+            };
+            return if_stmt(condition, body, rest);
+        };
+
+        let bind =
+            Stmt::Var(VarStmt::new(binding, None, Some(Rc::new(subject()))));
+        let (guard, rest) = match (self.guard, rest) {
+            (None, _) => return Stmt::Block(BlockStmt::new(vec![bind, body])),
+            (Some(guard), None) => {
+                return Stmt::Block(BlockStmt::new(vec![
+                    bind,
+                    if_stmt(guard, body, None),
+                ]))
+            }
+            (Some(guard), Some(rest)) => (guard, rest),
+        };
+
+        // The binding is only in scope for the guard and the body, so a
+        // flag carries whether the case matched out to the later cases.
+        let matched = synthetic(keyword, TokenType::Identifier, "case matched");
+        let boolean = |value| {
+            Rc::new(Expr::Literal(LiteralExpr::new(Some(Literal::Bool(value)))))
+        };
+        let set_matched = Stmt::Expression(ExpressionStmt::new(Rc::new(
+            Expr::Assign(AssignExpr::new(matched.clone(), boolean(true))),
+        )));
+        let not_matched = Expr::Unary(UnaryExpr::new(
+            synthetic(keyword, TokenType::Bang, "!"),
+            Rc::new(Expr::Variable(VariableExpr::new(matched.clone()))),
+        ));
+        Stmt::Block(BlockStmt::new(vec![
+            Stmt::Var(VarStmt::new(matched, None, Some(boolean(false)))),
+            Stmt::Block(BlockStmt::new(vec![
+                bind,
+                if_stmt(
+                    guard,
+                    Stmt::Block(BlockStmt::new(vec![set_matched, body])),
+                    None,
+                ),
+            ])),
+            if_stmt(not_matched, rest, None),
+        ]))
+    }
+}
+
+// A token the parser makes up for desugared code, placed at `at`.
+fn synthetic(at: &Token, token_type: TokenType, lexeme: &str) -> Token {
+    Token::new(token_type, lexeme.into(), None, at.line, at.source.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    // The lexeme and line of every token the tree keeps, in source order.
+    fn token_lines(source: &str) -> Vec<(String, u32)> {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().clone();
+        let statements = match Parser::new(&tokens).parse() {
+            Ok(statements) if !scanner.had_error() => statements,
+            _ => panic!("{source:?} should parse"),
+        };
+        statement_lines(&statements)
+    }
+
+    fn statement_lines(statements: &[Stmt]) -> Vec<(String, u32)> {
+        let mut lines = Vec::new();
+        for statement in statements {
+            stmt_lines(statement, &mut lines);
+        }
+        lines
+    }
+
+    fn push(token: &Token, lines: &mut Vec<(String, u32)>) {
+        lines.push((token.lexeme.to_string(), token.line));
+    }
+
+    fn stmt_lines(stmt: &Stmt, lines: &mut Vec<(String, u32)>) {
+        match stmt {
+            Stmt::Block(bs) => {
+                bs.statements.iter().for_each(|s| stmt_lines(s, lines));
+            }
+            Stmt::Break(bs) => push(&bs.keyword, lines),
+            Stmt::Continue(cs) => push(&cs.keyword, lines),
+            Stmt::Class(cs) => {
+                push(&cs.name, lines);
+                for method in &cs.methods {
+                    function_lines(method, lines);
+                }
+            }
+            Stmt::Expression(es) => expr_lines(&es.expression, lines),
+            Stmt::For(fs) => {
+                push(&fs.keyword, lines);
+                if let Some(initializer) = &fs.initializer {
+                    stmt_lines(initializer, lines);
+                }
+                if let Some(condition) = &fs.condition {
+                    expr_lines(condition, lines);
+                }
+                if let Some(increment) = &fs.increment {
+                    expr_lines(increment, lines);
+                }
+                stmt_lines(&fs.body, lines);
+            }
+            Stmt::Function(fs) => function_lines(fs, lines),
+            Stmt::If(is) => {
+                push(&is.keyword, lines);
+                expr_lines(&is.condition, lines);
+                stmt_lines(&is.then_branch, lines);
+                if let Some(else_branch) = &is.else_branch {
+                    stmt_lines(else_branch, lines);
+                }
+            }
+            Stmt::Print(ps) => expr_lines(&ps.expression, lines),
+            Stmt::Return(rs) => {
+                push(&rs.keyword, lines);
+                if let Some(value) = &rs.value {
+                    expr_lines(value, lines);
+                }
+            }
+            Stmt::Var(vs) => {
+                push(&vs.name, lines);
+                if let Some(initializer) = &vs.initializer {
+                    expr_lines(initializer, lines);
+                }
+            }
+            Stmt::While(ws) => {
+                push(&ws.keyword, lines);
+                expr_lines(&ws.condition, lines);
+                stmt_lines(&ws.body, lines);
+            }
+        }
+    }
+
+    fn function_lines(function: &FunctionStmt, lines: &mut Vec<(String, u32)>) {
+        push(&function.name, lines);
+        function.params.iter().for_each(|p| push(p, lines));
+        function.body.iter().for_each(|s| stmt_lines(s, lines));
+    }
+
+    fn expr_lines(expr: &Expr, lines: &mut Vec<(String, u32)>) {
+        match expr {
+            Expr::Assign(ae) => {
+                push(&ae.name, lines);
+                expr_lines(&ae.value, lines);
+            }
+            Expr::Binary(BinaryExpr {
+                left,
+                operator,
+                right,
+            })
+            | Expr::Logical(LogicalExpr {
+                left,
+                operator,
+                right,
+            }) => {
+                expr_lines(left, lines);
+                push(operator, lines);
+                expr_lines(right, lines);
+            }
+            Expr::Call(ce) => {
+                expr_lines(&ce.callee, lines);
+                ce.arguments.iter().for_each(|a| expr_lines(a, lines));
+                push(&ce.paren, lines);
+            }
+            Expr::Get(ge) => {
+                expr_lines(&ge.object, lines);
+                push(&ge.name, lines);
+            }
+            Expr::Set(se) => {
+                expr_lines(&se.object, lines);
+                push(&se.name, lines);
+                expr_lines(&se.value, lines);
+            }
+            Expr::Grouping(ge) => expr_lines(&ge.expression, lines),
+            Expr::Literal(_) => {}
+            Expr::Match(me) => {
+                push(&me.keyword, lines);
+                expr_lines(&me.subject, lines);
+                for arm in &me.arms {
+                    arm.pattern.iter().for_each(|p| expr_lines(p, lines));
+                    arm.binding.iter().for_each(|b| push(b, lines));
+                    arm.guard.iter().for_each(|g| expr_lines(g, lines));
+                    expr_lines(&arm.value, lines);
+                }
+            }
+            Expr::This(te) => push(&te.keyword, lines),
+            Expr::Unary(ue) => {
+                push(&ue.operator, lines);
+                expr_lines(&ue.right, lines);
+            }
+            Expr::Variable(ve) => push(&ve.name, lines),
+        }
+    }
+
+    const MULTI_LINE: &str = "\
+fun add(a,
+        b) {
+  return a
+    + b;
+}
+
+if (x)
+  print 1;
+else if (y) {
+  print 2;
+} else
+  print 3;
+
+{
+  {
+    var inner
+      = \"two
+lines\";
+  }
+}
+
+class Point
+{
+  init() { this.x = 0; }
+}
+
+while (false)
+  call(1,
+    2
+  );
+";
+
+    fn expected_lines() -> Vec<(String, u32)> {
+        [
+            ("add", 1),
+            ("a", 1),
+            ("b", 2),
+            ("return", 3),
+            ("a", 3),
+            ("+", 4),
+            ("b", 4),
+            ("if", 7),
+            ("x", 7),
+            ("if", 9),
+            ("y", 9),
+            ("inner", 16),
+            ("Point", 22),
+            ("init", 24),
+            ("this", 24),
+            ("x", 24),
+            ("while", 27),
+            ("call", 28),
+            (")", 30),
+        ]
+        .iter()
+        .map(|&(lexeme, line)| (lexeme.to_string(), line))
+        .collect()
+    }
+
+    #[test]
+    fn multi_line_constructs_keep_their_lines() {
+        assert_eq!(token_lines(MULTI_LINE), expected_lines());
+    }
+
+    #[test]
+    fn line_endings_and_comments_dont_shift_lines() {
+        let crlf = MULTI_LINE.replace('\n', "\r\n");
+        assert_eq!(token_lines(&crlf), expected_lines());
+
+        // A comment on every line, with a block comment standing in for
+        // each blank one.
+        let commented: String = MULTI_LINE
+            .lines()
+            .map(|line| match line {
+                "" => "/* blank\n */".to_string(),
+                line if line.ends_with("\"two") => line.to_string(),
+                line => format!("{line} // comment"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let shifted: Vec<(String, u32)> = expected_lines()
+            .into_iter()
+            .map(|(lexeme, line)| {
+                // Each earlier blank line became two.
+                let blanks = [6, 13, 21, 26].iter().filter(|&&b| b < line);
+                (lexeme, line + blanks.count() as u32)
+            })
+            .collect();
+        assert_eq!(token_lines(&commented), shifted);
+    }
+
+    #[test]
+    fn parses_tokens_as_they_are_scanned() {
+        let mut scanner = Scanner::new(MULTI_LINE);
+        let parsed =
+            Parser::from_tokens(scanner.iter(), ParserOptions::default())
+                .parse();
+        match parsed {
+            Ok(statements) => {
+                assert_eq!(statement_lines(&statements), expected_lines())
+            }
+            Err(_) => panic!("MULTI_LINE should parse"),
+        }
+    }
+
+    #[test]
+    fn tokens_without_an_eof_end_on_the_last_line() {
+        let mut scanner = Scanner::with_name("print 1\n  +", "test.lox");
+        let mut tokens = scanner.scan_tokens().clone();
+        assert_eq!(tokens.pop().map(|t| t.token_type), Some(TokenType::EOF));
+
+        let parsed =
+            Parser::from_tokens(tokens, ParserOptions::default()).parse();
+        let error = parsed.err();
+        assert_eq!(
+            error.as_ref().and_then(|e| e.location()),
+            Some(("test.lox", 2))
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+
+// The Lox half of the built-ins, see prelude.lox.
+pub const SOURCE: &str = include_str!("prelude.lox");
+pub const NAME: &str = "<prelude>";
+
+// The bundled prelude ready to run. It ships with the interpreter, so
+// failing to compile it is a bug rather than something to report.
+pub fn compile() -> Vec<Stmt> {
+    let mut scanner = Scanner::with_name(SOURCE, NAME);
+    let tokens = scanner.scan_tokens().clone();
+    let statements = Parser::new(&tokens)
+        .parse()
+        .ok()
+        .filter(|_| !scanner.had_error())
+        .unwrap_or_else(|| panic!("the bundled prelude doesn't parse"));
+    if Resolver::new().resolve(&statements).is_err() {
+        panic!("the bundled prelude doesn't resolve");
+    }
+
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declares_only_functions() {
+        let statements = compile();
+        assert!(!statements.is_empty());
+        assert!(statements
+            .iter()
+            .all(|statement| matches!(statement, Stmt::Function(_))));
+    }
+}
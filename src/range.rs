@@ -0,0 +1,150 @@
+use std::fmt::{self, Display};
+
+// The whole numbers from `start` up to but not including `end`, `step`
+// apart. Nothing is stored per element, so `range(1000000)` costs the same
+// as `range(3)`.
+#[derive(Clone, Debug)]
+pub struct LoxRange {
+    start: i64,
+    end: i64,
+    step: i64,
+}
+
+impl LoxRange {
+    // None if `step` is zero, which would never reach `end`.
+    pub fn new(start: i64, end: i64, step: i64) -> Option<LoxRange> {
+        if step == 0 {
+            return None;
+        }
+
+        Some(LoxRange { start, end, step })
+    }
+
+    pub fn len(&self) -> usize {
+        let (start, end, step) = self.wide();
+        let distance = if step > 0 { end - start } else { start - end };
+        if distance <= 0 {
+            return 0;
+        }
+
+        let len = (distance - 1) / step.abs() + 1;
+        usize::try_from(len).unwrap_or(usize::MAX)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<i64> {
+        if index >= self.len() {
+            return None;
+        }
+
+        // In bounds, so the element lies between start and end.
+        let (start, _, step) = self.wide();
+        Some((start + index as i128 * step) as i64)
+    }
+
+    // The elements from index `from` up to `to`, as another range. Indexes
+    // past the end are clamped, like slicing a list.
+    pub fn slice(&self, from: usize, to: usize) -> LoxRange {
+        let len = self.len();
+        let (from, to) = (from.min(len), to.min(len));
+        match self.get(from) {
+            Some(start) if from < to => {
+                let (_, _, step) = self.wide();
+                let end = start as i128 + (to - from) as i128 * step;
+                LoxRange {
+                    start,
+                    end: end.clamp(i64::MIN as i128, i64::MAX as i128) as i64,
+                    step: self.step,
+                }
+            }
+            _ => LoxRange {
+                start: 0,
+                end: 0,
+                step: 1,
+            },
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len()).map_while(|index| self.get(index))
+    }
+
+    // Wide enough that the arithmetic on start, end and step can't overflow.
+    fn wide(&self) -> (i128, i128, i128) {
+        (self.start as i128, self.end as i128, self.step as i128)
+    }
+}
+
+// Ranges are values: two ranges are equal when they hold the same numbers.
+impl PartialEq for LoxRange {
+    fn eq(&self, other: &Self) -> bool {
+        let len = self.len();
+        len == other.len()
+            && (len == 0 || self.start == other.start)
+            && (len <= 1 || self.step == other.step)
+    }
+}
+
+impl Display for LoxRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.step == 1 {
+            write!(f, "range({}, {})", self.start, self.end)
+        } else {
+            write!(f, "range({}, {}, {})", self.start, self.end, self.step)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(range: &LoxRange) -> Vec<i64> {
+        range.iter().collect()
+    }
+
+    fn range(start: i64, end: i64, step: i64) -> LoxRange {
+        LoxRange::new(start, end, step).unwrap_or_else(|| panic!("step 0"))
+    }
+
+    #[test]
+    fn counts_up_and_down() {
+        assert_eq!(items(&range(0, 5, 1)), [0, 1, 2, 3, 4]);
+        assert_eq!(items(&range(1, 10, 3)), [1, 4, 7]);
+        assert_eq!(items(&range(5, 0, -2)), [5, 3, 1]);
+        assert!(range(3, 3, 1).is_empty());
+        assert!(range(0, 5, -1).is_empty());
+        assert!(LoxRange::new(0, 5, 0).is_none());
+    }
+
+    #[test]
+    fn doesnt_overflow_at_the_ends() {
+        let all = range(i64::MIN, i64::MAX, 1);
+        assert_eq!(all.len(), usize::MAX);
+        assert_eq!(all.get(1), Some(i64::MIN + 1));
+
+        let wide = range(i64::MIN, i64::MAX, i64::MAX);
+        assert_eq!(items(&wide), [i64::MIN, -1, i64::MAX - 1]);
+    }
+
+    #[test]
+    fn slices_like_a_list() {
+        let evens = range(0, 20, 2);
+        assert_eq!(items(&evens.slice(2, 5)), [4, 6, 8]);
+        assert_eq!(items(&evens.slice(8, 100)), [16, 18]);
+        assert!(evens.slice(5, 2).is_empty());
+        assert_eq!(evens.slice(1, 3), range(2, 5, 2));
+        assert_eq!(range(4, 0, -1).slice(1, 3), range(3, 1, -1));
+    }
+
+    #[test]
+    fn compares_by_elements() {
+        assert_eq!(range(0, 10, 3), range(0, 11, 3));
+        assert_eq!(range(0, 0, 1), range(5, 1, 1));
+        assert_eq!(range(2, 3, 1), range(2, 0, -7));
+        assert_ne!(range(0, 10, 1), range(0, 10, 2));
+    }
+}
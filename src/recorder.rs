@@ -0,0 +1,120 @@
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+
+use crate::literal::Literal;
+use crate::token::Token;
+
+#[derive(Clone, Copy)]
+pub enum RecordKind {
+    Define,
+    Assign,
+}
+
+impl Display for RecordKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordKind::Define => write!(f, "define"),
+            RecordKind::Assign => write!(f, "assign"),
+        }
+    }
+}
+
+pub struct Record {
+    kind: RecordKind,
+    statement: usize,
+    line: u32,
+    name: String,
+    value: String,
+    type_name: &'static str,
+}
+
+// Keeps every variable definition and assignment made while a script runs so
+// a failed run can be inspected state change by state change afterwards.
+pub struct Recorder {
+    records: Vec<Record>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            records: Vec::new(),
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        kind: RecordKind,
+        statement: usize,
+        name: &Token,
+        value: &Literal,
+    ) {
+        self.records.push(Record {
+            kind,
+            statement,
+            line: name.line,
+            name: name.lexeme.to_string(),
+            value: value.to_string(),
+            type_name: value.type_name(),
+        });
+    }
+
+    pub fn write_text<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for r in &self.records {
+            let value = match r.type_name {
+                "string" => format!("\"{}\"", r.value),
+                _ => r.value.to_owned(),
+            };
+            writeln!(
+                out,
+                "#{} [line {}] {} {} = {}",
+                r.statement, r.line, r.kind, r.name, value
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "[")?;
+        for (i, r) in self.records.iter().enumerate() {
+            let separator = if i + 1 < self.records.len() { "," } else { "" };
+            writeln!(
+                out,
+                "  {{\"statement\": {}, \"line\": {}, \"kind\": \"{}\", \
+                 \"name\": \"{}\", \"value\": \"{}\", \"type\": \"{}\"}}{}",
+                r.statement,
+                r.line,
+                r.kind,
+                escape_json(&r.name),
+                escape_json(&r.value),
+                r.type_name,
+                separator
+            )?;
+        }
+        writeln!(out, "]")
+    }
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                escaped.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,157 @@
+use crate::analysis::{self, SemanticModel};
+use crate::scanner::Scanner;
+use crate::token_type::TokenType;
+
+// Renames the variable, function or class whose name is written at `line`
+// and `column` of `source`, along with every reference to it, and returns
+// the edited source. Fails without changing anything if the new name isn't
+// one, or if taking it would make some other name refer to something else,
+// like a local the renamed one would now shadow.
+pub fn rename(
+    name: &str,
+    source: &str,
+    line: u32,
+    column: u32,
+    new_name: &str,
+) -> Result<String, String> {
+    let model = analysis::analyze_named(name, source)
+        .ok_or("Unable to rename in code that doesn't parse.")?;
+    let occurrence = model
+        .at(line, column)
+        .ok_or_else(|| format!("There is no name at {line}:{column}."))?;
+    let old_name = occurrence.name.lexeme.clone();
+    let declaration = occurrence
+        .declaration
+        .ok_or_else(|| format!("'{old_name}' isn't declared in this file."))?;
+    if !is_identifier(new_name) {
+        return Err(format!("'{new_name}' can't be used as a name."));
+    }
+
+    let positions: Vec<(u32, u32)> = model
+        .references(declaration)
+        .map(|o| (o.name.line, o.name.column))
+        .collect();
+    let renamed = replace(source, &positions, old_name.len(), new_name);
+
+    // The same names in the same places, so each occurrence should still
+    // have the declaration it had before.
+    let unchanged = analysis::analyze_named(name, &renamed)
+        .is_some_and(|after| same_declarations(&model, &after));
+    if !unchanged {
+        return Err(format!(
+            "Renaming '{old_name}' to '{new_name}' would change what other \
+             names refer to."
+        ));
+    }
+    Ok(renamed)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut scanner = Scanner::new(name);
+    let tokens = scanner.scan_tokens().clone();
+    !scanner.had_error()
+        && tokens.len() == 2
+        && tokens[0].token_type == TokenType::Identifier
+        && *tokens[0].lexeme == *name
+}
+
+fn same_declarations(before: &SemanticModel, after: &SemanticModel) -> bool {
+    let before = before.occurrences().iter().map(|o| o.declaration);
+    let after = after.occurrences().iter().map(|o| o.declaration);
+    before.eq(after)
+}
+
+// Writes `new_name` over the `length` bytes of the old one at each line and
+// column in `positions`, which are in source order.
+fn replace(
+    source: &str,
+    positions: &[(u32, u32)],
+    length: usize,
+    new_name: &str,
+) -> String {
+    let mut renamed = String::with_capacity(source.len());
+    for (index, text) in source.split_inclusive('\n').enumerate() {
+        let line = index as u32 + 1;
+        let mut rest = 0;
+        for &(_, column) in positions.iter().filter(|(l, _)| *l == line) {
+            let start = text
+                .char_indices()
+                .nth(column as usize - 1)
+                .map_or(text.len(), |(i, _)| i);
+            renamed.push_str(&text[rest..start]);
+            renamed.push_str(new_name);
+            rest = start + length;
+        }
+        renamed.push_str(&text[rest..]);
+    }
+    renamed
+}
+
+// The lines `rename` changed, as a unified diff of `name`. Renaming never
+// adds or removes lines, so each changed line is a hunk of its own.
+pub fn diff(name: &str, before: &str, after: &str) -> String {
+    let mut diff = format!("--- {name}\n+++ {name}\n");
+    for (index, (old, new)) in before.lines().zip(after.lines()).enumerate() {
+        if old != new {
+            let line = index + 1;
+            diff.push_str(&format!("@@ -{line} +{line} @@\n-{old}\n+{new}\n"));
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "var count = 0;
+fun bump(by) {
+  count = count + by;
+  return count;
+}
+{ var count = \"shadow\"; print count; }
+print bump(1) + count;
+";
+
+    #[test]
+    fn renames_a_name_and_its_references() {
+        let renamed = rename("test.lox", SOURCE, 3, 5, "total");
+        assert_eq!(
+            renamed.as_deref(),
+            Ok("var total = 0;
+fun bump(by) {
+  total = total + by;
+  return total;
+}
+{ var count = \"shadow\"; print count; }
+print bump(1) + total;
+")
+        );
+
+        let renamed = rename("test.lox", SOURCE, 6, 32, "inner");
+        assert_eq!(
+            renamed.map(|source| diff("test.lox", SOURCE, &source)),
+            Ok("--- test.lox
++++ test.lox
+@@ -6 +6 @@
+-{ var count = \"shadow\"; print count; }
++{ var inner = \"shadow\"; print inner; }
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn refuses_renames_that_change_the_meaning() {
+        assert_eq!(
+            rename("test.lox", SOURCE, 2, 10, "count"),
+            Err("Renaming 'by' to 'count' would change what other names \
+                 refer to."
+                .to_string())
+        );
+        assert!(rename("test.lox", SOURCE, 1, 5, "while").is_err());
+        assert!(rename("test.lox", SOURCE, 1, 5, "two words").is_err());
+        assert!(rename("test.lox", SOURCE, 1, 1, "x").is_err());
+        assert!(rename("test.lox", "print clock;", 1, 7, "now").is_err());
+    }
+}
@@ -0,0 +1,1004 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::analysis::{SemanticModel, SymbolKind};
+use crate::environment::GlobalCache;
+use crate::error_reporter::LoxError;
+use crate::expr::{
+    AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr,
+    LiteralExpr, LogicalExpr, MatchExpr, SetExpr, ThisExpr, UnaryExpr,
+    VariableExpr,
+};
+use crate::stmt::{
+    BlockStmt, BreakStmt, ClassStmt, ContinueStmt, ExpressionStmt, ForStmt,
+    FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt, StmtVisitor, VarStmt,
+    WhileStmt,
+};
+use crate::symbol::{Symbol, INIT};
+use crate::token::Token;
+
+// What kind of function body the resolver is inside of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+// What kind of class body the resolver is inside of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+// What the code being resolved is run as. A script is checked as a whole,
+// so declaring a global twice is a mistake. At the prompt each line is
+// resolved on its own, and redefining a global is how a definition gets
+// fixed, so it's allowed there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Script,
+    Prompt,
+}
+
+// Which globals the code refers to, and from where. Top-level code always
+// runs, while the body of a top-level function only matters if something
+// reaches the function. Collected so unused functions can be dropped.
+#[derive(Default)]
+pub struct Usage {
+    top_level: HashSet<Symbol>,
+    functions: HashMap<Symbol, HashSet<Symbol>>,
+}
+
+impl Usage {
+    // Adds the usage of code resolved separately, like a prelude and the
+    // script that runs after it.
+    pub fn merge(&mut self, other: Usage) {
+        self.top_level.extend(other.top_level);
+        for (function, uses) in other.functions {
+            self.functions.entry(function).or_default().extend(uses);
+        }
+    }
+
+    // Every global that top-level code uses, directly or through the
+    // functions it calls.
+    pub fn reachable(&self) -> HashSet<Symbol> {
+        let mut reachable = self.top_level.clone();
+        let mut pending: Vec<Symbol> = reachable.iter().copied().collect();
+        while let Some(name) = pending.pop() {
+            for used in self.functions.get(&name).into_iter().flatten() {
+                if reachable.insert(*used) {
+                    pending.push(*used);
+                }
+            }
+        }
+        reachable
+    }
+}
+
+// A reference resolved as a global from inside local scopes, `depth` of
+// them. Environments are looked up by name at runtime, so if one of those
+// scopes declares the name further down, a closure called after that sees
+// the local instead, and the reference can't be cached as a global after all.
+struct PendingGlobal {
+    name: Rc<str>,
+    depth: usize,
+    cache: Rc<GlobalCache>,
+}
+
+// A local scope's names in slot order, with what is known about each.
+#[derive(Default)]
+struct Scope {
+    names: Vec<Rc<str>>,
+    // Whether each name, by slot, has been referred to yet.
+    used: Vec<bool>,
+    // Each name's declaration in the semantic model, by slot, if one is
+    // being built.
+    declarations: Vec<Option<usize>>,
+    // How many parameters the functions declared in this scope take, until
+    // something assigns them another value.
+    arities: HashMap<Rc<str>, usize>,
+}
+
+// Static checks that need to know where a statement sits, like a return
+// outside of any function. Runs after parsing, before anything executes.
+pub struct Resolver {
+    current_function: FunctionType,
+    current_class: ClassType,
+    mode: Mode,
+    first_error: Option<LoxError>,
+    // The local scopes around the current statement, innermost last, each
+    // with its names in slot order. They nest the same way the interpreter's
+    // environments do. Globals aren't tracked.
+    scopes: Vec<Scope>,
+    // Globals declared so far, to catch redeclarations in a script.
+    globals: HashSet<Symbol>,
+    // Like Scope::arities, for functions declared at the top level.
+    global_arities: HashMap<Symbol, usize>,
+    usage: Usage,
+    // The top-level function whose body is being resolved, if any.
+    top_level_function: Option<Symbol>,
+    pending_globals: Vec<PendingGlobal>,
+    // A line for every scope, declaration and reference, when requested.
+    dump: Option<String>,
+    // Every declaration and reference, when requested.
+    model: Option<SemanticModel>,
+    // Problems that don't stop the code from running, for the caller to
+    // report.
+    warnings: Vec<(Token, String)>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver {
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            mode: Mode::Script,
+            first_error: None,
+            scopes: Vec::new(),
+            globals: HashSet::new(),
+            global_arities: HashMap::new(),
+            usage: Usage::default(),
+            top_level_function: None,
+            pending_globals: Vec::new(),
+            dump: None,
+            model: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    // Also records where every variable is declared and which scope each
+    // reference resolves to, for scope_dump.
+    pub fn with_scope_dump() -> Resolver {
+        Resolver {
+            dump: Some(String::new()),
+            ..Resolver::new()
+        }
+    }
+
+    // Also builds a semantic model of the names in the code, for
+    // take_semantic_model.
+    pub fn with_semantic_model() -> Resolver {
+        Resolver {
+            model: Some(SemanticModel::default()),
+            ..Resolver::new()
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    // Which globals everything resolved so far refers to.
+    pub fn take_usage(&mut self) -> Usage {
+        std::mem::take(&mut self.usage)
+    }
+
+    pub fn scope_dump(&self) -> Option<&str> {
+        self.dump.as_deref()
+    }
+
+    // Where the names in everything resolved so far are declared and used.
+    pub fn take_semantic_model(&mut self) -> Option<SemanticModel> {
+        self.model.take().map(SemanticModel::finish)
+    }
+
+    // Calls to a known function with the wrong number of arguments and
+    // parameters a function never uses, each with the token to report it at.
+    pub fn take_warnings(&mut self) -> Vec<(Token, String)> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        for statement in statements {
+            statement.accept(self);
+        }
+
+        match self.first_error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // Resolves a function body as `kind`. Methods pass Method, or Initializer
+    // for `init`, which may only return without a value.
+    pub fn resolve_function(
+        &mut self,
+        function: &FunctionStmt,
+        kind: FunctionType,
+    ) {
+        let enclosing = std::mem::replace(&mut self.current_function, kind);
+
+        // Parameters and the body share one scope, like a call's environment.
+        self.begin_scope(format!("fun {}", function.name.lexeme));
+        for param in &function.params {
+            self.declare(param, SymbolKind::Parameter);
+        }
+        for statement in function.body.iter() {
+            statement.accept(self);
+        }
+        self.warn_unused_params(&function.params);
+        self.end_scope();
+
+        self.current_function = enclosing;
+    }
+
+    // Resolves a class declaration: its superclass, if any, and its methods.
+    pub fn resolve_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Token>,
+        methods: &[FunctionStmt],
+    ) {
+        self.declare(name, SymbolKind::Class);
+
+        let kind = match superclass {
+            Some(superclass) => {
+                self.resolve_local(superclass);
+                if superclass.lexeme == name.lexeme {
+                    self.error(
+                        superclass,
+                        "A class can't inherit from itself.",
+                    );
+                }
+                ClassType::Subclass
+            }
+            None => ClassType::Class,
+        };
+        let enclosing = std::mem::replace(&mut self.current_class, kind);
+
+        // Bound methods close over an environment holding only `this`.
+        self.begin_scope(format!("class {}", name.lexeme));
+        self.define("this".into(), None);
+        self.log("this -> slot 0".to_string());
+        for method in methods {
+            let kind = if method.name.symbol == INIT {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.resolve_function(method, kind);
+        }
+        self.end_scope();
+
+        self.current_class = enclosing;
+    }
+
+    // Checks a `this` expression.
+    pub fn resolve_this(&mut self, keyword: &Token) {
+        if self.current_class == ClassType::None {
+            self.error(keyword, "Can't use 'this' outside of a class.");
+        }
+        self.resolve_local(keyword);
+    }
+
+    // Checks a `super` expression.
+    pub fn resolve_super(&mut self, keyword: &Token) {
+        match self.current_class {
+            ClassType::None => {
+                self.error(keyword, "Can't use 'super' outside of a class.")
+            }
+            ClassType::Class => self.error(
+                keyword,
+                "Can't use 'super' in a class with no superclass.",
+            ),
+            ClassType::Subclass => {}
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        expr.accept(self)
+    }
+
+    fn begin_scope(&mut self, kind: String) {
+        self.log(kind);
+        self.scopes.push(Scope::default());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+
+        // What's left of the scope is now only the enclosing ones.
+        let depth = self.scopes.len();
+        for pending in &mut self.pending_globals {
+            pending.depth = pending.depth.min(depth);
+        }
+        self.pending_globals.retain(|pending| pending.depth > 0);
+    }
+
+    fn declare(&mut self, name: &Token, kind: SymbolKind) {
+        let redeclared = match self.scopes.last() {
+            Some(scope) => scope.names.contains(&name.lexeme),
+            None => {
+                !self.globals.insert(name.symbol) && self.mode == Mode::Script
+            }
+        };
+        if redeclared {
+            self.error(
+                name,
+                "Already a variable with this name in this scope.",
+            );
+        }
+
+        let depth = self.scopes.len();
+        for pending in &self.pending_globals {
+            if pending.depth >= depth && pending.name == name.lexeme {
+                pending.cache.set_global(false);
+            }
+        }
+
+        // Whatever the name was before, it isn't that function any more.
+        match self.scopes.last_mut() {
+            Some(scope) => scope.arities.remove(&name.lexeme),
+            None => self.global_arities.remove(&name.symbol),
+        };
+
+        let declaration = self
+            .model
+            .as_mut()
+            .and_then(|model| model.declare(name, kind, depth));
+        let slot = self.define(name.lexeme.clone(), declaration);
+        if self.dump.is_some() {
+            let slot = match slot {
+                Some(slot) => format!("slot {slot}"),
+                None => "global".to_string(),
+            };
+            self.log(format!(
+                "declare {}, line {} -> {slot}",
+                name.lexeme, name.line
+            ));
+        }
+    }
+
+    // Adds `name` to the innermost scope and returns its slot, or None at the
+    // top level. A redeclared name, already reported, keeps its first slot.
+    fn define(
+        &mut self,
+        name: Rc<str>,
+        declaration: Option<usize>,
+    ) -> Option<usize> {
+        let scope = self.scopes.last_mut()?;
+        match scope.names.iter().position(|n| *n == name) {
+            Some(slot) => Some(slot),
+            None => {
+                scope.names.push(name);
+                scope.used.push(false);
+                scope.declarations.push(declaration);
+                Some(scope.names.len() - 1)
+            }
+        }
+    }
+
+    // Finds the scope a reference reads from, counting outwards from the
+    // innermost one. References to globals are added to the usage.
+    fn resolve_local(&mut self, name: &Token) {
+        self.resolve_reference("", name);
+    }
+
+    // Marks a variable expression that reads a global, so the interpreter
+    // can cache where the global is.
+    fn resolve_variable(&mut self, expr: &VariableExpr) {
+        if !self.resolve_reference("", &expr.name) {
+            return;
+        }
+
+        expr.global.set_global(true);
+        if !self.scopes.is_empty() {
+            self.pending_globals.push(PendingGlobal {
+                name: expr.name.lexeme.clone(),
+                depth: self.scopes.len(),
+                cache: expr.global.clone(),
+            });
+        }
+    }
+
+    // Returns whether `name` is a global.
+    fn resolve_reference(&mut self, prefix: &str, name: &Token) -> bool {
+        let local =
+            self.scopes
+                .iter()
+                .rev()
+                .enumerate()
+                .find_map(|(depth, scope)| {
+                    let slot =
+                        scope.names.iter().position(|n| *n == name.lexeme)?;
+                    Some((depth, slot))
+                });
+        if let Some((depth, slot)) = local {
+            let index = self.scopes.len() - 1 - depth;
+            self.scopes[index].used[slot] = true;
+            if let Some(model) = &mut self.model {
+                model.refer(name, self.scopes[index].declarations[slot]);
+            }
+        } else {
+            if let Some(model) = &mut self.model {
+                model.refer(name, None);
+            }
+            let uses = match self.top_level_function {
+                Some(function) => {
+                    self.usage.functions.entry(function).or_default()
+                }
+                None => &mut self.usage.top_level,
+            };
+            uses.insert(name.symbol);
+        }
+
+        if self.dump.is_none() {
+            return local.is_none();
+        }
+
+        let resolved = match local {
+            Some((depth, slot)) => format!("depth {depth}, slot {slot}"),
+            None => "global".to_string(),
+        };
+        self.log(format!(
+            "{prefix}{}, line {} -> {resolved}",
+            name.lexeme, name.line
+        ));
+        local.is_none()
+    }
+
+    // The scope `name` resolves to from here, or None for a global.
+    fn scope_of(&mut self, name: &Token) -> Option<&mut Scope> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find(|scope| scope.names.contains(&name.lexeme))
+    }
+
+    // How many parameters the function `name` refers to takes, if it
+    // refers to a function declaration that nothing has reassigned.
+    fn known_arity(&mut self, name: &Token) -> Option<usize> {
+        if let Some(scope) = self.scope_of(name) {
+            return scope.arities.get(&name.lexeme).copied();
+        }
+        self.global_arities.get(&name.symbol).copied()
+    }
+
+    fn forget_arity(&mut self, name: &Token) {
+        if let Some(scope) = self.scope_of(name) {
+            scope.arities.remove(&name.lexeme);
+        } else {
+            self.global_arities.remove(&name.symbol);
+        }
+    }
+
+    fn check_arity(&mut self, expr: &CallExpr) {
+        let Expr::Variable(callee) = &*expr.callee else {
+            return;
+        };
+        let Some(arity) = self.known_arity(&callee.name) else {
+            return;
+        };
+        let count = expr.arguments.len();
+        if count != arity {
+            self.warn(
+                &callee.name,
+                format!("Expected {arity} arguments but got {count}."),
+            );
+        }
+    }
+
+    // Parameters named with a leading underscore are meant to go unused.
+    fn warn_unused_params(&mut self, params: &[Token]) {
+        let Some(scope) = self.scopes.last() else {
+            return;
+        };
+        let unused = params.iter().filter(|param| {
+            let slot = scope.names.iter().position(|n| *n == param.lexeme);
+            !param.lexeme.starts_with('_')
+                && slot.is_some_and(|slot| !scope.used[slot])
+        });
+        self.warnings.extend(unused.map(|param| {
+            let message =
+                format!("Parameter '{}' is never used.", param.lexeme);
+            (param.clone(), message)
+        }));
+    }
+
+    fn log(&mut self, line: String) {
+        let indent = "  ".repeat(self.scopes.len());
+        if let Some(dump) = &mut self.dump {
+            let _ = writeln!(dump, "{indent}{line}");
+        }
+    }
+
+    fn warn(&mut self, token: &Token, message: String) {
+        self.warnings.push((token.clone(), message));
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        let error = LoxError::parse_error(token.clone(), message.to_string());
+        self.first_error.get_or_insert(error);
+    }
+}
+
+impl ExprVisitor<()> for Resolver {
+    fn visit_binary_expr(&mut self, expr: &BinaryExpr) {
+        self.resolve_expr(&expr.left);
+        self.resolve_expr(&expr.right);
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) {
+        self.resolve_expr(&expr.expression);
+    }
+
+    fn visit_literal_expr(&mut self, _expr: &LiteralExpr) {}
+
+    fn visit_logical_exp(&mut self, expr: &LogicalExpr) {
+        self.resolve_expr(&expr.left);
+        self.resolve_expr(&expr.right);
+    }
+
+    fn visit_match_expr(&mut self, expr: &MatchExpr) {
+        self.resolve_expr(&expr.subject);
+        for arm in &expr.arms {
+            if let Some(pattern) = &arm.pattern {
+                self.resolve_expr(pattern);
+            }
+            if let Some(binding) = &arm.binding {
+                self.begin_scope("case".to_string());
+                self.declare(binding, SymbolKind::Local);
+            }
+            if let Some(guard) = &arm.guard {
+                self.resolve_expr(guard);
+            }
+            self.resolve_expr(&arm.value);
+            if arm.binding.is_some() {
+                self.end_scope();
+            }
+        }
+    }
+
+    fn visit_unary_expr(&mut self, expr: &UnaryExpr) {
+        self.resolve_expr(&expr.right);
+    }
+
+    fn visit_variable_expr(&mut self, expr: &VariableExpr) {
+        self.resolve_variable(expr);
+    }
+
+    fn visit_assignment_expr(&mut self, expr: &AssignExpr) {
+        self.resolve_expr(&expr.value);
+        self.resolve_reference("assign ", &expr.name);
+        self.forget_arity(&expr.name);
+    }
+
+    fn visit_call_expr(&mut self, expr: &CallExpr) {
+        self.resolve_expr(&expr.callee);
+        for argument in &expr.arguments {
+            self.resolve_expr(argument);
+        }
+        self.check_arity(expr);
+    }
+
+    fn visit_get_expr(&mut self, expr: &GetExpr) {
+        self.resolve_expr(&expr.object);
+    }
+
+    fn visit_set_expr(&mut self, expr: &SetExpr) {
+        self.resolve_expr(&expr.value);
+        self.resolve_expr(&expr.object);
+    }
+
+    fn visit_this_expr(&mut self, expr: &ThisExpr) {
+        self.resolve_this(&expr.keyword);
+    }
+}
+
+impl StmtVisitor<()> for Resolver {
+    fn visit_expression_stmt(&mut self, stmt: &ExpressionStmt) {
+        self.resolve_expr(&stmt.expression);
+    }
+
+    fn visit_print_stmt(&mut self, stmt: &PrintStmt) {
+        self.resolve_expr(&stmt.expression);
+    }
+
+    fn visit_var_stmt(&mut self, stmt: &VarStmt) {
+        if let Some(initializer) = &stmt.initializer {
+            self.resolve_expr(initializer);
+        }
+        let kind = if self.scopes.is_empty() {
+            SymbolKind::Global
+        } else {
+            SymbolKind::Local
+        };
+        self.declare(&stmt.name, kind);
+    }
+
+    fn visit_block_stmt(&mut self, stmt: &BlockStmt) {
+        self.begin_scope("block".to_string());
+        for statement in &stmt.statements {
+            statement.accept(self);
+        }
+        self.end_scope();
+    }
+
+    fn visit_if_stmt(&mut self, stmt: &IfStmt) {
+        self.resolve_expr(&stmt.condition);
+        stmt.then_branch.accept(self);
+        if let Some(else_branch) = &stmt.else_branch {
+            else_branch.accept(self);
+        }
+    }
+
+    fn visit_while_stmt(&mut self, stmt: &WhileStmt) {
+        self.resolve_expr(&stmt.condition);
+        stmt.body.accept(self);
+    }
+
+    fn visit_for_stmt(&mut self, stmt: &ForStmt) {
+        // The initializer's variable lives in a scope around the whole loop.
+        self.begin_scope("for".to_string());
+        if let Some(initializer) = &stmt.initializer {
+            initializer.accept(self);
+        }
+        if let Some(condition) = &stmt.condition {
+            self.resolve_expr(condition);
+        }
+        stmt.body.accept(self);
+        if let Some(increment) = &stmt.increment {
+            self.resolve_expr(increment);
+        }
+        self.end_scope();
+    }
+
+    fn visit_function_stmt(&mut self, stmt: &FunctionStmt) {
+        // Declared first so the body can call itself.
+        self.declare(&stmt.name, SymbolKind::Function);
+        let arity = stmt.params.len();
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.arities.insert(stmt.name.lexeme.clone(), arity);
+            }
+            None => {
+                self.global_arities.insert(stmt.name.symbol, arity);
+            }
+        }
+        if self.scopes.is_empty() {
+            let enclosing = self.top_level_function.replace(stmt.name.symbol);
+            self.resolve_function(stmt, FunctionType::Function);
+            self.top_level_function = enclosing;
+        } else {
+            self.resolve_function(stmt, FunctionType::Function);
+        }
+    }
+
+    fn visit_return_stmt(&mut self, stmt: &ReturnStmt) {
+        match self.current_function {
+            FunctionType::None => {
+                self.error(&stmt.keyword, "Can't return from top-level code.")
+            }
+            FunctionType::Initializer if stmt.value.is_some() => self.error(
+                &stmt.keyword,
+                "Can't return a value from an initializer.",
+            ),
+            _ => {}
+        }
+
+        if let Some(value) = &stmt.value {
+            self.resolve_expr(value);
+        }
+    }
+
+    fn visit_break_stmt(&mut self, _stmt: &BreakStmt) {}
+
+    fn visit_continue_stmt(&mut self, _stmt: &ContinueStmt) {}
+
+    fn visit_class_stmt(&mut self, stmt: &ClassStmt) {
+        self.resolve_class(&stmt.name, None, &stmt.methods);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let parsed = Parser::new(&tokens).parse();
+        parsed.unwrap_or_else(|_| panic!("test source should parse"))
+    }
+
+    fn resolve(source: &str) -> Result<(), LoxError> {
+        Resolver::new().resolve(&parse(source))
+    }
+
+    fn function(source: &str) -> FunctionStmt {
+        match parse(source).remove(0) {
+            Stmt::Function(function) => function,
+            _ => panic!("expected a function declaration"),
+        }
+    }
+
+    fn token(lexeme: &str) -> Token {
+        let tokens = Scanner::new(lexeme).scan_tokens().clone();
+        tokens[0].clone()
+    }
+
+    #[test]
+    fn rejects_return_from_top_level() {
+        assert!(resolve("return;").is_err());
+        assert!(resolve("{ return 1; }").is_err());
+    }
+
+    #[test]
+    fn allows_return_inside_functions() {
+        assert!(resolve("fun f() { return 1; }").is_ok());
+        assert!(resolve("fun f() { fun g() { return; } return g; }").is_ok());
+    }
+
+    #[test]
+    fn restores_function_type_after_a_function() {
+        assert!(resolve("fun f() { return 1; } return 2;").is_err());
+    }
+
+    #[test]
+    fn rejects_return_value_from_initializer() {
+        let init = function("fun init() { return 1; }");
+        let mut resolver = Resolver::new();
+        resolver.resolve_function(&init, FunctionType::Initializer);
+        assert!(resolver.resolve(&[]).is_err());
+    }
+
+    #[test]
+    fn allows_bare_return_from_initializer() {
+        let init = function("fun init() { if (true) return; }");
+        let mut resolver = Resolver::new();
+        resolver.resolve_function(&init, FunctionType::Initializer);
+        assert!(resolver.resolve(&[]).is_ok());
+    }
+
+    #[test]
+    fn allows_return_value_from_function_nested_in_initializer() {
+        let init = function("fun init() { fun helper() { return 1; } }");
+        let mut resolver = Resolver::new();
+        resolver.resolve_function(&init, FunctionType::Initializer);
+        assert!(resolver.resolve(&[]).is_ok());
+    }
+
+    #[test]
+    fn treats_init_methods_as_initializers() {
+        let init = function("fun init() { return 1; }");
+        let mut resolver = Resolver::new();
+        resolver.resolve_class(&token("Point"), None, &[init]);
+        assert!(resolver.resolve(&[]).is_err());
+
+        let other = function("fun area() { return 1; }");
+        let mut resolver = Resolver::new();
+        resolver.resolve_class(&token("Point"), None, &[other]);
+        assert!(resolver.resolve(&[]).is_ok());
+    }
+
+    #[test]
+    fn rejects_this_outside_of_a_class() {
+        let mut resolver = Resolver::new();
+        resolver.resolve_this(&token("this"));
+        assert!(resolver.resolve(&[]).is_err());
+    }
+
+    #[test]
+    fn rejects_super_without_a_superclass() {
+        let mut resolver = Resolver::new();
+        resolver.resolve_super(&token("super"));
+        assert!(resolver.resolve(&[]).is_err());
+
+        let mut resolver = Resolver::new();
+        resolver.current_class = ClassType::Class;
+        resolver.resolve_super(&token("super"));
+        assert!(resolver.resolve(&[]).is_err());
+
+        let mut resolver = Resolver::new();
+        resolver.current_class = ClassType::Subclass;
+        resolver.resolve_super(&token("super"));
+        assert!(resolver.resolve(&[]).is_ok());
+    }
+
+    #[test]
+    fn rejects_class_inheriting_from_itself() {
+        let mut resolver = Resolver::new();
+        resolver.resolve_class(&token("Point"), Some(&token("Point")), &[]);
+        assert!(resolver.resolve(&[]).is_err());
+
+        let mut resolver = Resolver::new();
+        resolver.resolve_class(&token("Point"), Some(&token("Shape")), &[]);
+        assert!(resolver.resolve(&[]).is_ok());
+    }
+
+    fn dump(source: &str) -> String {
+        let mut resolver = Resolver::with_scope_dump();
+        assert!(resolver.resolve(&parse(source)).is_ok());
+        resolver.scope_dump().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn dumps_globals_and_block_slots() {
+        let expected = "\
+declare a, line 1 -> global
+block
+  declare b, line 1 -> slot 0
+  b, line 1 -> depth 0, slot 0
+  declare c, line 1 -> slot 1
+  a, line 1 -> global
+  assign a, line 1 -> global
+";
+        assert_eq!(dump("var a; { var b; var c = b; a = a; }"), expected);
+    }
+
+    #[test]
+    fn dumps_closure_captures_by_depth() {
+        let expected = "\
+declare outer, line 1 -> global
+fun outer
+  declare x, line 1 -> slot 0
+  declare inner, line 1 -> slot 1
+  fun inner
+    x, line 1 -> depth 1, slot 0
+";
+        let source = "fun outer(x) { fun inner() { print x; } }";
+        assert_eq!(dump(source), expected);
+    }
+
+    #[test]
+    fn dumps_this_in_the_class_scope() {
+        let expected = "\
+declare Point, line 1 -> global
+class Point
+  this -> slot 0
+  fun area
+    this, line 1 -> depth 1, slot 0
+";
+        assert_eq!(dump("class Point { area() { return this; } }"), expected);
+    }
+
+    #[test]
+    fn rejects_redeclaring_a_local() {
+        assert!(resolve("{ var a; var a; }").is_err());
+        assert!(resolve("fun f(a, a) {}").is_err());
+        assert!(resolve("fun f(a) { var a; }").is_err());
+        assert!(resolve("{ fun g() {} class g {} }").is_err());
+    }
+
+    #[test]
+    fn allows_shadowing_in_an_inner_scope() {
+        assert!(resolve("var a; { var a; { var a; } }").is_ok());
+        assert!(resolve("fun f(a) { { var a; } }").is_ok());
+    }
+
+    #[test]
+    fn rejects_redeclaring_a_global_in_a_script() {
+        assert!(resolve("var a = 1; var a = 2;").is_err());
+        assert!(resolve("fun f() {} fun f() {}").is_err());
+    }
+
+    #[test]
+    fn allows_redefining_globals_at_the_prompt() {
+        let mut resolver = Resolver::new();
+        resolver.set_mode(Mode::Prompt);
+        assert!(resolver.resolve(&parse("var a = 1; var a = 2;")).is_ok());
+        assert!(resolver.resolve(&parse("fun a() {}")).is_ok());
+        assert!(resolver.resolve(&parse("{ var b; var b; }")).is_err());
+    }
+
+    // Whether the variable returned by the first function in `source` was
+    // marked as a global.
+    fn returns_a_global(source: &str) -> bool {
+        fn find(statements: &[Stmt]) -> Option<bool> {
+            statements.iter().find_map(|statement| match statement {
+                Stmt::Block(block) => find(&block.statements),
+                Stmt::Function(function) => find(&function.body),
+                Stmt::Return(ReturnStmt {
+                    value: Some(value), ..
+                }) => match &**value {
+                    Expr::Variable(variable) => {
+                        Some(variable.global.is_global())
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+        }
+
+        let statements = parse(source);
+        assert!(Resolver::new().resolve(&statements).is_ok());
+        find(&statements).unwrap_or_else(|| panic!("no returned variable"))
+    }
+
+    #[test]
+    fn marks_references_to_globals() {
+        assert!(returns_a_global("var a; fun f() { return a; }"));
+        assert!(returns_a_global("fun f() { { return f; } }"));
+        assert!(!returns_a_global("fun f(a) { return a; }"));
+        assert!(!returns_a_global("{ var a; fun f() { return a; } }"));
+    }
+
+    #[test]
+    fn unmarks_names_declared_later_in_an_enclosing_scope() {
+        assert!(!returns_a_global("{ fun f() { return a; } var a; }"));
+        assert!(!returns_a_global(
+            "fun g() { fun f() { { return a; } } var a; }"
+        ));
+        assert!(returns_a_global("{ { fun f() { return a; } } } { var a; }"));
+    }
+
+    fn warnings(source: &str) -> Vec<String> {
+        let mut resolver = Resolver::new();
+        assert!(resolver.resolve(&parse(source)).is_ok());
+        resolver
+            .take_warnings()
+            .into_iter()
+            .map(|(token, message)| {
+                format!("{} line {}: {message}", token.lexeme, token.line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn warns_about_calls_to_known_functions_with_the_wrong_arity() {
+        assert_eq!(
+            warnings(
+                "fun f(a) { return a; }
+                f();
+                f(1);
+                { fun g() {} g(2); }
+                fun h(b) { return f(b, b); }"
+            ),
+            [
+                "f line 2: Expected 1 arguments but got 0.",
+                "g line 4: Expected 0 arguments but got 1.",
+                "f line 5: Expected 1 arguments but got 2.",
+            ]
+        );
+    }
+
+    #[test]
+    fn trusts_no_arity_once_a_name_is_reassigned_or_shadowed() {
+        assert!(warnings(
+            "fun f() {}
+            f = clock;
+            f(1);
+            fun g() {}
+            { var g = clock; g(1); }
+            fun k(a) { return a; }
+            k = nil;
+            undefined(1, 2);"
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn warns_about_unused_parameters() {
+        assert_eq!(
+            warnings(
+                "fun f(a, b, _c) { return a; }
+                fun g(d) { fun inner() { return d; } return inner; }
+                class C { m(e) { this.e = e; } n(f) {} }"
+            ),
+            [
+                "b line 1: Parameter 'b' is never used.",
+                "f line 3: Parameter 'f' is never used.",
+            ]
+        );
+    }
+
+    #[test]
+    fn restores_class_type_after_a_class() {
+        let mut resolver = Resolver::new();
+        resolver.resolve_class(&token("Point"), None, &[]);
+        assert_eq!(resolver.current_class, ClassType::None);
+        assert_eq!(resolver.current_function, FunctionType::None);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::{self, Display};
+
+// A kind of access to the world outside the interpreter that natives need.
+// Only the natives that reach out get checked: readLine() or send() work on
+// a file or socket the script could only have got with the capability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    // open(), readFile(), writeFile() and fileExists().
+    FileSystem,
+    // tcpConnect(), tcpListen() and fetch().
+    Network,
+    // args(), exit(), atExit() and onInterrupt().
+    Process,
+    // clock() and now().
+    Clock,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 4] = [
+        Capability::FileSystem,
+        Capability::Network,
+        Capability::Process,
+        Capability::Clock,
+    ];
+
+    // The name each capability goes by in --deny and in errors.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::FileSystem => "fs",
+            Capability::Network => "net",
+            Capability::Process => "process",
+            Capability::Clock => "clock",
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Capability> {
+        Capability::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Which capabilities the natives may use. Everything is allowed by default;
+// an embedder running untrusted scripts denies what they shouldn't have, and
+// a native needing a denied capability fails with a runtime error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SandboxPolicy {
+    denied: u8,
+}
+
+impl SandboxPolicy {
+    pub fn allow_all() -> SandboxPolicy {
+        SandboxPolicy::default()
+    }
+
+    pub fn deny_all() -> SandboxPolicy {
+        Capability::ALL
+            .into_iter()
+            .fold(SandboxPolicy::default(), SandboxPolicy::deny)
+    }
+
+    pub fn allow(self, capability: Capability) -> SandboxPolicy {
+        SandboxPolicy {
+            denied: self.denied & !capability.bit(),
+        }
+    }
+
+    pub fn deny(self, capability: Capability) -> SandboxPolicy {
+        SandboxPolicy {
+            denied: self.denied | capability.bit(),
+        }
+    }
+
+    pub fn allows(self, capability: Capability) -> bool {
+        self.denied & capability.bit() == 0
+    }
+
+    // Reads a comma-separated list of capabilities to deny, as given to
+    // --deny, where `all` denies every one.
+    pub fn parse_denied(list: &str) -> Result<SandboxPolicy, String> {
+        let mut policy = SandboxPolicy::allow_all();
+        for name in list.split(',').map(str::trim) {
+            if name == "all" {
+                policy = SandboxPolicy::deny_all();
+                continue;
+            }
+            let capability = Capability::named(name)
+                .ok_or_else(|| format!("Unknown capability '{name}'."))?;
+            policy = policy.deny(capability);
+        }
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn denies_only_what_it_is_told_to() {
+        let policy = SandboxPolicy::allow_all().deny(Capability::Network);
+        assert!(!policy.allows(Capability::Network));
+        assert!(policy.allows(Capability::FileSystem));
+        assert!(policy
+            .allow(Capability::Network)
+            .allows(Capability::Network));
+
+        let none = SandboxPolicy::deny_all();
+        assert!(Capability::ALL.into_iter().all(|c| !none.allows(c)));
+        assert_eq!(none.allow(Capability::Clock).deny(Capability::Clock), none);
+    }
+
+    #[test]
+    fn parses_denied_lists() {
+        assert_eq!(
+            SandboxPolicy::parse_denied("fs, net").ok(),
+            Some(
+                SandboxPolicy::allow_all()
+                    .deny(Capability::FileSystem)
+                    .deny(Capability::Network)
+            )
+        );
+        assert_eq!(
+            SandboxPolicy::parse_denied("all").ok(),
+            Some(SandboxPolicy::deny_all())
+        );
+        assert!(SandboxPolicy::parse_denied("fs,gpu").is_err());
+    }
+}
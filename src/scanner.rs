@@ -1,32 +1,71 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 
+use crate::bigint::BigInt;
 use crate::error_reporter::LoxError;
+use crate::features::LanguageFeatures;
 use crate::literal::Literal;
-use crate::token::Token;
+use crate::source::Source;
+use crate::token::{Token, REPL};
 use crate::token_type::TokenType;
 
 const RADIX: u32 = 10;
 
 pub struct Scanner<'a> {
     source: &'a str,
+    source_name: Rc<str>,
     source_length: usize,
     tokens: Vec<Token>,
+    // The token scan_token just made, if it made one rather than skipping
+    // whitespace or a comment.
+    scanned: Option<Token>,
+    // Whether the EOF token has been handed out.
+    finished: bool,
     keywords: HashMap<&'a str, TokenType>,
     start: usize,
     current: usize,
     line: u32,
+    // The line the token being scanned starts on, which a string spanning
+    // several lines is reported at.
+    start_line: u32,
+    // The last token's start, as a byte offset and a column, which the next
+    // token's column is counted on from.
+    column_mark: (usize, u32),
+    had_error: bool,
+    features: LanguageFeatures,
 }
 
-impl Scanner<'_> {
-    pub fn new(source: &str) -> Scanner {
+impl<'a> Scanner<'a> {
+    pub fn new(source: &str) -> Scanner<'_> {
+        Scanner::with_name(source, REPL)
+    }
+
+    // Tokens remember `name` so diagnostics can say which file they are in.
+    pub fn with_name(source: &'a str, name: &str) -> Scanner<'a> {
+        Scanner::with_shared_name(source, Rc::from(name))
+    }
+
+    // Scans `source`, whose tokens share its name rather than each scan
+    // allocating its own.
+    pub fn from_source(source: &Source) -> Scanner<'_> {
+        Scanner::with_shared_name(source.text(), source.shared_name())
+    }
+
+    fn with_shared_name(source: &str, name: Rc<str>) -> Scanner<'_> {
         let mut keywords: HashMap<&str, TokenType> = HashMap::new();
         keywords.insert("and", TokenType::And);
+        keywords.insert("break", TokenType::Break);
+        keywords.insert("case", TokenType::Case);
         keywords.insert("class", TokenType::Class);
+        keywords.insert("continue", TokenType::Continue);
         keywords.insert("else", TokenType::Else);
+        keywords.insert("enum", TokenType::Enum);
         keywords.insert("false", TokenType::False);
         keywords.insert("for", TokenType::For);
         keywords.insert("fun", TokenType::Fun);
         keywords.insert("if", TokenType::If);
+        keywords.insert("is", TokenType::Is);
+        keywords.insert("match", TokenType::Match);
         keywords.insert("nil", TokenType::Nil);
         keywords.insert("or", TokenType::Or);
         keywords.insert("print", TokenType::Print);
@@ -39,39 +78,82 @@ impl Scanner<'_> {
 
         Scanner {
             source,
-            source_length: source.len() - 1,
+            source_name: name,
+            source_length: source.len(),
             tokens: Vec::new(),
+            scanned: None,
+            finished: false,
             keywords,
             start: 0,
             current: 0,
             line: 1,
+            start_line: 1,
+            column_mark: (0, 1),
+            had_error: false,
+            features: LanguageFeatures::default(),
         }
     }
 
+    // Without LanguageFeatures::TRIPLE_QUOTES, `"""` scans as an empty
+    // string and the start of another, as in the book.
+    pub fn set_features(&mut self, features: LanguageFeatures) {
+        self.features = features;
+    }
+
     pub fn scan_tokens(&mut self) -> &Vec<Token> {
+        while let Some(token) = self.next_token() {
+            self.tokens.push(token);
+        }
+
+        &self.tokens
+    }
+
+    // The tokens one at a time, scanned as they are asked for and ending
+    // with EOF, so a caller that doesn't keep them never holds them all.
+    // Errors are reported as the tokens around them are reached.
+    pub fn iter(&mut self) -> Tokens<'_, 'a> {
+        Tokens { scanner: self }
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
             self.scan_token();
+            if let Some(token) = self.scanned.take() {
+                return Some(token);
+            }
         }
 
-        self.tokens.push(Token::new(
+        if std::mem::replace(&mut self.finished, true) {
+            return None;
+        }
+        Some(Token::new(
             TokenType::EOF,
-            "".to_string(),
+            "".into(),
             None,
             self.line,
-        ));
+            self.source_name.clone(),
+        ))
+    }
 
-        &self.tokens
+    // `start` and `current` are byte offsets, so stepping over a character
+    // moves by its UTF-8 length and slices of the source stay valid.
+    // Whether scanning reported an error. The tokens are still usable by the
+    // parser, which then reports anything else wrong with the source.
+    pub fn had_error(&self) -> bool {
+        self.had_error
     }
 
-    fn advance(&mut self) -> char {
-        let current = self.current;
-        self.current += 1;
+    fn error(&mut self, line: u32, message: String) {
+        LoxError::scan_error(self.source_name.clone(), line, message);
+        self.had_error = true;
+    }
 
-        self.source
-            .chars()
-            .nth(current.try_into().unwrap())
-            .unwrap()
+    fn advance(&mut self) -> char {
+        let c = self.peek();
+        self.current += c.len_utf8();
+        c
     }
 
     fn add_token(&mut self, token_type: TokenType) {
@@ -83,9 +165,27 @@ impl Scanner<'_> {
         token_type: TokenType,
         literal: Option<Literal>,
     ) {
-        let text = self.source[self.start..self.current].to_string();
-        self.tokens
-            .push(Token::new(token_type, text, literal, self.line));
+        let text = self.source[self.start..self.current].into();
+        let mut token = Token::new(
+            token_type,
+            text,
+            literal,
+            self.start_line,
+            self.source_name.clone(),
+        );
+        token.column = self.start_column();
+        self.scanned = Some(token);
+    }
+
+    fn start_column(&mut self) -> u32 {
+        let (offset, column) = self.column_mark;
+        let between = &self.source[offset..self.start];
+        let column = match between.rfind('\n') {
+            Some(i) => between[i + 1..].chars().count() as u32 + 1,
+            None => column + between.chars().count() as u32,
+        };
+        self.column_mark = (self.start, column);
+        column
     }
 
     fn scan_token(&mut self) {
@@ -96,12 +196,14 @@ impl Scanner<'_> {
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            ':' => self.add_token(TokenType::Colon),
             ',' => self.add_token(TokenType::Comma),
             '.' => self.add_token(TokenType::Dot),
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
             '*' => self.add_token(TokenType::Star),
+            '%' => self.add_token(TokenType::Percent),
             '!' => {
                 if self.match_char('=') {
                     self.add_token(TokenType::BangEqual);
@@ -130,6 +232,15 @@ impl Scanner<'_> {
                     self.add_token(TokenType::Greater);
                 }
             }
+            '?' => {
+                if self.match_char('?') {
+                    self.add_token(TokenType::QuestionQuestion);
+                } else if self.match_char('.') {
+                    self.add_token(TokenType::QuestionDot);
+                } else {
+                    self.error(self.line, "Unexpected character.".to_string());
+                }
+            }
             '/' => {
                 if self.match_char('/') {
                     // A comment goes until the end of the line.
@@ -141,7 +252,7 @@ impl Scanner<'_> {
                     // Multiline comments can be nested
                     let mut stack = vec![self.line];
 
-                    while stack.len() != 0 && !self.is_at_end() {
+                    while !stack.is_empty() && !self.is_at_end() {
                         let char = self.peek();
                         let next_char = self.peek_next();
                         if char == '*' && next_char == '/' {
@@ -156,12 +267,9 @@ impl Scanner<'_> {
                         self.advance();
                     }
 
-                    if stack.len() != 0 && self.is_at_end() {
+                    if !stack.is_empty() && self.is_at_end() {
                         let line = stack.pop().unwrap_or(self.line);
-                        LoxError::scan_error(
-                            line,
-                            "Don't forget to close a multiline comment with closing sign: '*/'.".to_string()
-                        );
+                        self.error(line, "Don't forget to close a multiline comment with closing sign: '*/'.".to_string());
                     }
                 } else {
                     self.add_token(TokenType::Slash);
@@ -169,6 +277,7 @@ impl Scanner<'_> {
             }
             ' ' | '\r' | '\t' => {}
             '\n' => self.line += 1,
+            '"' if self.at_triple_quotes() => self.triple_quoted_string(),
             '"' => self.string(),
             c => {
                 if c.is_digit(RADIX) {
@@ -176,10 +285,7 @@ impl Scanner<'_> {
                 } else if self.is_alpha(c) {
                     self.identifier();
                 } else {
-                    LoxError::scan_error(
-                        self.line,
-                        "Unexpected character.".to_string(),
-                    );
+                    self.error(self.line, "Unexpected character.".to_string());
                 }
             }
         }
@@ -190,46 +296,20 @@ impl Scanner<'_> {
     }
 
     fn match_char(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
+        if self.is_at_end() || self.peek() != expected {
             return false;
         }
 
-        let current_char = self
-            .source
-            .chars()
-            .nth(self.current.try_into().unwrap())
-            .unwrap();
-
-        if current_char != expected {
-            return false;
-        }
-
-        self.current += 1;
-        return true;
+        self.current += expected.len_utf8();
+        true
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-
-        return self
-            .source
-            .chars()
-            .nth(self.current.try_into().unwrap())
-            .unwrap();
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-
-        return self
-            .source
-            .chars()
-            .nth((self.current + 1).try_into().unwrap())
-            .unwrap();
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     fn string(&mut self) {
@@ -241,7 +321,7 @@ impl Scanner<'_> {
         }
 
         if self.is_at_end() {
-            LoxError::scan_error(self.line, "Unterminated string.".to_string());
+            self.error(self.line, "Unterminated string.".to_string());
             return;
         }
 
@@ -252,7 +332,40 @@ impl Scanner<'_> {
 
         self.add_token_with_literal(
             TokenType::String,
-            Some(Literal::String(value.to_string())),
+            Some(Literal::String(value.into())),
+        );
+    }
+
+    // Whether the '"' just read opens a triple-quoted string.
+    fn at_triple_quotes(&self) -> bool {
+        self.features.contains(LanguageFeatures::TRIPLE_QUOTES)
+            && self.source[self.current..].starts_with("\"\"")
+    }
+
+    // Everything up to the closing `"""` is the string, quotes and line
+    // breaks included. Quotes just before the closing ones belong to the
+    // string, so `""""a""""` is `"a"`.
+    fn triple_quoted_string(&mut self) {
+        self.current += 2;
+        while !self.source[self.current..].starts_with("\"\"\"") {
+            if self.is_at_end() {
+                self.error(self.line, "Unterminated string.".to_string());
+                return;
+            }
+            if self.peek() == '\n' {
+                self.line += 1;
+            }
+            self.advance();
+        }
+        while self.source[self.current + 1..].starts_with("\"\"\"") {
+            self.advance();
+        }
+
+        let value = &self.source[self.start + 3..self.current];
+        self.current += 3;
+        self.add_token_with_literal(
+            TokenType::String,
+            Some(Literal::String(value.into())),
         );
     }
 
@@ -269,12 +382,26 @@ impl Scanner<'_> {
             }
         }
 
+        // Literals without a fraction are integers unless they don't fit,
+        // or bigints when they end in `n`.
         let value = &self.source[self.start..self.current];
+        if self.peek() == 'n'
+            && !self.is_alphanumeric(self.peek_next())
+            && !value.contains('.')
+            && self.features.contains(LanguageFeatures::BIGINTS)
+        {
+            let literal =
+                BigInt::parse(value).map(|n| Literal::BigInt(Rc::new(n)));
+            self.advance();
+            self.add_token_with_literal(TokenType::Number, literal);
+            return;
+        }
+        let literal = match value.parse() {
+            Ok(integer) => Literal::Integer(integer),
+            Err(_) => Literal::Number(value.parse().unwrap()),
+        };
 
-        self.add_token_with_literal(
-            TokenType::Number,
-            Some(Literal::Number(value.parse().unwrap())),
-        );
+        self.add_token_with_literal(TokenType::Number, Some(literal));
     }
 
     fn identifier(&mut self) {
@@ -286,8 +413,8 @@ impl Scanner<'_> {
         let token_type = self
             .keywords
             .get(text)
-            .unwrap_or(&TokenType::Identifier)
-            .clone();
+            .copied()
+            .unwrap_or(TokenType::Identifier);
 
         self.add_token(token_type);
     }
@@ -300,3 +427,307 @@ impl Scanner<'_> {
         self.is_alpha(c) || c.is_digit(RADIX)
     }
 }
+
+// Tokens as Scanner::iter scans them.
+pub struct Tokens<'s, 'a> {
+    scanner: &'s mut Scanner<'a>,
+}
+
+impl Iterator for Tokens<'_, '_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.scanner.next_token()
+    }
+}
+
+// Whether `name` scans as a single identifier rather than as a keyword or
+// something else entirely.
+pub fn is_identifier(name: &str) -> bool {
+    let scanner = Scanner::new(name);
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(c) if scanner.is_alpha(c) => {
+            chars.all(|c| scanner.is_alphanumeric(c))
+                && !scanner.keywords.contains_key(name)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TokenType::*;
+
+    // The type of every token but the final EOF, and whether scanning
+    // reported an error.
+    fn types(source: &str) -> (Vec<TokenType>, bool) {
+        let mut scanner = Scanner::new(source);
+        let mut types: Vec<TokenType> =
+            scanner.scan_tokens().iter().map(|t| t.token_type).collect();
+        assert_eq!(types.pop(), Some(EOF), "{source:?}");
+        (types, scanner.had_error())
+    }
+
+    fn single(source: &str) -> Token {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens();
+        assert_eq!(tokens.len(), 2, "{source:?}");
+        tokens[0].clone()
+    }
+
+    #[test]
+    fn scans_every_operator() {
+        let cases: &[(&str, &[TokenType])] = &[
+            ("(){}", &[LeftParen, RightParen, LeftBrace, RightBrace]),
+            (":,.;", &[Colon, Comma, Dot, Semicolon]),
+            ("-+*/%", &[Minus, Plus, Star, Slash, Percent]),
+            ("! !=", &[Bang, BangEqual]),
+            ("= ==", &[Equal, EqualEqual]),
+            ("> >=", &[Greater, GreaterEqual]),
+            ("< <=", &[Less, LessEqual]),
+            ("??", &[QuestionQuestion]),
+            ("?.", &[QuestionDot]),
+            ("!===", &[BangEqual, EqualEqual]),
+            ("<==", &[LessEqual, Equal]),
+            (
+                "a.b(c)",
+                &[
+                    Identifier, Dot, Identifier, LeftParen, Identifier,
+                    RightParen,
+                ],
+            ),
+        ];
+        for &(source, expected) in cases {
+            assert_eq!(types(source), (expected.to_vec(), false), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn tells_keywords_from_identifiers() {
+        let cases: &[(&str, TokenType)] = &[
+            ("and", And),
+            ("break", Break),
+            ("class", Class),
+            ("continue", Continue),
+            ("else", Else),
+            ("false", False),
+            ("for", For),
+            ("fun", Fun),
+            ("if", If),
+            ("is", Is),
+            ("nil", Nil),
+            ("or", Or),
+            ("print", Print),
+            ("return", Return),
+            ("super", Super),
+            ("this", This),
+            ("true", True),
+            ("var", Var),
+            ("while", While),
+            ("andy", Identifier),
+            ("orchid", Identifier),
+            ("_class", Identifier),
+            ("Nil", Identifier),
+            ("if2", Identifier),
+            ("_", Identifier),
+        ];
+        for &(source, expected) in cases {
+            assert_eq!(types(source), (vec![expected], false), "{source:?}");
+            assert_eq!(is_identifier(source), expected == Identifier);
+        }
+    }
+
+    #[test]
+    fn scans_numbers() {
+        let cases: &[(&str, Literal)] = &[
+            ("0", Literal::Integer(0)),
+            ("123", Literal::Integer(123)),
+            ("1.5", Literal::Number(1.5)),
+            ("007", Literal::Integer(7)),
+            ("9223372036854775807", Literal::Integer(i64::MAX)),
+            ("9223372036854775808", Literal::Number(9.223372036854776e18)),
+            ("12n", Literal::BigInt(Rc::new(BigInt::from(12)))),
+            (
+                "18446744073709551616n",
+                Literal::BigInt(Rc::new(
+                    BigInt::parse("18446744073709551616").unwrap_or_default(),
+                )),
+            ),
+        ];
+        for (source, expected) in cases {
+            let token = single(source);
+            assert_eq!(token.token_type, Number);
+            assert_eq!(token.literal.as_ref(), Some(expected), "{source:?}");
+        }
+
+        // A dot needs a digit after it to be part of the number.
+        assert_eq!(types("1."), (vec![Number, Dot], false));
+        assert_eq!(types(".5"), (vec![Dot, Number], false));
+        assert_eq!(types("1.x"), (vec![Number, Dot, Identifier], false));
+        // So does an `n` to make a bigint, which can't start a name either.
+        assert_eq!(types("2nd"), (vec![Number, Identifier], false));
+        assert_eq!(types("1.5n"), (vec![Number, Identifier], false));
+        let mut scanner = Scanner::new("12n");
+        scanner.set_features(LanguageFeatures::NONE);
+        assert_eq!(scanner.scan_tokens().len(), 3);
+        assert_eq!(types("1.2.3"), (vec![Number, Dot, Number], false));
+    }
+
+    #[test]
+    fn scans_strings() {
+        let token = single("\"two\nlines\"");
+        assert_eq!(token.token_type, String);
+        assert_eq!(token.lexeme.as_ref(), "\"two\nlines\"");
+        assert_eq!(token.literal, Some(Literal::String("two\nlines".into())));
+
+        let token = single("\"\"");
+        assert_eq!(token.literal, Some(Literal::String("".into())));
+        let token = single("\"// not a comment\"");
+        assert_eq!(
+            token.literal,
+            Some(Literal::String("// not a comment".into()))
+        );
+
+        assert_eq!(types("\"unterminated"), (vec![], true));
+        assert_eq!(types("a \"open\nstring"), (vec![Identifier], true));
+    }
+
+    #[test]
+    fn scans_triple_quoted_strings() {
+        let token = single("\"\"\"say \"hi\"\nand \\n \"\"\"");
+        assert_eq!(token.token_type, String);
+        assert_eq!(
+            token.literal,
+            Some(Literal::String("say \"hi\"\nand \\n ".into()))
+        );
+        assert_eq!(token.line, 1);
+
+        let cases = [
+            ("\"\"\"\"\"\"", ""),
+            ("\"\"\"\"quoted\"\"\"\"", "\"quoted\""),
+            ("\"\"\"a\"\"b\"\"\"", "a\"\"b"),
+        ];
+        for (source, value) in cases {
+            let token = single(source);
+            assert_eq!(
+                token.literal,
+                Some(Literal::String(value.into())),
+                "{source:?}"
+            );
+        }
+
+        assert_eq!(types("\"\"\"open\n\"\""), (vec![], true));
+        let (after, _) = types("\"\"\"a\"\"\" b");
+        assert_eq!(after, [String, Identifier]);
+    }
+
+    #[test]
+    fn triple_quotes_are_a_feature() {
+        let mut scanner = Scanner::new("\"\"\"a\"");
+        scanner.set_features(LanguageFeatures::NONE);
+        let types: Vec<TokenType> =
+            scanner.scan_tokens().iter().map(|t| t.token_type).collect();
+        assert_eq!(types, [String, String, EOF]);
+    }
+
+    #[test]
+    fn counts_columns_in_characters() {
+        let mut scanner = Scanner::new("var a = \"é\";\n  print \"ü\" + a;");
+        let columns: Vec<(u32, u32)> = scanner
+            .scan_tokens()
+            .iter()
+            .filter(|t| t.token_type != EOF)
+            .map(|t| (t.line, t.column))
+            .collect();
+        assert_eq!(
+            columns,
+            [(1, 1), (1, 5), (1, 7), (1, 9), (1, 12)]
+                .into_iter()
+                .chain([(2, 3), (2, 9), (2, 13), (2, 15), (2, 16)])
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn skips_comments() {
+        let cases: &[(&str, &[TokenType], bool)] = &[
+            ("// only a comment", &[], false),
+            ("a // b c\nd", &[Identifier, Identifier], false),
+            ("/* block */ a", &[Identifier], false),
+            (
+                "a /* one /* two */ still */ b",
+                &[Identifier, Identifier],
+                false,
+            ),
+            ("/* spans\nlines */ a", &[Identifier], false),
+            ("a / b", &[Identifier, Slash, Identifier], false),
+            ("/* never closed", &[], true),
+            ("/* /* nested */ never closed", &[], true),
+        ];
+        for &(source, expected, error) in cases {
+            assert_eq!(types(source), (expected.to_vec(), error), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn reports_unexpected_characters() {
+        let cases: &[(&str, &[TokenType])] = &[
+            ("@", &[]),
+            ("a # b", &[Identifier, Identifier]),
+            ("?", &[]),
+            ("a ? b", &[Identifier, Identifier]),
+            ("é", &[]),
+        ];
+        for &(source, expected) in cases {
+            assert_eq!(types(source), (expected.to_vec(), true), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn scans_lazily_to_the_same_tokens() {
+        let source = "var a = \"x\"; // note\n\nprint a + 1.5;";
+        let all = Scanner::new(source).scan_tokens().clone();
+
+        let mut scanner = Scanner::new(source);
+        let mut tokens = scanner.iter();
+        let first = tokens.next();
+        assert_eq!(first.map(|t| t.token_type), Some(Var));
+        // Only the first token has been scanned so far.
+        assert_eq!(scanner.current, 3);
+
+        let streamed: Vec<Token> = scanner.iter().collect();
+        assert_eq!(streamed.len(), all.len() - 1);
+        for (streamed, scanned) in streamed.iter().zip(&all[1..]) {
+            assert_eq!(
+                (streamed.token_type, &streamed.lexeme, streamed.line),
+                (scanned.token_type, &scanned.lexeme, scanned.line)
+            );
+        }
+        assert!(scanner.iter().next().is_none());
+    }
+
+    #[test]
+    fn counts_lines() {
+        let source = "a\nb\r\n\n/* c\n\n*/ d // e\n\"f\ng\" h\n";
+        let mut scanner = Scanner::new(source);
+        let lines: Vec<(&str, u32)> = scanner
+            .scan_tokens()
+            .iter()
+            .map(|t| (t.lexeme.as_ref(), t.line))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("a", 1),
+                ("b", 2),
+                ("d", 6),
+                ("\"f\ng\"", 7),
+                ("h", 8),
+                ("", 9)
+            ]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::loxc;
+use crate::stmt::Stmt;
+
+/* Prelude snapshot layout, all integers little endian
+snapshot → MAGIC u64(key) u32(count) ( u32(len) loxc )* ;
+*/
+
+const MAGIC: &[u8; 4] = b"LOXP";
+
+// Identifies what a snapshot was made from: the name and text of each
+// prelude, in order, and whatever else changes how they compile, such as
+// the language features. A snapshot with another key is out of date.
+// The hash isn't stable across builds of rlox, which at worst makes a new
+// build take the slow path once.
+pub fn key(preludes: &[(String, String)], settings: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    loxc::VERSION.hash(&mut hasher);
+    settings.hash(&mut hasher);
+    preludes.hash(&mut hasher);
+    hasher.finish()
+}
+
+// The compiled preludes, each in the .loxc format under the name it was
+// compiled from.
+pub fn write(key: u64, preludes: &[(&str, &[Stmt])]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&key.to_le_bytes());
+    bytes.extend_from_slice(&(preludes.len() as u32).to_le_bytes());
+    for (name, statements) in preludes {
+        let compiled = loxc::compile(name, statements);
+        bytes.extend_from_slice(&(compiled.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&compiled);
+    }
+    bytes
+}
+
+// The statements of each prelude in a snapshot written with `key`, or None
+// if it was written with another or isn't a snapshot at all.
+pub fn read(bytes: &[u8], key: u64) -> Option<Vec<Vec<Stmt>>> {
+    let rest = bytes.strip_prefix(MAGIC)?;
+    let (written, rest) = rest.split_first_chunk::<8>()?;
+    if u64::from_le_bytes(*written) != key {
+        return None;
+    }
+    let (count, mut rest) = rest.split_first_chunk::<4>()?;
+    let mut preludes = Vec::new();
+    for _ in 0..u32::from_le_bytes(*count) {
+        let (len, after) = rest.split_first_chunk::<4>()?;
+        let len = u32::from_le_bytes(*len) as usize;
+        if after.len() < len {
+            return None;
+        }
+        let (compiled, after) = after.split_at(len);
+        preludes.push(loxc::load(compiled).ok()?);
+        rest = after;
+    }
+    rest.is_empty().then_some(preludes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn reads_back_only_with_the_same_key() {
+        let preludes =
+            [("a.lox".to_string(), "fun f() { return 1; }".to_string())];
+        let key = key(&preludes, "all");
+        assert_ne!(key, super::key(&preludes, "none"));
+
+        let tokens = Scanner::new(&preludes[0].1).scan_tokens().clone();
+        let statements = Parser::new(&tokens).parse().unwrap_or_default();
+        let bytes = write(key, &[("a.lox", &statements)]);
+
+        let read_back = read(&bytes, key).unwrap_or_default();
+        assert_eq!(read_back.len(), 1);
+        assert!(matches!(read_back[0].as_slice(), [Stmt::Function(_)]));
+        assert!(read(&bytes, key + 1).is_none());
+        assert!(read(&bytes[..bytes.len() - 1], key).is_none());
+        assert!(read(b"LOXC", key).is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use std::rc::Rc;
+
+// A program's text and the name diagnostics call it by, with where each
+// line starts worked out once up front. It never changes after it is made,
+// so the scanner, the parser and anything reporting on the code can share
+// one behind an Rc.
+#[derive(Debug)]
+pub struct Source {
+    name: Rc<str>,
+    text: Box<str>,
+    // The byte offset each line starts at, the first line's being 0.
+    line_starts: Vec<usize>,
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Source {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Source {
+            name: name.into(),
+            text: text.into(),
+            line_starts,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // The name as tokens hold it, sharing this source's allocation.
+    pub fn shared_name(&self) -> Rc<str> {
+        self.name.clone()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    // The text of line `line`, counting from 1, without its line ending.
+    pub fn line(&self, line: u32) -> Option<&str> {
+        let index = (line as usize).checked_sub(1)?;
+        let start = *self.line_starts.get(index)?;
+        let end = self
+            .line_starts
+            .get(index + 1)
+            .map_or(self.text.len(), |&next| next - 1);
+        let text = &self.text[start..end];
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+
+    // The line and column, both counting from 1, of the character at byte
+    // `offset`. Columns count characters rather than bytes, and an offset
+    // past the end is placed just after the last character.
+    pub fn line_column(&self, offset: usize) -> (u32, u32) {
+        let offset = offset.min(self.text.len());
+        let index =
+            self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[index];
+        let column = self.text[start..]
+            .char_indices()
+            .take_while(|&(i, _)| start + i < offset)
+            .count();
+        (index as u32 + 1, column as u32 + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexes_lines() {
+        let source =
+            Source::new("test.lox", "var a;\r\nprint a;\n\nprint \"é\";");
+        assert_eq!(source.line_count(), 4);
+        assert_eq!(source.line(1), Some("var a;"));
+        assert_eq!(source.line(2), Some("print a;"));
+        assert_eq!(source.line(3), Some(""));
+        assert_eq!(source.line(4), Some("print \"é\";"));
+        assert_eq!(source.line(0), None);
+        assert_eq!(source.line(5), None);
+
+        let empty = Source::new("empty.lox", "");
+        assert_eq!(empty.line_count(), 1);
+        assert_eq!(empty.line(1), Some(""));
+    }
+
+    #[test]
+    fn converts_offsets_to_lines_and_columns() {
+        let source = Source::new("test.lox", "ab\n\"é\" + c\n");
+        assert_eq!(source.line_column(0), (1, 1));
+        assert_eq!(source.line_column(2), (1, 3));
+        assert_eq!(source.line_column(3), (2, 1));
+        // 'é' is two bytes but one column.
+        let plus = source.text().find('+').unwrap_or_default();
+        assert_eq!(source.line_column(plus), (2, 5));
+        assert_eq!(source.line_column(source.text().len()), (3, 1));
+        assert_eq!(source.line_column(1000), (3, 1));
+    }
+}
@@ -0,0 +1,192 @@
+use std::mem;
+use std::rc::Rc;
+
+use crate::expr::{
+    AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr,
+    LiteralExpr, LogicalExpr, MatchExpr, SetExpr, ThisExpr, UnaryExpr,
+    VariableExpr,
+};
+use crate::grammar::Precedence;
+use crate::literal::Literal;
+
+// Turns an expression back into Lox source. Parentheses are only emitted
+// where precedence requires them, unless the printer preserves groups, in
+// which case the ones the user wrote are kept too.
+pub struct SourcePrinter {
+    preserve_groups: bool,
+    // The loosest precedence the expression being printed may have without
+    // being wrapped in parentheses.
+    context: Precedence,
+}
+
+impl Default for SourcePrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SourcePrinter {
+    pub fn new() -> SourcePrinter {
+        SourcePrinter {
+            preserve_groups: false,
+            context: Precedence::Assignment,
+        }
+    }
+
+    pub fn preserving_groups() -> SourcePrinter {
+        SourcePrinter {
+            preserve_groups: true,
+            ..SourcePrinter::new()
+        }
+    }
+
+    pub fn print(&mut self, expr: &Expr) -> String {
+        self.print_at(expr, Precedence::Assignment)
+    }
+
+    fn print_at(&mut self, expr: &Expr, context: Precedence) -> String {
+        let outer = mem::replace(&mut self.context, context);
+        let printed = expr.accept(self);
+        self.context = outer;
+
+        printed
+    }
+
+    fn wrap(&self, precedence: Precedence, printed: String) -> String {
+        if precedence < self.context {
+            format!("({printed})")
+        } else {
+            printed
+        }
+    }
+
+    fn infix(
+        &mut self,
+        left: &Rc<Expr>,
+        operator: &str,
+        right: &Rc<Expr>,
+        precedence: Precedence,
+    ) -> String {
+        let left = self.print_at(left, precedence);
+        let right = self.print_at(right, precedence.next());
+
+        self.wrap(precedence, format!("{left} {operator} {right}"))
+    }
+}
+
+impl ExprVisitor<String> for SourcePrinter {
+    fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> String {
+        let precedence = Precedence::of_infix(expr.operator.token_type)
+            .unwrap_or(Precedence::Primary);
+        self.infix(&expr.left, &expr.operator.lexeme, &expr.right, precedence)
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> String {
+        if self.preserve_groups {
+            let inner = self.print_at(&expr.expression, Precedence::Assignment);
+            format!("({inner})")
+        } else if self.context == Precedence::Call
+            && expr.expression.in_optional_chain()
+        {
+            // Parentheses end an optional chain, so `(a?.b).c` keeps them.
+            let inner = self.print_at(&expr.expression, Precedence::Assignment);
+            format!("({inner})")
+        } else {
+            let context = self.context;
+            self.print_at(&expr.expression, context)
+        }
+    }
+
+    fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> String {
+        match &expr.value {
+            // Only a triple-quoted string can hold a double quote.
+            Some(Literal::String(s)) if s.contains('"') => {
+                format!("\"\"\"{s}\"\"\"")
+            }
+            Some(Literal::String(s)) => format!("\"{s}\""),
+            Some(literal) => literal.to_string(),
+            None => "nil".to_string(),
+        }
+    }
+
+    fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> String {
+        let right = self.print_at(&expr.right, Precedence::Unary);
+        self.wrap(
+            Precedence::Unary,
+            format!("{}{right}", expr.operator.lexeme),
+        )
+    }
+
+    fn visit_variable_expr(&mut self, expr: &VariableExpr) -> String {
+        expr.name.lexeme.to_string()
+    }
+
+    // Assignment is right-associative, so its value stays at Assignment.
+    fn visit_assignment_expr(&mut self, expr: &AssignExpr) -> String {
+        let value = self.print_at(&expr.value, Precedence::Assignment);
+        self.wrap(
+            Precedence::Assignment,
+            format!("{} = {value}", expr.name.lexeme),
+        )
+    }
+
+    fn visit_logical_exp(&mut self, expr: &LogicalExpr) -> String {
+        let precedence = Precedence::of_infix(expr.operator.token_type)
+            .unwrap_or(Precedence::Primary);
+        self.infix(&expr.left, &expr.operator.lexeme, &expr.right, precedence)
+    }
+
+    fn visit_match_expr(&mut self, expr: &MatchExpr) -> String {
+        let subject = self.print_at(&expr.subject, Precedence::Assignment);
+        let mut cases = Vec::new();
+        for arm in &expr.arms {
+            let mut case = match (&arm.pattern, &arm.binding) {
+                (Some(pattern), _) => {
+                    format!(
+                        "case {}",
+                        self.print_at(pattern, Precedence::Unary)
+                    )
+                }
+                (None, Some(binding)) => format!("case {}", binding.lexeme),
+                (None, None) => "case _".to_string(),
+            };
+            if let Some(guard) = &arm.guard {
+                let guard = self.print_at(guard, Precedence::Assignment);
+                case = format!("{case} if {guard}");
+            }
+            let value = self.print_at(&arm.value, Precedence::Assignment);
+            cases.push(format!("{case}: {value}"));
+        }
+        format!("match ({subject}) {{ {} }}", cases.join(" "))
+    }
+
+    fn visit_call_expr(&mut self, expr: &CallExpr) -> String {
+        let callee = self.print_at(&expr.callee, Precedence::Call);
+        let arguments: Vec<String> = expr
+            .arguments
+            .iter()
+            .map(|a| self.print_at(a, Precedence::Assignment))
+            .collect();
+
+        format!("{callee}({})", arguments.join(", "))
+    }
+
+    fn visit_get_expr(&mut self, expr: &GetExpr) -> String {
+        let object = self.print_at(&expr.object, Precedence::Call);
+        let operator = if expr.optional { "?." } else { "." };
+        format!("{object}{operator}{}", expr.name.lexeme)
+    }
+
+    fn visit_set_expr(&mut self, expr: &SetExpr) -> String {
+        let object = self.print_at(&expr.object, Precedence::Call);
+        let value = self.print_at(&expr.value, Precedence::Assignment);
+        self.wrap(
+            Precedence::Assignment,
+            format!("{object}.{} = {value}", expr.name.lexeme),
+        )
+    }
+
+    fn visit_this_expr(&mut self, _expr: &ThisExpr) -> String {
+        "this".to_string()
+    }
+}
@@ -0,0 +1,37 @@
+use std::cell::Cell;
+use std::fmt::{self, Display};
+
+thread_local! {
+    static LIVE_ENVIRONMENTS: Cell<usize> = const { Cell::new(0) };
+}
+
+pub fn environment_created() {
+    LIVE_ENVIRONMENTS.with(|count| count.set(count.get() + 1));
+}
+
+pub fn environment_dropped() {
+    LIVE_ENVIRONMENTS.with(|count| count.set(count.get().saturating_sub(1)));
+}
+
+pub fn live_environments() -> usize {
+    LIVE_ENVIRONMENTS.with(|count| count.get())
+}
+
+// A snapshot of the interpreter's allocations. Environments that never drop
+// after their scope ends are the usual sign of an Rc cycle through closures.
+pub struct Stats {
+    pub environments: usize,
+    pub globals: usize,
+    pub strings: usize,
+    pub calls: usize,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "environments: {}, globals: {}, strings: {}, calls: {}",
+            self.environments, self.globals, self.strings, self.calls
+        )
+    }
+}
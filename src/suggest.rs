@@ -0,0 +1,71 @@
+// Finds the name the user most likely meant when they misspelled one, for
+// "Did you mean ...?" hints in error messages.
+pub fn closest<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    // Short names only tolerate one typo, or every two-letter name would be
+    // suggested for every other.
+    let max_distance = ((name.chars().count() + 1) / 3).clamp(1, 3);
+
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        if candidate == name {
+            continue;
+        }
+        let distance = levenshtein(name, candidate);
+        if distance <= max_distance
+            && best.is_none_or(|(best, _)| distance < best)
+        {
+            best = Some((distance, candidate));
+        }
+    }
+
+    best.map(|(_, candidate)| candidate)
+}
+
+// How many single character insertions, deletions and substitutions turn
+// `a` into `b`.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // The distances from the part of `a` seen so far to each prefix of `b`.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_edits() {
+        assert_eq!(levenshtein("count", "count"), 0);
+        assert_eq!(levenshtein("cont", "count"), 1);
+        assert_eq!(levenshtein("coutn", "count"), 2);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("héllo", "hello"), 1);
+    }
+
+    #[test]
+    fn suggests_the_nearest_close_name() {
+        let names = ["counter", "count", "clock", "x"];
+        assert_eq!(closest("cont", names), Some("count"));
+        assert_eq!(closest("countr", names), Some("counter"));
+        assert_eq!(closest("clokc", names), Some("clock"));
+        assert_eq!(closest("y", names), Some("x"));
+        assert_eq!(closest("total", names), None);
+        assert_eq!(closest("x", ["x"]), None);
+    }
+}
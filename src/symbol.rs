@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::hash::{BuildHasherDefault, Hasher};
+use std::rc::Rc;
+
+// An interned name. Equal names share one symbol, so comparing or hashing a
+// symbol is a single integer operation instead of walking the string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+// Names the interpreter looks up itself are interned up front, in this order.
+const PREDEFINED: [&str; 2] = ["this", "init"];
+pub const THIS: Symbol = Symbol(0);
+pub const INIT: Symbol = Symbol(1);
+
+struct SymbolTable {
+    names: Vec<Rc<str>>,
+    symbols: HashMap<Rc<str>, Symbol>,
+}
+
+impl SymbolTable {
+    fn new() -> SymbolTable {
+        let mut table = SymbolTable {
+            names: Vec::new(),
+            symbols: HashMap::new(),
+        };
+        for name in PREDEFINED {
+            table.intern(name);
+        }
+        table
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(name) {
+            return *symbol;
+        }
+
+        let symbol = Symbol(self.names.len() as u32);
+        let name: Rc<str> = name.into();
+        self.names.push(name.clone());
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+}
+
+thread_local! {
+    static SYMBOLS: RefCell<SymbolTable> = RefCell::new(SymbolTable::new());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        SYMBOLS.with(|table| table.borrow_mut().intern(name))
+    }
+
+    // The interned text, shared with every other use of the same name.
+    pub fn name(self) -> Rc<str> {
+        SYMBOLS.with(|table| table.borrow().names[self.0 as usize].clone())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Symbols are small consecutive integers, so rather than running them through
+// SipHash they are spread over the table with one multiplication.
+#[derive(Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 << 8 | *byte as u64)
+                .wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.0 = (n as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
+
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+    use crate::token::Token;
+
+    fn identifiers(source: &str) -> Vec<Token> {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().clone();
+        tokens.into_iter().filter(|t| t.lexeme.len() > 1).collect()
+    }
+
+    #[test]
+    fn separately_scanned_names_share_a_symbol() {
+        let first = identifiers("var counter = this;");
+        let second = identifiers("print counter; print init;");
+        let [_, counter, this] = &first[..] else {
+            panic!("expected three names in {first:?}");
+        };
+        let [_, again, _, init] = &second[..] else {
+            panic!("expected four names in {second:?}");
+        };
+
+        assert_eq!(counter.symbol, again.symbol);
+        assert!(Rc::ptr_eq(&counter.lexeme, &again.lexeme));
+        assert_eq!(counter.symbol, Symbol::intern("counter"));
+        assert_ne!(counter.symbol, Symbol::intern("counters"));
+        assert_eq!((this.symbol, init.symbol), (THIS, INIT));
+        assert_eq!(&*again.symbol.name(), "counter");
+    }
+}
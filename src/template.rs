@@ -0,0 +1,159 @@
+// Text templates for the template() native. A placeholder is a dotted path
+// between double braces, `{{user.name}}`, with spaces allowed inside the
+// braces. Everything else is copied as it is; there are no loops or
+// conditions, scripts build those parts themselves and pass them in.
+
+#[derive(Debug, PartialEq)]
+pub enum Part<'a> {
+    Text(&'a str),
+    // The names along the path, so `{{a.b}}` is ["a", "b"].
+    Placeholder(Vec<&'a str>),
+}
+
+// How a value is written into the text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Escape {
+    #[default]
+    None,
+    // &, <, >, " and ' become entities, for values written into HTML.
+    Html,
+}
+
+// What happens to a placeholder whose path leads nowhere.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Missing {
+    #[default]
+    Error,
+    Empty,
+    // The placeholder stays in the text, so a later pass can fill it.
+    Keep,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TemplateOptions {
+    pub escape: Escape,
+    pub missing: Missing,
+}
+
+impl TemplateOptions {
+    // Reads a comma-separated list of options: `html` escapes values, and
+    // `empty` or `keep` say what to do about missing ones.
+    pub fn parse(list: &str) -> Result<TemplateOptions, String> {
+        let mut options = TemplateOptions::default();
+        for name in list.split(',').map(str::trim) {
+            match name {
+                "" => {}
+                "html" => options.escape = Escape::Html,
+                "empty" => options.missing = Missing::Empty,
+                "keep" => options.missing = Missing::Keep,
+                _ => return Err(format!("has no option '{name}'")),
+            }
+        }
+        Ok(options)
+    }
+}
+
+// Splits a template into its text and placeholders.
+pub fn parse(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        if open > 0 {
+            parts.push(Part::Text(&rest[..open]));
+        }
+        let inside = &rest[open + 2..];
+        let close = inside
+            .find("}}")
+            .ok_or_else(|| "has a '{{' without a '}}'".to_string())?;
+        parts.push(Part::Placeholder(path(&inside[..close])?));
+        rest = &inside[close + 2..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(parts)
+}
+
+fn path(placeholder: &str) -> Result<Vec<&str>, String> {
+    let names: Vec<&str> = placeholder.trim().split('.').collect();
+    let valid = |name: &&str| {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if names.iter().all(valid) {
+        Ok(names)
+    } else {
+        Err(format!(
+            "has an invalid placeholder '{{{{{placeholder}}}}}'"
+        ))
+    }
+}
+
+pub fn escape(text: &str, escape: Escape) -> String {
+    match escape {
+        Escape::None => text.to_string(),
+        Escape::Html => {
+            let mut escaped = String::with_capacity(text.len());
+            for c in text.chars() {
+                match c {
+                    '&' => escaped.push_str("&amp;"),
+                    '<' => escaped.push_str("&lt;"),
+                    '>' => escaped.push_str("&gt;"),
+                    '"' => escaped.push_str("&quot;"),
+                    '\'' => escaped.push_str("&#39;"),
+                    c => escaped.push(c),
+                }
+            }
+            escaped
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_text_and_placeholders() {
+        assert_eq!(
+            parse("Hi {{ user.name }}, {{n}}!"),
+            Ok(vec![
+                Part::Text("Hi "),
+                Part::Placeholder(vec!["user", "name"]),
+                Part::Text(", "),
+                Part::Placeholder(vec!["n"]),
+                Part::Text("!"),
+            ])
+        );
+        assert_eq!(parse("{ not one }"), Ok(vec![Part::Text("{ not one }")]));
+        assert!(parse("{{open").is_err());
+        assert!(parse("{{}}").is_err());
+        assert!(parse("{{a..b}}").is_err());
+        assert!(parse("{{1st}}").is_err());
+    }
+
+    #[test]
+    fn parses_options() {
+        assert_eq!(
+            TemplateOptions::parse("html, keep"),
+            Ok(TemplateOptions {
+                escape: Escape::Html,
+                missing: Missing::Keep,
+            })
+        );
+        assert_eq!(TemplateOptions::parse(""), Ok(TemplateOptions::default()));
+        assert!(TemplateOptions::parse("xml").is_err());
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape("<a href=\"x\">Tom & 'Jerry'</a>", Escape::Html),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+        assert_eq!(escape("<b>", Escape::None), "<b>");
+    }
+}
@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::lox::Lox;
+
+// Test files are the ones whose name ends in this, like `list_test.lox`.
+pub const TEST_FILE_SUFFIX: &str = "_test.lox";
+
+// What the test() calls in a script came to. A script that ran any tests
+// ends by printing the summary.
+#[derive(Debug, Default)]
+pub struct TestResults {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl TestResults {
+    pub fn ran(&self) -> bool {
+        self.passed + self.failed > 0
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "test result: {} passed, {} failed.",
+            self.passed, self.failed
+        )
+    }
+}
+
+// The test files in `paths`: files given directly, and the test files
+// anywhere under directories, in name order.
+pub fn discover(paths: &[&Path]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            discover_in(path, &mut files)?;
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+fn discover_in(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            discover_in(&path, files)?;
+        } else if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(TEST_FILE_SUFFIX))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// How running one test file went.
+struct FileRun {
+    status: i32,
+    passed: usize,
+    failed: usize,
+    // Everything the file printed and reported, in the order it happened.
+    output: Vec<u8>,
+}
+
+// Runs each file in a Lox made by `new_lox`, on up to `jobs` worker threads
+// with `stack_size` bytes of stack each. A Lox can't be sent between
+// threads, so each worker makes its own, a fresh one for every file: each
+// file starts from fresh globals and exit() ends only that file. Files are
+// reported in the order given, whatever order they finish in. A passing
+// file's output is kept back; a failing one's is shown under it. Returns
+// whether every file passed.
+pub fn run<F>(
+    files: &[PathBuf],
+    jobs: usize,
+    stack_size: usize,
+    new_lox: F,
+) -> bool
+where
+    F: Fn() -> Lox + Sync,
+{
+    let (mut passed_files, mut failed_files) = (0, 0);
+    let (mut passed_tests, mut failed_tests) = (0, 0);
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let all_ran = thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            let sender = sender.clone();
+            let (next, new_lox) = (&next, &new_lox);
+            let worker = thread::Builder::new().stack_size(stack_size);
+            let spawned = worker.spawn_scoped(scope, move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(index) else {
+                    break;
+                };
+                if sender.send((index, run_file(new_lox(), file))).is_err() {
+                    break;
+                }
+            });
+            if let Err(e) = spawned {
+                eprintln!("Unable to start a test thread: {e}");
+                return false;
+            }
+        }
+        drop(sender);
+
+        // Finished files wait here until every file before them is shown.
+        let mut finished = BTreeMap::new();
+        let mut shown = 0;
+        for (index, run) in receiver {
+            finished.insert(index, run);
+            while let Some(run) = finished.remove(&shown) {
+                let FileRun {
+                    status,
+                    passed,
+                    failed,
+                    output,
+                } = run;
+                let file = &files[shown];
+                shown += 1;
+                passed_tests += passed;
+                failed_tests += failed;
+
+                if status == 0 {
+                    passed_files += 1;
+                    println!("PASS {} ({passed} passed)", file.display());
+                } else {
+                    failed_files += 1;
+                    println!(
+                        "FAIL {} ({passed} passed, {failed} failed)",
+                        file.display()
+                    );
+                    for line in String::from_utf8_lossy(&output).lines() {
+                        println!("    {line}");
+                    }
+                }
+            }
+        }
+        true
+    });
+    if !all_ran {
+        return false;
+    }
+
+    println!(
+        "\n{} files: {passed_files} passed, {failed_files} failed. \
+         {} tests: {passed_tests} passed, {failed_tests} failed.",
+        files.len(),
+        passed_tests + failed_tests
+    );
+    failed_files == 0
+}
+
+// Runs `file` in `lox`, keeping what it writes. A panic fails the file with
+// the status a panicking process exits with; the message has already gone
+// to stderr.
+fn run_file(mut lox: Lox, file: &Path) -> FileRun {
+    let output = Rc::new(RefCell::new(Vec::new()));
+    lox.set_output(output.clone());
+    lox.set_error_output(output.clone());
+
+    let status = panic::catch_unwind(AssertUnwindSafe(|| {
+        lox.run_files_for_status(&[file])
+    }))
+    .unwrap_or(101);
+    let tests = lox.test_results();
+    FileRun {
+        status,
+        passed: tests.passed,
+        failed: tests.failed,
+        output: output.take(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn summarizes_the_results() {
+        let results = TestResults {
+            passed: 3,
+            failed: 1,
+        };
+        assert!(results.ran());
+        assert_eq!(results.summary(), "test result: 3 passed, 1 failed.");
+        assert!(!TestResults::default().ran());
+    }
+
+    #[test]
+    fn discovers_test_files_under_directories() {
+        let dir =
+            env::temp_dir().join(format!("rlox-tests-{}", std::process::id()));
+        let nested = dir.join("nested");
+        fs::create_dir_all(&nested).expect("the temp dir should be writable");
+        for file in ["b_test.lox", "a_test.lox", "helper.lox"] {
+            fs::write(dir.join(file), "")
+                .expect("the temp dir should be writable");
+        }
+        fs::write(nested.join("c_test.lox"), "")
+            .expect("the temp dir should be writable");
+
+        let found = discover(&[&dir]);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            found.ok(),
+            Some(vec![
+                dir.join("a_test.lox"),
+                dir.join("b_test.lox"),
+                nested.join("c_test.lox"),
+            ])
+        );
+    }
+}
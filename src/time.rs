@@ -0,0 +1,251 @@
+// Calendar dates for the time natives, in UTC. There is no time zone
+// database, so scripts wanting local time add their own offset.
+//
+// Formats understand these directives:
+//     %Y  year, four digits      %H  hour, 00 to 23
+//     %m  month, 01 to 12        %M  minute, 00 to 59
+//     %d  day, 01 to 31          %S  second, 00 to 59
+//     %%  a literal %
+
+#[derive(Debug, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+impl DateTime {
+    // The moment `epoch` seconds after 1970-01-01 00:00:00 UTC.
+    pub fn from_epoch(epoch: i64) -> DateTime {
+        let days = epoch.div_euclid(SECONDS_PER_DAY);
+        let seconds = epoch.rem_euclid(SECONDS_PER_DAY) as u32;
+        let (year, month, day) = civil_from_days(days);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+            second: seconds % 60,
+        }
+    }
+
+    pub fn to_epoch(&self) -> i64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        days * SECONDS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+
+    pub fn format(&self, format: &str) -> Result<String, String> {
+        let mut text = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                text.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('Y') => text.push_str(&format!("{:04}", self.year)),
+                Some('m') => text.push_str(&format!("{:02}", self.month)),
+                Some('d') => text.push_str(&format!("{:02}", self.day)),
+                Some('H') => text.push_str(&format!("{:02}", self.hour)),
+                Some('M') => text.push_str(&format!("{:02}", self.minute)),
+                Some('S') => text.push_str(&format!("{:02}", self.second)),
+                Some('%') => text.push('%'),
+                Some(other) => {
+                    return Err(format!("unknown directive %{other}"))
+                }
+                None => return Err("format ends with %".to_string()),
+            }
+        }
+
+        Ok(text)
+    }
+
+    // Reads `text` laid out as `format`. Fields the format leaves out are
+    // those of 1970-01-01 00:00:00.
+    pub fn parse(text: &str, format: &str) -> Result<DateTime, String> {
+        let mut date = DateTime::from_epoch(0);
+        let mut rest = text;
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            let directive = match c {
+                '%' => match chars.next() {
+                    Some(directive) => Some(directive),
+                    None => return Err("format ends with %".to_string()),
+                },
+                _ => None,
+            };
+            let (digits, max) = match directive {
+                Some('Y') => (4, 9999),
+                Some('m') => (2, 12),
+                Some('d') => (2, 31),
+                Some('H') => (2, 23),
+                Some('M' | 'S') => (2, 59),
+                Some('%') | None => {
+                    match rest.strip_prefix(c) {
+                        Some(after) => rest = after,
+                        None => {
+                            return Err(format!("expected '{c}' in \"{text}\""))
+                        }
+                    }
+                    continue;
+                }
+                Some(other) => {
+                    return Err(format!("unknown directive %{other}"))
+                }
+            };
+
+            let end = rest
+                .bytes()
+                .take(digits)
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+            let value = match rest[..end].parse() {
+                Ok(value) if value <= max => value,
+                _ => {
+                    return Err(format!(
+                        "expected a number for %{} in \"{text}\"",
+                        directive.unwrap_or('%')
+                    ))
+                }
+            };
+            rest = &rest[end..];
+
+            match directive {
+                Some('Y') => date.year = value as i64,
+                Some('m') => date.month = value,
+                Some('d') => date.day = value,
+                Some('H') => date.hour = value,
+                Some('M') => date.minute = value,
+                _ => date.second = value,
+            }
+        }
+
+        if !rest.is_empty() {
+            return Err(format!(
+                "unexpected \"{rest}\" at the end of \"{text}\""
+            ));
+        }
+        if date.month == 0
+            || date.day == 0
+            || date.day > days_in_month(date.year, date.month)
+        {
+            return Err(format!("\"{text}\" isn't a valid date"));
+        }
+
+        Ok(date)
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date, and back, computed
+// over 400-year eras as in http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5
+            + day as i64
+            - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_epochs_to_dates_and_back() {
+        for (epoch, formatted) in [
+            (0, "1970-01-01 00:00:00"),
+            (951_782_400, "2000-02-29 00:00:00"),
+            (1_700_000_000, "2023-11-14 22:13:20"),
+            (-1, "1969-12-31 23:59:59"),
+            (4_107_542_400, "2100-03-01 00:00:00"),
+        ] {
+            let date = DateTime::from_epoch(epoch);
+            assert_eq!(date.format("%Y-%m-%d %H:%M:%S"), Ok(formatted.into()));
+            assert_eq!(date.to_epoch(), epoch);
+        }
+
+        for days in -800_000..800_000 {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn parses_what_it_formats() {
+        let format = "%d/%m/%Y at %H:%M:%S (100%%)";
+        let text = DateTime::from_epoch(1_234_567_890).format(format);
+        assert_eq!(text, Ok("13/02/2009 at 23:31:30 (100%)".to_string()));
+        let date = DateTime::parse("13/02/2009 at 23:31:30 (100%)", format);
+        assert_eq!(date.map(|d| d.to_epoch()), Ok(1_234_567_890));
+
+        let date = DateTime::parse("2024-02-29", "%Y-%m-%d");
+        assert_eq!(date.map(|d| d.to_epoch()), Ok(1_709_164_800));
+    }
+
+    #[test]
+    fn rejects_bad_formats_and_dates() {
+        assert!(DateTime::from_epoch(0).format("%q").is_err());
+        assert!(DateTime::from_epoch(0).format("100%").is_err());
+        assert!(DateTime::parse("100%", "100%").is_err());
+        for (text, format) in [
+            ("2023-02-29", "%Y-%m-%d"),
+            ("2023-13-01", "%Y-%m-%d"),
+            ("2023-1x-01", "%Y-%m-%d"),
+            ("2023-01-01 extra", "%Y-%m-%d"),
+            ("2023/01/01", "%Y-%m-%d"),
+            ("24:00", "%H:%M"),
+        ] {
+            assert!(DateTime::parse(text, format).is_err(), "{text}");
+        }
+    }
+}
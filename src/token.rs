@@ -1,30 +1,60 @@
 use std::fmt::{self, Display};
+use std::rc::Rc;
 
 use crate::literal::Literal;
+use crate::symbol::Symbol;
 use crate::token_type::TokenType;
 
-#[derive(Debug)]
+// Source name given to code that doesn't come from a file.
+pub const REPL: &str = "<repl>";
+
+// Where a diagnostic points: `file:line`, or just the line for the REPL.
+pub fn location(source: &str, line: u32) -> String {
+    if source == REPL {
+        format!("line {line}")
+    } else {
+        format!("{source}:{line}")
+    }
+}
+
+// Cloning a token is cheap: the lexeme and the source name are shared. The
+// lexeme is interned, and `symbol` is what environments look names up by.
+#[derive(Clone, Debug)]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: Rc<str>,
+    pub symbol: Symbol,
     pub literal: Option<Literal>,
     pub line: u32,
+    // Counted in characters from 1, or 0 where it isn't known, as for the
+    // tokens the parser makes up or ones read back from a .loxc file.
+    pub column: u32,
+    pub source: Rc<str>,
 }
 
 impl Token {
     pub fn new(
         token_type: TokenType,
-        lexeme: String,
+        lexeme: Rc<str>,
         literal: Option<Literal>,
         line: u32,
+        source: Rc<str>,
     ) -> Token {
+        let symbol = Symbol::intern(&lexeme);
         Token {
             token_type,
-            lexeme,
+            lexeme: symbol.name(),
+            symbol,
             literal,
             line,
+            column: 0,
+            source,
         }
     }
+
+    pub fn location(&self) -> String {
+        location(&self.source, self.line)
+    }
 }
 
 impl Display for Token {
@@ -32,14 +62,3 @@ impl Display for Token {
         write!(f, "{} {}", self.token_type, self.lexeme)
     }
 }
-
-impl Clone for Token {
-    fn clone(&self) -> Self {
-        Self {
-            token_type: self.token_type,
-            lexeme: self.lexeme.to_string(),
-            literal: self.literal.clone(),
-            line: self.line,
-        }
-    }
-}
@@ -1,5 +1,6 @@
 use std::fmt::{self, Debug, Display};
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Copy, PartialEq)]
 pub enum TokenType {
     // Single-character tokens.
@@ -7,6 +8,7 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -14,6 +16,7 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
 
     // One or two character tokens.
     Bang,
@@ -24,6 +27,8 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    QuestionQuestion,
+    QuestionDot,
 
     // Literals.
     Identifier,
@@ -35,11 +40,17 @@ pub enum TokenType {
 
     // Keywords.
     And,
+    Break,
+    Case,
     Class,
+    Continue,
     Else,
+    Enum,
     Fun,
     For,
     If,
+    Is,
+    Match,
     Or,
     Print,
     Return,
@@ -59,6 +70,7 @@ impl Debug for TokenType {
             Self::RightParen => write!(f, "RightParen"),
             Self::LeftBrace => write!(f, "LeftBrace"),
             Self::RightBrace => write!(f, "RightBrace"),
+            Self::Colon => write!(f, "Colon"),
             Self::Comma => write!(f, "Comma"),
             Self::Dot => write!(f, "Dot"),
             Self::Minus => write!(f, "Minus"),
@@ -66,6 +78,7 @@ impl Debug for TokenType {
             Self::Semicolon => write!(f, "Semicolon"),
             Self::Slash => write!(f, "Slash"),
             Self::Star => write!(f, "Star"),
+            Self::Percent => write!(f, "Percent"),
             Self::Bang => write!(f, "Bang"),
             Self::BangEqual => write!(f, "BangEqual"),
             Self::Equal => write!(f, "Equal"),
@@ -74,16 +87,24 @@ impl Debug for TokenType {
             Self::GreaterEqual => write!(f, "GreaterEqual"),
             Self::Less => write!(f, "Less"),
             Self::LessEqual => write!(f, "LessEqual"),
+            Self::QuestionQuestion => write!(f, "QuestionQuestion"),
+            Self::QuestionDot => write!(f, "QuestionDot"),
             Self::Identifier => write!(f, "Identifier"),
             Self::Number => write!(f, "Number"),
             Self::String => write!(f, "String"),
             Self::And => write!(f, "And"),
+            Self::Break => write!(f, "Break"),
+            Self::Case => write!(f, "Case"),
             Self::Class => write!(f, "Class"),
+            Self::Continue => write!(f, "Continue"),
             Self::Else => write!(f, "Else"),
+            Self::Enum => write!(f, "Enum"),
             Self::False => write!(f, "False"),
             Self::Fun => write!(f, "Fun"),
             Self::For => write!(f, "For"),
             Self::If => write!(f, "If"),
+            Self::Is => write!(f, "Is"),
+            Self::Match => write!(f, "Match"),
             Self::Nil => write!(f, "Nil"),
             Self::Or => write!(f, "Or"),
             Self::Print => write!(f, "Print"),
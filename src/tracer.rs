@@ -0,0 +1,110 @@
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+use crate::literal::Literal;
+use crate::token::Token;
+use crate::value_printer::ValuePrinter;
+
+// Follows variables by name, in any scope, logging each definition of and
+// assignment to one with the value it had, the value it got, the line and
+// the calls that led there. A lighter way to see how a variable changes than
+// a watchpoint, which stops the program every time.
+#[derive(Default)]
+pub struct Tracer {
+    names: BTreeSet<Rc<str>>,
+    // The functions running, outermost first. Only kept while something is
+    // traced, so untraced programs don't pay for it.
+    calls: Vec<Rc<str>>,
+}
+
+impl Tracer {
+    pub fn trace(&mut self, name: &str) {
+        self.names.insert(name.into());
+    }
+
+    // Stops tracing `name`, if it was.
+    pub fn untrace(&mut self, name: &str) -> bool {
+        self.names.remove(name)
+    }
+
+    pub fn traces(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        !self.names.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(|name| &**name)
+    }
+
+    pub fn enter(&mut self, function: &Token) {
+        self.calls.push(function.lexeme.clone());
+    }
+
+    pub fn leave(&mut self) {
+        self.calls.pop();
+    }
+
+    // One line of the log, as "trace x: 1 -> 2 at main.lox:3 in add <- main
+    // <- <script>", innermost call first. `old` is None for a definition.
+    pub fn describe(
+        &self,
+        name: &Token,
+        old: Option<&Literal>,
+        new: &Literal,
+    ) -> String {
+        let printer = ValuePrinter::brief();
+        let change = match old {
+            Some(old) => {
+                format!("{} -> {}", printer.print(old), printer.print(new))
+            }
+            None => format!("defined as {}", printer.print(new)),
+        };
+        let mut stack: Vec<&str> =
+            self.calls.iter().rev().map(|call| &**call).collect();
+        stack.push("<script>");
+        format!(
+            "trace {}: {change} at {} in {}",
+            name.lexeme,
+            name.location(),
+            stack.join(" <- ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn describes_changes_with_the_calls_leading_there() {
+        let tokens = Scanner::new("fun add() { total = 3; }")
+            .scan_tokens()
+            .clone();
+        let (function, total) = (&tokens[1], &tokens[5]);
+
+        let mut tracer = Tracer::default();
+        tracer.trace("total");
+        assert!(tracer.traces("total") && !tracer.traces("add"));
+        assert_eq!(
+            tracer.describe(total, None, &Literal::Number(1.0)),
+            "trace total: defined as 1 at line 1 in <script>"
+        );
+
+        tracer.enter(function);
+        assert_eq!(
+            tracer.describe(
+                total,
+                Some(&Literal::Number(1.0)),
+                &Literal::String("3".into())
+            ),
+            "trace total: 1 -> \"3\" at line 1 in add <- <script>"
+        );
+        tracer.leave();
+
+        assert!(tracer.untrace("total") && !tracer.is_tracing());
+    }
+}
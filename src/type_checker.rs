@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+use crate::error_reporter::{report_warning, LoxError};
+use crate::expr::{
+    AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr,
+    LiteralExpr, LogicalExpr, MatchExpr, SetExpr, ThisExpr, UnaryExpr,
+    VariableExpr,
+};
+use crate::literal::Literal;
+use crate::stmt::{
+    BlockStmt, BreakStmt, ClassStmt, ContinueStmt, ExpressionStmt, ForStmt,
+    FunctionStmt, IfStmt, PrintStmt, ReturnStmt, Stmt, StmtVisitor, VarStmt,
+    WhileStmt,
+};
+use crate::token::Token;
+use crate::token_type::TokenType;
+
+#[derive(Clone, Debug)]
+pub struct Signature {
+    params: Vec<Type>,
+    ret: Type,
+}
+
+#[derive(Clone, Debug)]
+pub enum Type {
+    Any,
+    Number,
+    String,
+    Bool,
+    Nil,
+    Function(Option<Rc<Signature>>),
+    // A class itself, which is called to make an instance of it.
+    Class(Rc<str>),
+    // An instance of the class with this name.
+    Instance(Rc<str>),
+}
+
+impl Type {
+    // The built-in type `name` names. A class's name is resolved by the
+    // checker, which knows the classes in scope.
+    fn from_annotation(name: &Token) -> Option<Type> {
+        match &*name.lexeme {
+            "Any" => Some(Type::Any),
+            "Number" => Some(Type::Number),
+            "String" => Some(Type::String),
+            "Bool" => Some(Type::Bool),
+            "Nil" => Some(Type::Nil),
+            "Function" => Some(Type::Function(None)),
+            _ => None,
+        }
+    }
+
+    // Any is compatible with everything, which is what keeps the checking
+    // gradual: unannotated code is never reported.
+    fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Class(a), Type::Class(b))
+            | (Type::Instance(a), Type::Instance(b)) => a == b,
+            // A class is called like a function.
+            (Type::Function(_), Type::Class(_)) => true,
+            _ => matches!(
+                (self, other),
+                (Type::Any, _)
+                    | (_, Type::Any)
+                    | (Type::Number, Type::Number)
+                    | (Type::String, Type::String)
+                    | (Type::Bool, Type::Bool)
+                    | (Type::Nil, Type::Nil)
+                    | (Type::Function(_), Type::Function(_))
+            ),
+        }
+    }
+
+    fn is_any(&self) -> bool {
+        matches!(self, Type::Any)
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "Any"),
+            Type::Number => write!(f, "Number"),
+            Type::String => write!(f, "String"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Nil => write!(f, "Nil"),
+            Type::Function(_) => write!(f, "Function"),
+            Type::Class(name) => write!(f, "class {name}"),
+            Type::Instance(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+// Checks the optional type annotations against what can be known statically.
+// Mismatches are warnings, or errors when the checker is strict (--typed).
+pub struct TypeChecker {
+    strict: bool,
+    scopes: Vec<HashMap<Rc<str>, Type>>,
+    return_types: Vec<Type>,
+    first_error: Option<LoxError>,
+}
+
+impl TypeChecker {
+    pub fn new(strict: bool) -> TypeChecker {
+        TypeChecker {
+            strict,
+            scopes: vec![HashMap::new()],
+            return_types: Vec::new(),
+            first_error: None,
+        }
+    }
+
+    pub fn check(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        for statement in statements {
+            statement.accept(self);
+        }
+
+        match self.first_error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        expr.accept(self)
+    }
+
+    fn report(&mut self, token: &Token, message: String) {
+        if self.strict {
+            let error = LoxError::type_error(token.clone(), message);
+            self.first_error.get_or_insert(error);
+        } else {
+            report_warning(token, message);
+        }
+    }
+
+    fn expect(&mut self, token: &Token, expected: &Type, actual: &Type) {
+        if !expected.accepts(actual) {
+            self.report(
+                token,
+                format!("Type mismatch: expected {expected} but got {actual}."),
+            );
+        }
+    }
+
+    fn annotation(&mut self, annotation: &Option<Token>) -> Type {
+        match annotation {
+            None => Type::Any,
+            Some(name) => match Type::from_annotation(name) {
+                Some(t) => t,
+                None => match self.lookup(name) {
+                    Type::Class(class) => Type::Instance(class),
+                    _ => {
+                        self.report(
+                            name,
+                            format!("Unknown type '{}'.", name.lexeme),
+                        );
+                        Type::Any
+                    }
+                },
+            },
+        }
+    }
+
+    fn declare(&mut self, name: &Token, value_type: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), value_type);
+        }
+    }
+
+    fn lookup(&self, name: &Token) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+            .cloned()
+            .unwrap_or(Type::Any)
+    }
+
+    fn check_function(
+        &mut self,
+        function: &FunctionStmt,
+        params: Vec<Type>,
+        ret: Type,
+    ) {
+        self.scopes.push(HashMap::new());
+        for (param, param_type) in function.params.iter().zip(params) {
+            self.declare(param, param_type);
+        }
+        self.return_types.push(ret);
+        self.check_block(&function.body);
+        self.return_types.pop();
+        self.scopes.pop();
+    }
+
+    fn check_block(&mut self, statements: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for statement in statements {
+            statement.accept(self);
+        }
+        self.scopes.pop();
+    }
+}
+
+impl ExprVisitor<Type> for TypeChecker {
+    fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> Type {
+        let left = self.check_expr(&expr.left);
+        let right = self.check_expr(&expr.right);
+
+        match expr.operator.token_type {
+            TokenType::Plus => match (&left, &right) {
+                (Type::Number, Type::Number) => Type::Number,
+                (Type::String, Type::String | Type::Number)
+                | (Type::Number, Type::String) => Type::String,
+                _ if left.is_any() || right.is_any() => Type::Any,
+                _ => {
+                    self.report(
+                        &expr.operator,
+                        format!("Can't add {left} and {right}."),
+                    );
+                    Type::Any
+                }
+            },
+            TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash
+            | TokenType::Percent => {
+                self.expect(&expr.operator, &Type::Number, &left);
+                self.expect(&expr.operator, &Type::Number, &right);
+                Type::Number
+            }
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => {
+                self.expect(&expr.operator, &Type::Number, &left);
+                self.expect(&expr.operator, &Type::Number, &right);
+                Type::Bool
+            }
+            _ => Type::Bool,
+        }
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> Type {
+        self.check_expr(&expr.expression)
+    }
+
+    fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> Type {
+        match &expr.value {
+            Some(Literal::Number(_) | Literal::Integer(_)) => Type::Number,
+            Some(Literal::String(_)) => Type::String,
+            Some(Literal::Bool(_)) => Type::Bool,
+            Some(Literal::Nil) | None => Type::Nil,
+            _ => Type::Any,
+        }
+    }
+
+    fn visit_logical_exp(&mut self, expr: &LogicalExpr) -> Type {
+        self.check_expr(&expr.left);
+        self.check_expr(&expr.right);
+        Type::Any
+    }
+
+    fn visit_match_expr(&mut self, expr: &MatchExpr) -> Type {
+        self.check_expr(&expr.subject);
+        for arm in &expr.arms {
+            if let Some(pattern) = &arm.pattern {
+                self.check_expr(pattern);
+            }
+            self.scopes.push(HashMap::new());
+            if let Some(binding) = &arm.binding {
+                self.declare(binding, Type::Any);
+            }
+            if let Some(guard) = &arm.guard {
+                self.check_expr(guard);
+            }
+            self.check_expr(&arm.value);
+            self.scopes.pop();
+        }
+        Type::Any
+    }
+
+    fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> Type {
+        let right = self.check_expr(&expr.right);
+
+        match expr.operator.token_type {
+            TokenType::Minus => {
+                self.expect(&expr.operator, &Type::Number, &right);
+                Type::Number
+            }
+            _ => Type::Bool,
+        }
+    }
+
+    fn visit_variable_expr(&mut self, expr: &VariableExpr) -> Type {
+        self.lookup(&expr.name)
+    }
+
+    fn visit_assignment_expr(&mut self, expr: &AssignExpr) -> Type {
+        let value = self.check_expr(&expr.value);
+        let declared = self.lookup(&expr.name);
+        self.expect(&expr.name, &declared, &value);
+        value
+    }
+
+    fn visit_call_expr(&mut self, expr: &CallExpr) -> Type {
+        let callee = self.check_expr(&expr.callee);
+        let arguments: Vec<Type> =
+            expr.arguments.iter().map(|a| self.check_expr(a)).collect();
+
+        match callee {
+            Type::Function(Some(signature)) => {
+                for (param, arg) in signature.params.iter().zip(&arguments) {
+                    self.expect(&expr.paren, param, arg);
+                }
+                signature.ret.clone()
+            }
+            Type::Class(name) => Type::Instance(name),
+            Type::Any | Type::Function(None) => Type::Any,
+            other => {
+                self.report(
+                    &expr.paren,
+                    format!("Can't call a value of type {other}."),
+                );
+                Type::Any
+            }
+        }
+    }
+
+    // Fields aren't declared anywhere, so nothing is known about them.
+    fn visit_get_expr(&mut self, expr: &GetExpr) -> Type {
+        self.check_expr(&expr.object);
+        Type::Any
+    }
+
+    fn visit_set_expr(&mut self, expr: &SetExpr) -> Type {
+        self.check_expr(&expr.object);
+        self.check_expr(&expr.value)
+    }
+
+    fn visit_this_expr(&mut self, _expr: &ThisExpr) -> Type {
+        Type::Any
+    }
+}
+
+impl StmtVisitor<()> for TypeChecker {
+    fn visit_expression_stmt(&mut self, stmt: &ExpressionStmt) {
+        self.check_expr(&stmt.expression);
+    }
+
+    fn visit_print_stmt(&mut self, stmt: &PrintStmt) {
+        self.check_expr(&stmt.expression);
+    }
+
+    fn visit_var_stmt(&mut self, stmt: &VarStmt) {
+        let declared = self.annotation(&stmt.type_annotation);
+        if let Some(initializer) = &stmt.initializer {
+            let value = self.check_expr(initializer);
+            self.expect(&stmt.name, &declared, &value);
+        }
+        self.declare(&stmt.name, declared);
+    }
+
+    fn visit_block_stmt(&mut self, stmt: &BlockStmt) {
+        self.check_block(&stmt.statements);
+    }
+
+    fn visit_if_stmt(&mut self, stmt: &IfStmt) {
+        self.check_expr(&stmt.condition);
+        stmt.then_branch.accept(self);
+        if let Some(else_branch) = &stmt.else_branch {
+            else_branch.accept(self);
+        }
+    }
+
+    fn visit_while_stmt(&mut self, stmt: &WhileStmt) {
+        self.check_expr(&stmt.condition);
+        stmt.body.accept(self);
+    }
+
+    fn visit_for_stmt(&mut self, stmt: &ForStmt) {
+        self.scopes.push(HashMap::new());
+        if let Some(initializer) = &stmt.initializer {
+            initializer.accept(self);
+        }
+        if let Some(condition) = &stmt.condition {
+            self.check_expr(condition);
+        }
+        stmt.body.accept(self);
+        if let Some(increment) = &stmt.increment {
+            self.check_expr(increment);
+        }
+        self.scopes.pop();
+    }
+
+    fn visit_function_stmt(&mut self, stmt: &FunctionStmt) {
+        let params: Vec<Type> = stmt
+            .param_types
+            .iter()
+            .map(|t| self.annotation(t))
+            .collect();
+        let ret = self.annotation(&stmt.return_type);
+
+        let signature = Signature {
+            params: params.clone(),
+            ret: ret.clone(),
+        };
+        self.declare(&stmt.name, Type::Function(Some(Rc::new(signature))));
+        self.check_function(stmt, params, ret);
+    }
+
+    fn visit_return_stmt(&mut self, stmt: &ReturnStmt) {
+        let value = match &stmt.value {
+            Some(value) => self.check_expr(value),
+            None => Type::Nil,
+        };
+
+        if let Some(expected) = self.return_types.last().cloned() {
+            self.expect(&stmt.keyword, &expected, &value);
+        }
+    }
+
+    fn visit_break_stmt(&mut self, _stmt: &BreakStmt) {}
+
+    fn visit_continue_stmt(&mut self, _stmt: &ContinueStmt) {}
+
+    // Calling a class makes an instance of it, which annotations name by the
+    // class's name. What the call takes depends on its `init`, and what an
+    // instance holds isn't declared, so neither is checked.
+    fn visit_class_stmt(&mut self, stmt: &ClassStmt) {
+        self.declare(&stmt.name, Type::Class(stmt.name.lexeme.clone()));
+
+        for method in &stmt.methods {
+            let params: Vec<Type> = method
+                .param_types
+                .iter()
+                .map(|t| self.annotation(t))
+                .collect();
+            let ret = self.annotation(&method.return_type);
+            self.check_function(method, params, ret);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let parsed = Parser::new(&tokens).parse();
+        parsed.unwrap_or_else(|_| panic!("test source should parse"))
+    }
+
+    // The first problem --typed reports, with the lexeme it is reported at.
+    fn typed_error(source: &str) -> Option<(String, String)> {
+        match TypeChecker::new(true).check(&parse(source)) {
+            Ok(()) => None,
+            Err(LoxError::TypeError { token, message }) => {
+                Some((token.lexeme.to_string(), message))
+            }
+            Err(_) => panic!("{source} should only have type errors"),
+        }
+    }
+
+    fn mismatch(
+        at: &str,
+        expected: &str,
+        actual: &str,
+    ) -> Option<(String, String)> {
+        Some((
+            at.to_string(),
+            format!("Type mismatch: expected {expected} but got {actual}."),
+        ))
+    }
+
+    #[test]
+    fn reports_assigning_the_wrong_type() {
+        assert_eq!(
+            typed_error("var n: Number = \"one\";"),
+            mismatch("n", "Number", "String")
+        );
+        assert_eq!(
+            typed_error("var n: Number = 1; { n = true; }"),
+            mismatch("n", "Number", "Bool")
+        );
+        assert_eq!(
+            typed_error("var s: String = 1 - 2;"),
+            mismatch("s", "String", "Number")
+        );
+        assert_eq!(typed_error("var n: Number = 1; n = 2 * n;"), None);
+        assert_eq!(typed_error("var s: String = \"n\" + 1;"), None);
+
+        // Unannotated names are Any, which nothing is reported for.
+        assert_eq!(typed_error("var a = 1; a = \"one\";"), None);
+        assert_eq!(typed_error("fun f(x) { var n: Number = x; }"), None);
+    }
+
+    #[test]
+    fn checks_calls_and_returns_against_signatures() {
+        let source = "fun half(n: Number): Number { return n / 2; }";
+        assert_eq!(typed_error(source), None);
+        assert_eq!(
+            typed_error(&format!("{source} half(\"four\");")),
+            mismatch(")", "Number", "String")
+        );
+        assert_eq!(
+            typed_error(&format!("{source} var s: String = half(4);")),
+            mismatch("s", "String", "Number")
+        );
+        assert_eq!(
+            typed_error("fun name(): String { return 1; }"),
+            mismatch("return", "String", "Number")
+        );
+        assert_eq!(
+            typed_error("fun name(): String { return; }"),
+            mismatch("return", "String", "Nil")
+        );
+        assert_eq!(
+            typed_error("var n: Number = 1; n();"),
+            Some((
+                ")".to_string(),
+                "Can't call a value of type Number.".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn class_names_are_the_type_of_their_instances() {
+        let classes = "class Point {} class Line {}";
+        assert_eq!(
+            typed_error(&format!("{classes} var p: Point = Point();")),
+            None
+        );
+        assert_eq!(
+            typed_error(&format!("{classes} var p: Point = Line();")),
+            mismatch("p", "Point", "Line")
+        );
+        assert_eq!(
+            typed_error(&format!("{classes} fun f(p: Point) {{}} f(1);")),
+            mismatch(")", "Point", "Number")
+        );
+        assert_eq!(
+            typed_error(&format!("{classes} var make: Function = Point;")),
+            None
+        );
+        assert_eq!(
+            typed_error("class Point { equals(other: Point): Bool {} }"),
+            None
+        );
+        assert_eq!(
+            typed_error("{ class Local {} } var l: Local;"),
+            Some(("Local".to_string(), "Unknown type 'Local'.".to_string()))
+        );
+    }
+
+    #[test]
+    fn reports_unknown_types() {
+        let unknown = |name: &str| {
+            Some((name.to_string(), format!("Unknown type '{name}'.")))
+        };
+        assert_eq!(typed_error("var n: number = 1;"), unknown("number"));
+        assert_eq!(typed_error("fun f(x: Int) {}"), unknown("Int"));
+        assert_eq!(typed_error("fun f(): Void {}"), unknown("Void"));
+    }
+
+    #[test]
+    fn only_fails_when_typed() {
+        let source = parse("var n: Number = \"one\"; var x: Thing;");
+        assert!(TypeChecker::new(false).check(&source).is_ok());
+        assert!(TypeChecker::new(true).check(&source).is_err());
+    }
+}
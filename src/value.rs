@@ -0,0 +1,227 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+
+use crate::literal::Literal;
+
+// A Lox value handed to Rust code embedding the interpreter. It converts into
+// plain Rust types with TryFrom, so hosts never have to parse printed output:
+//
+//     let total: f64 = lox.eval_expr("price * quantity")?.try_into()?;
+//     let name: Option<String> = lox.eval_expr("user")?.try_into()?;
+#[derive(Clone, Debug)]
+pub struct LoxValue(Literal);
+
+impl LoxValue {
+    pub fn type_name(&self) -> &'static str {
+        self.0.type_name()
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self.0, Literal::Nil | Literal::NilImplicit)
+    }
+
+    pub fn as_literal(&self) -> &Literal {
+        &self.0
+    }
+
+    pub fn into_literal(self) -> Literal {
+        self.0
+    }
+}
+
+impl From<Literal> for LoxValue {
+    fn from(literal: Literal) -> Self {
+        LoxValue(literal)
+    }
+}
+
+impl Display for LoxValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Literal::NilImplicit => write!(f, "nil"),
+            literal => write!(f, "{literal}"),
+        }
+    }
+}
+
+// Returned when a LoxValue holds a different type than the one requested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueTypeError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl Display for ValueTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected a {} but got {}.", self.expected, self.found)
+    }
+}
+
+impl Error for ValueTypeError {}
+
+impl TryFrom<LoxValue> for f64 {
+    type Error = ValueTypeError;
+
+    fn try_from(value: LoxValue) -> Result<Self, Self::Error> {
+        match value.0 {
+            Literal::Number(n) => Ok(n),
+            Literal::Integer(n) => Ok(n as f64),
+            other => Err(ValueTypeError {
+                expected: "number",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<LoxValue> for String {
+    type Error = ValueTypeError;
+
+    fn try_from(value: LoxValue) -> Result<Self, Self::Error> {
+        match value.0 {
+            Literal::String(s) => Ok(s.to_string()),
+            other => Err(ValueTypeError {
+                expected: "string",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<LoxValue> for bool {
+    type Error = ValueTypeError;
+
+    fn try_from(value: LoxValue) -> Result<Self, Self::Error> {
+        match value.0 {
+            Literal::Bool(b) => Ok(b),
+            other => Err(ValueTypeError {
+                expected: "boolean",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
+
+// nil becomes None, anything else has to convert into the inner type.
+macro_rules! impl_try_from_option {
+    ($($t:ty),*) => {
+        $(
+            impl TryFrom<LoxValue> for Option<$t> {
+                type Error = ValueTypeError;
+
+                fn try_from(value: LoxValue) -> Result<Self, Self::Error> {
+                    if value.is_nil() {
+                        Ok(None)
+                    } else {
+                        <$t>::try_from(value).map(Some)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_option!(f64, String, bool);
+
+impl From<f64> for LoxValue {
+    fn from(n: f64) -> Self {
+        LoxValue(Literal::Number(n))
+    }
+}
+
+impl From<i32> for LoxValue {
+    fn from(n: i32) -> Self {
+        LoxValue(Literal::Integer(n.into()))
+    }
+}
+
+impl From<u32> for LoxValue {
+    fn from(n: u32) -> Self {
+        LoxValue(Literal::Integer(n.into()))
+    }
+}
+
+impl From<i64> for LoxValue {
+    fn from(n: i64) -> Self {
+        LoxValue(Literal::Integer(n))
+    }
+}
+
+impl From<bool> for LoxValue {
+    fn from(b: bool) -> Self {
+        LoxValue(Literal::Bool(b))
+    }
+}
+
+impl From<String> for LoxValue {
+    fn from(s: String) -> Self {
+        LoxValue(Literal::String(s.into()))
+    }
+}
+
+impl From<&str> for LoxValue {
+    fn from(s: &str) -> Self {
+        LoxValue(Literal::String(s.into()))
+    }
+}
+
+impl<T: Into<LoxValue>> From<Option<T>> for LoxValue {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(v) => v.into(),
+            None => LoxValue(Literal::Nil),
+        }
+    }
+}
+
+// With the `serde_json` feature, values cross over as serde_json::Value, so
+// any Serialize/Deserialize struct can be passed through serde_json::to_value
+// and serde_json::from_value. Arrays and objects have no Lox equivalent yet.
+#[cfg(feature = "serde_json")]
+impl TryFrom<serde_json::Value> for LoxValue {
+    type Error = ValueTypeError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        use serde_json::Value;
+
+        match value {
+            Value::Null => Ok(LoxValue(Literal::Nil)),
+            Value::Bool(b) => Ok(b.into()),
+            Value::Number(n) => match n.as_i64() {
+                Some(n) => Ok(n.into()),
+                None => Ok(n.as_f64().unwrap_or(f64::NAN).into()),
+            },
+            Value::String(s) => Ok(s.into()),
+            Value::Array(_) => Err(ValueTypeError {
+                expected: "number, string, boolean or nil",
+                found: "array",
+            }),
+            Value::Object(_) => Err(ValueTypeError {
+                expected: "number, string, boolean or nil",
+                found: "object",
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl TryFrom<LoxValue> for serde_json::Value {
+    type Error = ValueTypeError;
+
+    // Non-finite numbers have no JSON form and become null.
+    fn try_from(value: LoxValue) -> Result<Self, Self::Error> {
+        use serde_json::Value;
+
+        match value.0 {
+            Literal::Nil | Literal::NilImplicit => Ok(Value::Null),
+            Literal::Bool(b) => Ok(Value::Bool(b)),
+            Literal::Number(n) => Ok(Value::from(n)),
+            Literal::Integer(n) => Ok(Value::from(n)),
+            Literal::String(s) => Ok(Value::String(s.to_string())),
+            other => Err(ValueTypeError {
+                expected: "number, string, boolean or nil",
+                found: other.type_name(),
+            }),
+        }
+    }
+}
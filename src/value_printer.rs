@@ -0,0 +1,180 @@
+use crate::literal::Literal;
+
+// Turns a value into text, with lists, maps and instances shown with their
+// contents. How deep and how long that goes is configurable: anything nested
+// deeper than `max_depth` is elided as [...], {...} or Point {...}, and
+// containers stop after `max_length` elements with a trailing "...". A list
+// or map that contains itself is elided the same way where it repeats, so
+// cycles print instead of overflowing the stack.
+#[derive(Clone, Copy, Debug)]
+pub struct ValuePrinter {
+    max_depth: usize,
+    max_length: usize,
+    // Whether instances list their fields, as Point { x: 1 }, rather than
+    // showing as <Point instance>.
+    show_fields: bool,
+    // Whether a string is quoted even when it isn't inside a container.
+    quote_strings: bool,
+}
+
+impl Default for ValuePrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValuePrinter {
+    // The REPL's echo: strings quoted and instances with their fields.
+    pub fn new() -> ValuePrinter {
+        ValuePrinter {
+            max_depth: 4,
+            max_length: 100,
+            show_fields: true,
+            quote_strings: true,
+        }
+    }
+
+    // How print and string concatenation show a value: everything in full,
+    // with only cycles cut short.
+    pub fn display() -> ValuePrinter {
+        ValuePrinter {
+            max_depth: usize::MAX,
+            max_length: usize::MAX,
+            show_fields: false,
+            quote_strings: false,
+        }
+    }
+
+    // Short enough to quote a value inside an error message.
+    pub fn brief() -> ValuePrinter {
+        ValuePrinter {
+            max_depth: 1,
+            max_length: 3,
+            show_fields: false,
+            quote_strings: true,
+        }
+    }
+
+    pub fn with_max_depth(self, max_depth: usize) -> ValuePrinter {
+        ValuePrinter { max_depth, ..self }
+    }
+
+    pub fn with_max_length(self, max_length: usize) -> ValuePrinter {
+        ValuePrinter { max_length, ..self }
+    }
+
+    pub fn print(&self, value: &Literal) -> String {
+        let mut out = String::new();
+        match value {
+            Literal::String(s) if !self.quote_strings => out.push_str(s),
+            value => self.write(&mut out, value, &mut Vec::new()),
+        }
+        out
+    }
+
+    // `path` holds the containers being printed around `value`, outermost
+    // first, whose length is also the depth.
+    fn write(&self, out: &mut String, value: &Literal, path: &mut Vec<usize>) {
+        let address = match value {
+            Literal::List(items) => items.as_ptr() as usize,
+            Literal::Map(entries) => entries.as_ptr() as usize,
+            Literal::Instance(instance) => &**instance as *const _ as usize,
+            _ => 0,
+        };
+        let elided = path.len() >= self.max_depth || path.contains(&address);
+
+        match value {
+            Literal::String(s) => {
+                out.push('"');
+                out.push_str(s);
+                out.push('"');
+            }
+            Literal::List(_) if elided => out.push_str("[...]"),
+            Literal::List(items) => {
+                path.push(address);
+                out.push('[');
+                let items = items.borrow();
+                self.write_elements(out, items.iter(), |out, item| {
+                    self.write(out, item, path);
+                });
+                out.push(']');
+                path.pop();
+            }
+            Literal::Map(_) if elided => out.push_str("{...}"),
+            Literal::Map(entries) => {
+                path.push(address);
+                out.push('{');
+                let entries = entries.borrow();
+                self.write_elements(
+                    out,
+                    entries.iter(),
+                    |out, (key, value)| {
+                        self.write(out, key, path);
+                        out.push_str(": ");
+                        self.write(out, value, path);
+                    },
+                );
+                out.push('}');
+                path.pop();
+            }
+            Literal::Instance(instance) if self.show_fields => {
+                out.push_str(instance.class().name());
+                let fields = instance.fields();
+                if fields.is_empty() {
+                    out.push_str(" {}");
+                } else if elided {
+                    out.push_str(" {...}");
+                } else {
+                    path.push(address);
+                    out.push_str(" { ");
+                    self.write_elements(
+                        out,
+                        fields.iter(),
+                        |out, (name, value)| {
+                            out.push_str(name);
+                            out.push_str(": ");
+                            self.write(out, value, path);
+                        },
+                    );
+                    out.push_str(" }");
+                    path.pop();
+                }
+            }
+            Literal::NilImplicit => out.push_str("nil"),
+            other => out.push_str(&other.to_string()),
+        }
+    }
+
+    // Writes up to max_length elements separated by commas, and "..." in
+    // place of the rest.
+    fn write_elements<T>(
+        &self,
+        out: &mut String,
+        elements: impl Iterator<Item = T>,
+        mut write: impl FnMut(&mut String, T),
+    ) {
+        for (i, element) in elements.enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            if i == self.max_length {
+                out.push_str("...");
+                break;
+            }
+            write(out, element);
+        }
+    }
+}
+
+// A value's type followed by the value itself, briefly, for error messages:
+// string ("hi"), list ([1, 2, 3, ...]). Nil is just nil.
+pub fn describe(value: &Literal) -> String {
+    match value {
+        Literal::Nil | Literal::NilImplicit => "nil".to_string(),
+        value => format!(
+            "{} ({})",
+            value.type_name(),
+            ValuePrinter::brief().print(value)
+        ),
+    }
+}
@@ -0,0 +1,196 @@
+use crate::expr::Expr;
+use crate::stmt::{FunctionStmt, Stmt};
+
+// A pass over the syntax tree that only looks at some kinds of node. By
+// default every statement and expression is walked into, so an
+// implementation overrides `stmt` or `expr`, handles the variants it cares
+// about, and calls walk_stmt or walk_expr to carry on into the children:
+//
+//     fn expr(&mut self, expr: &Expr) {
+//         if let Expr::Call(_) = expr {
+//             self.calls += 1;
+//         }
+//         walk_expr(self, expr);
+//     }
+//
+// Passes that need something back from every node, like the interpreter or
+// the type checker, implement ExprVisitor and StmtVisitor instead.
+pub trait Walk {
+    fn stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+}
+
+pub fn walk_stmts<W: Walk + ?Sized>(walker: &mut W, statements: &[Stmt]) {
+    for statement in statements {
+        walker.stmt(statement);
+    }
+}
+
+// Walks the statements and expressions directly inside `stmt`, in the order
+// they appear in the source.
+pub fn walk_stmt<W: Walk + ?Sized>(walker: &mut W, stmt: &Stmt) {
+    match stmt {
+        Stmt::Block(bs) => walk_stmts(walker, &bs.statements),
+        Stmt::Break(_) | Stmt::Continue(_) => {}
+        Stmt::Class(cs) => {
+            for method in &cs.methods {
+                walk_function(walker, method);
+            }
+        }
+        Stmt::Expression(es) => walker.expr(&es.expression),
+        Stmt::For(fs) => {
+            if let Some(initializer) = &fs.initializer {
+                walker.stmt(initializer);
+            }
+            if let Some(condition) = &fs.condition {
+                walker.expr(condition);
+            }
+            if let Some(increment) = &fs.increment {
+                walker.expr(increment);
+            }
+            walker.stmt(&fs.body);
+        }
+        Stmt::Function(fs) => walk_function(walker, fs),
+        Stmt::If(is) => {
+            walker.expr(&is.condition);
+            walker.stmt(&is.then_branch);
+            if let Some(else_branch) = &is.else_branch {
+                walker.stmt(else_branch);
+            }
+        }
+        Stmt::Print(ps) => walker.expr(&ps.expression),
+        Stmt::Return(rs) => {
+            if let Some(value) = &rs.value {
+                walker.expr(value);
+            }
+        }
+        Stmt::Var(vs) => {
+            if let Some(initializer) = &vs.initializer {
+                walker.expr(initializer);
+            }
+        }
+        Stmt::While(ws) => {
+            walker.expr(&ws.condition);
+            walker.stmt(&ws.body);
+        }
+    }
+}
+
+// A method isn't a statement of its own, so this is how a pass gets into a
+// class's method bodies as well as a function's.
+pub fn walk_function<W: Walk + ?Sized>(
+    walker: &mut W,
+    function: &FunctionStmt,
+) {
+    walk_stmts(walker, &function.body);
+}
+
+// Walks the expressions directly inside `expr`, in the order they appear
+// in the source.
+pub fn walk_expr<W: Walk + ?Sized>(walker: &mut W, expr: &Expr) {
+    match expr {
+        Expr::Assign(ae) => walker.expr(&ae.value),
+        Expr::Binary(be) => {
+            walker.expr(&be.left);
+            walker.expr(&be.right);
+        }
+        Expr::Call(ce) => {
+            walker.expr(&ce.callee);
+            for argument in &ce.arguments {
+                walker.expr(argument);
+            }
+        }
+        Expr::Get(ge) => walker.expr(&ge.object),
+        Expr::Grouping(ge) => walker.expr(&ge.expression),
+        Expr::Literal(_) | Expr::This(_) | Expr::Variable(_) => {}
+        Expr::Logical(le) => {
+            walker.expr(&le.left);
+            walker.expr(&le.right);
+        }
+        Expr::Match(me) => {
+            walker.expr(&me.subject);
+            for arm in &me.arms {
+                arm.pattern.iter().for_each(|p| walker.expr(p));
+                arm.guard.iter().for_each(|g| walker.expr(g));
+                walker.expr(&arm.value);
+            }
+        }
+        Expr::Set(se) => {
+            walker.expr(&se.object);
+            walker.expr(&se.value);
+        }
+        Expr::Unary(ue) => walker.expr(&ue.right),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source).scan_tokens().clone();
+        let parsed = Parser::new(&tokens).parse();
+        parsed.unwrap_or_else(|_| panic!("test source should parse"))
+    }
+
+    // Only cares about variables, and leaves the rest to the defaults.
+    #[derive(Default)]
+    struct Variables(Vec<String>);
+
+    impl Walk for Variables {
+        fn expr(&mut self, expr: &Expr) {
+            if let Expr::Variable(ve) = expr {
+                self.0.push(ve.name.lexeme.to_string());
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn reaches_every_expression() {
+        let statements = parse(
+            "var a = b + -c;
+            fun f(x) { return g(x, h.i); }
+            class C { m() { print this.j = k ?? l; } }
+            for (var i = m; n; o) { if (p) while (q) r; }",
+        );
+        let mut variables = Variables::default();
+        walk_stmts(&mut variables, &statements);
+        assert_eq!(
+            variables.0,
+            ["b", "c", "g", "x", "h", "k", "l", "m", "n", "o", "p", "q", "r"]
+        );
+    }
+
+    // Counts the returns outside classes, never walking into one.
+    #[derive(Default)]
+    struct Returns(usize);
+
+    impl Walk for Returns {
+        fn stmt(&mut self, stmt: &Stmt) {
+            match stmt {
+                Stmt::Return(_) => self.0 += 1,
+                Stmt::Class(_) => {}
+                stmt => walk_stmt(self, stmt),
+            }
+        }
+    }
+
+    #[test]
+    fn overriding_a_node_can_stop_the_walk() {
+        let statements = parse(
+            "fun f() { if (true) return 1; { return 2; } }
+            class C { m() { return 3; } }",
+        );
+        let mut returns = Returns::default();
+        walk_stmts(&mut returns, &statements);
+        assert_eq!(returns.0, 2);
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// How often `rlox run --watch` looks at the files again. There is no file
+// system notification without a dependency, so it polls their modification
+// times instead.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// Notices when any of a set of files is modified.
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Watcher {
+    pub fn new<P: AsRef<Path>>(paths: &[P]) -> Watcher {
+        Watcher {
+            files: paths
+                .iter()
+                .map(|path| {
+                    let path = path.as_ref().to_path_buf();
+                    let modified = modified(&path);
+                    (path, modified)
+                })
+                .collect(),
+        }
+    }
+
+    // The files modified since the last call, or since the watcher was made.
+    // A file that can't be read, as while an editor is replacing it, counts
+    // as unchanged until it is back.
+    pub fn changed(&mut self) -> Vec<&Path> {
+        let mut changed = Vec::new();
+        for (path, last) in &mut self.files {
+            if let Some(now) = modified(path) {
+                if *last != Some(now) {
+                    *last = Some(now);
+                    changed.push(path.as_path());
+                }
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+
+    #[test]
+    fn notices_modified_files() {
+        let dir = env::temp_dir();
+        let watched =
+            dir.join(format!("rlox-watch-{}.lox", std::process::id()));
+        let missing =
+            dir.join(format!("rlox-watch-{}-missing.lox", std::process::id()));
+        fs::write(&watched, "print 1;")
+            .expect("the temp dir should be writable");
+
+        let mut watcher = Watcher::new(&[&watched, &missing]);
+        assert!(watcher.changed().is_empty());
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        let file = File::options().write(true).open(&watched);
+        assert!(file.and_then(|f| f.set_modified(later)).is_ok());
+        assert_eq!(watcher.changed(), [watched.as_path()]);
+        assert!(watcher.changed().is_empty());
+
+        fs::write(&missing, "print 2;")
+            .expect("the temp dir should be writable");
+        assert_eq!(watcher.changed(), [missing.as_path()]);
+
+        let _ = fs::remove_file(&watched);
+        let _ = fs::remove_file(&missing);
+        assert!(watcher.changed().is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+// `rlox check` reports what is wrong with each file without running any of
+// them.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn write(name: &str, source: &str) -> PathBuf {
+    let path = env::temp_dir()
+        .join(format!("rlox-check-{}-{name}.lox", std::process::id()));
+    fs::write(&path, source).expect("the temp dir should be writable");
+    path
+}
+
+fn check(paths: &[&PathBuf]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg("check")
+        .args(paths)
+        .output()
+        .expect("rlox should run");
+    for path in paths {
+        let _ = fs::remove_file(path);
+    }
+    output
+}
+
+#[test]
+fn passes_valid_files_without_running_them() {
+    let first = write("valid-first", "print \"ran\"; fun f() { return 1; }");
+    let second = write("valid-second", "sys.exit(3);");
+    let output = check(&[&first, &second]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn reports_the_problems_in_every_file() {
+    let parse = write("parse", "print 1\nprint 2;\nvar = 3;");
+    let resolve = write("resolve", "return 1;");
+    let valid = write("fine", "print 1;");
+    let output = check(&[&parse, &resolve, &valid]);
+
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let errors: Vec<&str> = stderr.lines().collect();
+    assert_eq!(errors.len(), 3, "{stderr}");
+    assert!(errors[0].starts_with(&format!("[{}:2]", parse.display())));
+    assert!(errors[1].starts_with(&format!("[{}:3]", parse.display())));
+    assert!(errors[2].starts_with(&format!("[{}:1]", resolve.display())));
+}
+
+#[test]
+fn warns_about_assignments_used_as_conditions() {
+    let typo = write("typo", "var a; var b;\nif (a = b) print a;");
+    let output = check(&[&typo]);
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr.trim_end(),
+        format!(
+            "[{}:2] Warning at 'a': Assignment used as a condition, did you \
+             mean '=='? Wrap it in parentheses to silence this warning.",
+            typo.display()
+        )
+    );
+
+    let intended = write(
+        "intended",
+        "var a; var b;\nif ((a = b)) print a;\nwhile ((a = nil)) {}",
+    );
+    let output = check(&[&intended]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+}
@@ -0,0 +1,367 @@
+// Runs the test suite from craftinginterpreters against the rlox binary and
+// reports how much of it passes. The suite isn't part of this repository:
+// copy or clone its test/ directory to tests/fixtures/craftinginterpreters,
+// or point LOX_TEST_DIR at it. Without it this harness does nothing, so
+// `cargo test` keeps passing on a fresh checkout.
+//
+//     cargo test --test conformance
+//         the whole suite, remembering the pass rate
+//     cargo test --test conformance -- closure string/multiline
+//         only the tests whose path contains one of the filters
+//     cargo test --test conformance -- --verbose
+//         also show how each failing test differed
+//
+// Each test annotates what it expects with comments, the same ones the
+// suite's own runner reads:
+//
+//     print a; // expect: 1
+//     a(); // expect runtime error: Undefined variable 'a'.
+//     var 1; // Error at '1': Expect variable name.
+//     // [line 3] Error: Unexpected character.
+//
+// Output goes to stdout line by line. A compile error goes to stderr and
+// exits with 65, a runtime error prints its message and [line n] to stderr
+// and exits with 70.
+
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Directories of the suite that jlox's runner skips too: benchmarks, the
+// chapters before statements existed, and clox's limits.
+const SKIPPED: &[&str] = &["benchmark", "expressions", "limit", "scanning"];
+
+// A test still running after this long is counted as failing.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Options {
+    filter: Vec<String>,
+    verbose: bool,
+}
+
+#[derive(Default)]
+struct Expectation {
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+    exit_code: i32,
+}
+
+struct Outcome {
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+    exit_code: Option<i32>,
+}
+
+fn main() {
+    let options = parse_options();
+
+    let root = match env::var_os("LOX_TEST_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join("craftinginterpreters"),
+    };
+    if !root.is_dir() {
+        println!(
+            "conformance: skipped, no test suite at {} (see tests/conformance.rs)",
+            root.display()
+        );
+        return;
+    }
+
+    let mut paths = Vec::new();
+    collect(&root, &mut paths);
+    paths.sort();
+
+    let mut passed = 0;
+    let mut total = 0;
+    for path in &paths {
+        let name = path
+            .strip_prefix(&root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        if SKIPPED
+            .iter()
+            .any(|dir| name.starts_with(&format!("{dir}/")))
+        {
+            continue;
+        }
+        if !options.filter.is_empty()
+            && !options.filter.iter().any(|f| name.contains(f.as_str()))
+        {
+            continue;
+        }
+
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Unable to read {}: {e}", path.display());
+                process::exit(66);
+            }
+        };
+
+        total += 1;
+        let expected = expectation(&source);
+        let outcome = run(path);
+        let failures = compare(&expected, &outcome);
+        if failures.is_empty() {
+            passed += 1;
+        } else {
+            println!("FAIL {name}");
+            if options.verbose {
+                for failure in failures {
+                    println!("     {failure}");
+                }
+            }
+        }
+    }
+
+    if total == 0 {
+        println!("conformance: no tests matched");
+        return;
+    }
+
+    let rate = passed as f64 * 100.0 / total as f64;
+    let change = match (options.filter.is_empty(), last_rate()) {
+        (true, Some(last)) => format!(" ({:+.1} since last run)", rate - last),
+        _ => String::new(),
+    };
+    println!("conformance: {passed}/{total} passed, {rate:.1}%{change}");
+
+    // Only a run of the whole suite is comparable with the last one.
+    if options.filter.is_empty() {
+        record(passed, total, rate);
+    }
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        filter: Vec::new(),
+        verbose: false,
+    };
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--verbose" => options.verbose = true,
+            // Whatever else cargo test passes along is meant for libtest.
+            _ if arg.starts_with('-') => {}
+            _ => options.filter.push(arg),
+        }
+    }
+
+    options
+}
+
+fn collect(dir: &Path, paths: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Unable to read {}: {e}", dir.display());
+            process::exit(66);
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect(&path, paths);
+        } else if path.extension().is_some_and(|e| e == "lox") {
+            paths.push(path);
+        }
+    }
+}
+
+// Reads the annotations in a test's source. Expectations meant only for
+// clox, written [c line n], don't apply to a tree-walker.
+fn expectation(source: &str) -> Expectation {
+    let mut expected = Expectation::default();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let Some((_, comment)) = line.split_once("// ") else {
+            continue;
+        };
+
+        if let Some(output) = comment.strip_prefix("expect: ") {
+            expected.stdout.push(output.to_string());
+        } else if let Some(message) =
+            comment.strip_prefix("expect runtime error: ")
+        {
+            expected.stderr.push(message.to_string());
+            expected.stderr.push(format!("[line {line_number}]"));
+            expected.exit_code = 70;
+        } else if comment.starts_with("Error") {
+            expected
+                .stderr
+                .push(format!("[line {line_number}] {comment}"));
+            expected.exit_code = 65;
+        } else if let Some(error) = comment.strip_prefix("[java line ") {
+            expected.stderr.push(format!("[line {error}"));
+            expected.exit_code = 65;
+        } else if comment.starts_with("[line ") {
+            expected.stderr.push(comment.to_string());
+            expected.exit_code = 65;
+        }
+    }
+
+    expected
+}
+
+fn run(path: &Path) -> Outcome {
+    let child = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Unable to run rlox: {e}");
+            process::exit(70);
+        }
+    };
+
+    // Read both pipes while waiting, so a test that prints a lot can't
+    // block on a full pipe.
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let exit_code = wait(&mut child);
+
+    // Locations name the script, where the suite's runner expects only the
+    // line number. The suite knows nothing of rlox's warnings.
+    let prefix = format!("[{}:", path.display());
+    let lines = |output: String| -> Vec<String> {
+        output
+            .lines()
+            .filter(|line| !line.contains("] Warning"))
+            .map(|line| line.replace(&prefix, "[line ").trim_end().to_string())
+            .collect()
+    };
+
+    Outcome {
+        stdout: lines(stdout.join().unwrap_or_default()),
+        stderr: lines(stderr.join().unwrap_or_default()),
+        exit_code,
+    }
+}
+
+fn read_in_background(
+    pipe: Option<impl Read + Send + 'static>,
+) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_string(&mut output);
+        }
+        output
+    })
+}
+
+// The exit code, or None if the test timed out or was killed by a signal.
+fn wait(child: &mut Child) -> Option<i32> {
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return status.code(),
+            Ok(None) if start.elapsed() < TIMEOUT => {
+                thread::sleep(Duration::from_millis(5));
+            }
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+}
+
+// How the outcome differs from what was expected, if at all.
+fn compare(expected: &Expectation, outcome: &Outcome) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    match outcome.exit_code {
+        Some(code) if code == expected.exit_code => {}
+        Some(code) => failures.push(format!(
+            "expected exit code {}, got {code}",
+            expected.exit_code
+        )),
+        None => failures.push(format!("timed out after {TIMEOUT:?}")),
+    }
+
+    compare_lines("stdout", &expected.stdout, &outcome.stdout, &mut failures);
+    // Only the first error matters for a compile error or runtime error:
+    // the suite doesn't annotate every error that follows from the first.
+    let stderr =
+        &outcome.stderr[..expected.stderr.len().min(outcome.stderr.len())];
+    if expected.stderr.is_empty() {
+        compare_lines("stderr", &[], &outcome.stderr, &mut failures);
+    } else {
+        compare_lines("stderr", &expected.stderr, stderr, &mut failures);
+    }
+
+    failures
+}
+
+fn compare_lines(
+    stream: &str,
+    expected: &[String],
+    actual: &[String],
+    failures: &mut Vec<String>,
+) {
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => continue,
+            (Some(e), Some(a)) => failures.push(format!(
+                "{stream} line {}: expected {e:?}, got {a:?}",
+                i + 1
+            )),
+            (Some(e), None) => {
+                failures.push(format!("{stream}: missing {e:?}"));
+            }
+            (None, Some(a)) => {
+                failures.push(format!("{stream}: unexpected {a:?}"));
+            }
+            (None, None) => unreachable!(),
+        }
+        // The first difference is enough to tell what went wrong.
+        return;
+    }
+}
+
+// The history is kept under target/, one `seconds passed total rate` line
+// per run of the whole suite.
+fn history_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("rlox-conformance")
+        .join("history.txt")
+}
+
+fn last_rate() -> Option<f64> {
+    let history = fs::read_to_string(history_path()).ok()?;
+    history.lines().last()?.split(' ').nth(3)?.parse().ok()
+}
+
+fn record(passed: usize, total: usize, rate: f64) {
+    let path = history_path();
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut history = fs::read_to_string(&path).unwrap_or_default();
+    history.push_str(&format!("{seconds} {passed} {total} {rate:.1}\n"));
+
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, history));
+    if let Err(e) = written {
+        eprintln!("Unable to write {}: {e}", path.display());
+    }
+}
@@ -0,0 +1,252 @@
+// Runs scripts that fail at runtime through the rlox binary, checking the
+// message, the [file:line] after it, and that the process exits with 70
+// rather than panicking.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+struct Outcome {
+    stdout: String,
+    // Only what follows the type checker's warnings: the error message and
+    // its location.
+    error: Vec<String>,
+    exit_code: Option<i32>,
+}
+
+fn run(name: &str, source: &str) -> Outcome {
+    let path = env::temp_dir().join(format!(
+        "rlox-runtime-errors-{}-{name}.lox",
+        std::process::id()
+    ));
+    fs::write(&path, source).expect("the temp dir should be writable");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg(&path)
+        .output()
+        .expect("rlox should run");
+    let _ = fs::remove_file(&path);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Outcome {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        error: stderr
+            .lines()
+            .filter(|line| !line.contains("] Warning"))
+            .map(|line| line.trim_end().replace(&location(&path), "[line "))
+            .collect(),
+        exit_code: output.status.code(),
+    }
+}
+
+fn location(path: &Path) -> String {
+    format!("[{}:", path.display())
+}
+
+fn assert_runtime_error(name: &str, source: &str, message: &str, line: u32) {
+    let outcome = run(name, source);
+    assert_eq!(outcome.exit_code, Some(70), "{source}");
+    assert_eq!(
+        outcome.error,
+        [message.to_string(), format!("[line {line}]")],
+        "{source}"
+    );
+}
+
+#[test]
+fn bad_operand_types() {
+    let cases = [
+        (
+            "negate",
+            "print -\"a\";",
+            "Operand must be a number, got string (\"a\").",
+        ),
+        (
+            "compare",
+            "print 1 < \"a\";",
+            "Operands must be numbers, got number (1) and string (\"a\").",
+        ),
+        (
+            "subtract",
+            "print \"a\" - 1;",
+            "Operands must be numbers, got string (\"a\") and number (1).",
+        ),
+        (
+            "add",
+            "print true + nil;",
+            "Operands must be two numbers or two strings, got boolean (true) and nil.",
+        ),
+    ];
+    for (name, source, message) in cases {
+        assert_runtime_error(name, source, message, 1);
+    }
+}
+
+#[test]
+fn undefined_variables() {
+    assert_runtime_error(
+        "read",
+        "print undefinedName;",
+        "Undefined variable 'undefinedName'.",
+        1,
+    );
+    assert_runtime_error(
+        "assign",
+        "var count = 0;\nconut = 1;",
+        "Undefined variable 'conut'. Did you mean 'count'?",
+        2,
+    );
+}
+
+#[test]
+fn calling_what_is_not_callable() {
+    let cases = [
+        ("string", "\"str\"();"),
+        ("number", "var n = 1;\nn();"),
+        ("nil", "nil();"),
+    ];
+    for (name, source) in cases {
+        let line = source.lines().count() as u32;
+        assert_runtime_error(
+            name,
+            source,
+            "Can only call functions and classes.",
+            line,
+        );
+    }
+}
+
+#[test]
+fn wrong_arity() {
+    let cases = [
+        (
+            "function",
+            "fun f(a) {}\nf(1, 2);",
+            "Expected 1 arguments but got 2.",
+        ),
+        (
+            "class",
+            "class A {}\nA(1);",
+            "Expected 0 arguments but got 1.",
+        ),
+        (
+            "initializer",
+            "class A { init(a) {} }\nA();",
+            "Expected 1 arguments but got 0.",
+        ),
+        ("native", "\nclock(1);", "Expected 0 arguments but got 1."),
+    ];
+    for (name, source, message) in cases {
+        assert_runtime_error(name, source, message, 2);
+    }
+}
+
+#[test]
+fn bad_indexes_and_keys() {
+    let cases = [
+        (
+            "at",
+            "var items = list(1, 2);\nprint at(items, 2);",
+            "Index 2 is out of bounds for a list of length 2.",
+        ),
+        (
+            "listSet",
+            "var items = list();\nlistSet(items, -1, 0);",
+            "Index -1 is out of bounds for a list of length 0.",
+        ),
+        (
+            "mapSet",
+            "var entries = mapNew();\nmapSet(entries, 0 / 0, 1);",
+            "mapSet() can't use NaN as a key.",
+        ),
+    ];
+    for (name, source, message) in cases {
+        assert_runtime_error(name, source, message, 2);
+    }
+}
+
+#[test]
+fn bad_native_arguments() {
+    let cases = [
+        (
+            "type",
+            "var flag = true;\nprint len(flag);",
+            "len() expects a string, list, map or range but got boolean (true).",
+        ),
+        (
+            "template",
+            "var values = mapNew();\nprint str.template(\"{{a}}\", values);",
+            "template() has no value for 'a'.",
+        ),
+        (
+            "callback",
+            "fun check(a) { print a; }\ntest(\"check\", check);",
+            "test() callbacks must take no arguments.",
+        ),
+    ];
+    for (name, source, message) in cases {
+        assert_runtime_error(name, source, message, 2);
+    }
+}
+
+#[test]
+fn properties_of_non_instances() {
+    assert_runtime_error(
+        "get",
+        "print 1.x;",
+        "Only instances and namespaces have properties.",
+        1,
+    );
+    assert_runtime_error("set", "1.x = 2;", "Only instances have fields.", 1);
+    assert_runtime_error(
+        "undefined",
+        "class A {}\nprint A().missing;",
+        "Undefined property 'missing'.",
+        2,
+    );
+}
+
+#[test]
+fn failed_assertions_show_the_condition() {
+    assert_runtime_error(
+        "values",
+        "var limit = 3;\nfun check(x) { assert(x > limit and len(\"ab\") == 2); }\ncheck(1);",
+        "Assertion failed: x > limit and len(\"ab\") == 2 (x=1, limit=3).",
+        2,
+    );
+    assert_runtime_error(
+        "message",
+        "var name = \"bob\";\nassert(name == \"ann\" or name == \"cy\", \"unknown\");",
+        "Assertion failed: name == \"ann\" or name == \"cy\" (name=\"bob\"): unknown",
+        2,
+    );
+    assert_runtime_error(
+        "literal",
+        "assert(nil);",
+        "Assertion failed: nil.",
+        1,
+    );
+
+    let outcome = run("passes", "assert(true); assert(1, \"no\"); print 1;");
+    assert_eq!(outcome.exit_code, Some(0));
+    assert_eq!(outcome.stdout, "1\n");
+}
+
+#[test]
+fn stack_overflow() {
+    assert_runtime_error(
+        "recursion",
+        "fun r() { r(); }\nr();",
+        "Stack overflow.",
+        1,
+    );
+}
+
+#[test]
+fn an_error_stops_the_script_after_what_ran_before_it() {
+    let outcome = run("stops", "print 1;\nprint nil - 1;\nprint 2;");
+    assert_eq!(outcome.exit_code, Some(70));
+    assert_eq!(outcome.stdout, "1\n");
+    assert_eq!(outcome.error.last().map(String::as_str), Some("[line 2]"));
+}
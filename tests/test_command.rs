@@ -0,0 +1,167 @@
+// `rlox test` finds the *_test.lox files under the paths it is given, runs
+// each in its own Lox, several at once, and sums up what their test() calls came to.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn test_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir()
+        .join(format!("rlox-test-command-{}-{name}", std::process::id()));
+    for (file, source) in files {
+        let path = dir.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .expect("the temp dir should be writable");
+        }
+        fs::write(&path, source).expect("the temp dir should be writable");
+    }
+    dir
+}
+
+fn rlox_test(dir: &Path) -> Output {
+    rlox_test_with(&[], dir)
+}
+
+fn rlox_test_with(options: &[&str], dir: &Path) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg("test")
+        .args(options)
+        .arg(dir)
+        .output()
+        .expect("rlox should run");
+    let _ = fs::remove_dir_all(dir);
+    output
+}
+
+#[test]
+fn passes_when_every_test_does() {
+    let dir = test_dir(
+        "passing",
+        &[
+            (
+                "list_test.lox",
+                "print \"hidden\";
+                fun sorts() { expectEq(sort(list(2, 1)), list(1, 2)); }
+                test(\"sorts\", sorts);",
+            ),
+            ("helper.lox", "sys.exit(3);"),
+        ],
+    );
+    let output = rlox_test(&dir);
+
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("hidden"), "{stdout}");
+    assert!(stdout.contains("list_test.lox (1 passed)"), "{stdout}");
+    assert!(
+        stdout.ends_with("1 tests: 1 passed, 0 failed.\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn shows_the_output_of_failing_files() {
+    let dir = test_dir(
+        "failing",
+        &[
+            ("a_test.lox", "fun fine() {} test(\"fine\", fine);"),
+            (
+                "nested/b_test.lox",
+                "fun wrong() { expectEq(1 + 1, 3); }
+                test(\"wrong\", wrong);
+                fun after() {} test(\"after\", after);",
+            ),
+        ],
+    );
+    let output = rlox_test(&dir);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].starts_with("PASS"), "{stdout}");
+    assert!(lines[1].starts_with("FAIL"), "{stdout}");
+    assert!(lines[1].ends_with("b_test.lox (1 passed, 1 failed)"));
+    assert!(stdout.contains("    Expected 3 but got 2."), "{stdout}");
+    assert!(stdout.contains("    Test 'wrong' failed."), "{stdout}");
+    assert!(
+        stdout.ends_with(
+            "2 files: 1 passed, 1 failed. 3 tests: 2 passed, 1 failed.\n"
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn reports_files_in_order_however_they_finish() {
+    // The first file takes longest, so the others finish before it.
+    let slow = "var n = 0; while (n < 200000) n = n + 1;
+        fun counts() { expectEq(n, 200000); } test(\"counts\", counts);";
+    let quick = "fun fine() {} test(\"fine\", fine);";
+    let dir = test_dir(
+        "parallel",
+        &[
+            ("a_test.lox", slow),
+            ("b_test.lox", quick),
+            (
+                "c_test.lox",
+                "fun wrong() { expectEq(1, 2); } test(\"wrong\", wrong);",
+            ),
+            ("d_test.lox", quick),
+        ],
+    );
+    let output = rlox_test_with(&["--jobs", "4"], &dir);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let positions: Vec<Option<usize>> = ["a", "b", "c", "d"]
+        .iter()
+        .map(|name| stdout.find(&format!("{name}_test.lox (")))
+        .collect();
+    assert!(positions.iter().all(Option::is_some), "{stdout}");
+    assert!(positions.is_sorted(), "{stdout}");
+    assert!(
+        stdout.ends_with(
+            "4 files: 3 passed, 1 failed. 4 tests: 3 passed, 1 failed.\n"
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn exit_ends_only_the_file_that_calls_it() {
+    let dir = test_dir(
+        "exit",
+        &[
+            (
+                "a_test.lox",
+                "var shared = 1;
+                fun fine() {} test(\"fine\", fine);
+                print \"leaving\";
+                sys.exit(4);",
+            ),
+            (
+                "b_test.lox",
+                "fun fresh() { expectEq(shared, nil); } test(\"fresh\", fresh);",
+            ),
+        ],
+    );
+    let output = rlox_test_with(&["--jobs", "1"], &dir);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].ends_with("a_test.lox (1 passed, 0 failed)"));
+    assert_eq!(lines[1], "    leaving", "{stdout}");
+    assert!(
+        stdout.contains("    Undefined variable 'shared'."),
+        "{stdout}"
+    );
+    assert!(
+        stdout.ends_with(
+            "2 files: 0 passed, 2 failed. 2 tests: 1 passed, 1 failed.\n"
+        ),
+        "{stdout}"
+    );
+}